@@ -0,0 +1,360 @@
+//! `#[derive(QemuOption)]` implements `OptionFormatting` and `OptionParsing`
+//! of qemu_rs for a struct describing one QEMU option, e.g.
+//!
+//! ```ignore
+//! #[derive(QemuOption)]
+//! #[qemu(option = "smp")]
+//! pub struct SmpConfig {
+//!     #[qemu(implied)]
+//!     pub cpus: Option<usize>,
+//!     pub maxcpus: Option<usize>,
+//! }
+//! ```
+//!
+//! Container attributes:
+//! - `option = "name"`: name of the option, required.
+//! - `prefix = "-"`: prefix of the option name.
+//! - `separator = ","`: separator between arguments. Options taking a single
+//!   value use `separator = ""`.
+//!
+//! Field attributes:
+//! - `rename = "key"`: key of the argument. Defaults to the field name with
+//!   `_` replaced by `-`.
+//! - `positional`: the value is written without its key, and the first
+//!   argument may omit the key when parsing.
+//! - `implied`: the value is written with its key, but the first argument may
+//!   omit the key when parsing, as in `-smp 2`.
+//! - `flag`: a `bool` written as the bare key when set.
+//! - `separator = ":"`: an `Option<Vec<T>>` written as one argument joined by
+//!   the separator. A `Vec<T>` without separator repeats the key instead.
+//! - `format_with = "path"`: `fn(&T) -> String` writing the value instead of
+//!   `Display`.
+//! - `parse_with = "path"`: `fn(&str) -> Result<T, E>` reading the value
+//!   instead of `FromStr`.
+//! - `flatten`: the arguments of another `QemuOption` struct are written and
+//!   parsed inline, e.g. throttling limits shared by several options.
+//! - `skip`: neither written nor parsed, the field takes its default value.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput, Error, Fields,
+    GenericArgument, LitStr, Path, PathArguments, Result, Type,
+};
+
+#[proc_macro_derive(QemuOption, attributes(qemu))]
+pub fn derive_qemu_option(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+struct ContainerAttrs {
+    option: Option<LitStr>,
+    prefix: String,
+    separator: String,
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    rename: Option<String>,
+    positional: bool,
+    implied: bool,
+    flag: bool,
+    flatten: bool,
+    skip: bool,
+    separator: Option<String>,
+    format_with: Option<Path>,
+    parse_with: Option<Path>,
+}
+
+/// How a field maps onto arguments, found from its type.
+enum Shape<'a> {
+    Required,
+    Optional,
+    OptionalList(&'a Type),
+    Repeated,
+    Flag,
+    Flatten,
+}
+
+fn container_attrs(attrs: &[Attribute]) -> Result<ContainerAttrs> {
+    let mut container = ContainerAttrs {
+        option: None,
+        prefix: "-".to_string(),
+        separator: ",".to_string(),
+    };
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("qemu")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("option") {
+                container.option = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("prefix") {
+                container.prefix = meta.value()?.parse::<LitStr>()?.value();
+            } else if meta.path.is_ident("separator") {
+                container.separator = meta.value()?.parse::<LitStr>()?.value();
+            } else {
+                return Err(meta.error("unknown qemu container attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(container)
+}
+
+fn field_attrs(attrs: &[Attribute]) -> Result<FieldAttrs> {
+    let mut field = FieldAttrs::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("qemu")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                field.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("positional") {
+                field.positional = true;
+            } else if meta.path.is_ident("implied") {
+                field.implied = true;
+            } else if meta.path.is_ident("flag") {
+                field.flag = true;
+            } else if meta.path.is_ident("flatten") {
+                field.flatten = true;
+            } else if meta.path.is_ident("skip") {
+                field.skip = true;
+            } else if meta.path.is_ident("separator") {
+                field.separator = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("format_with") {
+                field.format_with = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+            } else if meta.path.is_ident("parse_with") {
+                field.parse_with = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+            } else {
+                return Err(meta.error("unknown qemu field attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(field)
+}
+
+/// The `T` of `Wrapper<T>` if `ty` is a `Wrapper`.
+fn inner_type<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != wrapper {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    }
+}
+
+fn shape<'a>(ty: &'a Type, attrs: &FieldAttrs) -> Result<Shape<'a>> {
+    if attrs.flag {
+        return Ok(Shape::Flag);
+    }
+    if attrs.flatten {
+        return Ok(Shape::Flatten);
+    }
+    match (inner_type(ty, "Option"), &attrs.separator) {
+        (Some(inner), Some(_)) => match inner_type(inner, "Vec") {
+            Some(elem) => Ok(Shape::OptionalList(elem)),
+            None => Err(Error::new(
+                ty.span(),
+                "`separator` needs a field of type Option<Vec<T>>",
+            )),
+        },
+        (Some(_), None) => Ok(Shape::Optional),
+        (None, Some(_)) => Err(Error::new(
+            ty.span(),
+            "`separator` needs a field of type Option<Vec<T>>",
+        )),
+        (None, None) if inner_type(ty, "Vec").is_some() => Ok(Shape::Repeated),
+        (None, None) => Ok(Shape::Required),
+    }
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+    let container = container_attrs(&input.attrs)?;
+    let option = container
+        .option
+        .ok_or_else(|| Error::new(input.ident.span(), "missing #[qemu(option = \"...\")]"))?;
+    let prefix = &container.prefix;
+    let args_split_with = &container.separator;
+
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new(
+            input.ident.span(),
+            "QemuOption can only be derived for structs",
+        ));
+    };
+
+    let builder = quote!(::qemu_rs::command::builder);
+    let parser = quote!(::qemu_rs::command::parser);
+
+    let mut format_stmts = Vec::new();
+    let mut parse_exprs = Vec::new();
+    for (index, field) in data.fields.iter().enumerate() {
+        let attrs = field_attrs(&field.attrs)?;
+        // Tuple fields hold the value of options such as `-uuid`.
+        let (member, positional, key) = match &field.ident {
+            Some(ident) => (
+                quote!(#ident),
+                attrs.positional,
+                attrs
+                    .rename
+                    .clone()
+                    .unwrap_or_else(|| ident.to_string().replace('_', "-")),
+            ),
+            None => {
+                let index = syn::Index::from(index);
+                (quote!(#index), true, option.value())
+            }
+        };
+
+        if attrs.skip {
+            parse_exprs.push(quote!(::std::default::Default::default()));
+            continue;
+        }
+
+        let implied = positional || attrs.implied;
+        let (emitted_key, kv_split_with) = if positional {
+            (String::new(), quote!(::std::option::Option::None))
+        } else {
+            (key.clone(), quote!(::std::option::Option::Some("=")))
+        };
+        let format_value = |value: TokenStream2| match &attrs.format_with {
+            Some(path) => quote!(#path(#value)),
+            None => quote!(::std::string::ToString::to_string(#value)),
+        };
+        let arg = |value: TokenStream2| {
+            quote! {
+                args.push(#builder::KVArgQ {
+                    key: #emitted_key,
+                    kv_split_with: #kv_split_with,
+                    value: ::std::option::Option::Some(#value),
+                });
+            }
+        };
+        let parsed = match &attrs.parse_with {
+            Some(path) => quote!(args.parsed_with(#key, #implied, #path)?),
+            None => quote!(args.parsed(#key, #implied)?),
+        };
+
+        match shape(&field.ty, &attrs)? {
+            Shape::Required => {
+                let push = arg(format_value(quote!(&self.#member)));
+                format_stmts.push(push);
+                parse_exprs.push(quote!(#parsed.ok_or_else(|| args.missing(#key))?));
+            }
+            Shape::Optional => {
+                let push = arg(format_value(quote!(value)));
+                format_stmts.push(quote! {
+                    if let ::std::option::Option::Some(value) = &self.#member {
+                        #push
+                    }
+                });
+                parse_exprs.push(parsed);
+            }
+            Shape::OptionalList(elem) => {
+                let separator = attrs.separator.as_deref().unwrap_or_default();
+                let item = format_value(quote!(item));
+                let push = arg(quote! {
+                    value
+                        .iter()
+                        .map(|item| #item)
+                        .collect::<::std::vec::Vec<_>>()
+                        .join(#separator)
+                });
+                format_stmts.push(quote! {
+                    if let ::std::option::Option::Some(value) = &self.#member {
+                        #push
+                    }
+                });
+                parse_exprs.push(match &attrs.parse_with {
+                    Some(path) => quote!(args.parsed_list_with(#key, #implied, #separator, #path)?),
+                    None => quote!(args.parsed_list::<#elem>(#key, #implied, #separator)?),
+                });
+            }
+            Shape::Repeated => {
+                let push = arg(format_value(quote!(value)));
+                format_stmts.push(quote! {
+                    for value in &self.#member {
+                        #push
+                    }
+                });
+                parse_exprs.push(match &attrs.parse_with {
+                    Some(path) => quote!(args.parsed_all_with(#key, #path)?),
+                    None => quote!(args.parsed_all(#key)?),
+                });
+            }
+            Shape::Flag => {
+                format_stmts.push(quote! {
+                    if self.#member {
+                        args.push(#builder::KVArgQ {
+                            key: #key,
+                            kv_split_with: ::std::option::Option::None,
+                            value: ::std::option::Option::None,
+                        });
+                    }
+                });
+                parse_exprs.push(quote!(args.flag(#key)));
+            }
+            Shape::Flatten => {
+                format_stmts.push(quote! {
+                    args.extend(#builder::OptionFormatting::formatting(&self.#member).args);
+                });
+                parse_exprs.push(quote!(#parser::FromOptionArgs::from_args(args)?));
+            }
+        }
+    }
+
+    let construct = match &data.fields {
+        Fields::Named(fields) => {
+            let names = fields.named.iter().map(|field| &field.ident);
+            quote!(Self { #(#names: #parse_exprs,)* })
+        }
+        Fields::Unnamed(_) => quote!(Self(#(#parse_exprs,)*)),
+        Fields::Unit => quote!(Self),
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #builder::OptionFormatting for #name #ty_generics #where_clause {
+            fn formatting(&self) -> #builder::OptionQ<'_> {
+                #[allow(unused_mut)]
+                let mut args = ::std::vec::Vec::new();
+                #(#format_stmts)*
+                #builder::OptionQ {
+                    prefix: #prefix,
+                    raw: #option,
+                    option_args_split_with: " ",
+                    args_split_with: #args_split_with,
+                    args,
+                }
+            }
+        }
+
+        impl #impl_generics #parser::FromOptionArgs for #name #ty_generics #where_clause {
+            fn from_args(
+                args: &mut #parser::OptionArgs<'_>,
+            ) -> ::std::result::Result<Self, #parser::ParseError> {
+                ::std::result::Result::Ok(#construct)
+            }
+        }
+
+        impl #impl_generics #parser::OptionParsing for #name #ty_generics #where_clause {
+            fn parsing(
+                option: &#builder::OptionQ<'_>,
+            ) -> ::std::result::Result<Self, #parser::ParseError> {
+                let mut args = #parser::OptionArgs::new(option);
+                let config = #parser::FromOptionArgs::from_args(&mut args)?;
+                args.finish()?;
+                ::std::result::Result::Ok(config)
+            }
+        }
+    })
+}
@@ -1,18 +1,61 @@
 //! According to man page of qemu.
+use std::{borrow::Cow, ffi::OsString, fmt, process::Command};
 
-/// Build the command into a raw string.
+/// Derive `OptionFormatting` and `OptionParsing` for an option config.
+pub use qemu_rs_derive::QemuOption;
+
+/// Build the command into an argument vector.
 pub trait CommandBuild {
-    fn to_string(&self) -> String;
+    /// Arguments in the order they should be handed to the process, without
+    /// any shell splitting involved.
+    fn to_args(&self) -> Vec<OsString>;
 }
 
-pub trait OptionFormatting<'a> {
-    fn formatting(&self) -> OptionQ<'a>;
+pub trait OptionFormatting {
+    fn formatting(&self) -> OptionQ<'_>;
 }
 
-pub trait CommandFormatting<'a> {
-    fn formatting(&self) -> CommandQ<'a>;
+pub trait CommandFormatting {
+    fn formatting(&self) -> CommandQ<'_>;
+
+    /// Arguments of the formatted command, excluding the program itself.
+    fn to_args(&self) -> Vec<OsString> {
+        self.formatting().to_args()
+    }
+
+    /// A ready-to-spawn `Command` for the formatted command.
+    fn to_command(&self) -> Command {
+        self.formatting().to_command()
+    }
+
+    /// The formatted command quoted for a POSIX shell.
+    fn to_shell_string(&self) -> String {
+        self.formatting().to_shell_string()
+    }
 }
 
+/// Escape a value for QEMU's option parser, which splits arguments on `,`
+/// and reads a doubled `,,` as a literal comma.
+pub fn escape_value(value: &str) -> Cow<'_, str> {
+    if value.contains(',') {
+        Cow::Owned(value.replace(',', ",,"))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Quote a single argument for a POSIX shell. Arguments made only of
+/// characters the shell leaves alone are returned as they are.
+pub fn shell_quote(arg: &str) -> Cow<'_, str> {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        Cow::Borrowed(arg)
+    } else {
+        Cow::Owned(format!("'{}'", arg.replace('\'', "'\\''")))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct CommandQ<'a> {
     // Raw string of this command.
     pub raw: &'a str,
@@ -20,14 +63,48 @@ pub struct CommandQ<'a> {
     pub options: Vec<OptionQ<'a>>,
 }
 
+impl<'a> CommandQ<'a> {
+    /// Build a `std::process::Command` running `raw` with every option
+    /// passed as its own argument(s).
+    pub fn to_command(&self) -> Command {
+        let mut command = Command::new(self.raw);
+        command.args(self.to_args());
+        command
+    }
+
+    /// Rendering of the command that can be pasted into a shell, e.g. for
+    /// logs. Every argument is quoted as needed.
+    pub fn to_shell_string(&self) -> String {
+        std::iter::once(self.raw.into())
+            .chain(self.to_args())
+            .map(|arg| shell_quote(&arg.to_string_lossy()).into_owned())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
 impl<'a> CommandBuild for CommandQ<'a> {
-    fn to_string(&self) -> String {
-        self.options.iter().fold(self.raw.to_string(), |s, option| {
-            format!("{}{}{}", s, self.option_split_with, option.to_string())
-        })
+    fn to_args(&self) -> Vec<OsString> {
+        self.options
+            .iter()
+            .flat_map(|option| option.to_args())
+            .collect()
+    }
+}
+
+/// Display rendering of the whole command line. Only meant for humans: use
+/// `to_args` or `to_command` to actually run it.
+impl<'a> fmt::Display for CommandQ<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.raw)?;
+        for option in &self.options {
+            write!(f, "{}{}", self.option_split_with, option)?;
+        }
+        Ok(())
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct OptionQ<'a> {
     pub prefix: &'a str,
     // Raw string of this option.
@@ -37,48 +114,94 @@ pub struct OptionQ<'a> {
     pub args: Vec<KVArgQ<'a>>,
 }
 
+impl<'a> OptionQ<'a> {
+    #[inline]
+    fn name(&self) -> String {
+        format!("{}{}", self.prefix, self.raw)
+    }
+
+    #[inline]
+    fn joined_args(&self) -> String {
+        // Values only need escaping when QEMU splits the argument on commas,
+        // which depends on the option, not on how the arguments are joined.
+        let escape = !super::parser::is_single_value(self.raw);
+        self.args
+            .iter()
+            .map(|arg| arg.render(escape))
+            .collect::<Vec<_>>()
+            .join(self.args_split_with)
+    }
+}
+
 impl<'a> CommandBuild for OptionQ<'a> {
-    fn to_string(&self) -> String {
-        let mut b: bool = false;
-        self.args.iter().fold(
-            format!("{}{}", self.prefix, self.raw.to_string()),
-            |s, arg| {
-                format!(
-                    "{}{}{}",
-                    s,
-                    if b {
-                        self.args_split_with
-                    } else {
-                        b = true;
-                        self.option_args_split_with
-                    },
-                    arg.to_string()
-                )
-            },
-        )
+    fn to_args(&self) -> Vec<OsString> {
+        if self.args.is_empty() {
+            return vec![self.name().into()];
+        }
+        // Whitespace between the option and its arguments is where the shell
+        // would have split them, so they become separate arguments.
+        if self.option_args_split_with.trim().is_empty() {
+            vec![self.name().into(), self.joined_args().into()]
+        } else {
+            vec![format!(
+                "{}{}{}",
+                self.name(),
+                self.option_args_split_with,
+                self.joined_args()
+            )
+            .into()]
+        }
+    }
+}
+
+impl<'a> fmt::Display for OptionQ<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())?;
+        if !self.args.is_empty() {
+            write!(f, "{}{}", self.option_args_split_with, self.joined_args())?;
+        }
+        Ok(())
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct KVArgQ<'a> {
     pub key: &'a str,
     pub kv_split_with: Option<&'a str>,
     pub value: Option<String>,
 }
 
-impl<'a> CommandBuild for KVArgQ<'a> {
-    fn to_string(&self) -> String {
+impl<'a> KVArgQ<'a> {
+    /// Render the argument, applying QEMU's comma escaping to the value if
+    /// `escape` is set.
+    pub fn render(&self, escape: bool) -> String {
         match &self.value {
-            Some(s) => format!("{}{}{}", self.key, self.kv_split_with.unwrap_or(""), s),
-            None => format!("{}", self.key),
+            Some(s) => {
+                let value = if escape {
+                    escape_value(s)
+                } else {
+                    Cow::Borrowed(s.as_str())
+                };
+                format!("{}{}{}", self.key, self.kv_split_with.unwrap_or(""), value)
+            }
+            None => self.key.to_string(),
         }
     }
 }
 
+impl<'a> fmt::Display for KVArgQ<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use std::ffi::OsString;
+
     use crate::command::builder::CommandBuild;
 
-    use super::{CommandQ, KVArgQ, OptionQ};
+    use super::{shell_quote, CommandQ, KVArgQ, OptionFormatting, OptionQ};
 
     #[test]
     fn test_command_build_sound() {
@@ -92,9 +215,9 @@ mod test {
                     option_args_split_with: " ",
                     args_split_with: "",
                     args: vec![KVArgQ {
-                        key: "\"testmachine\"",
+                        key: "",
                         kv_split_with: None,
-                        value: None,
+                        value: Some("testmachine".to_string()),
                     }],
                 },
                 OptionQ {
@@ -267,6 +390,234 @@ mod test {
             ],
         };
         assert_eq!(cmd.to_string(),
-        "qemu-system-x86_64 -name \"testmachine\" -machine accel=kvm -M pc -m 768 -smp 2 -boot d -drive file=/images/sles/hda,if=virtio,index=0,media=disk,format=raw -drive file=/isos/image.iso,index=1,media=cdrom -net nic,model=virtio,macaddr=52:54:00:05:11:11 -net user -vga cirrus -balloon virtio")
+        "qemu-system-x86_64 -name testmachine -machine accel=kvm -M pc -m 768 -smp 2 -boot d -drive file=/images/sles/hda,if=virtio,index=0,media=disk,format=raw -drive file=/isos/image.iso,index=1,media=cdrom -net nic,model=virtio,macaddr=52:54:00:05:11:11 -net user -vga cirrus -balloon virtio")
+    }
+
+    #[test]
+    fn test_command_args_keep_spaces() {
+        let cmd = CommandQ {
+            raw: "qemu-system-x86_64",
+            option_split_with: " ",
+            options: vec![
+                OptionQ {
+                    prefix: "-",
+                    raw: "drive",
+                    option_args_split_with: " ",
+                    args_split_with: ",",
+                    args: vec![
+                        KVArgQ {
+                            key: "file",
+                            kv_split_with: Some("="),
+                            value: Some("/images/my disk.qcow2".to_string()),
+                        },
+                        KVArgQ {
+                            key: "format",
+                            kv_split_with: Some("="),
+                            value: Some("qcow2".to_string()),
+                        },
+                    ],
+                },
+                OptionQ {
+                    prefix: "-",
+                    raw: "nographic",
+                    option_args_split_with: " ",
+                    args_split_with: "",
+                    args: vec![],
+                },
+            ],
+        };
+        assert_eq!(
+            cmd.to_args(),
+            vec![
+                OsString::from("-drive"),
+                OsString::from("file=/images/my disk.qcow2,format=qcow2"),
+                OsString::from("-nographic"),
+            ]
+        );
+
+        let command = cmd.to_command();
+        assert_eq!(command.get_program(), "qemu-system-x86_64");
+        assert_eq!(command.get_args().count(), 3);
+    }
+
+    fn command_with_file(file: &str) -> CommandQ<'static> {
+        CommandQ {
+            raw: "qemu-system-x86_64",
+            option_split_with: " ",
+            options: vec![
+                OptionQ {
+                    prefix: "-",
+                    raw: "name",
+                    option_args_split_with: " ",
+                    args_split_with: ",",
+                    args: vec![KVArgQ {
+                        key: "",
+                        kv_split_with: None,
+                        value: Some("guest, \"one\"".to_string()),
+                    }],
+                },
+                OptionQ {
+                    prefix: "-",
+                    raw: "drive",
+                    option_args_split_with: " ",
+                    args_split_with: ",",
+                    args: vec![
+                        KVArgQ {
+                            key: "file",
+                            kv_split_with: Some("="),
+                            value: Some(file.to_string()),
+                        },
+                        KVArgQ {
+                            key: "if",
+                            kv_split_with: Some("="),
+                            value: Some("virtio".to_string()),
+                        },
+                    ],
+                },
+                OptionQ {
+                    prefix: "-",
+                    raw: "mem-path",
+                    option_args_split_with: " ",
+                    args_split_with: "",
+                    args: vec![KVArgQ {
+                        key: "",
+                        kv_split_with: None,
+                        value: Some("/dev/hugepages/a,b".to_string()),
+                    }],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_comma_escaping() {
+        let cmd = command_with_file("/images/a,b.img");
+        assert_eq!(
+            cmd.to_args(),
+            vec![
+                OsString::from("-name"),
+                OsString::from("guest,, \"one\""),
+                OsString::from("-drive"),
+                OsString::from("file=/images/a,,b.img,if=virtio"),
+                OsString::from("-mem-path"),
+                OsString::from("/dev/hugepages/a,b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comma_escaping_single_value() {
+        let option = |raw, value: &str| OptionQ {
+            prefix: "-",
+            raw,
+            option_args_split_with: " ",
+            args_split_with: "",
+            args: vec![KVArgQ {
+                key: "",
+                kv_split_with: None,
+                value: Some(value.to_string()),
+            }],
+        };
+        // QEMU splits a chardev spec on commas, but not a kernel path.
+        assert_eq!(
+            option("serial", "file:/tmp/a,b.log").to_string(),
+            "-serial file:/tmp/a,,b.log"
+        );
+        assert_eq!(
+            option("kernel", "/boot/a,b").to_string(),
+            "-kernel /boot/a,b"
+        );
+    }
+
+    #[test]
+    fn test_equal_sign_in_value() {
+        let cmd = command_with_file("/images/key=value.img");
+        assert_eq!(
+            cmd.to_string(),
+            "qemu-system-x86_64 -name guest,, \"one\" -drive file=/images/key=value.img,if=virtio -mem-path /dev/hugepages/a,b"
+        );
+    }
+
+    #[test]
+    fn test_shell_quoting() {
+        let cmd = command_with_file("/images/it's a disk.img");
+        assert_eq!(
+            cmd.to_shell_string(),
+            "qemu-system-x86_64 -name 'guest,, \"one\"' -drive 'file=/images/it'\\''s a disk.img,if=virtio' -mem-path /dev/hugepages/a,b"
+        );
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("size=512M,slots=2"), "size=512M,slots=2");
+    }
+
+    fn hex(value: &u32) -> String {
+        format!("{:#x}", value)
+    }
+
+    fn parse_hex(value: &str) -> Result<u32, std::num::ParseIntError> {
+        u32::from_str_radix(value.trim_start_matches("0x"), 16)
+    }
+
+    /// An option defined the way a downstream crate would.
+    #[derive(Debug, PartialEq, super::QemuOption)]
+    #[qemu(option = "chardev")]
+    struct Chardev {
+        #[qemu(positional)]
+        backend: String,
+        id: String,
+        #[qemu(flag)]
+        server: bool,
+        #[qemu(rename = "host-nodes", separator = "-")]
+        host_nodes: Option<Vec<usize>>,
+        #[qemu(rename = "hostfwd")]
+        forwards: Vec<String>,
+        #[qemu(format_with = "hex", parse_with = "parse_hex")]
+        mask: Option<u32>,
+        #[qemu(skip)]
+        note: Option<String>,
+    }
+
+    #[test]
+    fn test_derive_option() {
+        use crate::command::parser::{parse_args, OptionParsing, ParseError};
+
+        let chardev = Chardev {
+            backend: "socket".to_string(),
+            id: "mon,0".to_string(),
+            server: true,
+            host_nodes: Some(vec![0, 1]),
+            forwards: vec!["tcp::2222-:22".to_string(), "udp::53-:53".to_string()],
+            mask: Some(255),
+            note: None,
+        };
+        let option = chardev.formatting();
+        assert_eq!(
+            option.to_args(),
+            [
+                OsString::from("-chardev"),
+                OsString::from(
+                    "socket,id=mon,,0,server,host-nodes=0-1,hostfwd=tcp::2222-:22,hostfwd=udp::53-:53,mask=0xff"
+                ),
+            ]
+        );
+
+        let argv: Vec<String> = std::iter::once("qemu-system-x86_64".to_string())
+            .chain(
+                option
+                    .to_args()
+                    .into_iter()
+                    .map(|a| a.into_string().unwrap()),
+            )
+            .collect();
+        let cmd = parse_args(&argv).unwrap();
+        assert_eq!(Chardev::parsing(&cmd.options[0]), Ok(chardev));
+
+        let cmd = parse_args(&["qemu-system-x86_64", "-chardev", "pty,path=/dev/pts/1"]).unwrap();
+        assert_eq!(
+            Chardev::parsing(&cmd.options[0]),
+            Err(ParseError::MissingValue {
+                option: "chardev".to_string(),
+                key: "id".to_string()
+            })
+        );
     }
 }
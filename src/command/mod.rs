@@ -1 +1,2 @@
-pub mod builder;
\ No newline at end of file
+pub mod builder;
+pub mod parser;
\ No newline at end of file
@@ -0,0 +1,569 @@
+//! Parse a QEMU command line back into options.
+//!
+//! Parsing happens in two steps. `parse_args` splits an argv vector into a
+//! `CommandQ`, undoing the `,,` escaping of values. Configuration types then
+//! pick their arguments out of an `OptionQ` through `OptionParsing`.
+use std::{error::Error, fmt, str::FromStr};
+
+use super::builder::{CommandQ, KVArgQ, OptionQ};
+
+/// Options that take an argument, i.e. those declared with `HAS_ARG` in
+/// QEMU's qemu-options.def. Any other option is a flag.
+const ARG_OPTIONS: [&str; 92] = [
+    "D",
+    "L",
+    "M",
+    "accel",
+    "acpitable",
+    "action",
+    "add-fd",
+    "append",
+    "audio",
+    "audiodev",
+    "bios",
+    "blockdev",
+    "boot",
+    "cdrom",
+    "chardev",
+    "chroot",
+    "compat",
+    "cpu",
+    "d",
+    "debugcon",
+    "device",
+    "dfilter",
+    "display",
+    "drive",
+    "dtb",
+    "dump-vmstate",
+    "echr",
+    "fda",
+    "fdb",
+    "fsdev",
+    "fw_cfg",
+    "g",
+    "gdb",
+    "global",
+    "hda",
+    "hdb",
+    "hdc",
+    "hdd",
+    "icount",
+    "incoming",
+    "initrd",
+    "iscsi",
+    "k",
+    "kernel",
+    "loadvm",
+    "m",
+    "machine",
+    "mem-path",
+    "mon",
+    "monitor",
+    "msg",
+    "mtdblock",
+    "name",
+    "net",
+    "netdev",
+    "nic",
+    "numa",
+    "object",
+    "option-rom",
+    "overcommit",
+    "parallel",
+    "pflash",
+    "pidfile",
+    "plugin",
+    "prom-env",
+    "qmp",
+    "qmp-pretty",
+    "qtest",
+    "qtest-log",
+    "readconfig",
+    "rotate",
+    "rtc",
+    "run-with",
+    "runas",
+    "sandbox",
+    "sd",
+    "seed",
+    "semihosting-config",
+    "serial",
+    "set",
+    "smbios",
+    "smp",
+    "spice",
+    "tpmdev",
+    "trace",
+    "usbdevice",
+    "uuid",
+    "vga",
+    "virtfs",
+    "vnc",
+    "watchdog-action",
+    "xen-domid",
+];
+
+/// Options whose argument is a single value rather than a list of
+/// comma separated `key=value` pairs.
+const SINGLE_VALUE_OPTIONS: [&str; 13] = [
+    "L",
+    "append",
+    "bios",
+    "dtb",
+    "initrd",
+    "k",
+    "kernel",
+    "mem-path",
+    "pidfile",
+    "set",
+    "uuid",
+    "vga",
+    "watchdog-action",
+];
+
+/// Whether QEMU takes the argument of `-option` as is, rather than
+/// splitting it on commas.
+pub(crate) fn is_single_value(option: &str) -> bool {
+    SINGLE_VALUE_OPTIONS.contains(&option)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The argv vector was empty.
+    MissingProgram,
+    /// An option was given without the argument it requires.
+    MissingArgument { option: String },
+    /// A required key of an option was not given.
+    MissingValue { option: String, key: String },
+    /// A key was given a value that could not be parsed.
+    InvalidValue {
+        option: String,
+        key: String,
+        value: String,
+    },
+    /// A key that the option does not know about.
+    UnknownKey { option: String, key: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::MissingProgram => write!(f, "missing qemu program"),
+            ParseError::MissingArgument { option } => {
+                write!(f, "option -{} requires an argument", option)
+            }
+            ParseError::MissingValue { option, key } => {
+                write!(f, "option -{} requires '{}'", option, key)
+            }
+            ParseError::InvalidValue { option, key, value } => {
+                write!(f, "invalid value '{}' for '{}' of -{}", value, key, option)
+            }
+            ParseError::UnknownKey { option, key } => {
+                write!(f, "unknown key '{}' for -{}", key, option)
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Build an option from its parsed arguments.
+pub trait OptionParsing: Sized {
+    fn parsing(option: &OptionQ<'_>) -> Result<Self, ParseError>;
+}
+
+/// Build part of an option from its arguments, leaving the rest to the
+/// caller. `#[derive(QemuOption)]` implements it so that option configs can
+/// share groups of keys through `#[qemu(flatten)]`.
+pub trait FromOptionArgs: Sized {
+    fn from_args(args: &mut OptionArgs<'_>) -> Result<Self, ParseError>;
+}
+
+/// Split an argv vector, program first, into a `CommandQ`.
+pub fn parse_args<S: AsRef<str>>(argv: &[S]) -> Result<CommandQ<'_>, ParseError> {
+    let (program, rest) = argv.split_first().ok_or(ParseError::MissingProgram)?;
+    let mut options = Vec::new();
+    let mut rest = rest.iter().map(AsRef::as_ref).peekable();
+    while let Some(arg) = rest.next() {
+        // Arguments not attached to an option (e.g. a bare disk image) are
+        // kept as an option without a name.
+        let Some(name) = arg.strip_prefix('-') else {
+            options.push(positional_option(arg));
+            continue;
+        };
+        let name = name.strip_prefix('-').unwrap_or(name);
+        let mut option = OptionQ {
+            prefix: "-",
+            raw: name,
+            option_args_split_with: " ",
+            args_split_with: "",
+            args: vec![],
+        };
+        // Like QEMU, an option with an argument takes the next one whatever
+        // it looks like, e.g. `-append -v`.
+        if ARG_OPTIONS.contains(&name) {
+            let value = rest.next().ok_or_else(|| ParseError::MissingArgument {
+                option: name.to_string(),
+            })?;
+            if is_single_value(name) {
+                option.args.push(positional(value.to_string()));
+            } else {
+                option.args_split_with = ",";
+                option.args = split_args(value);
+            }
+        }
+        options.push(option);
+    }
+    Ok(CommandQ {
+        raw: program.as_ref(),
+        option_split_with: " ",
+        options,
+    })
+}
+
+#[inline]
+fn positional<'a>(value: String) -> KVArgQ<'a> {
+    KVArgQ {
+        key: "",
+        kv_split_with: None,
+        value: Some(value),
+    }
+}
+
+#[inline]
+fn positional_option(arg: &str) -> OptionQ<'_> {
+    OptionQ {
+        prefix: "",
+        raw: arg,
+        option_args_split_with: "",
+        args_split_with: "",
+        args: vec![],
+    }
+}
+
+/// Split `key=value,key=value` on single commas, turning `,,` back into `,`.
+fn split_args(arg: &str) -> Vec<KVArgQ<'_>> {
+    let mut args = Vec::new();
+    let mut start = 0;
+    let mut chars = arg.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c != ',' {
+            continue;
+        }
+        if chars.peek().is_some_and(|&(_, next)| next == ',') {
+            chars.next();
+            continue;
+        }
+        args.push(split_kv(&arg[start..i]));
+        start = i + 1;
+    }
+    args.push(split_kv(&arg[start..]));
+    args
+}
+
+#[inline]
+fn split_kv(arg: &str) -> KVArgQ<'_> {
+    // Keys never contain commas, so the first '=' ends the key even in
+    // escaped values.
+    match arg.split_once('=') {
+        Some((key, value)) if !key.contains(',') => KVArgQ {
+            key,
+            kv_split_with: Some("="),
+            value: Some(value.replace(",,", ",")),
+        },
+        _ => positional(arg.replace(",,", ",")),
+    }
+}
+
+/// Look up the arguments of an option, remembering which ones were used so
+/// that leftovers can be reported.
+pub struct OptionArgs<'a> {
+    option: &'a OptionQ<'a>,
+    used: Vec<bool>,
+}
+
+impl<'a> OptionArgs<'a> {
+    pub fn new(option: &'a OptionQ<'a>) -> Self {
+        Self {
+            option,
+            used: vec![false; option.args.len()],
+        }
+    }
+
+    /// Value of `key`. The first argument may omit the key, as in
+    /// `-smp 2` for `-smp cpus=2`, when `implied` is set. The legacy
+    /// boolean forms `key` and `nokey`, as in `server,nowait` of
+    /// `-chardev`, read as `on` and `off`.
+    pub fn value(&mut self, key: &str, implied: bool) -> Option<&'a str> {
+        let index = self.option.args.iter().enumerate().position(|(i, arg)| {
+            arg.key == key || (implied && i == 0 && arg.key.is_empty() && !self.used[0])
+        });
+        if let Some(index) = index {
+            self.used[index] = true;
+            return self.option.args[index].value.as_deref();
+        }
+        let (index, value) = self.option.args.iter().enumerate().find_map(|(i, arg)| {
+            let bare = arg
+                .value
+                .as_deref()
+                .filter(|_| arg.key.is_empty() && !self.used[i])?;
+            if bare == key {
+                Some((i, "on"))
+            } else if bare.strip_prefix("no") == Some(key) {
+                Some((i, "off"))
+            } else {
+                None
+            }
+        })?;
+        self.used[index] = true;
+        Some(value)
+    }
+
+    /// Value of `key` parsed into `T`.
+    pub fn parsed<T: FromStr>(
+        &mut self,
+        key: &str,
+        implied: bool,
+    ) -> Result<Option<T>, ParseError> {
+        match self.value(key, implied) {
+            None => Ok(None),
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| self.invalid(key, value)),
+        }
+    }
+
+    /// Value of `key` parsed by `f`, for values whose meaning depends on
+    /// the option, e.g. sizes without a unit.
+    pub fn parsed_with<T, E>(
+        &mut self,
+        key: &str,
+        implied: bool,
+        f: impl FnOnce(&str) -> Result<T, E>,
+    ) -> Result<Option<T>, ParseError> {
+        match self.value(key, implied) {
+            None => Ok(None),
+            Some(value) => f(value).map(Some).map_err(|_| self.invalid(key, value)),
+        }
+    }
+
+    /// Value of `key` split on `separator`, each item parsed into `T`, as in
+    /// `accel=kvm:tcg`.
+    pub fn parsed_list<T: FromStr>(
+        &mut self,
+        key: &str,
+        implied: bool,
+        separator: &str,
+    ) -> Result<Option<Vec<T>>, ParseError> {
+        self.parsed_list_with(key, implied, separator, str::parse)
+    }
+
+    /// Value of `key` split on `separator`, each item parsed by `f`.
+    pub fn parsed_list_with<T, E>(
+        &mut self,
+        key: &str,
+        implied: bool,
+        separator: &str,
+        f: impl Fn(&str) -> Result<T, E>,
+    ) -> Result<Option<Vec<T>>, ParseError> {
+        self.parsed_with(key, implied, |value| {
+            value.split(separator).map(&f).collect::<Result<_, _>>()
+        })
+    }
+
+    /// Values of every occurrence of `key` parsed into `T`, for keys that
+    /// may be repeated.
+    pub fn parsed_all<T: FromStr>(&mut self, key: &str) -> Result<Vec<T>, ParseError> {
+        self.parsed_all_with(key, str::parse)
+    }
+
+    /// Values of every occurrence of `key` parsed by `f`.
+    pub fn parsed_all_with<T, E>(
+        &mut self,
+        key: &str,
+        f: impl Fn(&str) -> Result<T, E>,
+    ) -> Result<Vec<T>, ParseError> {
+        let mut values = Vec::new();
+        for (i, arg) in self.option.args.iter().enumerate() {
+            if arg.key != key {
+                continue;
+            }
+            self.used[i] = true;
+            let value = arg.value.as_deref().unwrap_or_default();
+            values.push(f(value).map_err(|_| self.invalid(key, value))?);
+        }
+        Ok(values)
+    }
+
+    /// Whether the bare `key` was given, as in `server` of `-chardev`.
+    pub fn flag(&mut self, key: &str) -> bool {
+        let index = self.option.args.iter().enumerate().position(|(i, arg)| {
+            !self.used[i]
+                && (arg.key == key && arg.value.is_none()
+                    || arg.key.is_empty() && arg.value.as_deref() == Some(key))
+        });
+        match index {
+            Some(index) => {
+                self.used[index] = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Error for a value of `key` that cannot be parsed.
+    pub fn invalid(&self, key: &str, value: &str) -> ParseError {
+        ParseError::InvalidValue {
+            option: self.option.raw.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    /// Error for a key that must be given.
+    pub fn missing(&self, key: &str) -> ParseError {
+        ParseError::MissingValue {
+            option: self.option.raw.to_string(),
+            key: key.to_string(),
+        }
+    }
+
+    /// Fail on the first argument nobody asked for.
+    pub fn finish(self) -> Result<(), ParseError> {
+        match self.used.iter().position(|used| !used) {
+            None => Ok(()),
+            Some(index) => {
+                let arg = &self.option.args[index];
+                Err(ParseError::UnknownKey {
+                    option: self.option.raw.to_string(),
+                    key: if arg.key.is_empty() {
+                        arg.value.clone().unwrap_or_default()
+                    } else {
+                        arg.key.to_string()
+                    },
+                })
+            }
+        }
+    }
+}
+
+/// Parse the single positional value of options such as `-uuid`.
+pub fn single_value<'a>(option: &'a OptionQ<'a>) -> Result<&'a str, ParseError> {
+    let mut args = OptionArgs::new(option);
+    let value = args
+        .value("", true)
+        .ok_or_else(|| ParseError::MissingArgument {
+            option: option.raw.to_string(),
+        })?;
+    args.finish()?;
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::builder::CommandBuild;
+
+    #[test]
+    fn test_parse_args() {
+        let argv = [
+            "qemu-system-x86_64",
+            "-name",
+            "guest,, one,debug-threads=on",
+            "--nographic",
+            "-drive",
+            "file=/images/a,,b=c.img,if=virtio",
+            "-mem-path",
+            "/dev/hugepages/a,b",
+            "disk.img",
+        ];
+        let cmd = parse_args(&argv).unwrap();
+        assert_eq!(cmd.raw, "qemu-system-x86_64");
+        assert_eq!(cmd.options.len(), 5);
+
+        let name = &cmd.options[0];
+        assert_eq!(name.raw, "name");
+        assert_eq!(name.args[0].key, "");
+        assert_eq!(name.args[0].value.as_deref(), Some("guest, one"));
+        assert_eq!(name.args[1].key, "debug-threads");
+
+        assert_eq!(cmd.options[1].raw, "nographic");
+        assert!(cmd.options[1].args.is_empty());
+
+        let drive = &cmd.options[2];
+        assert_eq!(drive.args[0].key, "file");
+        assert_eq!(drive.args[0].value.as_deref(), Some("/images/a,b=c.img"));
+        assert_eq!(drive.args[1].value.as_deref(), Some("virtio"));
+
+        assert_eq!(
+            cmd.options[3].args[0].value.as_deref(),
+            Some("/dev/hugepages/a,b")
+        );
+        assert_eq!(cmd.options[4].prefix, "");
+        assert_eq!(cmd.options[4].raw, "disk.img");
+
+        // Formatting the parsed command gives back the same arguments.
+        let args: Vec<String> = cmd
+            .to_args()
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect();
+        assert_eq!(
+            args,
+            argv[1..]
+                .iter()
+                .map(|s| s.replace("--", "-"))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_parse_flags() {
+        let argv = [
+            "qemu-system-x86_64",
+            "-s",
+            "disk.img",
+            "-m",
+            "1G",
+            "-append",
+            "-v quiet",
+            "-mem-prealloc",
+        ];
+        let cmd = parse_args(&argv).unwrap();
+        let names: Vec<&str> = cmd.options.iter().map(|option| option.raw).collect();
+        assert_eq!(names, ["s", "disk.img", "m", "append", "mem-prealloc"]);
+        assert!(cmd.options[0].args.is_empty());
+        assert_eq!(cmd.options[1].prefix, "");
+        assert_eq!(cmd.options[2].args[0].value.as_deref(), Some("1G"));
+        assert_eq!(cmd.options[3].args[0].value.as_deref(), Some("-v quiet"));
+        assert!(cmd.options[4].args.is_empty());
+
+        assert_eq!(
+            parse_args(&["qemu-system-x86_64", "-s", "-m"]),
+            Err(ParseError::MissingArgument {
+                option: "m".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_option_args() {
+        let argv = ["qemu-system-x86_64", "-smp", "2,maxcpus=four,cores=1"];
+        let cmd = parse_args(&argv).unwrap();
+        let mut args = OptionArgs::new(&cmd.options[0]);
+        assert_eq!(args.parsed::<usize>("cpus", true), Ok(Some(2)));
+        assert!(matches!(
+            args.parsed::<usize>("maxcpus", false),
+            Err(ParseError::InvalidValue { .. })
+        ));
+        assert_eq!(
+            args.finish(),
+            Err(ParseError::UnknownKey {
+                option: "smp".to_string(),
+                key: "cores".to_string()
+            })
+        );
+    }
+}
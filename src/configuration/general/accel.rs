@@ -1,24 +1,31 @@
-//! This will depend on the hypervisor you run. Note that the default is TCG, which is 
+//! This will depend on the hypervisor you run. Note that the default is TCG, which is
 //! purely emulated, so you must specify an accelerator type to take advantage of hardware virtualization.
 
 use crate::command::builder::*;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AccelConfig<'a> {
+use super::{
+    types::{mib, Accelerator, ByteSize, KernelIrqchip, OnOff, TcgThread},
+    validate::*,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "accel")]
+pub struct AccelConfig {
     // This is used to enable an accelerator. Depending on  the  target
     // architecture,  kvm,  xen,  hax,  hvf,  nvmm,  whpx or tcg can be
     // available. By default, tcg is used. If there is  more  than  one
     // accelerator  specified, the next one is used if the previous one
     // fails to initialize.
-    pub name: &'a str,
+    #[qemu(positional, rename = "accel")]
+    pub name: Accelerator,
 
     // igd-passthru=on|off
     // When Xen is in use, this option  controls  whether  Intel
     // integrated  graphics devices can be passed through to the
     // guest (default=off)
     #[serde(rename = "igd-passthru", skip_serializing_if = "Option::is_none")]
-    pub igd_passthru: Option<&'a str>,
+    pub igd_passthru: Option<OnOff>,
 
     // kernel-irqchip=on|off|split
     // Controls KVM in-kernel irqchip support.  The  default  is
@@ -28,12 +35,14 @@ pub struct AccelConfig<'a> {
     // in-kernel irqchip completely is  not  recommended  except
     // for debugging purposes.
     #[serde(rename = "kernel-irqchip", skip_serializing_if = "Option::is_none")]
-    pub kernel_irqchip: Option<&'a str>,
+    pub kernel_irqchip: Option<KernelIrqchip>,
 
     // kvm-shadow-mem=size
     // Defines the size of the KVM shadow MMU.
+    // QEMU takes the size in bytes, without suffix.
     #[serde(rename = "kvm-shadow-mem", skip_serializing_if = "Option::is_none")]
-    pub kvm_shadow_mem: Option<usize>,
+    #[qemu(format_with = "ByteSize::to_bytes_string")]
+    pub kvm_shadow_mem: Option<ByteSize>,
 
     // split-wx=on|off
     // Controls  the  use  of split w^x mapping for the TCG code
@@ -42,13 +51,23 @@ pub struct AccelConfig<'a> {
     // other operating systems, this will default off,  but  one
     // may enable this for testing or debugging.
     #[serde(rename = "split-wx", skip_serializing_if = "Option::is_none")]
-    pub split_wx: Option<&'a str>,
+    pub split_wx: Option<OnOff>,
 
     // tb-size=n
     // Controls  the  size (in MiB) of the TCG translation block
     // cache.
-    #[serde(rename = "tb-size", skip_serializing_if = "Option::is_none")]
-    pub tb_size: Option<usize>,
+    // QEMU takes the size in MiB, without suffix.
+    #[serde(
+        rename = "tb-size",
+        default,
+        deserialize_with = "mib::option::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[qemu(
+        format_with = "ByteSize::to_mib_string",
+        parse_with = "ByteSize::parse_mib"
+    )]
+    pub tb_size: Option<ByteSize>,
 
     // thread=single|multi
     // Controls  number  of  TCG  threads.  When  the   TCG   is
@@ -58,7 +77,7 @@ pub struct AccelConfig<'a> {
     // back-end and front-ends support it  and  no  incompatible
     // TCG features have been enabled (e.g.  icount/replay).
     #[serde(rename = "thread", skip_serializing_if = "Option::is_none")]
-    pub thread: Option<&'a str>,
+    pub thread: Option<TcgThread>,
 
     // dirty-ring-size=n
     // When the KVM accelerator is used, it controls the size of
@@ -75,122 +94,103 @@ pub struct AccelConfig<'a> {
     pub dirty_ring_size: Option<usize>,
 }
 
-impl<'a> AccelConfig<'a> {
-    #[inline]
-    fn name(&self) -> Option<KVArgQ<'a>> {
-        Some(KVArgQ {
-            key: &self.name,
-            kv_split_with: None,
-            value: None,
-        })
-    }
+impl Validate for AccelConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        if let Some(value) = self.tb_size {
+            if !value.bytes().is_multiple_of(ByteSize::M) {
+                invalid(
+                    errors,
+                    path,
+                    "tb-size",
+                    ValidationErrorKind::Unaligned {
+                        value,
+                        align: ByteSize::mib(1),
+                    },
+                );
+            }
+        }
 
-    #[inline]
-    fn igd_passthru(&self) -> Option<KVArgQ<'a>> {
-        match self.igd_passthru {
-            None => None,
-            Some(igd_passthru) => Some(KVArgQ {
-                key: "igd-passthru",
-                kv_split_with: Some("="),
-                value: Some(igd_passthru.to_string()),
-            }),
+        // 0 disables the dirty ring.
+        if let Some(value) = self.dirty_ring_size {
+            if value != 0 && (value < 1024 || !value.is_power_of_two()) {
+                invalid(
+                    errors,
+                    path,
+                    "dirty-ring-size",
+                    ValidationErrorKind::NotPowerOfTwo { value, min: 1024 },
+                );
+            }
         }
     }
+}
+
+/// Builder for `AccelConfig`, e.g.
+///     AccelConfig::builder(Accelerator::Kvm).kernel_irqchip(KernelIrqchip::Split).build()
+#[derive(Debug, Clone)]
+pub struct AccelConfigBuilder {
+    config: AccelConfig,
+}
 
-    #[inline]
-    fn kernel_irqchip(&self) -> Option<KVArgQ<'a>> {
-        match self.kernel_irqchip {
-            None => None,
-            Some(kernel_irqchip) => Some(KVArgQ {
-                key: "kernel-irqchip",
-                kv_split_with: Some("="),
-                value: Some(kernel_irqchip.to_string()),
-            }),
+impl AccelConfig {
+    pub fn builder(name: Accelerator) -> AccelConfigBuilder {
+        AccelConfigBuilder {
+            config: AccelConfig {
+                name,
+                igd_passthru: None,
+                kernel_irqchip: None,
+                kvm_shadow_mem: None,
+                split_wx: None,
+                tb_size: None,
+                thread: None,
+                dirty_ring_size: None,
+            },
         }
     }
+}
 
-    #[inline]
-    fn kvm_shadow_mem(&self) -> Option<KVArgQ<'a>> {
-        match self.kvm_shadow_mem {
-            None => None,
-            Some(kvm_shadow_mem) => Some(KVArgQ {
-                key: "kvm-shadow-mem",
-                kv_split_with: Some("="),
-                value: Some(kvm_shadow_mem.to_string()),
-            }),
-        }
+impl AccelConfigBuilder {
+    pub fn igd_passthru(mut self, igd_passthru: impl Into<OnOff>) -> Self {
+        self.config.igd_passthru = Some(igd_passthru.into());
+        self
     }
 
-    #[inline]
-    fn split_wx(&self) -> Option<KVArgQ<'a>> {
-        match self.split_wx {
-            None => None,
-            Some(split_wx) => Some(KVArgQ {
-                key: "split-wx",
-                kv_split_with: Some("="),
-                value: Some(split_wx.to_string()),
-            }),
-        }
+    pub fn kernel_irqchip(mut self, kernel_irqchip: impl Into<KernelIrqchip>) -> Self {
+        self.config.kernel_irqchip = Some(kernel_irqchip.into());
+        self
     }
 
-    #[inline]
-    fn tb_size(&self) -> Option<KVArgQ<'a>> {
-        match self.tb_size {
-            None => None,
-            Some(tb_size) => Some(KVArgQ {
-                key: "tb-size",
-                kv_split_with: Some("="),
-                value: Some(tb_size.to_string()),
-            }),
-        }
+    pub fn kvm_shadow_mem(mut self, kvm_shadow_mem: ByteSize) -> Self {
+        self.config.kvm_shadow_mem = Some(kvm_shadow_mem);
+        self
     }
 
-    #[inline]
-    fn thread(&self) -> Option<KVArgQ<'a>> {
-        match self.thread {
-            None => None,
-            Some(thread) => Some(KVArgQ {
-                key: "thread",
-                kv_split_with: Some("="),
-                value: Some(thread.to_string()),
-            }),
-        }
+    pub fn split_wx(mut self, split_wx: impl Into<OnOff>) -> Self {
+        self.config.split_wx = Some(split_wx.into());
+        self
     }
 
-    #[inline]
-    fn dirty_ring_size(&self) -> Option<KVArgQ<'a>> {
-        match self.dirty_ring_size {
-            None => None,
-            Some(dirty_ring_size) => Some(KVArgQ {
-                key: "dirty-ring-size",
-                kv_split_with: Some("="),
-                value: Some(dirty_ring_size.to_string()),
-            }),
-        }
+    pub fn tb_size(mut self, tb_size: ByteSize) -> Self {
+        self.config.tb_size = Some(tb_size);
+        self
+    }
+
+    pub fn thread(mut self, thread: impl Into<TcgThread>) -> Self {
+        self.config.thread = Some(thread.into());
+        self
+    }
+
+    pub fn dirty_ring_size(mut self, dirty_ring_size: usize) -> Self {
+        self.config.dirty_ring_size = Some(dirty_ring_size);
+        self
+    }
+
+    pub fn build(self) -> AccelConfig {
+        self.config
     }
 }
 
-impl<'a> OptionFormatting<'a> for AccelConfig<'a> {
-    fn formatting(&self) -> OptionQ<'a> {
-        OptionQ {
-            prefix: "-",
-            raw: "accel",
-            option_args_split_with: " ",
-            args_split_with: ",",
-            args: vec![
-                self.name(),
-                self.igd_passthru(),
-                self.kernel_irqchip(),
-                self.kvm_shadow_mem(),
-                self.split_wx(),
-                self.tb_size(),
-                self.thread(),
-                self.dirty_ring_size(),
-            ]
-            .into_iter()
-            .filter(|x| x.is_some())
-            .map(|x| x.unwrap())
-            .collect(),
-        }
+impl From<AccelConfigBuilder> for AccelConfig {
+    fn from(builder: AccelConfigBuilder) -> Self {
+        builder.build()
     }
 }
@@ -1,24 +1,666 @@
+//! Audio backends and the sound cards playing through them, e.g.
+//!     -audiodev pipewire,id=snd0,out.latency=20000
+//!     -device intel-hda,id=hda0 -device hda-duplex,bus=hda0.0,audiodev=snd0
+//! or the -audio shorthand creating both:
+//!     -audio driver=pipewire,model=hda
 use crate::command::builder::*;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AudioConfig<'a> {
-    test: &'a str,
+use super::{
+    device::{Ac97Config, DeviceConfig, HdaDuplexConfig, Ich9IntelHdaConfig, IntelHdaConfig},
+    types::{qemu_enum, OnOff},
+    validate::*,
+};
+
+qemu_enum! {
+    /// Backends of -audiodev.
+    pub enum AudiodevDriver {
+        None = "none",
+        Wav = "wav",
+        Pa = "pa",
+        Pipewire = "pipewire",
+        Alsa = "alsa",
+        Oss = "oss",
+        Spice = "spice",
+    }
+}
+
+qemu_enum! {
+    /// Sample formats.
+    pub enum AudioFormat {
+        S8 = "s8",
+        S16 = "s16",
+        S32 = "s32",
+        U8 = "u8",
+        U16 = "u16",
+        U32 = "u32",
+        F32 = "f32",
+    }
+}
+
+/// Properties of the input direction of an -audiodev, `in.` in front of
+/// their keys. Times are in microseconds.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "audiodev")]
+pub struct AudiodevIn {
+    // in.mixing-engine=on|off
+    // Mix and resample in QEMU rather than in the backend. (default: on)
+    #[serde(rename = "mixing-engine", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "in.mixing-engine")]
+    pub mixing_engine: Option<OnOff>,
+
+    // in.fixed-settings=on|off,frequency=hz,channels=n,format=fmt
+    // Use one stream of the given settings for all voices of the guest,
+    // which frequency, channels and format only apply to. (default: on,
+    // 44100 Hz, 2 channels, s16)
+    #[serde(rename = "fixed-settings", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "in.fixed-settings")]
+    pub fixed_settings: Option<OnOff>,
+
+    #[serde(rename = "frequency", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "in.frequency")]
+    pub frequency: Option<u32>,
+
+    #[serde(rename = "channels", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "in.channels")]
+    pub channels: Option<u32>,
+
+    #[serde(rename = "format", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "in.format")]
+    pub format: Option<AudioFormat>,
+
+    // in.voices=n
+    // Number of voices to use. (default: 1)
+    #[serde(rename = "voices", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "in.voices")]
+    pub voices: Option<u32>,
+
+    // in.buffer-length=usecs
+    // Size of the buffer between QEMU and the backend.
+    #[serde(rename = "buffer-length", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "in.buffer-length")]
+    pub buffer_length: Option<u32>,
+
+    // in.name=name,stream-name=name (pa, pipewire)
+    // The sink or source to connect to, and the name of the stream.
+    #[serde(rename = "name", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "in.name")]
+    pub name: Option<String>,
+
+    #[serde(rename = "stream-name", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "in.stream-name")]
+    pub stream_name: Option<String>,
+
+    // in.latency=usecs (pa, pipewire)
+    // Latency asked of the sound server.
+    #[serde(rename = "latency", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "in.latency")]
+    pub latency: Option<u32>,
+
+    // in.dev=device (alsa, oss)
+    // The ALSA device, e.g. hw:0, or the OSS device, e.g. /dev/dsp.
+    #[serde(rename = "dev", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "in.dev")]
+    pub dev: Option<String>,
+
+    // in.period-length=usecs (alsa)
+    #[serde(rename = "period-length", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "in.period-length")]
+    pub period_length: Option<u32>,
+
+    // in.try-poll=on|off (alsa, oss)
+    // Poll the device instead of using a timer. (default: on)
+    #[serde(rename = "try-poll", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "in.try-poll")]
+    pub try_poll: Option<OnOff>,
+
+    // in.buffer-count=n (oss)
+    // Number of buffers, each of buffer-length. (default: 4)
+    #[serde(rename = "buffer-count", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "in.buffer-count")]
+    pub buffer_count: Option<u32>,
+}
+
+/// Properties of the output direction of an -audiodev, as those of
+/// `AudiodevIn` with `out.` in front of their keys.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "audiodev")]
+pub struct AudiodevOut {
+    // out.mixing-engine=on|off
+    #[serde(rename = "mixing-engine", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "out.mixing-engine")]
+    pub mixing_engine: Option<OnOff>,
+
+    // out.fixed-settings=on|off,frequency=hz,channels=n,format=fmt
+    #[serde(rename = "fixed-settings", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "out.fixed-settings")]
+    pub fixed_settings: Option<OnOff>,
+
+    #[serde(rename = "frequency", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "out.frequency")]
+    pub frequency: Option<u32>,
+
+    #[serde(rename = "channels", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "out.channels")]
+    pub channels: Option<u32>,
+
+    #[serde(rename = "format", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "out.format")]
+    pub format: Option<AudioFormat>,
+
+    // out.voices=n
+    #[serde(rename = "voices", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "out.voices")]
+    pub voices: Option<u32>,
+
+    // out.buffer-length=usecs
+    #[serde(rename = "buffer-length", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "out.buffer-length")]
+    pub buffer_length: Option<u32>,
+
+    // out.name=name,stream-name=name (pa, pipewire)
+    #[serde(rename = "name", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "out.name")]
+    pub name: Option<String>,
+
+    #[serde(rename = "stream-name", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "out.stream-name")]
+    pub stream_name: Option<String>,
+
+    // out.latency=usecs (pa, pipewire)
+    #[serde(rename = "latency", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "out.latency")]
+    pub latency: Option<u32>,
+
+    // out.dev=device (alsa, oss)
+    #[serde(rename = "dev", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "out.dev")]
+    pub dev: Option<String>,
+
+    // out.period-length=usecs (alsa)
+    #[serde(rename = "period-length", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "out.period-length")]
+    pub period_length: Option<u32>,
+
+    // out.try-poll=on|off (alsa, oss)
+    #[serde(rename = "try-poll", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "out.try-poll")]
+    pub try_poll: Option<OnOff>,
+
+    // out.buffer-count=n (oss)
+    #[serde(rename = "buffer-count", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "out.buffer-count")]
+    pub buffer_count: Option<u32>,
+}
+
+/// Checks shared by both directions.
+macro_rules! impl_direction {
+    ($direction:ty) => {
+        impl $direction {
+            pub fn is_empty(&self) -> bool {
+                *self == Self::default()
+            }
+
+            fn validate(
+                &self,
+                driver: AudiodevDriver,
+                path: &str,
+                errors: &mut Vec<ValidationError>,
+            ) {
+                use AudiodevDriver::*;
+                let driver_keys: [(&str, bool, &[AudiodevDriver]); 7] = [
+                    ("name", self.name.is_some(), &[Pa, Pipewire]),
+                    ("stream-name", self.stream_name.is_some(), &[Pa, Pipewire]),
+                    ("latency", self.latency.is_some(), &[Pa, Pipewire]),
+                    ("dev", self.dev.is_some(), &[Alsa, Oss]),
+                    ("period-length", self.period_length.is_some(), &[Alsa]),
+                    ("try-poll", self.try_poll.is_some(), &[Alsa, Oss]),
+                    ("buffer-count", self.buffer_count.is_some(), &[Oss]),
+                ];
+                for (key, given, drivers) in driver_keys {
+                    if given && !drivers.contains(&driver) {
+                        invalid(
+                            errors,
+                            path,
+                            key,
+                            ValidationErrorKind::NotSupported {
+                                by: format!("driver={}", driver),
+                            },
+                        );
+                    }
+                }
+
+                if self.fixed_settings == Some(OnOff::Off) {
+                    for (key, given) in [
+                        ("frequency", self.frequency.is_some()),
+                        ("channels", self.channels.is_some()),
+                        ("format", self.format.is_some()),
+                    ] {
+                        if given {
+                            invalid(
+                                errors,
+                                path,
+                                key,
+                                ValidationErrorKind::Conflicts {
+                                    key: "fixed-settings=off",
+                                },
+                            );
+                        }
+                    }
+                }
+                for (key, value) in [
+                    ("frequency", self.frequency),
+                    ("channels", self.channels),
+                    ("voices", self.voices),
+                ] {
+                    if value == Some(0) {
+                        invalid(
+                            errors,
+                            path,
+                            key,
+                            ValidationErrorKind::Invalid {
+                                expected: "a value above 0",
+                            },
+                        );
+                    }
+                }
+                // ALSA needs room for at least one period in the buffer.
+                if let (Some(buffer), Some(period)) = (self.buffer_length, self.period_length) {
+                    if buffer < period {
+                        invalid(
+                            errors,
+                            path,
+                            "buffer-length",
+                            ValidationErrorKind::Invalid {
+                                expected: "a buffer-length no shorter than period-length",
+                            },
+                        );
+                    }
+                }
+            }
+        }
+    };
+}
+
+impl_direction!(AudiodevIn);
+impl_direction!(AudiodevOut);
+
+/// Define an audio backend that sound cards play and record through.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "audiodev")]
+pub struct AudioDevConfig {
+    // none|wav|pa|pipewire|alsa|oss|spice
+    #[serde(rename = "driver")]
+    #[qemu(positional)]
+    pub driver: AudiodevDriver,
+
+    // id=id
+    #[serde(rename = "id")]
+    pub id: String,
+
+    // timer-period=usecs
+    // How often QEMU moves audio between the backend and the guest.
+    // (default: 10000)
+    #[serde(rename = "timer-period", skip_serializing_if = "Option::is_none")]
+    pub timer_period: Option<u32>,
+
+    // in.prop=value
+    #[serde(rename = "in", default, skip_serializing_if = "AudiodevIn::is_empty")]
+    #[qemu(flatten)]
+    pub input: AudiodevIn,
+
+    // out.prop=value
+    #[serde(rename = "out", default, skip_serializing_if = "AudiodevOut::is_empty")]
+    #[qemu(flatten)]
+    pub output: AudiodevOut,
+
+    // path=path (wav)
+    // The file to record the output of the guest to. (default: qemu.wav)
+    #[serde(rename = "path", skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+
+    // server=address (pa)
+    // The PulseAudio server to connect to. (default: the session server)
+    #[serde(rename = "server", skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+
+    // try-mmap=on|off,exclusive=on|off (oss)
+    // Map the device into memory, and open it without others sharing it.
+    #[serde(rename = "try-mmap", skip_serializing_if = "Option::is_none")]
+    pub try_mmap: Option<OnOff>,
+
+    #[serde(rename = "exclusive", skip_serializing_if = "Option::is_none")]
+    pub exclusive: Option<OnOff>,
 }
 
-impl<'a> OptionFormatting<'a> for AudioConfig<'a> {
-    fn formatting(&self) -> OptionQ<'a> {
-        todo!()
+impl Validate for AudioDevConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        use AudiodevDriver::*;
+        let driver_keys: [(&str, bool, &[AudiodevDriver]); 4] = [
+            ("path", self.path.is_some(), &[Wav]),
+            ("server", self.server.is_some(), &[Pa]),
+            ("try-mmap", self.try_mmap.is_some(), &[Oss]),
+            ("exclusive", self.exclusive.is_some(), &[Oss]),
+        ];
+        for (key, given, drivers) in driver_keys {
+            if given && !drivers.contains(&self.driver) {
+                invalid(
+                    errors,
+                    path,
+                    key,
+                    ValidationErrorKind::NotSupported {
+                        by: format!("driver={}", self.driver),
+                    },
+                );
+            }
+        }
+        if self.timer_period == Some(0) {
+            invalid(
+                errors,
+                path,
+                "timer-period",
+                ValidationErrorKind::Invalid {
+                    expected: "a value above 0",
+                },
+            );
+        }
+        self.input
+            .validate(self.driver, &format!("{}.in", path), errors);
+        self.output
+            .validate(self.driver, &format!("{}.out", path), errors);
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AudioDevConfig<'a> {
-    test: &'a str,
+qemu_enum! {
+    /// Sound cards of -audio.
+    pub enum AudioModel {
+        /// intel-hda with an hda-duplex codec.
+        Hda = "hda",
+        Ac97 = "ac97",
+        Es1370 = "es1370",
+        Sb16 = "sb16",
+        Virtio = "virtio",
+    }
 }
 
-impl<'a> OptionFormatting<'a> for AudioDevConfig<'a> {
-    fn formatting(&self) -> OptionQ<'a> {
-        todo!()
+/// Create an audio backend and, with `model`, a sound card playing through
+/// it, without naming either.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "audio")]
+pub struct AudioConfig {
+    // driver=driver
+    #[serde(rename = "driver")]
+    #[qemu(implied)]
+    pub driver: AudiodevDriver,
+
+    // model=hda|ac97|es1370|sb16|virtio
+    #[serde(rename = "model", skip_serializing_if = "Option::is_none")]
+    pub model: Option<AudioModel>,
+}
+
+impl AudioConfig {
+    pub fn new(driver: AudiodevDriver, model: Option<AudioModel>) -> Self {
+        AudioConfig { driver, model }
     }
-}
\ No newline at end of file
+}
+
+qemu_enum! {
+    /// Sound cards added with `ConfigBuilder::sound`.
+    pub enum SoundCard {
+        /// intel-hda with an hda-duplex codec, for i440fx.
+        IntelHda = "intel-hda",
+        /// ich9-intel-hda with an hda-duplex codec, built into q35.
+        Ich9IntelHda = "ich9-intel-hda",
+        AC97 = "AC97",
+    }
+}
+
+impl SoundCard {
+    /// The devices of the card with the given id, playing through the
+    /// audiodev with the given id. HDA codecs sit on bus `id.0` of their
+    /// controller.
+    pub fn devices(self, id: &str, audiodev: &str) -> Vec<DeviceConfig> {
+        let codec = || {
+            HdaDuplexConfig::builder()
+                .bus(format!("{}.0", id))
+                .audiodev(audiodev)
+                .into()
+        };
+        match self {
+            SoundCard::IntelHda => vec![IntelHdaConfig::builder().id(id).into(), codec()],
+            SoundCard::Ich9IntelHda => vec![Ich9IntelHdaConfig::builder().id(id).into(), codec()],
+            SoundCard::AC97 => vec![Ac97Config::builder().id(id).audiodev(audiodev).into()],
+        }
+    }
+}
+
+/// Check that audiodev ids are unique and every sound device plays through
+/// a defined audiodev.
+pub(crate) fn validate_references(
+    audiodevs: &[AudioDevConfig],
+    devices: &[DeviceConfig],
+    errors: &mut Vec<ValidationError>,
+) {
+    for (i, audiodev) in audiodevs.iter().enumerate() {
+        if audiodevs[..i].iter().any(|other| other.id == audiodev.id) {
+            invalid(
+                errors,
+                &format!("audiodev[{}]", i),
+                "id",
+                ValidationErrorKind::Duplicate {
+                    id: audiodev.id.clone(),
+                },
+            );
+        }
+    }
+    for (i, device) in devices.iter().enumerate() {
+        if let Some(id) = device.audiodev() {
+            if !audiodevs.iter().any(|audiodev| audiodev.id == id) {
+                invalid(
+                    errors,
+                    &format!("device[{}]", i),
+                    "audiodev",
+                    ValidationErrorKind::UnknownReference { id: id.to_string() },
+                );
+            }
+        }
+    }
+}
+
+/// Builder for `AudioDevConfig`, e.g.
+///     AudioDevConfig::pipewire("snd0").output(AudiodevOut {
+///         latency: Some(20000),
+///         ..Default::default()
+///     }).build()
+#[derive(Debug, Clone)]
+pub struct AudioDevConfigBuilder {
+    config: AudioDevConfig,
+}
+
+impl AudioDevConfig {
+    pub fn builder(driver: AudiodevDriver, id: impl Into<String>) -> AudioDevConfigBuilder {
+        AudioDevConfigBuilder {
+            config: AudioDevConfig {
+                driver,
+                id: id.into(),
+                timer_period: None,
+                input: Default::default(),
+                output: Default::default(),
+                path: None,
+                server: None,
+                try_mmap: None,
+                exclusive: None,
+            },
+        }
+    }
+
+    /// A backend that plays nothing and records silence.
+    pub fn none(id: impl Into<String>) -> AudioDevConfigBuilder {
+        Self::builder(AudiodevDriver::None, id)
+    }
+
+    /// Record the output of the guest to the WAV file `path`.
+    pub fn wav(id: impl Into<String>, path: impl Into<String>) -> AudioDevConfigBuilder {
+        Self::builder(AudiodevDriver::Wav, id).path(path)
+    }
+
+    pub fn pa(id: impl Into<String>) -> AudioDevConfigBuilder {
+        Self::builder(AudiodevDriver::Pa, id)
+    }
+
+    pub fn pipewire(id: impl Into<String>) -> AudioDevConfigBuilder {
+        Self::builder(AudiodevDriver::Pipewire, id)
+    }
+
+    pub fn alsa(id: impl Into<String>) -> AudioDevConfigBuilder {
+        Self::builder(AudiodevDriver::Alsa, id)
+    }
+
+    pub fn oss(id: impl Into<String>) -> AudioDevConfigBuilder {
+        Self::builder(AudiodevDriver::Oss, id)
+    }
+
+    /// Audio of the SPICE display, only usable with -spice.
+    pub fn spice(id: impl Into<String>) -> AudioDevConfigBuilder {
+        Self::builder(AudiodevDriver::Spice, id)
+    }
+}
+
+impl AudioDevConfigBuilder {
+    pub fn timer_period(mut self, timer_period: u32) -> Self {
+        self.config.timer_period = Some(timer_period);
+        self
+    }
+
+    pub fn input(mut self, input: AudiodevIn) -> Self {
+        self.config.input = input;
+        self
+    }
+
+    pub fn output(mut self, output: AudiodevOut) -> Self {
+        self.config.output = output;
+        self
+    }
+
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.config.path = Some(path.into());
+        self
+    }
+
+    pub fn server(mut self, server: impl Into<String>) -> Self {
+        self.config.server = Some(server.into());
+        self
+    }
+
+    pub fn try_mmap(mut self, try_mmap: impl Into<OnOff>) -> Self {
+        self.config.try_mmap = Some(try_mmap.into());
+        self
+    }
+
+    pub fn exclusive(mut self, exclusive: impl Into<OnOff>) -> Self {
+        self.config.exclusive = Some(exclusive.into());
+        self
+    }
+
+    pub fn build(self) -> AudioDevConfig {
+        self.config
+    }
+}
+
+impl From<AudioDevConfigBuilder> for AudioDevConfig {
+    fn from(builder: AudioDevConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::parser::*;
+
+    #[test]
+    fn test_audiodev() {
+        let alsa = AudioDevConfig::alsa("snd0")
+            .timer_period(5000)
+            .input(AudiodevIn {
+                dev: Some("hw:1".to_string()),
+                ..Default::default()
+            })
+            .output(AudiodevOut {
+                frequency: Some(48000),
+                format: Some(AudioFormat::S32),
+                buffer_length: Some(40000),
+                period_length: Some(10000),
+                ..Default::default()
+            })
+            .build();
+        let formatted = "-audiodev alsa,id=snd0,timer-period=5000,in.dev=hw:1,out.frequency=48000,out.format=s32,out.buffer-length=40000,out.period-length=10000";
+        assert_eq!(alsa.formatting().to_string(), formatted);
+        assert_eq!(
+            serde_json::to_string(&alsa).unwrap(),
+            r#"{"driver":"alsa","id":"snd0","timer-period":5000,"in":{"dev":"hw:1"},"out":{"frequency":48000,"format":"s32","buffer-length":40000,"period-length":10000}}"#
+        );
+        let audio = AudioConfig::new(AudiodevDriver::Pa, Some(AudioModel::Hda));
+        assert_eq!(audio.formatting().to_string(), "-audio driver=pa,model=hda");
+
+        let argv = [
+            "qemu-system-x86_64",
+            "-audiodev",
+            &formatted[10..],
+            "-audio",
+            "pa,model=hda",
+            "-audiodev",
+            "wav,id=snd1,out.latency=1000,in.volume=50",
+        ];
+        let command = parse_args(&argv).unwrap();
+        assert_eq!(AudioDevConfig::parsing(&command.options[0]), Ok(alsa));
+        assert_eq!(AudioConfig::parsing(&command.options[1]), Ok(audio));
+        assert_eq!(
+            AudioDevConfig::parsing(&command.options[2]),
+            Err(ParseError::UnknownKey {
+                option: "audiodev".to_string(),
+                key: "in.volume".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let audiodevs = vec![
+            AudioDevConfig::wav("snd0", "/tmp/out.wav")
+                .server("/run/pulse/native")
+                .output(AudiodevOut {
+                    latency: Some(20000),
+                    fixed_settings: Some(OnOff::Off),
+                    frequency: Some(48000),
+                    ..Default::default()
+                })
+                .build(),
+            AudioDevConfig::alsa("snd0")
+                .input(AudiodevIn {
+                    buffer_length: Some(5000),
+                    period_length: Some(10000),
+                    channels: Some(0),
+                    ..Default::default()
+                })
+                .build(),
+        ];
+        let mut devices = SoundCard::Ich9IntelHda.devices("hda0", "snd0");
+        devices.extend(SoundCard::AC97.devices("ac97", "snd1"));
+        let mut errors = Vec::new();
+        audiodevs.validate("audiodev", &mut errors);
+        validate_references(&audiodevs, &devices, &mut errors);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "audiodev[0].server: not supported by driver=wav",
+                "audiodev[0].out.latency: not supported by driver=wav",
+                "audiodev[0].out.frequency: conflicts with 'fixed-settings=off'",
+                "audiodev[1].in.channels: expected a value above 0",
+                "audiodev[1].in.buffer-length: expected a buffer-length no shorter than period-length",
+                "audiodev[1].id: 'snd0' is used more than once",
+                "device[2].audiodev: 'snd1' is not defined",
+            ]
+        );
+    }
+}
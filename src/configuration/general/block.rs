@@ -0,0 +1,1037 @@
+//! Block devices: the legacy all-in-one -drive and the node graph built
+//! with -blockdev.
+//!
+//! A -blockdev graph is a chain of nodes, each referring to its child by
+//! node name, e.g. a qcow2 format node on top of a file protocol node:
+//!     -blockdev driver=file,node-name=disk0-file,filename=/images/disk0.qcow2
+//!     -blockdev driver=qcow2,node-name=disk0,file=disk0-file
+//!     -device virtio-blk-pci,drive=disk0
+use crate::command::builder::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    types::{qemu_enum, ByteSize, OnOff, OnOffAuto},
+    validate::*,
+};
+
+qemu_enum! {
+    /// Block drivers. `file` and `host_device` are protocol drivers reading
+    /// the host, `qcow2` and `raw` are format drivers on top of another node.
+    pub enum BlockDriver {
+        File = "file",
+        HostDevice = "host_device",
+        Qcow2 = "qcow2",
+        Raw = "raw",
+        Throttle = "throttle",
+    }
+}
+
+impl BlockDriver {
+    /// Whether the driver opens a host file or device by `filename`.
+    pub fn is_protocol(&self) -> bool {
+        matches!(self, BlockDriver::File | BlockDriver::HostDevice)
+    }
+
+    /// Whether the driver interprets the data of a child node.
+    pub fn is_format(&self) -> bool {
+        matches!(self, BlockDriver::Qcow2 | BlockDriver::Raw)
+    }
+}
+
+qemu_enum! {
+    /// aio=threads|native|io_uring
+    pub enum Aio {
+        Threads = "threads",
+        Native = "native",
+        IoUring = "io_uring",
+    }
+}
+
+qemu_enum! {
+    /// discard=ignore|unmap
+    pub enum Discard {
+        Ignore = "ignore" | "off",
+        Unmap = "unmap" | "on",
+    }
+}
+
+qemu_enum! {
+    /// detect-zeroes=off|on|unmap
+    pub enum DetectZeroes {
+        Off = "off",
+        On = "on",
+        Unmap = "unmap",
+    }
+}
+
+qemu_enum! {
+    /// cache=none|writeback|writethrough|directsync|unsafe of -drive.
+    pub enum CacheMode {
+        None = "none",
+        Writeback = "writeback",
+        Writethrough = "writethrough",
+        Directsync = "directsync",
+        Unsafe = "unsafe",
+    }
+}
+
+impl CacheMode {
+    /// Whether the mode bypasses the host page cache (cache.direct=on).
+    pub fn is_direct(&self) -> bool {
+        matches!(self, CacheMode::None | CacheMode::Directsync)
+    }
+}
+
+qemu_enum! {
+    /// if=ide|scsi|sd|mtd|floppy|pflash|virtio|none
+    pub enum DriveInterface {
+        Ide = "ide",
+        Scsi = "scsi",
+        Sd = "sd",
+        Mtd = "mtd",
+        Floppy = "floppy",
+        Pflash = "pflash",
+        Virtio = "virtio",
+        None = "none",
+    }
+}
+
+qemu_enum! {
+    /// media=disk|cdrom
+    pub enum Media {
+        Disk = "disk",
+        Cdrom = "cdrom",
+    }
+}
+
+/// Define a new block device node.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "blockdev")]
+pub struct BlockdevConfig {
+    // driver
+    // Specifies the block driver to use for the given node.
+    #[serde(rename = "driver")]
+    pub driver: BlockDriver,
+
+    // node-name
+    // This defines the name of the block driver node by which it will be
+    // referenced later. The name must be unique.
+    #[serde(rename = "node-name")]
+    pub node_name: String,
+
+    // discard=discard
+    // discard is one of "ignore" (or "off") or "unmap" (or "on") and
+    // controls whether discard requests are passed to the underlying
+    // storage. (default: ignore)
+    #[serde(rename = "discard", skip_serializing_if = "Option::is_none")]
+    pub discard: Option<Discard>,
+
+    // cache.direct=on|off
+    // The host page cache can be avoided with cache.direct=on.
+    // (default: off)
+    #[serde(rename = "cache.direct", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "cache.direct")]
+    pub cache_direct: Option<OnOff>,
+
+    // cache.no-flush=on|off
+    // In case you don't care about data integrity over host failures, you
+    // can use cache.no-flush=on. (default: off)
+    #[serde(rename = "cache.no-flush", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "cache.no-flush")]
+    pub cache_no_flush: Option<OnOff>,
+
+    // read-only=on|off
+    // If true, no modifications to the image are allowed. (default: off)
+    #[serde(rename = "read-only", skip_serializing_if = "Option::is_none")]
+    pub read_only: Option<OnOff>,
+
+    // auto-read-only=on|off
+    // If true, QEMU may fall back to read-only usage even when read-only=off
+    // is requested. (default: off)
+    #[serde(rename = "auto-read-only", skip_serializing_if = "Option::is_none")]
+    pub auto_read_only: Option<OnOff>,
+
+    // force-share=on|off
+    // Override the image locking system of QEMU by forcing the node to use
+    // shared permissions. Requires read-only=on. (default: off)
+    #[serde(rename = "force-share", skip_serializing_if = "Option::is_none")]
+    pub force_share: Option<OnOff>,
+
+    // detect-zeroes=on|off|unmap
+    // Enable the automatic conversion of plain zero writes by the OS to
+    // driver specific optimized zero write commands. unmap requires
+    // discard=unmap. (default: off)
+    #[serde(rename = "detect-zeroes", skip_serializing_if = "Option::is_none")]
+    pub detect_zeroes: Option<DetectZeroes>,
+
+    // filename (file, host_device)
+    // The path to the image file or host device in the local filesystem.
+    #[serde(rename = "filename", skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+
+    // aio=threads|native|io_uring (file, host_device)
+    // native requires cache.direct=on. (default: threads)
+    #[serde(rename = "aio", skip_serializing_if = "Option::is_none")]
+    pub aio: Option<Aio>,
+
+    // locking=on|off|auto (file, host_device)
+    // Specifies whether the image file is protected with Linux OFD / POSIX
+    // locks. (default: auto)
+    #[serde(rename = "locking", skip_serializing_if = "Option::is_none")]
+    pub locking: Option<OnOffAuto>,
+
+    // file (qcow2, raw, throttle)
+    // Reference to the node name of the child node holding the data.
+    #[serde(rename = "file", skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+
+    // backing (qcow2)
+    // Reference to the node name of the backing file, or "null" to disable
+    // the default backing file.
+    #[serde(rename = "backing", skip_serializing_if = "Option::is_none")]
+    pub backing: Option<String>,
+
+    // lazy-refcounts=on|off (qcow2)
+    // Whether to enable the lazy refcounts feature.
+    #[serde(rename = "lazy-refcounts", skip_serializing_if = "Option::is_none")]
+    pub lazy_refcounts: Option<OnOff>,
+
+    // cache-size (qcow2)
+    // The maximum total size of the L2 table and refcount block caches in
+    // bytes.
+    #[serde(rename = "cache-size", skip_serializing_if = "Option::is_none")]
+    #[qemu(format_with = "ByteSize::to_bytes_string")]
+    pub cache_size: Option<ByteSize>,
+
+    // l2-cache-size (qcow2)
+    // The maximum size of the L2 table cache in bytes.
+    #[serde(rename = "l2-cache-size", skip_serializing_if = "Option::is_none")]
+    #[qemu(format_with = "ByteSize::to_bytes_string")]
+    pub l2_cache_size: Option<ByteSize>,
+
+    // offset (raw)
+    // Offset of the image within the child node, in bytes.
+    #[serde(rename = "offset", skip_serializing_if = "Option::is_none")]
+    #[qemu(format_with = "ByteSize::to_bytes_string")]
+    pub offset: Option<ByteSize>,
+
+    // size (raw)
+    // Size of the image within the child node, in bytes.
+    #[serde(rename = "size", skip_serializing_if = "Option::is_none")]
+    #[qemu(format_with = "ByteSize::to_bytes_string")]
+    pub size: Option<ByteSize>,
+
+    // throttle-group (throttle)
+    // The id of the throttle-group object whose limits apply to the node.
+    #[serde(rename = "throttle-group", skip_serializing_if = "Option::is_none")]
+    pub throttle_group: Option<String>,
+}
+
+impl BlockdevConfig {
+    /// Whether `option` is a -blockdev of a driver this crate models, given
+    /// in the key=value form with its children referred to by node name,
+    /// rather than as JSON or with inline child nodes such as
+    /// `file.driver=file`.
+    pub(crate) fn matches(option: &OptionQ<'_>) -> bool {
+        let driver = option.args.iter().find(|arg| arg.key == "driver");
+        driver
+            .and_then(|arg| arg.value.as_deref())
+            .is_some_and(|driver| driver.parse::<BlockDriver>().is_ok())
+            && option.args.iter().all(|arg| {
+                arg.key
+                    .split_once('.')
+                    .is_none_or(|(prefix, _)| prefix == "cache")
+            })
+    }
+
+    /// Driver specific keys which are given, with the drivers accepting them.
+    fn driver_keys(&self) -> [(&'static str, bool, &'static [BlockDriver]); 11] {
+        use BlockDriver::*;
+        [
+            ("filename", self.filename.is_some(), &[File, HostDevice]),
+            ("aio", self.aio.is_some(), &[File, HostDevice]),
+            ("locking", self.locking.is_some(), &[File, HostDevice]),
+            ("file", self.file.is_some(), &[Qcow2, Raw, Throttle]),
+            ("backing", self.backing.is_some(), &[Qcow2]),
+            ("lazy-refcounts", self.lazy_refcounts.is_some(), &[Qcow2]),
+            ("cache-size", self.cache_size.is_some(), &[Qcow2]),
+            ("l2-cache-size", self.l2_cache_size.is_some(), &[Qcow2]),
+            ("offset", self.offset.is_some(), &[Raw]),
+            ("size", self.size.is_some(), &[Raw]),
+            ("throttle-group", self.throttle_group.is_some(), &[Throttle]),
+        ]
+    }
+
+    /// Node names of the children this node refers to.
+    pub fn children(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [("file", &self.file), ("backing", &self.backing)]
+            .into_iter()
+            .filter_map(|(key, child)| child.as_deref().map(|child| (key, child)))
+            // "null" explicitly disables the backing file.
+            .filter(|&(key, child)| !(key == "backing" && child == "null"))
+    }
+}
+
+impl Validate for BlockdevConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        for (key, given, drivers) in self.driver_keys() {
+            if given && !drivers.contains(&self.driver) {
+                invalid(
+                    errors,
+                    path,
+                    key,
+                    ValidationErrorKind::NotSupported {
+                        by: format!("driver={}", self.driver),
+                    },
+                );
+            }
+        }
+
+        let required: &[(&'static str, bool)] = match self.driver {
+            BlockDriver::File | BlockDriver::HostDevice => &[("filename", self.filename.is_some())],
+            BlockDriver::Qcow2 | BlockDriver::Raw => &[("file", self.file.is_some())],
+            BlockDriver::Throttle => &[
+                ("file", self.file.is_some()),
+                ("throttle-group", self.throttle_group.is_some()),
+            ],
+        };
+        for &(key, given) in required {
+            if !given {
+                invalid(
+                    errors,
+                    path,
+                    "driver",
+                    ValidationErrorKind::Requires { key },
+                );
+            }
+        }
+
+        if self.aio == Some(Aio::Native) && self.cache_direct != Some(OnOff::On) {
+            invalid(
+                errors,
+                path,
+                "aio",
+                ValidationErrorKind::Requires {
+                    key: "cache.direct=on",
+                },
+            );
+        }
+        if self.detect_zeroes == Some(DetectZeroes::Unmap) && self.discard != Some(Discard::Unmap) {
+            invalid(
+                errors,
+                path,
+                "detect-zeroes",
+                ValidationErrorKind::Requires {
+                    key: "discard=unmap",
+                },
+            );
+        }
+        if self.force_share == Some(OnOff::On) && self.read_only != Some(OnOff::On) {
+            invalid(
+                errors,
+                path,
+                "force-share",
+                ValidationErrorKind::Requires {
+                    key: "read-only=on",
+                },
+            );
+        }
+    }
+}
+
+/// I/O throttling limits of a -drive, in bytes or operations per second.
+/// Limits of drives in the same `group` are shared.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "drive")]
+pub struct ThrottleConfig {
+    // bps=b,bps_rd=r,bps_wr=w
+    // Specify bandwidth throttling limits in bytes per second, either for
+    // all request types or for reads or writes only.
+    #[serde(rename = "bps", skip_serializing_if = "Option::is_none")]
+    pub bps: Option<u64>,
+
+    #[serde(rename = "bps_rd", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "bps_rd")]
+    pub bps_rd: Option<u64>,
+
+    #[serde(rename = "bps_wr", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "bps_wr")]
+    pub bps_wr: Option<u64>,
+
+    // bps_max=bm,bps_rd_max=rm,bps_wr_max=wm
+    // Specify bursts in bytes per second, either for all request types or
+    // for reads or writes only. Bursts allow the guest I/O to spike above
+    // the limit temporarily.
+    #[serde(rename = "bps_max", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "bps_max")]
+    pub bps_max: Option<u64>,
+
+    #[serde(rename = "bps_rd_max", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "bps_rd_max")]
+    pub bps_rd_max: Option<u64>,
+
+    #[serde(rename = "bps_wr_max", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "bps_wr_max")]
+    pub bps_wr_max: Option<u64>,
+
+    // iops=i,iops_rd=r,iops_wr=w
+    // Specify request rate limits in requests per second, either for all
+    // request types or for reads or writes only.
+    #[serde(rename = "iops", skip_serializing_if = "Option::is_none")]
+    pub iops: Option<u64>,
+
+    #[serde(rename = "iops_rd", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "iops_rd")]
+    pub iops_rd: Option<u64>,
+
+    #[serde(rename = "iops_wr", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "iops_wr")]
+    pub iops_wr: Option<u64>,
+
+    // iops_max=bm,iops_rd_max=rm,iops_wr_max=wm
+    // Specify bursts in requests per second, either for all request types
+    // or for reads or writes only.
+    #[serde(rename = "iops_max", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "iops_max")]
+    pub iops_max: Option<u64>,
+
+    #[serde(rename = "iops_rd_max", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "iops_rd_max")]
+    pub iops_rd_max: Option<u64>,
+
+    #[serde(rename = "iops_wr_max", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "iops_wr_max")]
+    pub iops_wr_max: Option<u64>,
+
+    // iops_size=is
+    // Let every is bytes of a request count as a new request for iops
+    // throttling purposes.
+    #[serde(rename = "iops_size", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "iops_size")]
+    pub iops_size: Option<u64>,
+
+    // group=g
+    // Join a throttling quota group with given name g. All drives that are
+    // members of the same group are accounted for together.
+    #[serde(rename = "group", skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+impl ThrottleConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Validate for ThrottleConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        // A total limit cannot be combined with a read or write limit, and a
+        // burst needs the limit it bursts above.
+        let limits = [
+            ("bps", self.bps, self.bps_max),
+            ("bps_rd", self.bps_rd, self.bps_rd_max),
+            ("bps_wr", self.bps_wr, self.bps_wr_max),
+            ("iops", self.iops, self.iops_max),
+            ("iops_rd", self.iops_rd, self.iops_rd_max),
+            ("iops_wr", self.iops_wr, self.iops_wr_max),
+        ];
+        for total in [0, 3] {
+            let (total_key, total_value, _) = limits[total];
+            for (key, value, _) in &limits[total + 1..total + 3] {
+                if total_value.is_some() && value.is_some() {
+                    invalid(
+                        errors,
+                        path,
+                        key,
+                        ValidationErrorKind::Conflicts { key: total_key },
+                    );
+                }
+            }
+        }
+        for (key, value, max) in limits {
+            if max.is_some() && value.is_none() {
+                invalid(
+                    errors,
+                    path,
+                    &format!("{}_max", key),
+                    ValidationErrorKind::Requires { key },
+                );
+            }
+        }
+    }
+}
+
+/// Define a new drive. This includes creating a block driver node (the
+/// backend) as well as a guest device, and is mostly a shortcut for
+/// defining the corresponding -blockdev and -device options.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "drive")]
+pub struct DriveConfig {
+    // file=file
+    // This option defines which disk image to use with this drive.
+    #[serde(rename = "file", skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+
+    // id=name
+    // Name of the drive, used to refer to it from -device.
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    // if=interface
+    // This option defines on which type on interface the drive is
+    // connected.
+    #[serde(rename = "if", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "if")]
+    pub interface: Option<DriveInterface>,
+
+    // bus=bus,unit=unit
+    // These options define where is connected the drive by defining the
+    // bus number and the unit id.
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<usize>,
+
+    #[serde(rename = "unit", skip_serializing_if = "Option::is_none")]
+    pub unit: Option<usize>,
+
+    // index=index
+    // This option defines where the drive is connected by using an index
+    // in the list of available connectors of a given interface type.
+    #[serde(rename = "index", skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+
+    // media=media
+    // This option defines the type of the media: disk or cdrom.
+    #[serde(rename = "media", skip_serializing_if = "Option::is_none")]
+    pub media: Option<Media>,
+
+    // format=format
+    // Specify which disk format will be used rather than detecting the
+    // format. Can be used to specify format=raw to avoid interpreting an
+    // untrusted format header.
+    #[serde(rename = "format", skip_serializing_if = "Option::is_none")]
+    pub format: Option<BlockDriver>,
+
+    // snapshot=on|off
+    // snapshot is "on" or "off" and controls snapshot mode for the given
+    // drive.
+    #[serde(rename = "snapshot", skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<OnOff>,
+
+    // cache=cache
+    // cache is "none", "writeback", "unsafe", "directsync" or
+    // "writethrough" and controls how the host cache is used to access
+    // block data.
+    #[serde(rename = "cache", skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheMode>,
+
+    // aio=aio
+    // aio is "threads", "native", or "io_uring" and selects between pthread
+    // based disk I/O, native Linux AIO, or Linux io_uring API.
+    #[serde(rename = "aio", skip_serializing_if = "Option::is_none")]
+    pub aio: Option<Aio>,
+
+    // discard=discard
+    #[serde(rename = "discard", skip_serializing_if = "Option::is_none")]
+    pub discard: Option<Discard>,
+
+    // detect-zeroes=on|off|unmap
+    #[serde(rename = "detect-zeroes", skip_serializing_if = "Option::is_none")]
+    pub detect_zeroes: Option<DetectZeroes>,
+
+    // read-only=on|off
+    // Open drive file as read-only.
+    #[serde(rename = "read-only", skip_serializing_if = "Option::is_none")]
+    pub read_only: Option<OnOff>,
+
+    #[serde(
+        rename = "throttling",
+        default,
+        skip_serializing_if = "ThrottleConfig::is_empty"
+    )]
+    #[qemu(flatten)]
+    pub throttling: ThrottleConfig,
+}
+
+impl DriveConfig {
+    /// Whether `option` is a -drive this crate models, i.e. one of a known
+    /// format without dotted keys such as `file.driver=nbd` or
+    /// `throttling.bps-total=n`.
+    pub(crate) fn matches(option: &OptionQ<'_>) -> bool {
+        option.args.iter().all(|arg| {
+            !arg.key.contains('.')
+                && (arg.key != "format"
+                    || arg
+                        .value
+                        .as_deref()
+                        .is_some_and(|format| format.parse::<BlockDriver>().is_ok()))
+        })
+    }
+}
+
+impl Validate for DriveConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        if let Some(format) = self.format.filter(|format| !format.is_format()) {
+            invalid(
+                errors,
+                path,
+                "format",
+                ValidationErrorKind::NotSupported {
+                    by: format!("format={}", format),
+                },
+            );
+        }
+        if self.aio == Some(Aio::Native) && !self.cache.is_some_and(|cache| cache.is_direct()) {
+            invalid(
+                errors,
+                path,
+                "aio",
+                ValidationErrorKind::Requires {
+                    key: "cache=none|directsync",
+                },
+            );
+        }
+        if self.detect_zeroes == Some(DetectZeroes::Unmap) && self.discard != Some(Discard::Unmap) {
+            invalid(
+                errors,
+                path,
+                "detect-zeroes",
+                ValidationErrorKind::Requires {
+                    key: "discard=unmap",
+                },
+            );
+        }
+        self.throttling.validate(path, errors);
+    }
+}
+
+/// Check that every -blockdev refers only to nodes defined before it, as
+/// QEMU creates the nodes in command line order. A node defined later is
+/// reported as not defined.
+pub(crate) fn validate_graph(blockdevs: &[BlockdevConfig], errors: &mut Vec<ValidationError>) {
+    for (i, blockdev) in blockdevs.iter().enumerate() {
+        for (key, child) in blockdev.children() {
+            if !blockdevs[..i].iter().any(|node| node.node_name == child) {
+                invalid(
+                    errors,
+                    &format!("blockdev[{}]", i),
+                    key,
+                    ValidationErrorKind::UnknownReference {
+                        id: child.to_string(),
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Builder for `BlockdevConfig`, e.g.
+///     BlockdevConfig::qcow2("disk0", "disk0-file").discard(Discard::Unmap).build()
+#[derive(Debug, Clone)]
+pub struct BlockdevConfigBuilder {
+    config: BlockdevConfig,
+}
+
+impl BlockdevConfig {
+    pub fn builder(driver: BlockDriver, node_name: impl Into<String>) -> BlockdevConfigBuilder {
+        BlockdevConfigBuilder {
+            config: BlockdevConfig {
+                driver,
+                node_name: node_name.into(),
+                discard: None,
+                cache_direct: None,
+                cache_no_flush: None,
+                read_only: None,
+                auto_read_only: None,
+                force_share: None,
+                detect_zeroes: None,
+                filename: None,
+                aio: None,
+                locking: None,
+                file: None,
+                backing: None,
+                lazy_refcounts: None,
+                cache_size: None,
+                l2_cache_size: None,
+                offset: None,
+                size: None,
+                throttle_group: None,
+            },
+        }
+    }
+
+    /// A `file` protocol node reading the image at `filename`.
+    pub fn file(
+        node_name: impl Into<String>,
+        filename: impl Into<String>,
+    ) -> BlockdevConfigBuilder {
+        Self::builder(BlockDriver::File, node_name).filename(filename)
+    }
+
+    /// A `host_device` protocol node reading the block device at `filename`.
+    pub fn host_device(
+        node_name: impl Into<String>,
+        filename: impl Into<String>,
+    ) -> BlockdevConfigBuilder {
+        Self::builder(BlockDriver::HostDevice, node_name).filename(filename)
+    }
+
+    /// A `qcow2` format node on top of the node named `file`.
+    pub fn qcow2(node_name: impl Into<String>, file: impl Into<String>) -> BlockdevConfigBuilder {
+        Self::builder(BlockDriver::Qcow2, node_name).file(file)
+    }
+
+    /// A `raw` format node on top of the node named `file`.
+    pub fn raw(node_name: impl Into<String>, file: impl Into<String>) -> BlockdevConfigBuilder {
+        Self::builder(BlockDriver::Raw, node_name).file(file)
+    }
+
+    /// A `throttle` filter node applying the limits of `throttle_group` to
+    /// the node named `file`.
+    pub fn throttle(
+        node_name: impl Into<String>,
+        file: impl Into<String>,
+        throttle_group: impl Into<String>,
+    ) -> BlockdevConfigBuilder {
+        Self::builder(BlockDriver::Throttle, node_name)
+            .file(file)
+            .throttle_group(throttle_group)
+    }
+}
+
+impl BlockdevConfigBuilder {
+    pub fn discard(mut self, discard: impl Into<Discard>) -> Self {
+        self.config.discard = Some(discard.into());
+        self
+    }
+
+    pub fn cache_direct(mut self, cache_direct: impl Into<OnOff>) -> Self {
+        self.config.cache_direct = Some(cache_direct.into());
+        self
+    }
+
+    pub fn cache_no_flush(mut self, cache_no_flush: impl Into<OnOff>) -> Self {
+        self.config.cache_no_flush = Some(cache_no_flush.into());
+        self
+    }
+
+    pub fn read_only(mut self, read_only: impl Into<OnOff>) -> Self {
+        self.config.read_only = Some(read_only.into());
+        self
+    }
+
+    pub fn auto_read_only(mut self, auto_read_only: impl Into<OnOff>) -> Self {
+        self.config.auto_read_only = Some(auto_read_only.into());
+        self
+    }
+
+    pub fn force_share(mut self, force_share: impl Into<OnOff>) -> Self {
+        self.config.force_share = Some(force_share.into());
+        self
+    }
+
+    pub fn detect_zeroes(mut self, detect_zeroes: impl Into<DetectZeroes>) -> Self {
+        self.config.detect_zeroes = Some(detect_zeroes.into());
+        self
+    }
+
+    pub fn filename(mut self, filename: impl Into<String>) -> Self {
+        self.config.filename = Some(filename.into());
+        self
+    }
+
+    pub fn aio(mut self, aio: impl Into<Aio>) -> Self {
+        self.config.aio = Some(aio.into());
+        self
+    }
+
+    pub fn locking(mut self, locking: impl Into<OnOffAuto>) -> Self {
+        self.config.locking = Some(locking.into());
+        self
+    }
+
+    pub fn file(mut self, file: impl Into<String>) -> Self {
+        self.config.file = Some(file.into());
+        self
+    }
+
+    pub fn backing(mut self, backing: impl Into<String>) -> Self {
+        self.config.backing = Some(backing.into());
+        self
+    }
+
+    pub fn lazy_refcounts(mut self, lazy_refcounts: impl Into<OnOff>) -> Self {
+        self.config.lazy_refcounts = Some(lazy_refcounts.into());
+        self
+    }
+
+    pub fn cache_size(mut self, cache_size: ByteSize) -> Self {
+        self.config.cache_size = Some(cache_size);
+        self
+    }
+
+    pub fn l2_cache_size(mut self, l2_cache_size: ByteSize) -> Self {
+        self.config.l2_cache_size = Some(l2_cache_size);
+        self
+    }
+
+    pub fn offset(mut self, offset: ByteSize) -> Self {
+        self.config.offset = Some(offset);
+        self
+    }
+
+    pub fn size(mut self, size: ByteSize) -> Self {
+        self.config.size = Some(size);
+        self
+    }
+
+    pub fn throttle_group(mut self, throttle_group: impl Into<String>) -> Self {
+        self.config.throttle_group = Some(throttle_group.into());
+        self
+    }
+
+    pub fn build(self) -> BlockdevConfig {
+        self.config
+    }
+}
+
+impl From<BlockdevConfigBuilder> for BlockdevConfig {
+    fn from(builder: BlockdevConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+/// Builder for `DriveConfig`, e.g.
+///     DriveConfig::builder().file("disk.qcow2").interface(DriveInterface::Virtio).build()
+#[derive(Debug, Clone)]
+pub struct DriveConfigBuilder {
+    config: DriveConfig,
+}
+
+impl DriveConfig {
+    pub fn builder() -> DriveConfigBuilder {
+        DriveConfigBuilder {
+            config: DriveConfig::default(),
+        }
+    }
+}
+
+impl DriveConfigBuilder {
+    pub fn file(mut self, file: impl Into<String>) -> Self {
+        self.config.file = Some(file.into());
+        self
+    }
+
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
+    }
+
+    pub fn interface(mut self, interface: impl Into<DriveInterface>) -> Self {
+        self.config.interface = Some(interface.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: usize) -> Self {
+        self.config.bus = Some(bus);
+        self
+    }
+
+    pub fn unit(mut self, unit: usize) -> Self {
+        self.config.unit = Some(unit);
+        self
+    }
+
+    pub fn index(mut self, index: usize) -> Self {
+        self.config.index = Some(index);
+        self
+    }
+
+    pub fn media(mut self, media: impl Into<Media>) -> Self {
+        self.config.media = Some(media.into());
+        self
+    }
+
+    pub fn format(mut self, format: impl Into<BlockDriver>) -> Self {
+        self.config.format = Some(format.into());
+        self
+    }
+
+    pub fn snapshot(mut self, snapshot: impl Into<OnOff>) -> Self {
+        self.config.snapshot = Some(snapshot.into());
+        self
+    }
+
+    pub fn cache(mut self, cache: impl Into<CacheMode>) -> Self {
+        self.config.cache = Some(cache.into());
+        self
+    }
+
+    pub fn aio(mut self, aio: impl Into<Aio>) -> Self {
+        self.config.aio = Some(aio.into());
+        self
+    }
+
+    pub fn discard(mut self, discard: impl Into<Discard>) -> Self {
+        self.config.discard = Some(discard.into());
+        self
+    }
+
+    pub fn detect_zeroes(mut self, detect_zeroes: impl Into<DetectZeroes>) -> Self {
+        self.config.detect_zeroes = Some(detect_zeroes.into());
+        self
+    }
+
+    pub fn read_only(mut self, read_only: impl Into<OnOff>) -> Self {
+        self.config.read_only = Some(read_only.into());
+        self
+    }
+
+    pub fn throttling(mut self, throttling: ThrottleConfig) -> Self {
+        self.config.throttling = throttling;
+        self
+    }
+
+    pub fn build(self) -> DriveConfig {
+        self.config
+    }
+}
+
+impl From<DriveConfigBuilder> for DriveConfig {
+    fn from(builder: DriveConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::parser::{parse_args, OptionParsing};
+
+    #[test]
+    fn test_blockdev_chain() {
+        let file = BlockdevConfig::file("disk0-file", "/images/disk0.qcow2")
+            .aio(Aio::Native)
+            .cache_direct(true)
+            .build();
+        let qcow2 = BlockdevConfig::qcow2("disk0", "disk0-file")
+            .discard(Discard::Unmap)
+            .detect_zeroes(DetectZeroes::Unmap)
+            .l2_cache_size(ByteSize::mib(4))
+            .build();
+        assert_eq!(
+            file.formatting().to_string(),
+            "-blockdev driver=file,node-name=disk0-file,cache.direct=on,filename=/images/disk0.qcow2,aio=native"
+        );
+        assert_eq!(
+            qcow2.formatting().to_string(),
+            "-blockdev driver=qcow2,node-name=disk0,discard=unmap,detect-zeroes=unmap,file=disk0-file,l2-cache-size=4194304"
+        );
+
+        let argv = [
+            "qemu-system-x86_64",
+            "-blockdev",
+            "driver=qcow2,node-name=disk0,discard=unmap,detect-zeroes=unmap,file=disk0-file,l2-cache-size=4194304",
+        ];
+        let command = parse_args(&argv).unwrap();
+        assert_eq!(
+            BlockdevConfig::parsing(&command.options[0]),
+            Ok(qcow2.clone())
+        );
+
+        let mut errors = Vec::new();
+        validate_graph(&[file.clone(), qcow2.clone()], &mut errors);
+        [file.clone(), qcow2.clone()]
+            .to_vec()
+            .validate("blockdev", &mut errors);
+        assert!(errors.is_empty());
+
+        validate_graph(&[qcow2, file], &mut errors);
+        assert_eq!(
+            errors[0].to_string(),
+            "blockdev[0].file: 'disk0-file' is not defined"
+        );
+    }
+
+    #[test]
+    fn test_blockdev_validate() {
+        let mut errors = Vec::new();
+        BlockdevConfig::raw("disk0", "disk0-file")
+            .filename("/images/disk0.img")
+            .aio(Aio::Native)
+            .build()
+            .validate("blockdev[1]", &mut errors);
+        BlockdevConfig::builder(BlockDriver::Throttle, "disk1")
+            .build()
+            .validate("blockdev[2]", &mut errors);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "blockdev[1].filename: not supported by driver=raw",
+                "blockdev[1].aio: not supported by driver=raw",
+                "blockdev[1].aio: requires 'cache.direct=on'",
+                "blockdev[2].driver: requires 'file'",
+                "blockdev[2].driver: requires 'throttle-group'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_drive() {
+        let drive = DriveConfig::builder()
+            .file("/images/a,b.img")
+            .id("drive0")
+            .interface(DriveInterface::None)
+            .format(BlockDriver::Raw)
+            .cache(CacheMode::None)
+            .aio(Aio::IoUring)
+            .throttling(ThrottleConfig {
+                bps: Some(10485760),
+                iops_rd: Some(500),
+                group: Some("tg0".to_string()),
+                ..Default::default()
+            })
+            .build();
+        assert_eq!(
+            drive.formatting().to_string(),
+            "-drive file=/images/a,,b.img,id=drive0,if=none,format=raw,cache=none,aio=io_uring,bps=10485760,iops_rd=500,group=tg0"
+        );
+
+        let args: Vec<String> = std::iter::once("qemu-system-x86_64".to_string())
+            .chain(
+                drive
+                    .formatting()
+                    .to_args()
+                    .into_iter()
+                    .map(|arg| arg.into_string().unwrap()),
+            )
+            .collect();
+        let command = parse_args(&args).unwrap();
+        assert_eq!(DriveConfig::parsing(&command.options[0]), Ok(drive.clone()));
+
+        let json = serde_json::to_value(&drive).unwrap();
+        assert_eq!(json["throttling"]["bps"], 10485760);
+        assert_eq!(serde_json::from_value::<DriveConfig>(json).unwrap(), drive);
+
+        let mut errors = Vec::new();
+        DriveConfig::builder()
+            .format(BlockDriver::File)
+            .aio(Aio::Native)
+            .throttling(ThrottleConfig {
+                bps: Some(1),
+                bps_wr: Some(1),
+                iops_max: Some(1),
+                ..Default::default()
+            })
+            .build()
+            .validate("drive[0]", &mut errors);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "drive[0].format: not supported by format=file",
+                "drive[0].aio: requires 'cache=none|directsync'",
+                "drive[0].bps_wr: conflicts with 'bps'",
+                "drive[0].iops_max: requires 'iops'",
+            ]
+        );
+    }
+}
@@ -1,15 +1,548 @@
-//! 
+//! Boot order and boot menu, and direct kernel boot without a bootloader
+//! in the guest, e.g.
+//!     -boot order=c,once=d,menu=on,strict=on
+//!     -kernel /boot/vmlinuz -initrd /boot/initrd.img -append "console=ttyS0 root=/dev/vda1 rw"
+use std::{error::Error, fmt, str::FromStr};
+
 use crate::command::builder::*;
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{
+    types::{qemu_enum, OnOff},
+    validate::*,
+};
+
+qemu_enum! {
+    /// A boot device of the BIOS, as a drive letter.
+    pub enum BootDevice {
+        /// The first floppy drive.
+        Floppy = "a",
+        /// The second floppy drive.
+        Floppy2 = "b",
+        /// The first hard disk.
+        Disk = "c",
+        /// The first CD-ROM.
+        Cdrom = "d",
+        /// Network boot from the first, second or third NIC.
+        Network = "n",
+        Network2 = "o",
+        Network3 = "p",
+    }
+}
+
+/// Boot devices in the order they are tried, written as their drive
+/// letters, e.g. `cdn`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BootOrder(pub Vec<BootDevice>);
+
+impl fmt::Display for BootOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0
+            .iter()
+            .try_for_each(|device| f.write_str(device.as_str()))
+    }
+}
+
+impl FromStr for BootOrder {
+    type Err = InvalidBootOrder;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.chars()
+            .map(|c| c.to_string().parse())
+            .collect::<Result<_, _>>()
+            .map(BootOrder)
+            .map_err(|_| InvalidBootOrder(s.to_string()))
+    }
+}
+
+impl<const N: usize> From<[BootDevice; N]> for BootOrder {
+    fn from(devices: [BootDevice; N]) -> Self {
+        BootOrder(devices.to_vec())
+    }
+}
+
+/// A boot order that cannot be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidBootOrder(pub String);
+
+impl fmt::Display for InvalidBootOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid boot order '{}', expected drive letters from {}",
+            self.0,
+            BootDevice::TOKENS.join("")
+        )
+    }
+}
+
+impl Error for InvalidBootOrder {}
+
+impl Serialize for BootOrder {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BootOrder {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "boot")]
+pub struct BootConfig {
+    // order=drives
+    // Boot devices in order of preference. Only honoured by BIOS
+    // firmware, UEFI guests use bootindex of -device instead.
+    #[serde(rename = "order", skip_serializing_if = "Option::is_none")]
+    #[qemu(implied)]
+    pub order: Option<BootOrder>,
+
+    // once=drives
+    // Boot order for the first boot only, the next reset uses order.
+    #[serde(rename = "once", skip_serializing_if = "Option::is_none")]
+    pub once: Option<BootOrder>,
+
+    // menu=on|off
+    // Interactive boot menu, as far as the firmware supports it.
+    #[serde(rename = "menu", skip_serializing_if = "Option::is_none")]
+    pub menu: Option<OnOff>,
+
+    // splash=sp_name
+    // Picture shown as logo while the boot menu is up (menu=on).
+    #[serde(rename = "splash", skip_serializing_if = "Option::is_none")]
+    pub splash: Option<String>,
+
+    // splash-time=sp_time
+    // How long the splash picture is shown, in milliseconds.
+    #[serde(rename = "splash-time", skip_serializing_if = "Option::is_none")]
+    pub splash_time: Option<u16>,
+
+    // reboot-timeout=rb_timeout
+    // Milliseconds before rebooting when no boot device is found, -1
+    // (default) waits forever.
+    #[serde(rename = "reboot-timeout", skip_serializing_if = "Option::is_none")]
+    pub reboot_timeout: Option<i32>,
+
+    // strict=on|off
+    // Do not fall back to devices outside the boot order.
+    #[serde(rename = "strict", skip_serializing_if = "Option::is_none")]
+    pub strict: Option<OnOff>,
+}
+
+impl Validate for BootConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        for (key, order) in [("order", &self.order), ("once", &self.once)] {
+            let Some(BootOrder(devices)) = order else {
+                continue;
+            };
+            let mut repeated = (1..devices.len()).filter(|&i| devices[..i].contains(&devices[i]));
+            if let Some(device) = repeated.next().map(|i| devices[i]) {
+                let id = device.to_string();
+                invalid(errors, path, key, ValidationErrorKind::Duplicate { id });
+            }
+        }
+        if self.splash.is_some() && self.menu != Some(OnOff::On) {
+            invalid(
+                errors,
+                path,
+                "splash",
+                ValidationErrorKind::Requires { key: "menu" },
+            );
+        }
+        match self.reboot_timeout {
+            Some(timeout) if timeout < -1 => invalid(
+                errors,
+                path,
+                "reboot-timeout",
+                ValidationErrorKind::Invalid {
+                    expected: "-1 or a number of milliseconds",
+                },
+            ),
+            Some(timeout) if timeout > 0xffff => invalid(
+                errors,
+                path,
+                "reboot-timeout",
+                ValidationErrorKind::AboveMaximum {
+                    value: timeout as usize,
+                    max: 0xffff,
+                },
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// Boot the given Linux kernel image directly.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "kernel", separator = "")]
+pub struct KernelConfig(pub String);
+
+impl KernelConfig {
+    pub fn new(path: impl Into<String>) -> Self {
+        KernelConfig(path.into())
+    }
+}
+
+/// Initial ram disk of -kernel.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "initrd", separator = "")]
+pub struct InitrdConfig(pub String);
+
+impl InitrdConfig {
+    pub fn new(path: impl Into<String>) -> Self {
+        InitrdConfig(path.into())
+    }
+}
+
+/// Command line of -kernel.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "append", separator = "")]
+pub struct AppendConfig(pub String);
+
+impl AppendConfig {
+    pub fn new(cmdline: impl Into<String>) -> Self {
+        AppendConfig(cmdline.into())
+    }
+}
+
+impl From<KernelCmdline> for AppendConfig {
+    fn from(cmdline: KernelCmdline) -> Self {
+        AppendConfig(cmdline.to_string())
+    }
+}
+
+/// Device tree blob handed to -kernel, on machines that boot with one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "dtb", separator = "")]
+pub struct DtbConfig(pub String);
+
+impl DtbConfig {
+    pub fn new(path: impl Into<String>) -> Self {
+        DtbConfig(path.into())
+    }
+}
+
+/// Check that -initrd, -append and -dtb come with the -kernel they apply
+/// to.
+pub(crate) fn validate_kernel(
+    kernel: Option<&KernelConfig>,
+    others: [(&str, bool); 3],
+    errors: &mut Vec<ValidationError>,
+) {
+    if kernel.is_some() {
+        return;
+    }
+    for (path, given) in others {
+        if given {
+            errors.push(ValidationError {
+                path: path.to_string(),
+                kind: ValidationErrorKind::Requires { key: "kernel" },
+            });
+        }
+    }
+}
+
+/// A Linux kernel command line, built parameter by parameter, e.g.
+///     KernelCmdline::new().console("ttyS0,115200").root("/dev/vda1").rw().build()
+/// gives `console=ttyS0,115200 root=/dev/vda1 rw`. Values with spaces are
+/// quoted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KernelCmdline {
+    params: Vec<(String, Option<String>)>,
+}
+
+/// A kernel command line with an unterminated quote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidKernelCmdline(pub String);
+
+impl fmt::Display for InvalidKernelCmdline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unterminated quote in kernel command line '{}'", self.0)
+    }
+}
+
+impl Error for InvalidKernelCmdline {}
+
+impl KernelCmdline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a console. The kernel writes to every console given, and the
+    /// last one becomes /dev/console, e.g. `ttyS0,115200` or `hvc0`.
+    pub fn console(self, console: impl Into<String>) -> Self {
+        self.param("console", console)
+    }
+
+    /// Set the root device, replacing an earlier one, e.g. `/dev/vda1` or
+    /// `LABEL=root`.
+    pub fn root(self, root: impl Into<String>) -> Self {
+        self.set("root", root)
+    }
+
+    /// Mount the root device read-write.
+    pub fn rw(self) -> Self {
+        self.remove("ro").flag("rw")
+    }
+
+    /// Mount the root device read-only.
+    pub fn ro(self) -> Self {
+        self.remove("rw").flag("ro")
+    }
+
+    /// Add `key=value`, keeping earlier values of `key`.
+    pub fn param(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.params.push((key.into(), Some(value.into())));
+        self
+    }
+
+    /// Set `key=value`, replacing earlier values of `key`.
+    pub fn set(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        let key = key.into();
+        self.remove(&key).param(key, value)
+    }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BootConfig<'a> {
-    #[serde(rename="menu", skip_serializing_if="Option::is_none")]
-    menu: Option<&'a str>,
+    /// Add a bare parameter, e.g. `quiet`.
+    pub fn flag(mut self, key: impl Into<String>) -> Self {
+        let key = key.into();
+        if !self.params.iter().any(|(k, v)| *k == key && v.is_none()) {
+            self.params.push((key, None));
+        }
+        self
+    }
+
+    /// Drop every occurrence of `key`.
+    pub fn remove(mut self, key: &str) -> Self {
+        self.params.retain(|(k, _)| k != key);
+        self
+    }
+
+    /// The last value of `key`, which is the one the kernel uses.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, v)| v.as_deref())
+    }
+
+    /// Every console, the last one being /dev/console.
+    pub fn consoles(&self) -> impl Iterator<Item = &str> {
+        self.params
+            .iter()
+            .filter(|(k, _)| k == "console")
+            .filter_map(|(_, v)| v.as_deref())
+    }
+
+    pub fn build(self) -> AppendConfig {
+        self.into()
+    }
+}
+
+impl fmt::Display for KernelCmdline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, value)) in self.params.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            match value {
+                None => write!(f, "{}", key)?,
+                Some(value) if value.contains(char::is_whitespace) => {
+                    write!(f, "{}=\"{}\"", key, value)?
+                }
+                Some(value) => write!(f, "{}={}", key, value)?,
+            }
+        }
+        Ok(())
+    }
 }
 
-impl<'a> OptionFormatting<'a> for BootConfig<'a> {
-    fn formatting(&self) -> OptionQ<'a> {
-        todo!()
+impl FromStr for KernelCmdline {
+    type Err = InvalidKernelCmdline;
+
+    /// Split on whitespace outside double quotes, as the kernel does.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = Vec::new();
+        let mut word = String::new();
+        let mut quoted = false;
+        for c in s.chars() {
+            match c {
+                '"' => quoted = !quoted,
+                c if c.is_whitespace() && !quoted => {
+                    if !word.is_empty() {
+                        words.push(std::mem::take(&mut word));
+                    }
+                }
+                c => word.push(c),
+            }
+        }
+        if quoted {
+            return Err(InvalidKernelCmdline(s.to_string()));
+        }
+        if !word.is_empty() {
+            words.push(word);
+        }
+        let params = words
+            .into_iter()
+            .map(|word| match word.split_once('=') {
+                Some((key, value)) => (key.to_string(), Some(value.to_string())),
+                None => (word, None),
+            })
+            .collect();
+        Ok(KernelCmdline { params })
     }
-}
\ No newline at end of file
+}
+
+/// Builder for `BootConfig`, e.g.
+///     BootConfig::builder().order([BootDevice::Disk]).menu(true).build()
+#[derive(Debug, Clone)]
+pub struct BootConfigBuilder {
+    config: BootConfig,
+}
+
+impl BootConfig {
+    pub fn builder() -> BootConfigBuilder {
+        BootConfigBuilder {
+            config: BootConfig {
+                order: None,
+                once: None,
+                menu: None,
+                splash: None,
+                splash_time: None,
+                reboot_timeout: None,
+                strict: None,
+            },
+        }
+    }
+}
+
+impl BootConfigBuilder {
+    pub fn order(mut self, order: impl Into<BootOrder>) -> Self {
+        self.config.order = Some(order.into());
+        self
+    }
+
+    pub fn once(mut self, once: impl Into<BootOrder>) -> Self {
+        self.config.once = Some(once.into());
+        self
+    }
+
+    pub fn menu(mut self, menu: impl Into<OnOff>) -> Self {
+        self.config.menu = Some(menu.into());
+        self
+    }
+
+    pub fn splash(mut self, splash: impl Into<String>) -> Self {
+        self.config.splash = Some(splash.into());
+        self
+    }
+
+    pub fn splash_time(mut self, splash_time: u16) -> Self {
+        self.config.splash_time = Some(splash_time);
+        self
+    }
+
+    pub fn reboot_timeout(mut self, reboot_timeout: i32) -> Self {
+        self.config.reboot_timeout = Some(reboot_timeout);
+        self
+    }
+
+    pub fn strict(mut self, strict: impl Into<OnOff>) -> Self {
+        self.config.strict = Some(strict.into());
+        self
+    }
+
+    pub fn build(self) -> BootConfig {
+        self.config
+    }
+}
+
+impl From<BootConfigBuilder> for BootConfig {
+    fn from(builder: BootConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::parser::*;
+
+    #[test]
+    fn test_boot() {
+        let boot = BootConfig::builder()
+            .order([BootDevice::Disk, BootDevice::Network])
+            .once([BootDevice::Cdrom])
+            .menu(true)
+            .splash_time(5000)
+            .reboot_timeout(-1)
+            .strict(true)
+            .build();
+        let formatted =
+            "-boot order=cn,once=d,menu=on,splash-time=5000,reboot-timeout=-1,strict=on";
+        assert_eq!(boot.formatting().to_string(), formatted);
+        let argv = ["qemu-system-x86_64", "-boot", &formatted[6..]];
+        let command = parse_args(&argv).unwrap();
+        assert_eq!(BootConfig::parsing(&command.options[0]), Ok(boot.clone()));
+        let json = r#"{"order":"cn","once":"d","menu":"on","splash-time":5000,"reboot-timeout":-1,"strict":"on"}"#;
+        assert_eq!(serde_json::to_string(&boot).unwrap(), json);
+
+        // The legacy form gives the order alone.
+        let argv = ["qemu-system-x86_64", "-boot", "dc"];
+        let command = parse_args(&argv).unwrap();
+        assert_eq!(
+            BootConfig::parsing(&command.options[0]),
+            Ok(BootConfig::builder()
+                .order([BootDevice::Cdrom, BootDevice::Disk])
+                .build())
+        );
+        assert!("cx".parse::<BootOrder>().is_err());
+
+        let mut errors = Vec::new();
+        BootConfig::builder()
+            .order([BootDevice::Disk, BootDevice::Cdrom, BootDevice::Disk])
+            .splash("/boot/splash.bmp")
+            .reboot_timeout(70000)
+            .build()
+            .validate("boot", &mut errors);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "boot.order: 'c' is used more than once",
+                "boot.splash: requires 'menu'",
+                "boot.reboot-timeout: 70000 exceeds the maximum of 65535",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_kernel_cmdline() {
+        let cmdline = KernelCmdline::new()
+            .console("tty0")
+            .console("ttyS0,115200")
+            .root("/dev/sda1")
+            .ro()
+            .root("/dev/vda1")
+            .rw()
+            .set("init", "/sbin/init --log-level=debug")
+            .flag("quiet");
+        let formatted = r#"console=tty0 console=ttyS0,115200 root=/dev/vda1 rw init="/sbin/init --log-level=debug" quiet"#;
+        assert_eq!(cmdline.to_string(), formatted);
+        assert_eq!(formatted.parse::<KernelCmdline>(), Ok(cmdline.clone()));
+        assert_eq!(cmdline.get("root"), Some("/dev/vda1"));
+        assert_eq!(
+            cmdline.consoles().collect::<Vec<_>>(),
+            ["tty0", "ttyS0,115200"]
+        );
+        assert!("init=\"/sbin/init".parse::<KernelCmdline>().is_err());
+
+        let append = cmdline.build();
+        let argv = ["qemu-system-x86_64", "-append", &append.0];
+        let command = parse_args(&argv).unwrap();
+        assert_eq!(AppendConfig::parsing(&command.options[0]), Ok(append));
+    }
+}
@@ -0,0 +1,551 @@
+//! Character devices and the serial ports and monitors attached to them.
+//!
+//! Front-ends refer to a -chardev by its id, e.g. a QMP monitor on a unix
+//! socket:
+//!     -chardev socket,id=qmp0,path=/run/vm/qmp.sock,server=on,wait=off
+//!     -qmp chardev:qmp0
+use crate::command::builder::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    types::{qemu_enum, ByteSize, InvalidToken, OnOff},
+    validate::*,
+};
+
+qemu_enum! {
+    /// Backends of -chardev.
+    pub enum ChardevBackend {
+        Socket = "socket",
+        Pty = "pty",
+        File = "file",
+        Pipe = "pipe",
+        Stdio = "stdio",
+        Null = "null",
+        Ringbuf = "ringbuf" | "memory",
+    }
+}
+
+/// Define a new character device.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "chardev")]
+pub struct ChardevConfig {
+    // socket|pty|file|pipe|stdio|null|ringbuf
+    #[serde(rename = "backend")]
+    #[qemu(positional, rename = "backend")]
+    pub backend: ChardevBackend,
+
+    // id=id
+    // The id front-ends refer to by chardev:id or chardev=id.
+    #[serde(rename = "id")]
+    pub id: String,
+
+    // path=path (socket, file, pipe)
+    // The unix socket to listen on or connect to, the file to log to, or
+    // the pipe (path.in and path.out, or path itself) to use.
+    #[serde(rename = "path", skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+
+    // host=host,port=port (socket)
+    // The TCP address to listen on or connect to.
+    #[serde(rename = "host", skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+
+    #[serde(rename = "port", skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+
+    // server=on|off (socket)
+    // Whether to listen rather than connect. (default: off)
+    #[serde(rename = "server", skip_serializing_if = "Option::is_none")]
+    pub server: Option<OnOff>,
+
+    // wait=on|off (socket)
+    // Whether a server blocks waiting for a client before the guest runs.
+    // (default: on)
+    #[serde(rename = "wait", skip_serializing_if = "Option::is_none")]
+    pub wait: Option<OnOff>,
+
+    // reconnect=seconds (socket)
+    // For a client, try to reconnect after this many seconds when the
+    // remote end goes away.
+    #[serde(rename = "reconnect", skip_serializing_if = "Option::is_none")]
+    pub reconnect: Option<u64>,
+
+    // append=on|off (file)
+    // Append to the file instead of truncating it. (default: off)
+    #[serde(rename = "append", skip_serializing_if = "Option::is_none")]
+    pub append: Option<OnOff>,
+
+    // signal=on|off (stdio)
+    // Whether ^C on stdio terminates QEMU. (default: on)
+    #[serde(rename = "signal", skip_serializing_if = "Option::is_none")]
+    pub signal: Option<OnOff>,
+
+    // size=size (ringbuf)
+    // Size of the ring buffer, a power of two. (default: 64K)
+    #[serde(rename = "size", skip_serializing_if = "Option::is_none")]
+    pub size: Option<ByteSize>,
+
+    // mux=on|off
+    // Share the device between several front-ends, e.g. a serial port and
+    // the HMP monitor.
+    #[serde(rename = "mux", skip_serializing_if = "Option::is_none")]
+    pub mux: Option<OnOff>,
+
+    // logfile=path, logappend=on|off
+    // Log all traffic received from the guest to a file.
+    #[serde(rename = "logfile", skip_serializing_if = "Option::is_none")]
+    pub logfile: Option<String>,
+
+    #[serde(rename = "logappend", skip_serializing_if = "Option::is_none")]
+    pub logappend: Option<OnOff>,
+}
+
+impl ChardevConfig {
+    /// Backend specific keys which are given, with the backends accepting
+    /// them.
+    fn backend_keys(&self) -> [(&'static str, bool, &'static [ChardevBackend]); 9] {
+        use ChardevBackend::*;
+        [
+            ("path", self.path.is_some(), &[Socket, File, Pipe]),
+            ("host", self.host.is_some(), &[Socket]),
+            ("port", self.port.is_some(), &[Socket]),
+            ("server", self.server.is_some(), &[Socket]),
+            ("wait", self.wait.is_some(), &[Socket]),
+            ("reconnect", self.reconnect.is_some(), &[Socket]),
+            ("append", self.append.is_some(), &[File]),
+            ("signal", self.signal.is_some(), &[Stdio]),
+            ("size", self.size.is_some(), &[Ringbuf]),
+        ]
+    }
+
+    /// The path of a unix socket the device listens on.
+    pub fn unix_server_path(&self) -> Option<&str> {
+        (self.backend == ChardevBackend::Socket && self.server == Some(OnOff::On))
+            .then_some(self.path.as_deref())
+            .flatten()
+    }
+}
+
+impl Validate for ChardevConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        for (key, given, backends) in self.backend_keys() {
+            if given && !backends.contains(&self.backend) {
+                invalid(
+                    errors,
+                    path,
+                    key,
+                    ValidationErrorKind::NotSupported {
+                        by: format!("backend={}", self.backend),
+                    },
+                );
+            }
+        }
+
+        match self.backend {
+            ChardevBackend::Socket if self.path.is_some() && self.port.is_some() => invalid(
+                errors,
+                path,
+                "port",
+                ValidationErrorKind::Conflicts { key: "path" },
+            ),
+            ChardevBackend::Socket if self.path.is_none() && self.port.is_none() => invalid(
+                errors,
+                path,
+                "backend",
+                ValidationErrorKind::Requires { key: "path" },
+            ),
+            ChardevBackend::File | ChardevBackend::Pipe if self.path.is_none() => invalid(
+                errors,
+                path,
+                "backend",
+                ValidationErrorKind::Requires { key: "path" },
+            ),
+            _ => {}
+        }
+
+        if self.wait.is_some() && self.server != Some(OnOff::On) {
+            invalid(
+                errors,
+                path,
+                "wait",
+                ValidationErrorKind::Requires { key: "server=on" },
+            );
+        }
+        if self.reconnect.is_some() && self.server == Some(OnOff::On) {
+            invalid(
+                errors,
+                path,
+                "reconnect",
+                ValidationErrorKind::Conflicts { key: "server=on" },
+            );
+        }
+        if let Some(size) = self.size {
+            if !size.bytes().is_power_of_two() {
+                invalid(
+                    errors,
+                    path,
+                    "size",
+                    ValidationErrorKind::Invalid {
+                        expected: "a power of two",
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// -serial, -monitor and -qmp name their chardev as `chardev:id`.
+fn chardev_spec(id: &str) -> String {
+    format!("chardev:{}", id)
+}
+
+fn parse_chardev_spec(spec: &str) -> Result<String, InvalidToken> {
+    spec.strip_prefix("chardev:")
+        .map(str::to_string)
+        .ok_or_else(|| InvalidToken {
+            value: spec.to_string(),
+            expected: &["chardev:id"],
+        })
+}
+
+/// Whether `option` attaches a front-end to a -chardev by id, rather than
+/// through a shorthand such as `-serial stdio` this crate does not model.
+pub(crate) fn is_chardev_spec(option: &OptionQ<'_>) -> bool {
+    matches!(option.args.as_slice(), [arg] if arg.value.as_deref().is_some_and(|v| v.starts_with("chardev:")))
+}
+
+/// Redirect the next virtual serial port to the chardev with the given id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "serial", separator = "")]
+pub struct SerialConfig(
+    #[qemu(format_with = "chardev_spec", parse_with = "parse_chardev_spec")] pub String,
+);
+
+impl SerialConfig {
+    pub fn new(chardev: impl Into<String>) -> Self {
+        SerialConfig(chardev.into())
+    }
+}
+
+/// Redirect the HMP monitor to the chardev with the given id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "monitor", separator = "")]
+pub struct MonitorConfig(
+    #[qemu(format_with = "chardev_spec", parse_with = "parse_chardev_spec")] pub String,
+);
+
+impl MonitorConfig {
+    pub fn new(chardev: impl Into<String>) -> Self {
+        MonitorConfig(chardev.into())
+    }
+}
+
+/// Redirect a QMP monitor to the chardev with the given id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "qmp", separator = "")]
+pub struct QmpConfig(
+    #[qemu(format_with = "chardev_spec", parse_with = "parse_chardev_spec")] pub String,
+);
+
+impl QmpConfig {
+    pub fn new(chardev: impl Into<String>) -> Self {
+        QmpConfig(chardev.into())
+    }
+}
+
+/// Check that chardev ids are unique, that every front-end (given as its
+/// path and the chardev id it uses) names a defined chardev, and that only
+/// chardevs with mux=on are shared.
+pub(crate) fn validate_references<'a>(
+    chardevs: &[ChardevConfig],
+    users: impl IntoIterator<Item = (String, &'a str)>,
+    errors: &mut Vec<ValidationError>,
+) {
+    for (i, chardev) in chardevs.iter().enumerate() {
+        if chardevs[..i].iter().any(|other| other.id == chardev.id) {
+            invalid(
+                errors,
+                &format!("chardev[{}]", i),
+                "id",
+                ValidationErrorKind::Duplicate {
+                    id: chardev.id.clone(),
+                },
+            );
+        }
+    }
+
+    let mut used: Vec<&str> = Vec::new();
+    for (path, id) in users {
+        match chardevs.iter().find(|chardev| chardev.id == id) {
+            None => errors.push(ValidationError {
+                path,
+                kind: ValidationErrorKind::UnknownReference { id: id.to_string() },
+            }),
+            Some(chardev) if used.contains(&id) && chardev.mux != Some(OnOff::On) => {
+                errors.push(ValidationError {
+                    path,
+                    kind: ValidationErrorKind::Duplicate { id: id.to_string() },
+                })
+            }
+            Some(_) => used.push(id),
+        }
+    }
+}
+
+/// Builder for `ChardevConfig`, e.g.
+///     ChardevConfig::unix_socket("qmp0", "/run/vm/qmp.sock").server(true).wait(false).build()
+#[derive(Debug, Clone)]
+pub struct ChardevConfigBuilder {
+    config: ChardevConfig,
+}
+
+impl ChardevConfig {
+    pub fn builder(backend: ChardevBackend, id: impl Into<String>) -> ChardevConfigBuilder {
+        ChardevConfigBuilder {
+            config: ChardevConfig {
+                backend,
+                id: id.into(),
+                path: None,
+                host: None,
+                port: None,
+                server: None,
+                wait: None,
+                reconnect: None,
+                append: None,
+                signal: None,
+                size: None,
+                mux: None,
+                logfile: None,
+                logappend: None,
+            },
+        }
+    }
+
+    /// A unix socket at `path`.
+    pub fn unix_socket(id: impl Into<String>, path: impl Into<String>) -> ChardevConfigBuilder {
+        Self::builder(ChardevBackend::Socket, id).path(path)
+    }
+
+    /// A TCP socket at `host:port`.
+    pub fn tcp_socket(
+        id: impl Into<String>,
+        host: impl Into<String>,
+        port: u16,
+    ) -> ChardevConfigBuilder {
+        Self::builder(ChardevBackend::Socket, id)
+            .host(host)
+            .port(port)
+    }
+
+    /// A new pseudo terminal, reported by QEMU on startup.
+    pub fn pty(id: impl Into<String>) -> ChardevConfigBuilder {
+        Self::builder(ChardevBackend::Pty, id)
+    }
+
+    /// Log the output of the guest to `path`.
+    pub fn file(id: impl Into<String>, path: impl Into<String>) -> ChardevConfigBuilder {
+        Self::builder(ChardevBackend::File, id).path(path)
+    }
+
+    /// A named pipe at `path`.
+    pub fn pipe(id: impl Into<String>, path: impl Into<String>) -> ChardevConfigBuilder {
+        Self::builder(ChardevBackend::Pipe, id).path(path)
+    }
+
+    /// The standard input and output of QEMU.
+    pub fn stdio(id: impl Into<String>) -> ChardevConfigBuilder {
+        Self::builder(ChardevBackend::Stdio, id)
+    }
+
+    /// A device that discards output and never produces input.
+    pub fn null(id: impl Into<String>) -> ChardevConfigBuilder {
+        Self::builder(ChardevBackend::Null, id)
+    }
+
+    /// A ring buffer in memory, readable through the monitor.
+    pub fn ringbuf(id: impl Into<String>) -> ChardevConfigBuilder {
+        Self::builder(ChardevBackend::Ringbuf, id)
+    }
+}
+
+impl ChardevConfigBuilder {
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.config.path = Some(path.into());
+        self
+    }
+
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.config.host = Some(host.into());
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.config.port = Some(port);
+        self
+    }
+
+    pub fn server(mut self, server: impl Into<OnOff>) -> Self {
+        self.config.server = Some(server.into());
+        self
+    }
+
+    pub fn wait(mut self, wait: impl Into<OnOff>) -> Self {
+        self.config.wait = Some(wait.into());
+        self
+    }
+
+    pub fn reconnect(mut self, reconnect: u64) -> Self {
+        self.config.reconnect = Some(reconnect);
+        self
+    }
+
+    pub fn append(mut self, append: impl Into<OnOff>) -> Self {
+        self.config.append = Some(append.into());
+        self
+    }
+
+    pub fn signal(mut self, signal: impl Into<OnOff>) -> Self {
+        self.config.signal = Some(signal.into());
+        self
+    }
+
+    pub fn size(mut self, size: ByteSize) -> Self {
+        self.config.size = Some(size);
+        self
+    }
+
+    pub fn mux(mut self, mux: impl Into<OnOff>) -> Self {
+        self.config.mux = Some(mux.into());
+        self
+    }
+
+    pub fn logfile(mut self, logfile: impl Into<String>) -> Self {
+        self.config.logfile = Some(logfile.into());
+        self
+    }
+
+    pub fn logappend(mut self, logappend: impl Into<OnOff>) -> Self {
+        self.config.logappend = Some(logappend.into());
+        self
+    }
+
+    pub fn build(self) -> ChardevConfig {
+        self.config
+    }
+}
+
+impl From<ChardevConfigBuilder> for ChardevConfig {
+    fn from(builder: ChardevConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::parser::{parse_args, OptionParsing};
+
+    #[test]
+    fn test_chardev() {
+        let qmp = ChardevConfig::unix_socket("qmp0", "/run/vm,1/qmp.sock")
+            .server(true)
+            .wait(false)
+            .build();
+        assert_eq!(
+            qmp.formatting().to_string(),
+            "-chardev socket,id=qmp0,path=/run/vm,,1/qmp.sock,server=on,wait=off"
+        );
+        assert_eq!(qmp.unix_server_path(), Some("/run/vm,1/qmp.sock"));
+        assert_eq!(
+            QmpConfig::new("qmp0").formatting().to_string(),
+            "-qmp chardev:qmp0"
+        );
+        assert_eq!(
+            ChardevConfig::ringbuf("log0")
+                .size(ByteSize::kib(128))
+                .build()
+                .formatting()
+                .to_string(),
+            "-chardev ringbuf,id=log0,size=128K"
+        );
+
+        let argv = [
+            "qemu-system-x86_64",
+            "-chardev",
+            "socket,id=qmp0,path=/run/vm,,1/qmp.sock,server=on,wait=off",
+            "-qmp",
+            "chardev:qmp0",
+            "-serial",
+            "stdio",
+        ];
+        let command = parse_args(&argv).unwrap();
+        assert_eq!(ChardevConfig::parsing(&command.options[0]), Ok(qmp));
+        assert_eq!(
+            QmpConfig::parsing(&command.options[1]),
+            Ok(QmpConfig::new("qmp0"))
+        );
+        assert!(is_chardev_spec(&command.options[1]));
+        assert!(!is_chardev_spec(&command.options[2]));
+
+        // The legacy boolean forms of server and wait.
+        let argv = [
+            "qemu-system-x86_64",
+            "-chardev",
+            "socket,id=c0,path=/tmp/s,server,nowait",
+            "-chardev",
+            "socket,id=c1,path=/tmp/s,server,wait",
+        ];
+        let command = parse_args(&argv).unwrap();
+        assert_eq!(
+            ChardevConfig::parsing(&command.options[0]),
+            Ok(ChardevConfig::unix_socket("c0", "/tmp/s")
+                .server(true)
+                .wait(false)
+                .build())
+        );
+        assert_eq!(
+            ChardevConfig::parsing(&command.options[1]),
+            Ok(ChardevConfig::unix_socket("c1", "/tmp/s")
+                .server(true)
+                .wait(true)
+                .build())
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let chardevs = [
+            ChardevConfig::tcp_socket("mon0", "127.0.0.1", 4444)
+                .path("/tmp/mon.sock")
+                .wait(false)
+                .build(),
+            ChardevConfig::file("log0", "/tmp/log")
+                .size(ByteSize::kib(3))
+                .build(),
+            ChardevConfig::stdio("mon0").mux(true).build(),
+        ];
+        let mut errors = Vec::new();
+        chardevs.to_vec().validate("chardev", &mut errors);
+        validate_references(
+            &chardevs,
+            [
+                ("serial[0]".to_string(), "log0"),
+                ("monitor[0]".to_string(), "log0"),
+                ("qmp[0]".to_string(), "qmp0"),
+            ],
+            &mut errors,
+        );
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "chardev[0].port: conflicts with 'path'",
+                "chardev[0].wait: requires 'server=on'",
+                "chardev[1].size: not supported by backend=file",
+                "chardev[1].size: expected a power of two",
+                "chardev[2].id: 'mon0' is used more than once",
+                "monitor[0]: 'log0' is used more than once",
+                "qmp[0]: 'qmp0' is not defined",
+            ]
+        );
+    }
+}
@@ -1,82 +1,236 @@
-use crate::command::builder::*;
+use crate::command::{builder::*, parser::*};
 use serde::{Deserialize, Serialize};
 
 use super::{
-    accel::AccelConfig, add_fd::AddFdConfig, audio::{AudioConfig, AudioDevConfig}, boot::BootConfig, cpus::x86_64::CpuConfig, device::DeviceConfig, global::GlobalConfig, language::LanguageConfig, machine::MachineConfig, memory::{MConfig, MemPathConfig, MemPreallocConfig}, name::{NameConfig, UuidConfig}, numa::NumaConfig, set::SetConfig, smp::SmpConfig
+    accel::AccelConfig,
+    add_fd::AddFdConfig,
+    audio::{self, AudioConfig, AudioDevConfig, SoundCard},
+    block::{self, BlockdevConfig, DriveConfig},
+    boot::{self, AppendConfig, BootConfig, DtbConfig, InitrdConfig, KernelConfig},
+    chardev::{self, ChardevConfig, MonitorConfig, QmpConfig, SerialConfig},
+    cpus::CpuConfig,
+    device::{self, DeviceConfig},
+    display::{DisplayConfig, VgaConfig, VncConfig},
+    firmware::{self, BiosConfig, FirmwareDescriptor, FirmwareError, FirmwareMapping},
+    global::GlobalConfig,
+    language::LanguageConfig,
+    machine::MachineConfig,
+    machine_list::{self, MachineCatalog},
+    memory::{MConfig, MemPathConfig, MemPreallocConfig},
+    name::{NameConfig, UuidConfig},
+    network::{self, NetdevConfig},
+    numa::{self, NumaConfig},
+    object::{self, ObjectConfig, ObjectRef, ObjectType},
+    pci::{self, PciError, PciLayout},
+    process::{self, MsgConfig, OvercommitConfig, RunWithConfig, SandboxConfig},
+    set::SetConfig,
+    smp::SmpConfig,
+    types::{one_or_many, Accelerator, Arch, OnOff, OnOffAuto},
+    validate::*,
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Config<'a> {
-    #[serde(rename = "qemu", borrow)]
-    pub qemu: &'a str,
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(rename = "qemu")]
+    pub qemu: String,
 
-    #[serde(rename = "machine", borrow)]
-    pub machine_config: Option<MachineConfig<'a>>,
+    #[serde(rename = "machine")]
+    pub machine_config: Option<MachineConfig>,
 
-    #[serde(rename = "cpu", borrow)]
-    pub cpu_config: Option<CpuConfig<'a>>,
+    #[serde(rename = "cpu")]
+    pub cpu_config: Option<CpuConfig>,
 
-    #[serde(rename = "accel", borrow)]
-    pub accel_config: Option<AccelConfig<'a>>,
+    #[serde(rename = "accel")]
+    pub accel_config: Option<AccelConfig>,
 
     #[serde(rename = "smp")]
     pub smp_config: Option<SmpConfig>,
 
-    #[serde(rename = "numa", borrow)]
-    pub numa_config: Option<NumaConfig<'a>>,
+    #[serde(
+        rename = "numa",
+        default,
+        deserialize_with = "one_or_many::deserialize",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub numa_config: Vec<NumaConfig>,
 
-    #[serde(rename = "add-fd", borrow)]
-    pub add_fd_config: Option<AddFdConfig<'a>>,
+    #[serde(
+        rename = "add-fd",
+        default,
+        deserialize_with = "one_or_many::deserialize",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub add_fd_config: Vec<AddFdConfig>,
 
-    #[serde(rename="set", borrow)]
-    pub set_config: Option<SetConfig<'a>>,
+    #[serde(
+        rename = "set",
+        default,
+        deserialize_with = "one_or_many::deserialize",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub set_config: Vec<SetConfig>,
 
-    #[serde(rename="global", borrow)]
-    pub global_config: Option<GlobalConfig<'a>>,
+    #[serde(
+        rename = "global",
+        default,
+        deserialize_with = "one_or_many::deserialize",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub global_config: Vec<GlobalConfig>,
 
-    #[serde(rename="boot", borrow)]
-    pub boot_config: Option<BootConfig<'a>>,
+    #[serde(rename = "boot")]
+    pub boot_config: Option<BootConfig>,
 
-    #[serde(rename="m")]
+    #[serde(rename = "kernel")]
+    pub kernel_config: Option<KernelConfig>,
+
+    #[serde(rename = "initrd")]
+    pub initrd_config: Option<InitrdConfig>,
+
+    #[serde(rename = "append")]
+    pub append_config: Option<AppendConfig>,
+
+    #[serde(rename = "dtb")]
+    pub dtb_config: Option<DtbConfig>,
+
+    #[serde(rename = "bios")]
+    pub bios_config: Option<BiosConfig>,
+
+    #[serde(rename = "m")]
     pub m_config: Option<MConfig>,
 
-    #[serde(rename="mem-path", borrow)]
-    pub mem_path_config: Option<MemPathConfig<'a>>,
+    #[serde(rename = "mem-path")]
+    pub mem_path_config: Option<MemPathConfig>,
 
-    #[serde(rename="mem-prealloc")]
+    #[serde(rename = "mem-prealloc")]
     pub mem_prealloc_config: Option<MemPreallocConfig>,
 
-    #[serde(rename="k", borrow)]
-    pub language_config: Option<LanguageConfig<'a>>,
+    #[serde(rename = "k")]
+    pub language_config: Option<LanguageConfig>,
+
+    #[serde(rename = "display")]
+    pub display_config: Option<DisplayConfig>,
+
+    #[serde(rename = "vnc")]
+    pub vnc_config: Option<VncConfig>,
+
+    #[serde(rename = "vga")]
+    pub vga_config: Option<VgaConfig>,
+
+    #[serde(rename = "audio")]
+    pub audio_config: Option<AudioConfig>,
+
+    #[serde(
+        rename = "audiodev",
+        default,
+        deserialize_with = "one_or_many::deserialize",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub audiodev_config: Vec<AudioDevConfig>,
+
+    #[serde(rename = "name")]
+    pub name_config: Option<NameConfig>,
+
+    #[serde(rename = "uuid")]
+    pub uuid_config: Option<UuidConfig>,
+
+    #[serde(rename = "sandbox")]
+    pub sandbox_config: Option<SandboxConfig>,
+
+    #[serde(rename = "run-with")]
+    pub run_with_config: Option<RunWithConfig>,
 
-    #[serde(rename="audio", borrow)]
-    pub audio_config: Option<AudioConfig<'a>>,
+    #[serde(rename = "msg")]
+    pub msg_config: Option<MsgConfig>,
 
-    #[serde(rename="audiodev", borrow)]
-    pub audiodev_config: Option<AudioDevConfig<'a>>,
+    #[serde(rename = "overcommit")]
+    pub overcommit_config: Option<OvercommitConfig>,
 
-    #[serde(rename="device", borrow)]
-    pub device_config: Option<DeviceConfig<'a>>,
+    #[serde(
+        rename = "object",
+        default,
+        deserialize_with = "one_or_many::deserialize",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub object_config: Vec<ObjectConfig>,
 
-    #[serde(rename="name", borrow)]
-    pub name_config: Option<NameConfig<'a>>,
+    #[serde(
+        rename = "blockdev",
+        default,
+        deserialize_with = "one_or_many::deserialize",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub blockdev_config: Vec<BlockdevConfig>,
 
-    #[serde(rename="uuid", borrow)]
-    pub uuid_config: Option<UuidConfig<'a>>,
+    #[serde(
+        rename = "drive",
+        default,
+        deserialize_with = "one_or_many::deserialize",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub drive_config: Vec<DriveConfig>,
+
+    #[serde(
+        rename = "netdev",
+        default,
+        deserialize_with = "one_or_many::deserialize",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub netdev_config: Vec<NetdevConfig>,
+
+    #[serde(
+        rename = "device",
+        default,
+        deserialize_with = "one_or_many::deserialize",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub device_config: Vec<DeviceConfig>,
+
+    #[serde(
+        rename = "chardev",
+        default,
+        deserialize_with = "one_or_many::deserialize",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub chardev_config: Vec<ChardevConfig>,
+
+    #[serde(
+        rename = "serial",
+        default,
+        deserialize_with = "one_or_many::deserialize",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub serial_config: Vec<SerialConfig>,
+
+    #[serde(
+        rename = "monitor",
+        default,
+        deserialize_with = "one_or_many::deserialize",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub monitor_config: Vec<MonitorConfig>,
+
+    #[serde(
+        rename = "qmp",
+        default,
+        deserialize_with = "one_or_many::deserialize",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub qmp_config: Vec<QmpConfig>,
 }
 
-impl<'a> Config<'a> {
+impl Config {
+    /// Format a single (`Option`) or repeatable (`Vec`) option.
     #[inline]
-    fn f<T: OptionFormatting<'a>>(x: &Option<T>) -> Option<OptionQ<'a>> {
-        match x {
-            None => None,
-            Some(t) => Some(t.formatting()),
-        }
+    fn f<'a, T: OptionFormatting + 'a>(x: impl IntoIterator<Item = &'a T>) -> Vec<OptionQ<'a>> {
+        x.into_iter().map(|t| t.formatting()).collect()
     }
 }
 
-impl<'a> CommandFormatting<'a> for Config<'a> {
-    fn formatting(&self) -> CommandQ<'a> {
+impl CommandFormatting for Config {
+    /// Options come out in field order, repeated ones in the order they were
+    /// added, so the same config always gives the same command line.
+    fn formatting(&self) -> CommandQ<'_> {
         CommandQ {
             raw: &self.qemu,
             option_split_with: " ",
@@ -90,15 +244,1392 @@ impl<'a> CommandFormatting<'a> for Config<'a> {
                 Self::f(&self.set_config),
                 Self::f(&self.global_config),
                 Self::f(&self.boot_config),
+                Self::f(&self.kernel_config),
+                Self::f(&self.initrd_config),
+                Self::f(&self.append_config),
+                Self::f(&self.dtb_config),
+                Self::f(&self.bios_config),
                 Self::f(&self.m_config),
                 Self::f(&self.mem_path_config),
                 Self::f(&self.mem_prealloc_config),
                 Self::f(&self.language_config),
+                Self::f(&self.display_config),
+                Self::f(&self.vnc_config),
+                Self::f(&self.vga_config),
+                Self::f(&self.audio_config),
+                Self::f(&self.audiodev_config),
+                Self::f(&self.name_config),
+                Self::f(&self.uuid_config),
+                Self::f(&self.sandbox_config),
+                Self::f(&self.run_with_config),
+                Self::f(&self.msg_config),
+                Self::f(&self.overcommit_config),
+                Self::f(&self.object_config),
+                Self::f(&self.blockdev_config),
+                Self::f(&self.drive_config),
+                Self::f(&self.netdev_config),
+                Self::f(&self.device_config),
+                Self::f(&self.chardev_config),
+                Self::f(&self.serial_config),
+                Self::f(&self.monitor_config),
+                Self::f(&self.qmp_config),
             ]
             .into_iter()
-            .filter(|x| x.is_some())
-            .map(|x| x.unwrap())
+            .flatten()
             .collect(),
         }
     }
 }
+
+impl Config {
+    /// Check the config for values QEMU would reject, e.g. a CPU topology
+    /// that does not add up. All problems are reported, not only the first.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        self.accel_config.validate("accel", &mut errors);
+        self.cpu_config.validate("cpu", &mut errors);
+        if let Some(cpu) = &self.cpu_config {
+            cpu.validate_accel("cpu", &self.accelerators(), &mut errors);
+        }
+        if let Some(arch) = self.arch() {
+            if let Some(machine) = &self.machine_config {
+                machine.validate_arch(arch, &mut errors);
+            }
+            if let Some(cpu) = &self.cpu_config {
+                cpu.validate_arch("cpu", arch, &mut errors);
+            }
+        }
+        self.smp_config.validate("smp", &mut errors);
+        self.boot_config.validate("boot", &mut errors);
+        boot::validate_kernel(
+            self.kernel_config.as_ref(),
+            [
+                ("initrd", self.initrd_config.is_some()),
+                ("append", self.append_config.is_some()),
+                ("dtb", self.dtb_config.is_some()),
+            ],
+            &mut errors,
+        );
+        self.m_config.validate("m", &mut errors);
+        self.display_config.validate("display", &mut errors);
+        self.vnc_config.validate("vnc", &mut errors);
+        self.sandbox_config.validate("sandbox", &mut errors);
+        self.run_with_config.validate("run-with", &mut errors);
+        process::validate_guest_name(
+            self.msg_config.as_ref(),
+            self.name_config.as_ref(),
+            &mut errors,
+        );
+        if let Some(overcommit) = &self.overcommit_config {
+            overcommit.validate_accel("overcommit", &self.accelerators(), &mut errors);
+        }
+        self.numa_config.validate("numa", &mut errors);
+        numa::validate_topology(
+            &self.numa_config,
+            self.smp_config.as_ref(),
+            self.m_config.as_ref(),
+            &self.object_config,
+            self.machine_config
+                .as_ref()
+                .and_then(|machine| machine.hmat)
+                == Some(OnOff::On),
+            &mut errors,
+        );
+        self.object_config.validate("object", &mut errors);
+        object::validate_references(&self.object_config, self.object_refs(), &mut errors);
+        self.blockdev_config.validate("blockdev", &mut errors);
+        block::validate_graph(&self.blockdev_config, &mut errors);
+        self.drive_config.validate("drive", &mut errors);
+        self.netdev_config.validate("netdev", &mut errors);
+        network::validate_references(&self.netdev_config, &self.device_config, &mut errors);
+        self.device_config.validate("device", &mut errors);
+        self.audiodev_config.validate("audiodev", &mut errors);
+        audio::validate_references(&self.audiodev_config, &self.device_config, &mut errors);
+        device::validate_references(&self.device_config, &self.block_ids(), &mut errors);
+        pci::validate_addresses(&self.device_config, &mut errors);
+        firmware::validate_pflash(
+            self.machine_config.as_ref(),
+            self.bios_config.as_ref(),
+            &self.block_ids(),
+            &mut errors,
+        );
+        self.chardev_config.validate("chardev", &mut errors);
+        chardev::validate_references(&self.chardev_config, self.chardev_users(), &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// `validate`, and check the machine type against the machines of the
+    /// QEMU binary, e.g. `machine_list::snapshot("x86_64")`.
+    pub fn validate_with(&self, catalog: &MachineCatalog) -> Result<(), Vec<ValidationError>> {
+        let mut errors = self.validate().err().unwrap_or_default();
+        if let Some(machine) = &self.machine_config {
+            machine_list::validate_machine(catalog, machine, &mut errors);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Replace a machine alias such as `q35` by the versioned machine it
+    /// stands for, or select the default machine when there is none, so
+    /// the guest keeps the same hardware when QEMU is upgraded.
+    pub fn pin_machine(&mut self, catalog: &MachineCatalog) -> Result<(), Vec<ValidationError>> {
+        match &mut self.machine_config {
+            Some(machine) => {
+                let mut errors = Vec::new();
+                machine_list::validate_machine(catalog, machine, &mut errors);
+                if !errors.is_empty() {
+                    return Err(errors);
+                }
+                if let Some(name) = catalog.resolve(&machine.m_type) {
+                    machine.m_type = name.to_string();
+                }
+            }
+            None => {
+                self.machine_config = catalog
+                    .default_machine()
+                    .map(|machine| MachineConfig::builder(machine.name.as_str()).build());
+            }
+        }
+        Ok(())
+    }
+
+    /// The target architecture of the QEMU binary, if it is a
+    /// `qemu-system-<arch>` binary this crate knows.
+    pub fn arch(&self) -> Option<Arch> {
+        Arch::from_binary(&self.qemu)
+    }
+
+    /// The accelerators QEMU tries, in order: -accel, else those of
+    /// -machine, else TCG.
+    fn accelerators(&self) -> Vec<Accelerator> {
+        match (&self.accel_config, &self.machine_config) {
+            (Some(accel), _) => vec![accel.name],
+            (
+                None,
+                Some(MachineConfig {
+                    accel: Some(accels),
+                    ..
+                }),
+            ) => accels.clone(),
+            _ => vec![Accelerator::Tcg],
+        }
+    }
+
+    /// Every reference to an -object from other options.
+    fn object_refs(&self) -> impl Iterator<Item = ObjectRef<'_>> {
+        let machine = self.machine_config.iter().filter_map(|machine| {
+            Some(ObjectRef {
+                path: "machine.memory-backend".to_string(),
+                id: machine.memory_backend.as_deref()?,
+                accepts: ObjectType::is_memory_backend,
+                expected: "a memory backend",
+            })
+        });
+        let vnc = self.vnc_config.iter().flat_map(|vnc| {
+            let secret = vnc.password_secret.as_deref().map(|id| ObjectRef {
+                path: "vnc.password-secret".to_string(),
+                id,
+                accepts: |qom_type| *qom_type == ObjectType::Secret,
+                expected: "a secret",
+            });
+            let tls = vnc.tls_creds.as_deref().map(|id| ObjectRef {
+                path: "vnc.tls-creds".to_string(),
+                id,
+                accepts: ObjectType::is_tls_creds,
+                expected: "a tls-creds object",
+            });
+            secret.into_iter().chain(tls)
+        });
+        let nodes = self
+            .numa_config
+            .iter()
+            .enumerate()
+            .filter_map(|(i, numa)| match numa {
+                NumaConfig::Node(node) => Some(ObjectRef {
+                    path: format!("numa[{}].memdev", i),
+                    id: node.memdev.as_deref()?,
+                    accepts: ObjectType::is_memory_backend,
+                    expected: "a memory backend",
+                }),
+                _ => None,
+            });
+        let blockdevs = self
+            .blockdev_config
+            .iter()
+            .enumerate()
+            .filter_map(|(i, blockdev)| {
+                Some(ObjectRef {
+                    path: format!("blockdev[{}].throttle-group", i),
+                    id: blockdev.throttle_group.as_deref()?,
+                    accepts: |qom_type| *qom_type == ObjectType::ThrottleGroup,
+                    expected: "a throttle-group",
+                })
+            });
+        let devices = self
+            .device_config
+            .iter()
+            .enumerate()
+            .flat_map(|(i, device)| {
+                let iothread = device.iothread().map(|id| ObjectRef {
+                    path: format!("device[{}].iothread", i),
+                    id,
+                    accepts: |qom_type| *qom_type == ObjectType::Iothread,
+                    expected: "an iothread",
+                });
+                let rng = device.rng().map(|id| ObjectRef {
+                    path: format!("device[{}].rng", i),
+                    id,
+                    accepts: |qom_type| {
+                        matches!(qom_type, ObjectType::RngRandom | ObjectType::RngBuiltin)
+                    },
+                    expected: "an rng object",
+                });
+                iothread.into_iter().chain(rng)
+            });
+        machine
+            .chain(vnc)
+            .chain(nodes)
+            .chain(blockdevs)
+            .chain(devices)
+    }
+
+    /// Node names of -blockdev and ids of -drive, which devices refer to.
+    fn block_ids(&self) -> Vec<&str> {
+        let nodes = self.blockdev_config.iter().map(|b| b.node_name.as_str());
+        let drives = self.drive_config.iter().filter_map(|d| d.id.as_deref());
+        nodes.chain(drives).collect()
+    }
+
+    /// Every option attached to a chardev, as its path and the chardev id.
+    fn chardev_users(&self) -> impl Iterator<Item = (String, &str)> {
+        fn each<'a, T>(
+            name: &'static str,
+            x: &'a [T],
+            id: impl Fn(&'a T) -> Option<&'a str> + 'a,
+        ) -> impl Iterator<Item = (String, &'a str)> + 'a {
+            x.iter()
+                .enumerate()
+                .filter_map(move |(i, t)| Some((format!("{}[{}]", name, i), id(t)?)))
+        }
+        each("netdev", &self.netdev_config, |n| n.chardev.as_deref())
+            .map(|(path, id)| (path + ".chardev", id))
+            .chain(each("serial", &self.serial_config, |s| Some(&s.0)))
+            .chain(each("monitor", &self.monitor_config, |m| Some(&m.0)))
+            .chain(each("qmp", &self.qmp_config, |q| Some(&q.0)))
+    }
+
+    /// Boot the firmware of `descriptor`, replacing the firmware chosen
+    /// before: -bios, or the pflash0 and pflash1 blockdevs of the machine.
+    /// Flash firmware is attached as those blockdevs; `vars` is the
+    /// variable store of the VM, see `FlashMapping::install_vars`. Firmware
+    /// requiring SMM turns it on. Without -machine, flash firmware gets the
+    /// UEFI machine of the architecture, q35 or virt. The machine must be
+    /// one the descriptor supports.
+    pub fn set_firmware(
+        &mut self,
+        descriptor: &FirmwareDescriptor,
+        vars: Option<&str>,
+    ) -> Result<(), FirmwareError> {
+        if let FirmwareMapping::Flash(flash) = &descriptor.mapping {
+            if flash.template().is_some() && vars.is_none() {
+                return Err(FirmwareError::MissingVars);
+            }
+        }
+        let arch = self.arch();
+        let default_machine = match (&self.machine_config, &descriptor.mapping) {
+            (None, FirmwareMapping::Flash(_)) => {
+                // QEMU's default x86 machine, pc, cannot run secure boot.
+                let m_type = match arch {
+                    Some(Arch::X86_64) => "q35",
+                    Some(Arch::Aarch64 | Arch::Riscv64) => "virt",
+                    _ => return Err(FirmwareError::MissingMachine),
+                };
+                Some(MachineConfig::builder(m_type).build())
+            }
+            _ => None,
+        };
+        let machine = self.machine_config.as_ref().or(default_machine.as_ref());
+        let catalog = arch.and_then(|arch| machine_list::snapshot(arch.as_str()));
+        if let (Some(machine), Some(catalog)) = (machine, catalog) {
+            if !descriptor.supports(&catalog, &machine.m_type) {
+                return Err(FirmwareError::UnsupportedMachine(machine.m_type.clone()));
+            }
+        }
+
+        let secure = GlobalConfig::new("cfi.pflash01", "secure", "on");
+        self.bios_config = None;
+        self.blockdev_config.retain(|blockdev| {
+            !["pflash0", "pflash0-file", "pflash1", "pflash1-file"]
+                .contains(&blockdev.node_name.as_str())
+        });
+        self.global_config.retain(|global| *global != secure);
+        if default_machine.is_some() {
+            self.machine_config = default_machine;
+        }
+        if let Some(machine) = &mut self.machine_config {
+            machine.pflash0 = None;
+            machine.pflash1 = None;
+        }
+
+        let flash = match &descriptor.mapping {
+            FirmwareMapping::Flash(flash) => flash,
+            FirmwareMapping::Memory { filename } => {
+                self.bios_config = Some(BiosConfig::new(filename));
+                return Ok(());
+            }
+            FirmwareMapping::Kernel { filename } => {
+                self.kernel_config = Some(KernelConfig::new(filename));
+                return Ok(());
+            }
+        };
+        for (pflash, node) in flash.blockdevs(vars) {
+            if let Some(machine) = &mut self.machine_config {
+                match pflash {
+                    "pflash0" => machine.pflash0 = Some(node.node_name.clone()),
+                    _ => machine.pflash1 = Some(node.node_name.clone()),
+                }
+            }
+            self.blockdev_config.push(node);
+        }
+        if descriptor.requires_smm() {
+            if let Some(machine) = &mut self.machine_config {
+                machine.smm = Some(OnOffAuto::On);
+            }
+            self.global_config.push(secure);
+        }
+        Ok(())
+    }
+
+    /// Give every PCI device without `bus` and `addr` a place on the bus
+    /// layout of the machine, adding root ports and bridges as needed. The
+    /// addresses are stored in the config, so save it afterwards to keep
+    /// them stable. On q35, `spare_root_ports` empty root ports are kept for
+    /// hotplug.
+    pub fn assign_pci_addresses(&mut self, spare_root_ports: usize) -> Result<(), PciError> {
+        // QEMU defaults to pc for x86.
+        let m_type = self
+            .machine_config
+            .as_ref()
+            .map_or("pc", |machine| machine.m_type.as_str());
+        let layout = PciLayout::from_machine(m_type)
+            .ok_or_else(|| PciError::UnsupportedMachine(m_type.to_string()))?;
+        pci::assign_addresses(layout, &mut self.device_config, spare_root_ports)
+    }
+
+    /// The unix socket of the first QMP monitor that listens on one, for a
+    /// client to connect to once QEMU is running.
+    pub fn qmp_socket_path(&self) -> Option<&str> {
+        self.qmp_config.iter().find_map(|qmp| {
+            self.chardev_config
+                .iter()
+                .find(|chardev| chardev.id == qmp.0)?
+                .unix_server_path()
+        })
+    }
+}
+
+/// Builder for `Config`. Every option takes either the option config itself
+/// or its builder. Repeatable options (`numa`, `add_fd`, `set`, `global`,
+/// `audiodev`, `object`, `blockdev`, `drive`, `netdev`, `device`, `chardev`,
+/// `serial`, `monitor`, `qmp`) append one occurrence per call, e.g.
+///     Config::builder("qemu-system-x86_64")
+///         .machine(MachineConfig::builder("q35").accel(["kvm"]))
+///         .smp(SmpConfig::builder().cpus(4))
+///         .memory(MConfig::builder(2048))
+///         .build()
+#[derive(Debug, Clone)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl Config {
+    pub fn builder(qemu: impl Into<String>) -> ConfigBuilder {
+        ConfigBuilder {
+            config: Config {
+                qemu: qemu.into(),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl ConfigBuilder {
+    pub fn machine(mut self, machine: impl Into<MachineConfig>) -> Self {
+        self.config.machine_config = Some(machine.into());
+        self
+    }
+
+    pub fn cpu(mut self, cpu: impl Into<CpuConfig>) -> Self {
+        self.config.cpu_config = Some(cpu.into());
+        self
+    }
+
+    pub fn accel(mut self, accel: impl Into<AccelConfig>) -> Self {
+        self.config.accel_config = Some(accel.into());
+        self
+    }
+
+    pub fn smp(mut self, smp: impl Into<SmpConfig>) -> Self {
+        self.config.smp_config = Some(smp.into());
+        self
+    }
+
+    pub fn numa(mut self, numa: impl Into<NumaConfig>) -> Self {
+        self.config.numa_config.push(numa.into());
+        self
+    }
+
+    pub fn add_fd(mut self, add_fd: impl Into<AddFdConfig>) -> Self {
+        self.config.add_fd_config.push(add_fd.into());
+        self
+    }
+
+    pub fn set(mut self, set: impl Into<SetConfig>) -> Self {
+        self.config.set_config.push(set.into());
+        self
+    }
+
+    pub fn global(mut self, global: impl Into<GlobalConfig>) -> Self {
+        self.config.global_config.push(global.into());
+        self
+    }
+
+    pub fn boot(mut self, boot: impl Into<BootConfig>) -> Self {
+        self.config.boot_config = Some(boot.into());
+        self
+    }
+
+    pub fn kernel(mut self, kernel: impl Into<KernelConfig>) -> Self {
+        self.config.kernel_config = Some(kernel.into());
+        self
+    }
+
+    pub fn initrd(mut self, initrd: impl Into<InitrdConfig>) -> Self {
+        self.config.initrd_config = Some(initrd.into());
+        self
+    }
+
+    /// The kernel command line, e.g. a `KernelCmdline`.
+    pub fn append(mut self, append: impl Into<AppendConfig>) -> Self {
+        self.config.append_config = Some(append.into());
+        self
+    }
+
+    pub fn dtb(mut self, dtb: impl Into<DtbConfig>) -> Self {
+        self.config.dtb_config = Some(dtb.into());
+        self
+    }
+
+    pub fn bios(mut self, bios: impl Into<BiosConfig>) -> Self {
+        self.config.bios_config = Some(bios.into());
+        self
+    }
+
+    pub fn memory(mut self, memory: impl Into<MConfig>) -> Self {
+        self.config.m_config = Some(memory.into());
+        self
+    }
+
+    pub fn mem_path(mut self, mem_path: impl Into<MemPathConfig>) -> Self {
+        self.config.mem_path_config = Some(mem_path.into());
+        self
+    }
+
+    pub fn mem_prealloc(mut self, mem_prealloc: impl Into<MemPreallocConfig>) -> Self {
+        self.config.mem_prealloc_config = Some(mem_prealloc.into());
+        self
+    }
+
+    pub fn language(mut self, language: impl Into<LanguageConfig>) -> Self {
+        self.config.language_config = Some(language.into());
+        self
+    }
+
+    pub fn display(mut self, display: impl Into<DisplayConfig>) -> Self {
+        self.config.display_config = Some(display.into());
+        self
+    }
+
+    pub fn vnc(mut self, vnc: impl Into<VncConfig>) -> Self {
+        self.config.vnc_config = Some(vnc.into());
+        self
+    }
+
+    pub fn vga(mut self, vga: impl Into<VgaConfig>) -> Self {
+        self.config.vga_config = Some(vga.into());
+        self
+    }
+
+    pub fn audio(mut self, audio: impl Into<AudioConfig>) -> Self {
+        self.config.audio_config = Some(audio.into());
+        self
+    }
+
+    pub fn audiodev(mut self, audiodev: impl Into<AudioDevConfig>) -> Self {
+        self.config.audiodev_config.push(audiodev.into());
+        self
+    }
+
+    pub fn name(mut self, name: impl Into<NameConfig>) -> Self {
+        self.config.name_config = Some(name.into());
+        self
+    }
+
+    pub fn uuid(mut self, uuid: impl Into<UuidConfig>) -> Self {
+        self.config.uuid_config = Some(uuid.into());
+        self
+    }
+
+    pub fn sandbox(mut self, sandbox: impl Into<SandboxConfig>) -> Self {
+        self.config.sandbox_config = Some(sandbox.into());
+        self
+    }
+
+    pub fn run_with(mut self, run_with: impl Into<RunWithConfig>) -> Self {
+        self.config.run_with_config = Some(run_with.into());
+        self
+    }
+
+    pub fn msg(mut self, msg: impl Into<MsgConfig>) -> Self {
+        self.config.msg_config = Some(msg.into());
+        self
+    }
+
+    pub fn overcommit(mut self, overcommit: impl Into<OvercommitConfig>) -> Self {
+        self.config.overcommit_config = Some(overcommit.into());
+        self
+    }
+
+    pub fn object(mut self, object: impl Into<ObjectConfig>) -> Self {
+        self.config.object_config.push(object.into());
+        self
+    }
+
+    pub fn blockdev(mut self, blockdev: impl Into<BlockdevConfig>) -> Self {
+        self.config.blockdev_config.push(blockdev.into());
+        self
+    }
+
+    pub fn drive(mut self, drive: impl Into<DriveConfig>) -> Self {
+        self.config.drive_config.push(drive.into());
+        self
+    }
+
+    pub fn netdev(mut self, netdev: impl Into<NetdevConfig>) -> Self {
+        self.config.netdev_config.push(netdev.into());
+        self
+    }
+
+    pub fn device(mut self, device: impl Into<DeviceConfig>) -> Self {
+        self.config.device_config.push(device.into());
+        self
+    }
+
+    pub fn chardev(mut self, chardev: impl Into<ChardevConfig>) -> Self {
+        self.config.chardev_config.push(chardev.into());
+        self
+    }
+
+    pub fn serial(mut self, serial: impl Into<SerialConfig>) -> Self {
+        self.config.serial_config.push(serial.into());
+        self
+    }
+
+    pub fn monitor(mut self, monitor: impl Into<MonitorConfig>) -> Self {
+        self.config.monitor_config.push(monitor.into());
+        self
+    }
+
+    pub fn qmp(mut self, qmp: impl Into<QmpConfig>) -> Self {
+        self.config.qmp_config.push(qmp.into());
+        self
+    }
+
+    /// A sound card with the given id playing through the audiodev with the
+    /// given id, see `SoundCard::devices`.
+    pub fn sound(mut self, card: SoundCard, id: &str, audiodev: &str) -> Self {
+        self.config.device_config.extend(card.devices(id, audiodev));
+        self
+    }
+
+    /// A QMP monitor listening on the unix socket `path`, through a socket
+    /// chardev with the given id.
+    pub fn qmp_socket(self, id: impl Into<String>, path: impl Into<String>) -> Self {
+        let id = id.into();
+        self.chardev(
+            ChardevConfig::unix_socket(id.clone(), path)
+                .server(true)
+                .wait(false),
+        )
+        .qmp(QmpConfig::new(id))
+    }
+
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
+
+/// A `Config` recovered from a command line, together with the options it
+/// does not model.
+#[derive(Debug)]
+pub struct ParsedConfig<'a> {
+    pub config: Config,
+    pub unrecognised: Vec<&'a OptionQ<'a>>,
+}
+
+impl Config {
+    #[inline]
+    fn p<'a, T: OptionParsing>(
+        x: &mut Option<T>,
+        option: &'a OptionQ<'a>,
+        unrecognised: &mut Vec<&'a OptionQ<'a>>,
+    ) -> Result<(), ParseError> {
+        // Only one occurrence fits in the config, later ones are reported.
+        if x.is_some() {
+            unrecognised.push(option);
+        } else {
+            *x = Some(T::parsing(option)?);
+        }
+        Ok(())
+    }
+
+    /// Parse an option that may be given many times, keeping their order.
+    #[inline]
+    fn r<T: OptionParsing>(x: &mut Vec<T>, option: &OptionQ<'_>) -> Result<(), ParseError> {
+        x.push(T::parsing(option)?);
+        Ok(())
+    }
+
+    /// Rebuild a `Config` from a command split by `parse_args`, e.g.
+    ///     let argv = ["qemu-system-x86_64", "-smp", "2"];
+    ///     let command = parse_args(&argv)?;
+    ///     let parsed = Config::from_command(&command)?;
+    ///
+    /// Options that appear once in a `Config` keep their first occurrence
+    /// and report later ones in `unrecognised`. QEMU merges the keys of a
+    /// repeated -machine or -overcommit and tries a repeated -accel in
+    /// order, neither of which is modelled here, so callers that need them
+    /// must handle the reported occurrences themselves.
+    pub fn from_command<'a>(command: &'a CommandQ<'a>) -> Result<ParsedConfig<'a>, ParseError> {
+        let mut config = Config {
+            qemu: command.raw.to_string(),
+            ..Default::default()
+        };
+        let mut unrecognised = Vec::new();
+        for option in &command.options {
+            let u = &mut unrecognised;
+            match (option.prefix, option.raw) {
+                ("-", "machine" | "M") => Self::p(&mut config.machine_config, option, u)?,
+                ("-", "cpu") => Self::p(&mut config.cpu_config, option, u)?,
+                ("-", "accel") => Self::p(&mut config.accel_config, option, u)?,
+                ("-", "smp") => Self::p(&mut config.smp_config, option, u)?,
+                ("-", "numa") => Self::r(&mut config.numa_config, option)?,
+                ("-", "add-fd") => Self::r(&mut config.add_fd_config, option)?,
+                ("-", "set") => Self::r(&mut config.set_config, option)?,
+                ("-", "global") => Self::r(&mut config.global_config, option)?,
+                ("-", "boot") => Self::p(&mut config.boot_config, option, u)?,
+                ("-", "kernel") => Self::p(&mut config.kernel_config, option, u)?,
+                ("-", "initrd") => Self::p(&mut config.initrd_config, option, u)?,
+                ("-", "append") => Self::p(&mut config.append_config, option, u)?,
+                ("-", "dtb") => Self::p(&mut config.dtb_config, option, u)?,
+                ("-", "bios") => Self::p(&mut config.bios_config, option, u)?,
+                ("-", "m") => Self::p(&mut config.m_config, option, u)?,
+                ("-", "mem-path") => Self::p(&mut config.mem_path_config, option, u)?,
+                ("-", "mem-prealloc") => Self::p(&mut config.mem_prealloc_config, option, u)?,
+                ("-", "k") => Self::p(&mut config.language_config, option, u)?,
+                ("-", "display") => Self::p(&mut config.display_config, option, u)?,
+                ("-", "vnc") => Self::p(&mut config.vnc_config, option, u)?,
+                ("-", "vga") => Self::p(&mut config.vga_config, option, u)?,
+                ("-", "audio") => Self::p(&mut config.audio_config, option, u)?,
+                ("-", "audiodev") => Self::r(&mut config.audiodev_config, option)?,
+                ("-", "name") => Self::p(&mut config.name_config, option, u)?,
+                ("-", "uuid") => Self::p(&mut config.uuid_config, option, u)?,
+                ("-", "sandbox") => Self::p(&mut config.sandbox_config, option, u)?,
+                ("-", "run-with") => Self::p(&mut config.run_with_config, option, u)?,
+                ("-", "msg") => Self::p(&mut config.msg_config, option, u)?,
+                ("-", "overcommit") => Self::p(&mut config.overcommit_config, option, u)?,
+                ("-", "object") if ObjectConfig::matches(option) => {
+                    Self::r(&mut config.object_config, option)?
+                }
+                ("-", "blockdev") if BlockdevConfig::matches(option) => {
+                    Self::r(&mut config.blockdev_config, option)?
+                }
+                ("-", "drive") if DriveConfig::matches(option) => {
+                    Self::r(&mut config.drive_config, option)?
+                }
+                ("-", "netdev") if NetdevConfig::matches(option) => {
+                    Self::r(&mut config.netdev_config, option)?
+                }
+                ("-", "device") if DeviceConfig::matches(option) => {
+                    Self::r(&mut config.device_config, option)?
+                }
+                ("-", "chardev") => Self::r(&mut config.chardev_config, option)?,
+                ("-", "serial") if chardev::is_chardev_spec(option) => {
+                    Self::r(&mut config.serial_config, option)?
+                }
+                ("-", "monitor") if chardev::is_chardev_spec(option) => {
+                    Self::r(&mut config.monitor_config, option)?
+                }
+                ("-", "qmp") if chardev::is_chardev_spec(option) => {
+                    Self::r(&mut config.qmp_config, option)?
+                }
+                _ => unrecognised.push(option),
+            }
+        }
+        Ok(ParsedConfig {
+            config,
+            unrecognised,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::configuration::general::{
+        audio::*, block::*, boot::*, chardev::*, device::*, display::*, firmware::*, machine::*,
+        network::*, numa::*, object::*, process::*, types::*,
+    };
+    use serde::de::DeserializeOwned;
+    use std::{collections::HashMap, fs, thread};
+
+    #[test]
+    fn test_round_trip() {
+        let json = fs::read_to_string("./test_json/full.json").unwrap();
+        let config: Config = serde_json::from_str(&json).unwrap();
+
+        let argv: Vec<String> = std::iter::once(config.qemu.to_string())
+            .chain(
+                config
+                    .to_args()
+                    .into_iter()
+                    .map(|arg| arg.into_string().unwrap()),
+            )
+            .collect();
+        let command = parse_args(&argv).unwrap();
+        let parsed = Config::from_command(&command).unwrap();
+
+        assert!(parsed.unrecognised.is_empty());
+        assert_eq!(parsed.config, config);
+    }
+
+    #[test]
+    fn test_parse_script() {
+        let argv = [
+            "qemu-system-x86_64",
+            "-enable-kvm",
+            "-M",
+            "pc,accel=kvm:tcg",
+            "-smp",
+            "2",
+            "-m",
+            "768,slots=2,maxmem=4G",
+            "-name",
+            "guest=vm,,1",
+            "-nographic",
+            "disk.img",
+        ];
+        let command = parse_args(&argv).unwrap();
+        let parsed = Config::from_command(&command).unwrap();
+
+        let machine = parsed.config.machine_config.unwrap();
+        assert_eq!(machine.m_type, "pc");
+        assert_eq!(
+            machine.accel,
+            Some(vec![Accelerator::Kvm, Accelerator::Tcg])
+        );
+        assert_eq!(parsed.config.smp_config.unwrap().cpus, Some(2));
+        let m_config = parsed.config.m_config.unwrap();
+        assert_eq!(m_config.size, ByteSize::mib(768));
+        assert_eq!(m_config.maxmem, Some(ByteSize::gib(4)));
+        assert_eq!(
+            parsed
+                .unrecognised
+                .iter()
+                .map(|option| option.raw)
+                .collect::<Vec<_>>(),
+            vec!["enable-kvm", "nographic", "disk.img"]
+        );
+    }
+
+    #[test]
+    fn test_parse_unmodelled() {
+        let argv = [
+            "qemu-system-x86_64",
+            "-blockdev",
+            "driver=qcow2,node-name=d0,file.driver=file,file.filename=/x.qcow2",
+            "-blockdev",
+            r#"{"driver":"file","node-name":"d1","filename":"/y.img"}"#,
+            "-blockdev",
+            "driver=nbd,node-name=d2,server.type=inet,server.host=nas,export=d2",
+            "-blockdev",
+            "driver=file,node-name=d3,filename=/z.img,cache.direct=on",
+            "-drive",
+            "file.driver=file,file.filename=/x.img,if=none,id=d4",
+            "-drive",
+            "file=/x.vmdk,format=vmdk,if=none,id=d5",
+            "-netdev",
+            r#"{"type":"user","id":"net0"}"#,
+            "-netdev",
+            "user,id=net1",
+            "-device",
+            r#"{"driver":"virtio-blk-pci","drive":"d0"}"#,
+            "-device",
+            "virtio-blk-pci,drive=d3",
+        ];
+        let command = parse_args(&argv).unwrap();
+        let parsed = Config::from_command(&command).unwrap();
+
+        assert_eq!(parsed.config.blockdev_config.len(), 1);
+        assert!(parsed.config.drive_config.is_empty());
+        assert_eq!(parsed.config.netdev_config.len(), 1);
+        assert_eq!(parsed.config.device_config.len(), 1);
+        assert_eq!(
+            parsed
+                .unrecognised
+                .iter()
+                .map(|option| option.to_string())
+                .collect::<Vec<_>>(),
+            [
+                &argv[1..3],
+                &argv[3..5],
+                &argv[5..7],
+                &argv[9..11],
+                &argv[11..13],
+                &argv[13..15],
+                &argv[17..19],
+            ]
+            .map(|option| option.join(" "))
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let argv = ["qemu-system-x86_64", "-smp", "cpus=2,sokcets=1"];
+        let command = parse_args(&argv).unwrap();
+        assert_eq!(
+            Config::from_command(&command).unwrap_err(),
+            ParseError::UnknownKey {
+                option: "smp".to_string(),
+                key: "sokcets".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_builder() {
+        let config = Config::builder("qemu-system-x86_64")
+            .machine(
+                MachineConfig::builder("pc-q35-8.2")
+                    .accel([Accelerator::Kvm, Accelerator::Tcg])
+                    .vmport(OnOffAuto::Off)
+                    .dump_guest_core(false)
+                    .mem_merge(true)
+                    .nvdimm(true)
+                    .hmat(true),
+            )
+            .cpu(
+                CpuConfig::builder("Skylake-Server-v4")
+                    .enable("avx512f")
+                    .disable("hle")
+                    .l3_cache(true),
+            )
+            .accel(
+                AccelConfig::builder(Accelerator::Kvm)
+                    .kernel_irqchip(KernelIrqchip::Split)
+                    .dirty_ring_size(4096),
+            )
+            .smp(
+                SmpConfig::builder()
+                    .cpus(4)
+                    .maxcpus(8)
+                    .sockets(2)
+                    .dies(1)
+                    .cores(2)
+                    .threads(2),
+            )
+            .numa(
+                NumaNodeConfig::builder()
+                    .nodeid(0)
+                    .cpus(0..=3)
+                    .memdev("ram-node0"),
+            )
+            .numa(
+                NumaNodeConfig::builder()
+                    .nodeid(1)
+                    .cpus(4..=7)
+                    .memdev("ram-node1"),
+            )
+            .numa(NumaDistConfig::new(0, 1, 20))
+            .numa(
+                NumaHmatLbConfig::builder(0, 1, HmatHierarchy::Memory, HmatDataType::AccessLatency)
+                    .latency(20),
+            )
+            .add_fd(AddFdConfig::builder(3, 2).opaque("rdwr:/path/to/file"))
+            .set(SetConfig::new("netdev", "net0", "vhost", "on"))
+            .global(GlobalConfig::new("ide-hd", "physical_block_size", "4096"))
+            .global(GlobalConfig::new("ide-hd", "logical_block_size", "512"))
+            .boot(
+                BootConfig::builder()
+                    .order([BootDevice::Disk, BootDevice::Cdrom])
+                    .menu(true)
+                    .strict(true),
+            )
+            .memory(
+                MConfig::builder(ByteSize::gib(2))
+                    .slots(2)
+                    .maxmem(ByteSize::gib(8)),
+            )
+            .mem_path(MemPathConfig::new("/dev/hugepages"))
+            .mem_prealloc(MemPreallocConfig::new())
+            .language(LanguageConfig::new("en-us"))
+            .display(DisplayConfig::none())
+            .vnc(
+                VncConfig::unix("/run/qemu/vnc.sock")
+                    .password_secret("vnc-pw")
+                    .share(VncShare::ForceShared),
+            )
+            .vga(VgaConfig::new(VgaType::None))
+            .audiodev(AudioDevConfig::pipewire("snd0").output(AudiodevOut {
+                stream_name: Some("vm".to_string()),
+                latency: Some(20000),
+                ..Default::default()
+            }))
+            .name(
+                NameConfig::builder("test, machine")
+                    .process("qemu-test")
+                    .debug_threads(OnOff::On),
+            )
+            .uuid(UuidConfig::new("12345678-1234-1234-1234-123456789abc"))
+            .sandbox(SandboxConfig::strict())
+            .run_with(RunWithConfig::builder().chroot("/var/empty").user("qemu"))
+            .msg(MsgConfig::builder().timestamp(true).guest_name(true))
+            .overcommit(OvercommitConfig::builder().mem_lock(true))
+            .object(ObjectConfig::iothread("iothread0").poll_max_ns(32768))
+            .object(ObjectConfig::rng_random("rng0", "/dev/urandom"))
+            .object(ObjectConfig::secret_file("vnc-pw", "/run/qemu/vnc-pw"))
+            .object(ObjectConfig::memory_backend_ram(
+                "ram-node0",
+                ByteSize::gib(1),
+            ))
+            .object(ObjectConfig::memory_backend_ram(
+                "ram-node1",
+                ByteSize::gib(1),
+            ))
+            .blockdev(
+                BlockdevConfig::file("disk0-file", "/images/disk0.qcow2")
+                    .cache_direct(true)
+                    .aio(Aio::Native),
+            )
+            .blockdev(
+                BlockdevConfig::qcow2("disk0", "disk0-file")
+                    .discard(Discard::Unmap)
+                    .detect_zeroes(DetectZeroes::Unmap),
+            )
+            .drive(
+                DriveConfig::builder()
+                    .file("/images/install.iso")
+                    .id("cd0")
+                    .interface(DriveInterface::Ide)
+                    .media(Media::Cdrom)
+                    .read_only(true),
+            )
+            .netdev(NetdevConfig::user("net0").hostfwd(HostFwd::tcp(2222, 22)))
+            .device(VirtioNetPciConfig::builder("net0").mac("52:54:00:12:34:56"))
+            .device(VirtioBlkPciConfig::builder("disk0").iothread("iothread0"))
+            .device(VirtioRngPciConfig::builder().rng("rng0"))
+            .device(VirtioGpuPciConfig::builder().xres(1920).yres(1080))
+            .device(GenericDeviceConfig::new("usb-tablet").set("port", 1))
+            .sound(SoundCard::Ich9IntelHda, "hda0", "snd0")
+            .chardev(ChardevConfig::pty("serial0"))
+            .serial(SerialConfig::new("serial0"))
+            .qmp_socket("qmp0", "/run/qemu/qmp.sock")
+            .build();
+
+        let json = fs::read_to_string("./test_json/full.json").unwrap();
+        let expected: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(config, expected);
+        assert_eq!(config.qmp_socket_path(), Some("/run/qemu/qmp.sock"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let json = fs::read_to_string("./test_json/full.json").unwrap();
+        let config: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(config.validate(), Ok(()));
+
+        let config = Config::builder("qemu-system-x86_64")
+            .accel(AccelConfig::builder(Accelerator::Kvm).dirty_ring_size(1000))
+            .smp(
+                SmpConfig::builder()
+                    .cpus(8)
+                    .maxcpus(6)
+                    .sockets(2)
+                    .cores(2)
+                    .threads(2),
+            )
+            .memory(MConfig::builder(ByteSize::gib(2)).slots(2))
+            .build();
+        let errors = config.validate().unwrap_err();
+        assert_eq!(
+            errors
+                .iter()
+                .map(|e| (e.path.as_str(), &e.kind))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "accel.dirty-ring-size",
+                    &ValidationErrorKind::NotPowerOfTwo {
+                        value: 1000,
+                        min: 1024
+                    }
+                ),
+                (
+                    "smp.cpus",
+                    &ValidationErrorKind::CpusAboveMaxcpus {
+                        cpus: 8,
+                        maxcpus: 6
+                    }
+                ),
+                (
+                    "smp.maxcpus",
+                    &ValidationErrorKind::TopologyMismatch {
+                        topology: 8,
+                        maxcpus: 6
+                    }
+                ),
+                ("m.slots", &ValidationErrorKind::Requires { key: "maxmem" }),
+            ]
+        );
+
+        let config = Config::builder("qemu-system-x86_64")
+            .memory(
+                MConfig::builder(ByteSize::gib(2))
+                    .slots(2)
+                    .maxmem(ByteSize::gib(1)),
+            )
+            .build();
+        assert_eq!(
+            config.validate().unwrap_err()[0].to_string(),
+            "m.maxmem: maxmem=1G is below size=2G"
+        );
+
+        let config = Config::builder("qemu-system-x86_64")
+            .vnc(
+                VncConfig::tcp("0.0.0.0", 0)
+                    .password_secret("vnc-pw")
+                    .tls_creds("vnc-pw"),
+            )
+            .object(ObjectConfig::secret_file("vnc-pw", "/run/vnc-pw"))
+            .build();
+        assert_eq!(
+            config.validate().unwrap_err()[0].to_string(),
+            "vnc.tls-creds: expected a tls-creds object"
+        );
+    }
+
+    #[test]
+    fn test_machine_catalog() {
+        let catalog = machine_list::snapshot("x86_64").unwrap();
+        let mut config = Config::builder("qemu-system-x86_64")
+            .machine(MachineConfig::builder("q35").accel([Accelerator::Kvm]))
+            .build();
+        assert_eq!(config.validate_with(&catalog), Ok(()));
+        config.pin_machine(&catalog).unwrap();
+        assert_eq!(config.machine_config.as_ref().unwrap().m_type, "pc-q35-8.2");
+
+        let mut config = Config::builder("qemu-system-x86_64").build();
+        config.pin_machine(&catalog).unwrap();
+        assert_eq!(config.machine_config.unwrap().m_type, "pc-i440fx-8.2");
+
+        let mut config = Config::builder("qemu-system-x86_64")
+            .machine(MachineConfig::builder("virt"))
+            .build();
+        let errors = config.pin_machine(&catalog).unwrap_err();
+        assert_eq!(config.validate_with(&catalog), Err(errors.clone()));
+        assert_eq!(
+            errors[0].to_string(),
+            "machine.type: not supported by qemu-system-x86_64"
+        );
+    }
+
+    #[test]
+    fn test_arch() {
+        let config = Config::builder("/usr/bin/qemu-system-aarch64")
+            .machine(
+                MachineConfig::builder("virt")
+                    .accel([Accelerator::Kvm])
+                    .gic_version(GicVersion::Host)
+                    .highmem(true),
+            )
+            .cpu(CpuConfig::host().pmu(true))
+            .build();
+        assert_eq!(config.arch(), Some(Arch::Aarch64));
+        assert_eq!(
+            config.to_args()[..2],
+            [
+                "-machine",
+                "type=virt,accel=kvm,gic-version=host,highmem=on"
+            ]
+        );
+        let catalog = machine_list::snapshot("aarch64").unwrap();
+        assert_eq!(config.validate_with(&catalog), Ok(()));
+
+        let config = Config::builder("qemu-system-riscv64")
+            .machine(
+                MachineConfig::builder("virt")
+                    .aia(RiscvAia::AplicImsic)
+                    .aes_key_wrap(true)
+                    .smm(false),
+            )
+            .cpu(CpuConfig::builder("Skylake-Server-v4").l3_cache(true))
+            .build();
+        assert_eq!(
+            config
+                .validate()
+                .unwrap_err()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                "machine.aes-key-wrap: not supported by qemu-system-riscv64",
+                "machine.smm: not supported by qemu-system-riscv64",
+                "cpu.model: not supported by qemu-system-riscv64",
+                "cpu.l3-cache: not supported by qemu-system-riscv64",
+            ]
+        );
+
+        let config = Config::builder("qemu-system-s390x")
+            .machine(
+                MachineConfig::builder("s390-ccw-virtio")
+                    .aes_key_wrap(true)
+                    .dea_key_wrap(false)
+                    .sgx_epc_0_memdev(0),
+            )
+            .cpu(CpuConfig::builder("z14-base"))
+            .build();
+        assert_eq!(
+            config.validate().unwrap_err()[0].to_string(),
+            "machine.sgx-epc.0.memdev: not supported by qemu-system-s390x"
+        );
+
+        // Other targets take CPU features as name=value, kept as written.
+        for (arch, cpu) in [
+            ("aarch64", "max,sve=on,sve256=on"),
+            ("riscv64", "rv64,v=true,vlen=256"),
+        ] {
+            let qemu = format!("qemu-system-{}", arch);
+            let argv = [qemu.as_str(), "-machine", "virt", "-cpu", cpu];
+            let command = parse_args(&argv).unwrap();
+            let config = Config::from_command(&command).unwrap().config;
+            assert_eq!(config.to_args()[3], cpu);
+            assert_eq!(config.validate(), Ok(()));
+        }
+        let config = Config::builder("qemu-system-aarch64")
+            .machine(MachineConfig::builder("virt"))
+            .cpu(CpuConfig::max().enable("sve"))
+            .build();
+        assert_eq!(
+            config.validate().unwrap_err()[0].to_string(),
+            "cpu.features: not supported by qemu-system-aarch64"
+        );
+
+        // Binaries that are not qemu-system-<arch> are not checked.
+        let config = Config::builder("qemu-kvm")
+            .machine(MachineConfig::builder("pseries").highmem(false))
+            .build();
+        assert_eq!(config.arch(), None);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_direct_kernel() {
+        let config = Config::builder("qemu-system-x86_64")
+            .kernel(KernelConfig::new("/ci/bzImage"))
+            .initrd(InitrdConfig::new("/ci/initrd.img"))
+            .append(
+                KernelCmdline::new()
+                    .console("ttyS0,115200")
+                    .root("/dev/vda1")
+                    .rw(),
+            )
+            .build();
+        let args = config.to_args();
+        assert_eq!(
+            args,
+            [
+                "-kernel",
+                "/ci/bzImage",
+                "-initrd",
+                "/ci/initrd.img",
+                "-append",
+                "console=ttyS0,115200 root=/dev/vda1 rw",
+            ]
+        );
+        let argv = std::iter::once("qemu-system-x86_64")
+            .chain(args.iter().map(|arg| arg.to_str().unwrap()))
+            .collect::<Vec<_>>();
+        let command = parse_args(&argv).unwrap();
+        assert_eq!(Config::from_command(&command).unwrap().config, config);
+        assert_eq!(config.validate(), Ok(()));
+
+        let config = Config::builder("qemu-system-x86_64")
+            .append(AppendConfig::new("console=ttyS0"))
+            .build();
+        assert_eq!(
+            config.validate().unwrap_err()[0].to_string(),
+            "append: requires 'kernel'"
+        );
+    }
+
+    #[test]
+    fn test_firmware() {
+        let descriptors = FirmwareDescriptor::load_dir("./test_json/firmware").unwrap();
+        let catalog = machine_list::snapshot("x86_64").unwrap();
+        let secure = FirmwareDescriptor::select_uefi(&descriptors, &catalog, "q35", true).unwrap();
+        let mut config = Config::builder("qemu-system-x86_64")
+            .machine(MachineConfig::builder("q35"))
+            .build();
+        assert!(matches!(
+            config.set_firmware(secure, None),
+            Err(FirmwareError::MissingVars)
+        ));
+        config
+            .set_firmware(secure, Some("/var/lib/vms/vm-1/VARS.fd"))
+            .unwrap();
+        // Firmware chosen again replaces the first one.
+        config
+            .set_firmware(secure, Some("/var/lib/vms/vm-1/VARS.fd"))
+            .unwrap();
+        assert_eq!(
+            config.to_args(),
+            [
+                "-machine",
+                "type=q35,smm=on,pflash0=pflash0,pflash1=pflash1",
+                "-global",
+                "driver=cfi.pflash01,property=secure,value=on",
+                "-blockdev",
+                "driver=file,node-name=pflash0,read-only=on,filename=/usr/share/edk2/x64/OVMF_CODE.secboot.4m.fd",
+                "-blockdev",
+                "driver=file,node-name=pflash1,read-only=off,filename=/var/lib/vms/vm-1/VARS.fd",
+            ]
+        );
+        assert_eq!(config.validate(), Ok(()));
+
+        // SeaBIOS replaces the flash firmware, and flash firmware replaces
+        // it in turn.
+        config.set_firmware(&descriptors[2], None).unwrap();
+        assert_eq!(
+            config.to_args(),
+            [
+                "-machine",
+                "type=q35,smm=on",
+                "-bios",
+                "/usr/share/seabios/bios-256k.bin"
+            ]
+        );
+        assert_eq!(config.validate(), Ok(()));
+        config
+            .set_firmware(&descriptors[1], Some("/var/lib/vms/vm-1/VARS.fd"))
+            .unwrap();
+        assert_eq!(config.bios_config, None);
+        assert_eq!(config.global_config, []);
+        assert_eq!(config.validate(), Ok(()));
+
+        // Without -machine, flash firmware gets q35 rather than QEMU's pc.
+        let mut config = Config::builder("qemu-system-x86_64").build();
+        config
+            .set_firmware(secure, Some("/var/lib/vms/vm-1/VARS.fd"))
+            .unwrap();
+        assert_eq!(
+            config.machine_config.as_ref().map(|m| m.m_type.as_str()),
+            Some("q35")
+        );
+        assert_eq!(config.validate(), Ok(()));
+
+        let mut config = Config::builder("qemu-system-x86_64")
+            .machine(MachineConfig::builder("pc"))
+            .build();
+        assert!(matches!(
+            config.set_firmware(secure, Some("/var/lib/vms/vm-1/VARS.fd")),
+            Err(FirmwareError::UnsupportedMachine(machine)) if machine == "pc"
+        ));
+        assert_eq!(config.global_config, []);
+        let mut config = Config::builder("qemu-kvm").build();
+        assert!(matches!(
+            config.set_firmware(&descriptors[1], Some("/var/lib/vms/vm-1/VARS.fd")),
+            Err(FirmwareError::MissingMachine)
+        ));
+    }
+
+    #[test]
+    fn test_repeated_options() {
+        // A single object is still accepted where a list is expected.
+        let config: Config = serde_json::from_str(
+            r#"{
+                "qemu": "qemu-system-x86_64",
+                "add-fd": {"fd": 3, "set": 2},
+                "global": [
+                    {"driver": "ide-hd", "property": "physical_block_size", "value": "4096"},
+                    {"driver": "cfi.pflash01", "property": "secure", "value": "on"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.add_fd_config,
+            vec![AddFdConfig::builder(3, 2).build()]
+        );
+        assert_eq!(config.global_config.len(), 2);
+        assert_eq!(
+            config.to_args(),
+            [
+                "-add-fd",
+                "fd=3,set=2",
+                "-global",
+                "driver=ide-hd,property=physical_block_size,value=4096",
+                "-global",
+                "driver=cfi.pflash01,property=secure,value=on",
+            ]
+        );
+
+        let json = serde_json::to_value(&config).unwrap();
+        assert!(json["add-fd"].is_array());
+        assert!(json.get("set").is_none());
+
+        let argv = [
+            "qemu-system-x86_64",
+            "-global",
+            "ide-hd.physical_block_size=4096",
+            "-add-fd",
+            "fd=3,set=2",
+            "-global",
+            "driver=cfi.pflash01,property=secure,value=on",
+        ];
+        let command = parse_args(&argv).unwrap();
+        let parsed = Config::from_command(&command).unwrap();
+        assert!(parsed.unrecognised.is_empty());
+        assert_eq!(parsed.config, config);
+    }
+
+    #[test]
+    fn test_owned_across_threads() {
+        fn assert_owned<T: DeserializeOwned + Send + Sync + 'static>() {}
+        assert_owned::<Config>();
+
+        let config = crate::utils::read_json_from_file("./test_json/full.json").unwrap();
+        let expected = config.to_args();
+
+        let mut vms = HashMap::new();
+        vms.insert("vm-1".to_string(), config);
+        let handle = thread::spawn(move || vms.remove("vm-1").unwrap().to_args());
+        assert_eq!(handle.join().unwrap(), expected);
+    }
+}
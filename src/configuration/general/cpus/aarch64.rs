@@ -0,0 +1,55 @@
+//! CPU models of qemu-system-aarch64, as listed by `-cpu help`. Arm
+//! features such as SVE are switched with `sve=on|off`, e.g.
+//!     -cpu max,sve=on,sve256=on
+//!     -cpu neoverse-n1
+
+/// The models of QEMU 8.2, including the 32-bit cores of the Arm boards.
+pub const CPU_MODELS: &[&str] = &[
+    "a64fx",
+    "arm1026",
+    "arm1136",
+    "arm1136-r2",
+    "arm1176",
+    "arm11mpcore",
+    "arm926",
+    "arm946",
+    "cortex-a15",
+    "cortex-a35",
+    "cortex-a53",
+    "cortex-a55",
+    "cortex-a57",
+    "cortex-a7",
+    "cortex-a710",
+    "cortex-a72",
+    "cortex-a76",
+    "cortex-a8",
+    "cortex-a9",
+    "cortex-m0",
+    "cortex-m3",
+    "cortex-m33",
+    "cortex-m4",
+    "cortex-m55",
+    "cortex-m7",
+    "cortex-r5",
+    "cortex-r52",
+    "cortex-r5f",
+    "host",
+    "max",
+    "neoverse-n1",
+    "neoverse-n2",
+    "neoverse-v1",
+    "pxa250",
+    "pxa255",
+    "pxa260",
+    "pxa261",
+    "pxa262",
+    "pxa270",
+    "sa1100",
+    "sa1110",
+    "ti925t",
+];
+
+/// Whether qemu-system-aarch64 has the model.
+pub fn is_model(model: &str) -> bool {
+    CPU_MODELS.contains(&model)
+}
@@ -1 +1,605 @@
-pub mod x86_64;
\ No newline at end of file
+//! Type of the vCPUs. Most accelerators offer a host CPU model which
+//! passes the host CPU through without filtering out any features. Named
+//! models such as Skylake-Server-v4 give a guest CPU that stays the same
+//! on every host, so the guest can be migrated. The models of each
+//! architecture are listed in its submodule, e.g.
+//!     -cpu host,migratable=off,host-phys-bits=on
+//!     -cpu Skylake-Server-v4,+avx512f,-hle,pmu=off
+//!     -cpu max,sve=on,sve256=on
+pub mod aarch64;
+pub mod ppc64;
+pub mod riscv64;
+pub mod s390x;
+pub mod x86_64;
+
+use std::{error::Error, fmt, str::FromStr};
+
+use crate::command::{builder::*, parser::*};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{
+    types::{Accelerator, Arch, OnOff},
+    validate::*,
+};
+
+/// Whether the QEMU binary of the architecture has the CPU model.
+pub fn is_model(arch: Arch, model: &str) -> bool {
+    match arch {
+        Arch::X86_64 => x86_64::is_model(model),
+        Arch::Aarch64 => aarch64::is_model(model),
+        Arch::Riscv64 => riscv64::is_model(model),
+        Arch::S390x => s390x::is_model(model),
+        Arch::Ppc64 => ppc64::is_model(model),
+    }
+}
+
+/// A CPU feature turned on or off on top of the model, written as `+avx2`
+/// or `-hle`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CpuFeature {
+    pub name: String,
+    pub enabled: bool,
+}
+
+impl CpuFeature {
+    pub fn enable(name: impl Into<String>) -> Self {
+        CpuFeature {
+            name: name.into(),
+            enabled: true,
+        }
+    }
+
+    pub fn disable(name: impl Into<String>) -> Self {
+        CpuFeature {
+            name: name.into(),
+            enabled: false,
+        }
+    }
+
+    /// Whether `name` can be a feature, i.e. it would not be read as
+    /// another argument.
+    pub(crate) fn is_name(name: &str) -> bool {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    }
+}
+
+/// A CPU feature that cannot be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidCpuFeature(pub String);
+
+impl fmt::Display for InvalidCpuFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid CPU feature '{}', expected +name or -name",
+            self.0
+        )
+    }
+}
+
+impl Error for InvalidCpuFeature {}
+
+impl fmt::Display for CpuFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.enabled { '+' } else { '-' };
+        write!(f, "{}{}", sign, self.name)
+    }
+}
+
+impl FromStr for CpuFeature {
+    type Err = InvalidCpuFeature;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (enabled, name) = match s.split_at_checked(1) {
+            Some(("+", name)) => (true, name),
+            Some(("-", name)) => (false, name),
+            _ => return Err(InvalidCpuFeature(s.to_string())),
+        };
+        if !Self::is_name(name) {
+            return Err(InvalidCpuFeature(s.to_string()));
+        }
+        Ok(CpuFeature {
+            name: name.to_string(),
+            enabled,
+        })
+    }
+}
+
+impl Serialize for CpuFeature {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for CpuFeature {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// A CPU property this crate does not model, kept as written, e.g.
+/// `hv-spinlocks=0x1fff` or `tsc-frequency=2000000000`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CpuProp {
+    pub key: String,
+    pub value: String,
+}
+
+impl CpuProp {
+    pub fn new(key: impl Into<String>, value: impl ToString) -> Self {
+        CpuProp {
+            key: key.into(),
+            value: value.to_string(),
+        }
+    }
+}
+
+/// A CPU property that cannot be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidCpuProp(pub String);
+
+impl fmt::Display for InvalidCpuProp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid CPU property '{}', expected name=value", self.0)
+    }
+}
+
+impl Error for InvalidCpuProp {}
+
+impl fmt::Display for CpuProp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.key, self.value)
+    }
+}
+
+impl FromStr for CpuProp {
+    type Err = InvalidCpuProp;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((key, value)) if CpuFeature::is_name(key) => Ok(CpuProp::new(key, value)),
+            _ => Err(InvalidCpuProp(s.to_string())),
+        }
+    }
+}
+
+impl Serialize for CpuProp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for CpuProp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CpuConfig {
+    // host|max|model
+    // host passes the host CPU through, max enables every feature the
+    // accelerator supports, any other name is a fixed model.
+    #[serde(rename = "model")]
+    pub model: String,
+
+    // +feature|-feature
+    // Features turned on or off on top of the model, in order.
+    #[serde(rename = "features", default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<CpuFeature>,
+
+    // pmu=on|off
+    // Expose the host performance monitoring unit to the guest.
+    #[serde(rename = "pmu", skip_serializing_if = "Option::is_none")]
+    pub pmu: Option<OnOff>,
+
+    // l3-cache=on|off
+    // Report an L3 cache in CPUID, which lets guests schedule better.
+    #[serde(rename = "l3-cache", skip_serializing_if = "Option::is_none")]
+    pub l3_cache: Option<OnOff>,
+
+    // migratable=on|off (host and max)
+    // Leave out features that would block migration (default=on).
+    #[serde(rename = "migratable", skip_serializing_if = "Option::is_none")]
+    pub migratable: Option<OnOff>,
+
+    // phys-bits=n
+    // Number of physical address bits the guest sees.
+    #[serde(rename = "phys-bits", skip_serializing_if = "Option::is_none")]
+    pub phys_bits: Option<u8>,
+
+    // host-phys-bits=on|off
+    // Take the number of physical address bits from the host.
+    #[serde(rename = "host-phys-bits", skip_serializing_if = "Option::is_none")]
+    pub host_phys_bits: Option<OnOff>,
+
+    // prop=value
+    // Any other property, e.g. hv-spinlocks=0x1fff, written as given and
+    // in order.
+    #[serde(rename = "props", default, skip_serializing_if = "Vec::is_empty")]
+    pub props: Vec<CpuProp>,
+}
+
+impl CpuConfig {
+    /// Whether the model depends on the host CPU, which makes the guest
+    /// non-portable.
+    pub fn is_host_dependent(&self) -> bool {
+        matches!(self.model.as_str(), "host" | "max")
+    }
+
+    /// The properties as `(key, value)`, in the order they are written.
+    fn props(&self) -> [(&'static str, Option<String>); 5] {
+        let on_off = |value: Option<OnOff>| value.map(|v| v.to_string());
+        [
+            ("pmu", on_off(self.pmu)),
+            ("l3-cache", on_off(self.l3_cache)),
+            ("migratable", on_off(self.migratable)),
+            ("phys-bits", self.phys_bits.map(|bits| bits.to_string())),
+            ("host-phys-bits", on_off(self.host_phys_bits)),
+        ]
+    }
+
+    /// Check that the QEMU binary has the model and its properties. Only
+    /// x86 and Arm CPUs have a `pmu` property, the others are x86 only.
+    /// Other targets take features as `name=value` only, so `+feature` and
+    /// `-feature` are x86 only too.
+    pub(crate) fn validate_arch(&self, path: &str, arch: Arch, errors: &mut Vec<ValidationError>) {
+        let not_supported = || ValidationErrorKind::NotSupported { by: arch.binary() };
+        if !is_model(arch, &self.model) {
+            invalid(errors, path, "model", not_supported());
+        }
+        if !self.features.is_empty() && arch != Arch::X86_64 {
+            invalid(errors, path, "features", not_supported());
+        }
+        for (key, value) in self.props() {
+            let archs: &[Arch] = match key {
+                "pmu" => &[Arch::X86_64, Arch::Aarch64],
+                _ => &[Arch::X86_64],
+            };
+            if value.is_some() && !archs.contains(&arch) {
+                invalid(errors, path, key, not_supported());
+            }
+        }
+    }
+
+    /// Check that the accelerators QEMU tries can run the model. `host`
+    /// needs KVM or HVF, and without -accel QEMU falls back to TCG.
+    pub(crate) fn validate_accel(
+        &self,
+        path: &str,
+        accels: &[Accelerator],
+        errors: &mut Vec<ValidationError>,
+    ) {
+        let passthrough =
+            |accel: &Accelerator| matches!(accel, Accelerator::Kvm | Accelerator::Hvf);
+        if self.model == "host" && !accels.iter().any(passthrough) {
+            let accels = accels.iter().map(Accelerator::as_str).collect::<Vec<_>>();
+            let by = format!("accel={}", accels.join(":"));
+            invalid(
+                errors,
+                path,
+                "model",
+                ValidationErrorKind::NotSupported { by },
+            );
+        }
+    }
+}
+
+impl OptionFormatting for CpuConfig {
+    fn formatting(&self) -> OptionQ<'_> {
+        let positional = |value: String| KVArgQ {
+            key: "",
+            kv_split_with: None,
+            value: Some(value),
+        };
+        let model = positional(self.model.to_string());
+        let features = self.features.iter().map(|f| positional(f.to_string()));
+        let props = self.props().into_iter().filter_map(|(key, value)| {
+            Some(KVArgQ {
+                key,
+                kv_split_with: Some("="),
+                value: Some(value?),
+            })
+        });
+        let extra = self.props.iter().map(|prop| KVArgQ {
+            key: &prop.key,
+            kv_split_with: Some("="),
+            value: Some(prop.value.to_string()),
+        });
+        OptionQ {
+            prefix: "-",
+            raw: "cpu",
+            option_args_split_with: " ",
+            args_split_with: ",",
+            args: std::iter::once(model)
+                .chain(features)
+                .chain(props)
+                .chain(extra)
+                .collect(),
+        }
+    }
+}
+
+impl OptionParsing for CpuConfig {
+    /// A bare `feature` is read as `+feature`. Other `key=value` properties,
+    /// `feature=on|off` included, are kept as written.
+    fn parsing(option: &OptionQ<'_>) -> Result<Self, ParseError> {
+        let args = OptionArgs::new(option);
+        let model = match option.args.first() {
+            Some(KVArgQ {
+                key: "",
+                value: Some(model),
+                ..
+            }) if !model.starts_with(['+', '-']) => model,
+            _ => return Err(args.missing("model")),
+        };
+        let mut cpu = CpuConfig::builder(model.as_str()).build();
+        for arg in &option.args[1..] {
+            let value = arg.value.as_deref().unwrap_or_default();
+            let on_off = || {
+                value
+                    .parse::<OnOff>()
+                    .map(Some)
+                    .map_err(|_| args.invalid(arg.key, value))
+            };
+            match arg.key {
+                "" => {
+                    let feature = match value.parse() {
+                        Ok(feature) => feature,
+                        Err(_) if CpuFeature::is_name(value) => CpuFeature::enable(value),
+                        Err(_) => {
+                            return Err(ParseError::UnknownKey {
+                                option: option.raw.to_string(),
+                                key: value.to_string(),
+                            })
+                        }
+                    };
+                    cpu.features.push(feature);
+                }
+                "pmu" => cpu.pmu = on_off()?,
+                "l3-cache" => cpu.l3_cache = on_off()?,
+                "migratable" => cpu.migratable = on_off()?,
+                "phys-bits" => {
+                    let bits = value.parse().map_err(|_| args.invalid(arg.key, value))?;
+                    cpu.phys_bits = Some(bits);
+                }
+                "host-phys-bits" => cpu.host_phys_bits = on_off()?,
+                key if CpuFeature::is_name(key) => cpu.props.push(CpuProp::new(key, value)),
+                key => {
+                    return Err(ParseError::UnknownKey {
+                        option: option.raw.to_string(),
+                        key: key.to_string(),
+                    })
+                }
+            }
+        }
+        Ok(cpu)
+    }
+}
+
+impl Validate for CpuConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        if !CpuFeature::is_name(&self.model) {
+            let kind = ValidationErrorKind::Invalid {
+                expected: "a CPU model name",
+            };
+            invalid(errors, path, "model", kind);
+        }
+        for (i, feature) in self.features.iter().enumerate() {
+            if self.features[..i].iter().any(|f| f.name == feature.name) {
+                let id = feature.name.to_string();
+                invalid(
+                    errors,
+                    path,
+                    "features",
+                    ValidationErrorKind::Duplicate { id },
+                );
+            }
+        }
+        if self.migratable.is_some() && !self.is_host_dependent() {
+            let by = format!("model {}", self.model);
+            invalid(
+                errors,
+                path,
+                "migratable",
+                ValidationErrorKind::NotSupported { by },
+            );
+        }
+        match self.phys_bits {
+            Some(_) if self.host_phys_bits == Some(OnOff::On) => invalid(
+                errors,
+                path,
+                "phys-bits",
+                ValidationErrorKind::Conflicts {
+                    key: "host-phys-bits",
+                },
+            ),
+            Some(bits) if !(32..=52).contains(&bits) => invalid(
+                errors,
+                path,
+                "phys-bits",
+                ValidationErrorKind::Invalid {
+                    expected: "a number of physical address bits from 32 to 52",
+                },
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// Builder for `CpuConfig`, e.g.
+///     CpuConfig::builder("Skylake-Server-v4").enable("avx512f").disable("hle").build()
+#[derive(Debug, Clone)]
+pub struct CpuConfigBuilder {
+    config: CpuConfig,
+}
+
+impl CpuConfig {
+    pub fn builder(model: impl Into<String>) -> CpuConfigBuilder {
+        CpuConfigBuilder {
+            config: CpuConfig {
+                model: model.into(),
+                features: Vec::new(),
+                pmu: None,
+                l3_cache: None,
+                migratable: None,
+                phys_bits: None,
+                host_phys_bits: None,
+                props: Vec::new(),
+            },
+        }
+    }
+
+    /// The host CPU passed through, for guests that never migrate.
+    pub fn host() -> CpuConfigBuilder {
+        Self::builder("host")
+    }
+
+    /// Every feature the accelerator supports.
+    pub fn max() -> CpuConfigBuilder {
+        Self::builder("max")
+    }
+}
+
+impl CpuConfigBuilder {
+    pub fn enable(mut self, feature: impl Into<String>) -> Self {
+        self.config.features.push(CpuFeature::enable(feature));
+        self
+    }
+
+    pub fn disable(mut self, feature: impl Into<String>) -> Self {
+        self.config.features.push(CpuFeature::disable(feature));
+        self
+    }
+
+    pub fn pmu(mut self, pmu: impl Into<OnOff>) -> Self {
+        self.config.pmu = Some(pmu.into());
+        self
+    }
+
+    pub fn l3_cache(mut self, l3_cache: impl Into<OnOff>) -> Self {
+        self.config.l3_cache = Some(l3_cache.into());
+        self
+    }
+
+    pub fn migratable(mut self, migratable: impl Into<OnOff>) -> Self {
+        self.config.migratable = Some(migratable.into());
+        self
+    }
+
+    pub fn phys_bits(mut self, phys_bits: u8) -> Self {
+        self.config.phys_bits = Some(phys_bits);
+        self
+    }
+
+    pub fn host_phys_bits(mut self, host_phys_bits: impl Into<OnOff>) -> Self {
+        self.config.host_phys_bits = Some(host_phys_bits.into());
+        self
+    }
+
+    pub fn prop(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.config.props.push(CpuProp::new(key, value));
+        self
+    }
+
+    pub fn build(self) -> CpuConfig {
+        self.config
+    }
+}
+
+impl From<CpuConfigBuilder> for CpuConfig {
+    fn from(builder: CpuConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &str) -> Result<CpuConfig, ParseError> {
+        let argv = ["qemu-system-x86_64", "-cpu", args];
+        let command = parse_args(&argv).unwrap();
+        CpuConfig::parsing(&command.options[0])
+    }
+
+    #[test]
+    fn test_cpu() {
+        let cpu = CpuConfig::builder("Skylake-Server-v4")
+            .enable("avx512f")
+            .disable("hle")
+            .pmu(false)
+            .l3_cache(true)
+            .phys_bits(46)
+            .build();
+        let formatted = "-cpu Skylake-Server-v4,+avx512f,-hle,pmu=off,l3-cache=on,phys-bits=46";
+        assert_eq!(cpu.formatting().to_string(), formatted);
+        assert_eq!(parse(&formatted[5..]), Ok(cpu.clone()));
+        let json = r#"{"model":"Skylake-Server-v4","features":["+avx512f","-hle"],"pmu":"off","l3-cache":"on","phys-bits":46}"#;
+        assert_eq!(serde_json::to_string(&cpu).unwrap(), json);
+        assert_eq!(serde_json::from_str::<CpuConfig>(json).unwrap(), cpu);
+
+        // Other properties, features given as feature=on|off included, are
+        // kept as written.
+        let cpu = CpuConfig::host()
+            .enable("hv_relaxed")
+            .migratable(false)
+            .prop("vmx", "on")
+            .prop("hv_spinlocks", "0x1fff")
+            .prop("tsc-frequency", 2000000000)
+            .build();
+        assert_eq!(
+            parse("host,hv_relaxed,vmx=on,hv_spinlocks=0x1fff,migratable=off,tsc-frequency=2000000000"),
+            Ok(cpu.clone())
+        );
+        let formatted =
+            "-cpu host,+hv_relaxed,migratable=off,vmx=on,hv_spinlocks=0x1fff,tsc-frequency=2000000000";
+        assert_eq!(cpu.formatting().to_string(), formatted);
+        assert_eq!(parse(&formatted[5..]), Ok(cpu.clone()));
+        let json = r#"{"model":"host","features":["+hv_relaxed"],"migratable":"off","props":["vmx=on","hv_spinlocks=0x1fff","tsc-frequency=2000000000"]}"#;
+        assert_eq!(serde_json::to_string(&cpu).unwrap(), json);
+        assert_eq!(serde_json::from_str::<CpuConfig>(json).unwrap(), cpu);
+        assert!(parse("+avx2").is_err());
+        assert!(parse("max,pmu=maybe").is_err());
+        assert!(
+            serde_json::from_str::<CpuConfig>(r#"{"model":"max","features":["avx2"]}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let cpu = CpuConfig::builder("Skylake-Server-v4")
+            .enable("avx2")
+            .disable("avx2")
+            .migratable(true)
+            .phys_bits(46)
+            .host_phys_bits(true)
+            .build();
+        let mut errors = Vec::new();
+        cpu.validate("cpu", &mut errors);
+        CpuConfig::host().phys_bits(64).build().validate_accel(
+            "cpu",
+            &[Accelerator::Tcg],
+            &mut errors,
+        );
+        CpuConfig::host()
+            .phys_bits(64)
+            .build()
+            .validate("cpu", &mut errors);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "cpu.features: 'avx2' is used more than once",
+                "cpu.migratable: not supported by model Skylake-Server-v4",
+                "cpu.phys-bits: conflicts with 'host-phys-bits'",
+                "cpu.model: not supported by accel=tcg",
+                "cpu.phys-bits: expected a number of physical address bits from 32 to 52",
+            ]
+        );
+    }
+}
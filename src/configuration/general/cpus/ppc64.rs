@@ -0,0 +1,46 @@
+//! CPU models of qemu-system-ppc64, as listed by `-cpu help`. A family
+//! name such as `power9` stands for its newest revision, e.g.
+//!     -cpu power9_v2.2
+//!     -cpu POWER10
+
+/// The 64-bit server models of QEMU 8.2, revisions and family names.
+pub const CPU_MODELS: &[&str] = &[
+    "970_v2.2",
+    "970fx_v1.0",
+    "970fx_v2.0",
+    "970fx_v2.1",
+    "970fx_v3.0",
+    "970fx_v3.1",
+    "970mp_v1.0",
+    "970mp_v1.1",
+    "power5+_v2.1",
+    "power7_v2.3",
+    "power7+_v2.1",
+    "power8e_v2.1",
+    "power8_v2.0",
+    "power8nvl_v1.0",
+    "power9_v2.0",
+    "power9_v2.2",
+    "power10_v2.0",
+    "970",
+    "970fx",
+    "970mp",
+    "power5+",
+    "power5gs",
+    "power7",
+    "power7+",
+    "power8e",
+    "power8",
+    "power8nvl",
+    "power9",
+    "power10",
+    "host",
+    "max",
+];
+
+/// Whether qemu-system-ppc64 has the model. QEMU ignores the case of
+/// PowerPC model names.
+pub fn is_model(model: &str) -> bool {
+    let model = model.to_ascii_lowercase();
+    CPU_MODELS.contains(&model.as_str())
+}
@@ -0,0 +1,22 @@
+//! CPU models of qemu-system-riscv64, as listed by `-cpu help`. ISA
+//! extensions are switched by name, e.g.
+//!     -cpu rv64,v=true,vlen=256
+//!     -cpu sifive-u54
+
+/// The models of QEMU 8.2.
+pub const CPU_MODELS: &[&str] = &[
+    "host",
+    "max",
+    "rv64",
+    "shakti-c",
+    "sifive-e51",
+    "sifive-u54",
+    "thead-c906",
+    "veyron-v1",
+    "x-rv128",
+];
+
+/// Whether qemu-system-riscv64 has the model.
+pub fn is_model(model: &str) -> bool {
+    CPU_MODELS.contains(&model)
+}
@@ -0,0 +1,42 @@
+//! CPU models of qemu-system-s390x, as listed by `-cpu help`. Every
+//! generation also comes as `<model>-base`, with only the features of
+//! the first machine of the generation, and `<model>-full`, e.g.
+//!     -cpu z14-base,aes=on
+//!     -cpu host
+
+/// The models of QEMU 8.2, without the `-base` and `-full` variants.
+pub const CPU_MODELS: &[&str] = &[
+    "host", "max", "qemu", "z900", "z900.2", "z900.3", "z800", "z990", "z990.2", "z990.3", "z890",
+    "z990.4", "z890.2", "z990.5", "z890.3", "z9EC", "z9EC.2", "z9BC", "z9EC.3", "z9BC.2", "z10EC",
+    "z10EC.2", "z10BC", "z10EC.3", "z10BC.2", "z196", "z114", "z196.2", "z114.2", "zEC12",
+    "zEC12.2", "zBC12", "z13", "z13.2", "z13s", "z14", "z14.2", "z14ZR1", "gen15a", "gen15b",
+    "gen16a", "gen16b",
+];
+
+/// The models that have no `-base` and `-full` variants.
+const UNVERSIONED: &[&str] = &["host", "max", "qemu"];
+
+/// Whether qemu-system-s390x has the model.
+pub fn is_model(model: &str) -> bool {
+    let generation = model
+        .strip_suffix("-base")
+        .or_else(|| model.strip_suffix("-full"));
+    match generation {
+        Some(generation) => !UNVERSIONED.contains(&generation) && CPU_MODELS.contains(&generation),
+        None => CPU_MODELS.contains(&model),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_models() {
+        assert!(is_model("z14"));
+        assert!(is_model("gen16a-base"));
+        assert!(is_model("z13s-full"));
+        assert!(!is_model("host-base"));
+        assert!(!is_model("z17"));
+    }
+}
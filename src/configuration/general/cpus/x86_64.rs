@@ -1,15 +1,79 @@
-//! Type and number/topology of vCPUs, Most accelerators offer a host cpu option which
-//! simply passes through your host CPU configuration without filtering out any features.
-use crate::command::builder::*;
-use serde::{Deserialize, Serialize};
+//! CPU models of qemu-system-x86_64, as listed by `-cpu help`. Features
+//! are switched with `+feature` and `-feature`, e.g.
+//!     -cpu Skylake-Server-v4,+avx512f,-hle,pmu=off
+//!     -cpu host,migratable=off,host-phys-bits=on
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CpuConfig<'a> {
-    test: &'a str,
-}
+/// The models of QEMU 8.2, without the `-v<n>` versions each named model
+/// also comes as.
+pub const CPU_MODELS: &[&str] = &[
+    "486",
+    "athlon",
+    "base",
+    "Broadwell",
+    "Broadwell-IBRS",
+    "Broadwell-noTSX",
+    "Broadwell-noTSX-IBRS",
+    "Cascadelake-Server",
+    "Cascadelake-Server-noTSX",
+    "Conroe",
+    "Cooperlake",
+    "core2duo",
+    "coreduo",
+    "Denverton",
+    "Dhyana",
+    "EPYC",
+    "EPYC-Genoa",
+    "EPYC-IBPB",
+    "EPYC-Milan",
+    "EPYC-Rome",
+    "GraniteRapids",
+    "Haswell",
+    "Haswell-IBRS",
+    "Haswell-noTSX",
+    "Haswell-noTSX-IBRS",
+    "host",
+    "Icelake-Server",
+    "Icelake-Server-noTSX",
+    "IvyBridge",
+    "IvyBridge-IBRS",
+    "KnightsMill",
+    "kvm32",
+    "kvm64",
+    "max",
+    "n270",
+    "Nehalem",
+    "Nehalem-IBRS",
+    "Opteron_G1",
+    "Opteron_G2",
+    "Opteron_G3",
+    "Opteron_G4",
+    "Opteron_G5",
+    "Penryn",
+    "pentium",
+    "pentium2",
+    "pentium3",
+    "phenom",
+    "qemu32",
+    "qemu64",
+    "SandyBridge",
+    "SandyBridge-IBRS",
+    "SapphireRapids",
+    "Skylake-Client",
+    "Skylake-Client-IBRS",
+    "Skylake-Client-noTSX-IBRS",
+    "Skylake-Server",
+    "Skylake-Server-IBRS",
+    "Skylake-Server-noTSX-IBRS",
+    "Snowridge",
+    "Westmere",
+    "Westmere-IBRS",
+];
 
-impl<'a> OptionFormatting<'a> for CpuConfig<'a> {
-    fn formatting(&self) -> OptionQ<'a> {
-        todo!()
-    }
-}
\ No newline at end of file
+/// Whether qemu-system-x86_64 has the model, e.g. `Skylake-Server-v4`.
+pub fn is_model(model: &str) -> bool {
+    let unversioned = model
+        .rsplit_once("-v")
+        .filter(|(_, version)| !version.is_empty() && version.bytes().all(|b| b.is_ascii_digit()))
+        .map_or(model, |(name, _)| name);
+    CPU_MODELS.contains(&unversioned)
+}
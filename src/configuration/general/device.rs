@@ -1,13 +1,2285 @@
-use crate::command::builder::*;
-use serde::{Deserialize, Serialize};
+//! Devices of -device, either of a driver with typed properties or of any
+//! driver with its properties as strings, e.g.
+//!     -device virtio-blk-pci,id=vda,drive=disk0,iothread=iothread0
+//!     -device usb-tablet,bus=usb.0
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DeviceConfig<'a> {
-    test: &'a str,
+use crate::command::{builder::*, parser::*};
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use super::{
+    types::{OnOff, OnOffAuto},
+    validate::*,
+};
+
+/// Add a device. JSON gives the driver as `driver` next to the properties,
+/// as in the JSON form of -device.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "driver")]
+pub enum DeviceConfig {
+    #[serde(rename = "virtio-blk-pci")]
+    VirtioBlkPci(VirtioBlkPciConfig),
+
+    #[serde(rename = "virtio-net-pci")]
+    VirtioNetPci(VirtioNetPciConfig),
+
+    #[serde(rename = "e1000")]
+    E1000(E1000Config),
+
+    #[serde(rename = "e1000e")]
+    E1000e(E1000eConfig),
+
+    #[serde(rename = "virtio-scsi-pci")]
+    VirtioScsiPci(VirtioScsiPciConfig),
+
+    #[serde(rename = "scsi-hd")]
+    ScsiHd(ScsiHdConfig),
+
+    #[serde(rename = "virtio-rng-pci")]
+    VirtioRngPci(VirtioRngPciConfig),
+
+    #[serde(rename = "virtio-balloon-pci")]
+    VirtioBalloonPci(VirtioBalloonPciConfig),
+
+    #[serde(rename = "virtio-gpu-pci")]
+    VirtioGpuPci(VirtioGpuPciConfig),
+
+    #[serde(rename = "intel-hda")]
+    IntelHda(IntelHdaConfig),
+
+    #[serde(rename = "ich9-intel-hda")]
+    Ich9IntelHda(Ich9IntelHdaConfig),
+
+    #[serde(rename = "hda-duplex")]
+    HdaDuplex(HdaDuplexConfig),
+
+    #[serde(rename = "AC97")]
+    Ac97(Ac97Config),
+
+    #[serde(rename = "pcie-root-port")]
+    PcieRootPort(PcieRootPortConfig),
+
+    #[serde(rename = "vfio-pci")]
+    VfioPci(VfioPciConfig),
+
+    /// Any other driver.
+    #[serde(untagged)]
+    Generic(GenericDeviceConfig),
+}
+
+impl DeviceConfig {
+    pub fn driver(&self) -> &str {
+        match self {
+            DeviceConfig::VirtioBlkPci(_) => "virtio-blk-pci",
+            DeviceConfig::VirtioNetPci(_) => "virtio-net-pci",
+            DeviceConfig::E1000(_) => "e1000",
+            DeviceConfig::E1000e(_) => "e1000e",
+            DeviceConfig::VirtioScsiPci(_) => "virtio-scsi-pci",
+            DeviceConfig::ScsiHd(_) => "scsi-hd",
+            DeviceConfig::VirtioRngPci(_) => "virtio-rng-pci",
+            DeviceConfig::VirtioBalloonPci(_) => "virtio-balloon-pci",
+            DeviceConfig::VirtioGpuPci(_) => "virtio-gpu-pci",
+            DeviceConfig::IntelHda(_) => "intel-hda",
+            DeviceConfig::Ich9IntelHda(_) => "ich9-intel-hda",
+            DeviceConfig::HdaDuplex(_) => "hda-duplex",
+            DeviceConfig::Ac97(_) => "AC97",
+            DeviceConfig::PcieRootPort(_) => "pcie-root-port",
+            DeviceConfig::VfioPci(_) => "vfio-pci",
+            DeviceConfig::Generic(device) => &device.driver,
+        }
+    }
+
+    /// The properties every device takes.
+    fn common(&self) -> (&Option<String>, &Option<String>, &Option<String>) {
+        match self {
+            DeviceConfig::VirtioBlkPci(d) => (&d.id, &d.bus, &d.addr),
+            DeviceConfig::VirtioNetPci(d) => (&d.id, &d.bus, &d.addr),
+            DeviceConfig::E1000(d) => (&d.id, &d.bus, &d.addr),
+            DeviceConfig::E1000e(d) => (&d.id, &d.bus, &d.addr),
+            DeviceConfig::VirtioScsiPci(d) => (&d.id, &d.bus, &d.addr),
+            DeviceConfig::ScsiHd(d) => (&d.id, &d.bus, &d.addr),
+            DeviceConfig::VirtioRngPci(d) => (&d.id, &d.bus, &d.addr),
+            DeviceConfig::VirtioBalloonPci(d) => (&d.id, &d.bus, &d.addr),
+            DeviceConfig::VirtioGpuPci(d) => (&d.id, &d.bus, &d.addr),
+            DeviceConfig::IntelHda(d) => (&d.id, &d.bus, &d.addr),
+            DeviceConfig::Ich9IntelHda(d) => (&d.id, &d.bus, &d.addr),
+            DeviceConfig::HdaDuplex(d) => (&d.id, &d.bus, &d.addr),
+            DeviceConfig::Ac97(d) => (&d.id, &d.bus, &d.addr),
+            DeviceConfig::PcieRootPort(d) => (&d.id, &d.bus, &d.addr),
+            DeviceConfig::VfioPci(d) => (&d.id, &d.bus, &d.addr),
+            DeviceConfig::Generic(d) => (&d.id, &d.bus, &d.addr),
+        }
+    }
+
+    fn common_mut(&mut self) -> (&mut Option<String>, &mut Option<String>) {
+        match self {
+            DeviceConfig::VirtioBlkPci(d) => (&mut d.bus, &mut d.addr),
+            DeviceConfig::VirtioNetPci(d) => (&mut d.bus, &mut d.addr),
+            DeviceConfig::E1000(d) => (&mut d.bus, &mut d.addr),
+            DeviceConfig::E1000e(d) => (&mut d.bus, &mut d.addr),
+            DeviceConfig::VirtioScsiPci(d) => (&mut d.bus, &mut d.addr),
+            DeviceConfig::ScsiHd(d) => (&mut d.bus, &mut d.addr),
+            DeviceConfig::VirtioRngPci(d) => (&mut d.bus, &mut d.addr),
+            DeviceConfig::VirtioBalloonPci(d) => (&mut d.bus, &mut d.addr),
+            DeviceConfig::VirtioGpuPci(d) => (&mut d.bus, &mut d.addr),
+            DeviceConfig::IntelHda(d) => (&mut d.bus, &mut d.addr),
+            DeviceConfig::Ich9IntelHda(d) => (&mut d.bus, &mut d.addr),
+            DeviceConfig::HdaDuplex(d) => (&mut d.bus, &mut d.addr),
+            DeviceConfig::Ac97(d) => (&mut d.bus, &mut d.addr),
+            DeviceConfig::PcieRootPort(d) => (&mut d.bus, &mut d.addr),
+            DeviceConfig::VfioPci(d) => (&mut d.bus, &mut d.addr),
+            DeviceConfig::Generic(d) => (&mut d.bus, &mut d.addr),
+        }
+    }
+
+    /// Whether `option` is a -device given in the key=value form rather
+    /// than as JSON.
+    pub(crate) fn matches(option: &OptionQ<'_>) -> bool {
+        option
+            .args
+            .first()
+            .and_then(|arg| arg.value.as_deref())
+            .is_some_and(|driver| !driver.starts_with('{'))
+    }
+
+    /// Plug the device into `bus` at `addr`.
+    pub(crate) fn set_place(&mut self, bus: String, addr: String) {
+        let (bus_field, addr_field) = self.common_mut();
+        *bus_field = Some(bus);
+        *addr_field = Some(addr);
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.common().0.as_deref()
+    }
+
+    pub fn bus(&self) -> Option<&str> {
+        self.common().1.as_deref()
+    }
+
+    pub fn addr(&self) -> Option<&str> {
+        self.common().2.as_deref()
+    }
+
+    /// The -netdev the device is connected to.
+    pub fn netdev(&self) -> Option<&str> {
+        match self {
+            DeviceConfig::VirtioNetPci(d) => d.netdev.as_deref(),
+            DeviceConfig::E1000(d) => d.netdev.as_deref(),
+            DeviceConfig::E1000e(d) => d.netdev.as_deref(),
+            DeviceConfig::Generic(d) => d.prop("netdev"),
+            _ => None,
+        }
+    }
+
+    /// The block node or -drive the device presents to the guest.
+    pub fn drive(&self) -> Option<&str> {
+        match self {
+            DeviceConfig::VirtioBlkPci(d) => Some(&d.drive),
+            DeviceConfig::ScsiHd(d) => Some(&d.drive),
+            DeviceConfig::Generic(d) => d.prop("drive"),
+            _ => None,
+        }
+    }
+
+    /// The iothread object the device runs in.
+    pub fn iothread(&self) -> Option<&str> {
+        match self {
+            DeviceConfig::VirtioBlkPci(d) => d.iothread.as_deref(),
+            DeviceConfig::VirtioScsiPci(d) => d.iothread.as_deref(),
+            DeviceConfig::Generic(d) => d.prop("iothread"),
+            _ => None,
+        }
+    }
+
+    /// The audiodev a sound device plays through.
+    pub fn audiodev(&self) -> Option<&str> {
+        match self {
+            DeviceConfig::HdaDuplex(d) => d.audiodev.as_deref(),
+            DeviceConfig::Ac97(d) => d.audiodev.as_deref(),
+            DeviceConfig::Generic(d) => d.prop("audiodev"),
+            _ => None,
+        }
+    }
+
+    /// The rng object the device takes entropy from.
+    pub fn rng(&self) -> Option<&str> {
+        match self {
+            DeviceConfig::VirtioRngPci(d) => d.rng.as_deref(),
+            DeviceConfig::Generic(d) => d.prop("rng"),
+            _ => None,
+        }
+    }
+}
+
+impl OptionFormatting for DeviceConfig {
+    fn formatting(&self) -> OptionQ<'_> {
+        let mut option = match self {
+            DeviceConfig::VirtioBlkPci(d) => d.formatting(),
+            DeviceConfig::VirtioNetPci(d) => d.formatting(),
+            DeviceConfig::E1000(d) => d.formatting(),
+            DeviceConfig::E1000e(d) => d.formatting(),
+            DeviceConfig::VirtioScsiPci(d) => d.formatting(),
+            DeviceConfig::ScsiHd(d) => d.formatting(),
+            DeviceConfig::VirtioRngPci(d) => d.formatting(),
+            DeviceConfig::VirtioBalloonPci(d) => d.formatting(),
+            DeviceConfig::VirtioGpuPci(d) => d.formatting(),
+            DeviceConfig::IntelHda(d) => d.formatting(),
+            DeviceConfig::Ich9IntelHda(d) => d.formatting(),
+            DeviceConfig::HdaDuplex(d) => d.formatting(),
+            DeviceConfig::Ac97(d) => d.formatting(),
+            DeviceConfig::PcieRootPort(d) => d.formatting(),
+            DeviceConfig::VfioPci(d) => d.formatting(),
+            DeviceConfig::Generic(d) => return d.formatting(),
+        };
+        option.args.insert(
+            0,
+            KVArgQ {
+                key: "",
+                kv_split_with: None,
+                value: Some(self.driver().to_string()),
+            },
+        );
+        option
+    }
+}
+
+/// JSON is read like the command line, so a driver with typed properties
+/// given one this crate does not model stays in generic form.
+impl<'de> Deserialize<'de> for DeviceConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let generic = GenericDeviceConfig::deserialize(deserializer)?;
+        DeviceConfig::parsing(&generic.formatting()).map_err(de::Error::custom)
+    }
+}
+
+impl OptionParsing for DeviceConfig {
+    /// A driver with typed properties falls back to the generic form when
+    /// its properties do not fit the typed form, e.g. one this crate does
+    /// not model or a value outside the typed range, so no -device is lost.
+    fn parsing(option: &OptionQ<'_>) -> Result<Self, ParseError> {
+        fn typed<T: FromOptionArgs>(
+            option: &OptionQ<'_>,
+            variant: fn(T) -> DeviceConfig,
+        ) -> Result<DeviceConfig, ParseError> {
+            let mut args = OptionArgs::new(option);
+            args.value("driver", true);
+            let device = T::from_args(&mut args)?;
+            args.finish()?;
+            Ok(variant(device))
+        }
+
+        let generic = GenericDeviceConfig::parsing(option)?;
+        let device = match generic.driver.as_str() {
+            "virtio-blk-pci" => typed(option, DeviceConfig::VirtioBlkPci),
+            "virtio-net-pci" => typed(option, DeviceConfig::VirtioNetPci),
+            "e1000" => typed(option, DeviceConfig::E1000),
+            "e1000e" => typed(option, DeviceConfig::E1000e),
+            "virtio-scsi-pci" => typed(option, DeviceConfig::VirtioScsiPci),
+            "scsi-hd" => typed(option, DeviceConfig::ScsiHd),
+            "virtio-rng-pci" => typed(option, DeviceConfig::VirtioRngPci),
+            "virtio-balloon-pci" => typed(option, DeviceConfig::VirtioBalloonPci),
+            "virtio-gpu-pci" => typed(option, DeviceConfig::VirtioGpuPci),
+            "intel-hda" => typed(option, DeviceConfig::IntelHda),
+            "ich9-intel-hda" => typed(option, DeviceConfig::Ich9IntelHda),
+            "hda-duplex" => typed(option, DeviceConfig::HdaDuplex),
+            "AC97" => typed(option, DeviceConfig::Ac97),
+            "pcie-root-port" => typed(option, DeviceConfig::PcieRootPort),
+            "vfio-pci" => typed(option, DeviceConfig::VfioPci),
+            _ => return Ok(DeviceConfig::Generic(generic)),
+        };
+        match device {
+            Err(
+                ParseError::UnknownKey { .. }
+                | ParseError::MissingValue { .. }
+                | ParseError::InvalidValue { .. },
+            ) => Ok(DeviceConfig::Generic(generic)),
+            device => device,
+        }
+    }
+}
+
+impl Validate for DeviceConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        match self {
+            DeviceConfig::VirtioNetPci(d) => d.validate(path, errors),
+            DeviceConfig::E1000(d) => validate_mac(path, &d.mac, errors),
+            DeviceConfig::E1000e(d) => validate_mac(path, &d.mac, errors),
+            DeviceConfig::VirtioGpuPci(d) => d.validate(path, errors),
+            DeviceConfig::VfioPci(d) => d.validate(path, errors),
+            _ => {}
+        }
+    }
+}
+
+/// Check that device ids are unique and every disk presents a block node
+/// or -drive defined before, not taken by another device.
+pub(crate) fn validate_references(
+    devices: &[DeviceConfig],
+    block_ids: &[&str],
+    errors: &mut Vec<ValidationError>,
+) {
+    for (i, device) in devices.iter().enumerate() {
+        let path = format!("device[{}]", i);
+        if let Some(id) = device.id() {
+            if devices[..i].iter().any(|other| other.id() == Some(id)) {
+                invalid(
+                    errors,
+                    &path,
+                    "id",
+                    ValidationErrorKind::Duplicate { id: id.to_string() },
+                );
+            }
+        }
+        if let Some(drive) = device.drive() {
+            let kind = if !block_ids.contains(&drive) {
+                ValidationErrorKind::UnknownReference {
+                    id: drive.to_string(),
+                }
+            } else if devices[..i]
+                .iter()
+                .any(|other| other.drive() == Some(drive))
+            {
+                ValidationErrorKind::Duplicate {
+                    id: drive.to_string(),
+                }
+            } else {
+                continue;
+            };
+            invalid(errors, &path, "drive", kind);
+        }
+    }
+}
+
+/// A device of any driver, e.g.
+///     -device usb-tablet,bus=usb.0,port=1
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenericDeviceConfig {
+    #[serde(rename = "driver")]
+    pub driver: String,
+
+    // id=id
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    // bus=bus,addr=addr
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+
+    // prop=value
+    // Any other property of the driver, emitted in key order. JSON may give
+    // the values as strings, numbers or booleans.
+    #[serde(flatten, deserialize_with = "scalar_props")]
+    pub props: BTreeMap<String, String>,
+}
+
+fn scalar_props<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Scalar {
+        String(String),
+        Bool(bool),
+        Number(serde_json::Number),
+    }
+
+    let props = BTreeMap::<String, Scalar>::deserialize(deserializer)?;
+    Ok(props
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Scalar::String(value) => value,
+                Scalar::Bool(value) => value.to_string(),
+                Scalar::Number(value) => value.to_string(),
+            };
+            (key, value)
+        })
+        .collect())
+}
+
+impl GenericDeviceConfig {
+    pub fn new(driver: impl Into<String>) -> Self {
+        GenericDeviceConfig {
+            driver: driver.into(),
+            id: None,
+            bus: None,
+            addr: None,
+            props: BTreeMap::new(),
+        }
+    }
+
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.addr = Some(addr.into());
+        self
+    }
+
+    pub fn prop(&self, key: &str) -> Option<&str> {
+        self.props.get(key).map(String::as_str)
+    }
+
+    pub fn set(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.props.insert(key.into(), value.to_string());
+        self
+    }
+}
+
+impl OptionFormatting for GenericDeviceConfig {
+    fn formatting(&self) -> OptionQ<'_> {
+        let kv = |key, value: &String| KVArgQ {
+            key,
+            kv_split_with: Some("="),
+            value: Some(value.to_string()),
+        };
+        let driver = KVArgQ {
+            key: "",
+            kv_split_with: None,
+            value: Some(self.driver.to_string()),
+        };
+        let common = [("id", &self.id), ("bus", &self.bus), ("addr", &self.addr)]
+            .into_iter()
+            .filter_map(|(key, value)| Some(kv(key, value.as_ref()?)));
+        let props = self.props.iter().map(|(key, value)| kv(key, value));
+        OptionQ {
+            prefix: "-",
+            raw: "device",
+            option_args_split_with: " ",
+            args_split_with: ",",
+            args: std::iter::once(driver).chain(common).chain(props).collect(),
+        }
+    }
+}
+
+impl OptionParsing for GenericDeviceConfig {
+    fn parsing(option: &OptionQ<'_>) -> Result<Self, ParseError> {
+        let mut args = OptionArgs::new(option);
+        let driver = args
+            .parsed::<String>("driver", true)?
+            .ok_or_else(|| args.missing("driver"))?;
+        let mut device = GenericDeviceConfig::new(driver);
+        for (i, arg) in option.args.iter().enumerate() {
+            let value = arg.value.clone().unwrap_or_default();
+            match arg.key {
+                "driver" => {}
+                "" if i == 0 => {}
+                "" => {
+                    return Err(ParseError::UnknownKey {
+                        option: option.raw.to_string(),
+                        key: value,
+                    })
+                }
+                "id" => device.id = Some(value),
+                "bus" => device.bus = Some(value),
+                "addr" => device.addr = Some(value),
+                key => {
+                    device.props.insert(key.to_string(), value);
+                }
+            }
+        }
+        Ok(device)
+    }
+}
+
+/// A virtio disk on PCI.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "device")]
+pub struct VirtioBlkPciConfig {
+    // id=id,bus=bus,addr=addr
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+
+    // drive=id
+    // The -blockdev node name or -drive id of the disk.
+    #[serde(rename = "drive")]
+    pub drive: String,
+
+    // iothread=id
+    // Process the requests of the disk in the given iothread object.
+    #[serde(rename = "iothread", skip_serializing_if = "Option::is_none")]
+    pub iothread: Option<String>,
+
+    // num-queues=n
+    #[serde(rename = "num-queues", skip_serializing_if = "Option::is_none")]
+    pub num_queues: Option<usize>,
+
+    // serial=serial
+    // The serial number the guest sees.
+    #[serde(rename = "serial", skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+
+    // bootindex=n
+    #[serde(rename = "bootindex", skip_serializing_if = "Option::is_none")]
+    pub bootindex: Option<i32>,
+}
+
+/// A virtio NIC on PCI, connected to the -netdev named by `netdev`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "device")]
+pub struct VirtioNetPciConfig {
+    // id=id,bus=bus,addr=addr
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+
+    // netdev=id
+    // The id of the -netdev backend. Without one the NIC has no link.
+    #[serde(rename = "netdev", skip_serializing_if = "Option::is_none")]
+    pub netdev: Option<String>,
+
+    // mac=macaddr
+    // The MAC address of the NIC, e.g. 52:54:00:12:34:56.
+    #[serde(rename = "mac", skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+
+    // mq=on|off
+    // Enable multiqueue, matching queues=n of the netdev.
+    #[serde(rename = "mq", skip_serializing_if = "Option::is_none")]
+    pub mq: Option<OnOff>,
+
+    // vectors=n
+    // Number of MSI-X vectors, 2 * queues + 2 for multiqueue.
+    #[serde(rename = "vectors", skip_serializing_if = "Option::is_none")]
+    pub vectors: Option<usize>,
+
+    // bootindex=n
+    #[serde(rename = "bootindex", skip_serializing_if = "Option::is_none")]
+    pub bootindex: Option<i32>,
+}
+
+impl Validate for VirtioNetPciConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        validate_mac(path, &self.mac, errors);
+    }
+}
+
+fn validate_mac(path: &str, mac: &Option<String>, errors: &mut Vec<ValidationError>) {
+    if let Some(mac) = mac {
+        let octets: Vec<&str> = mac.split(':').collect();
+        let valid = octets.len() == 6
+            && octets
+                .iter()
+                .all(|octet| octet.len() == 2 && u8::from_str_radix(octet, 16).is_ok());
+        if !valid {
+            invalid(
+                errors,
+                path,
+                "mac",
+                ValidationErrorKind::Invalid {
+                    expected: "a MAC address such as 52:54:00:12:34:56",
+                },
+            );
+        }
+    }
+}
+
+/// An Intel 82540EM NIC on conventional PCI, connected to the -netdev
+/// named by `netdev`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "device")]
+pub struct E1000Config {
+    // id=id,bus=bus,addr=addr
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+
+    // netdev=id
+    #[serde(rename = "netdev", skip_serializing_if = "Option::is_none")]
+    pub netdev: Option<String>,
+
+    // mac=macaddr
+    #[serde(rename = "mac", skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+
+    // bootindex=n
+    #[serde(rename = "bootindex", skip_serializing_if = "Option::is_none")]
+    pub bootindex: Option<i32>,
+}
+
+/// An Intel 82574L NIC on PCIe, connected to the -netdev named by
+/// `netdev`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "device")]
+pub struct E1000eConfig {
+    // id=id,bus=bus,addr=addr
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+
+    // netdev=id
+    #[serde(rename = "netdev", skip_serializing_if = "Option::is_none")]
+    pub netdev: Option<String>,
+
+    // mac=macaddr
+    #[serde(rename = "mac", skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+
+    // bootindex=n
+    #[serde(rename = "bootindex", skip_serializing_if = "Option::is_none")]
+    pub bootindex: Option<i32>,
+}
+
+/// A virtio SCSI controller on PCI. Its disks are scsi-hd devices on bus
+/// `<id>.0`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "device")]
+pub struct VirtioScsiPciConfig {
+    // id=id,bus=bus,addr=addr
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+
+    // iothread=id
+    #[serde(rename = "iothread", skip_serializing_if = "Option::is_none")]
+    pub iothread: Option<String>,
+
+    // num_queues=n
+    #[serde(rename = "num_queues", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "num_queues")]
+    pub num_queues: Option<usize>,
+}
+
+/// A SCSI disk, on the bus of a SCSI controller such as virtio-scsi-pci.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "device")]
+pub struct ScsiHdConfig {
+    // id=id,bus=bus,addr=addr
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+
+    // drive=id
+    #[serde(rename = "drive")]
+    pub drive: String,
+
+    // channel=c,scsi-id=t,lun=l
+    // The address of the disk on the SCSI bus.
+    #[serde(rename = "channel", skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u32>,
+
+    #[serde(rename = "scsi-id", skip_serializing_if = "Option::is_none")]
+    pub scsi_id: Option<u32>,
+
+    #[serde(rename = "lun", skip_serializing_if = "Option::is_none")]
+    pub lun: Option<u32>,
+
+    // serial=serial
+    #[serde(rename = "serial", skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+
+    // bootindex=n
+    #[serde(rename = "bootindex", skip_serializing_if = "Option::is_none")]
+    pub bootindex: Option<i32>,
+}
+
+/// A virtio entropy source on PCI.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "device")]
+pub struct VirtioRngPciConfig {
+    // id=id,bus=bus,addr=addr
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+
+    // rng=id
+    // The rng-random or rng-builtin object to read from. (default: a new
+    // rng-builtin)
+    #[serde(rename = "rng", skip_serializing_if = "Option::is_none")]
+    pub rng: Option<String>,
+
+    // max-bytes=n,period=ms
+    // Rate limit the guest to n bytes every period milliseconds.
+    #[serde(rename = "max-bytes", skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+
+    #[serde(rename = "period", skip_serializing_if = "Option::is_none")]
+    pub period: Option<u64>,
+}
+
+/// A virtio memory balloon on PCI.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "device")]
+pub struct VirtioBalloonPciConfig {
+    // id=id,bus=bus,addr=addr
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+
+    // deflate-on-oom=on|off
+    // Let the guest take memory back from the balloon when it runs out.
+    #[serde(rename = "deflate-on-oom", skip_serializing_if = "Option::is_none")]
+    pub deflate_on_oom: Option<OnOff>,
+
+    // free-page-reporting=on|off
+    // Let the guest report free pages for the host to reclaim.
+    #[serde(
+        rename = "free-page-reporting",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub free_page_reporting: Option<OnOff>,
+}
+
+/// A virtio GPU on PCI, without the legacy VGA of virtio-vga. Combine it
+/// with `-vga none`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "device")]
+pub struct VirtioGpuPciConfig {
+    // id=id,bus=bus,addr=addr
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+
+    // max_outputs=n
+    // Number of heads, up to 16. (default: 1)
+    #[serde(rename = "max_outputs", skip_serializing_if = "Option::is_none")]
+    pub max_outputs: Option<u32>,
+
+    // xres=width,yres=height
+    // The preferred resolution reported to the guest.
+    #[serde(rename = "xres", skip_serializing_if = "Option::is_none")]
+    pub xres: Option<u32>,
+
+    #[serde(rename = "yres", skip_serializing_if = "Option::is_none")]
+    pub yres: Option<u32>,
+
+    // edid=on|off
+    // Give the guest EDID data of the outputs. (default: on)
+    #[serde(rename = "edid", skip_serializing_if = "Option::is_none")]
+    pub edid: Option<OnOff>,
+}
+
+/// The most heads a virtio GPU has.
+const VIRTIO_GPU_MAX_OUTPUTS: u32 = 16;
+
+impl Validate for VirtioGpuPciConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        match self.max_outputs {
+            Some(0) => invalid(
+                errors,
+                path,
+                "max_outputs",
+                ValidationErrorKind::Invalid {
+                    expected: "at least one output",
+                },
+            ),
+            Some(n) if n > VIRTIO_GPU_MAX_OUTPUTS => invalid(
+                errors,
+                path,
+                "max_outputs",
+                ValidationErrorKind::AboveMaximum {
+                    value: n as usize,
+                    max: VIRTIO_GPU_MAX_OUTPUTS as usize,
+                },
+            ),
+            _ => {}
+        }
+        if self.xres.is_some() != self.yres.is_some() {
+            let (key, other) = if self.xres.is_some() {
+                ("xres", "yres")
+            } else {
+                ("yres", "xres")
+            };
+            invalid(
+                errors,
+                path,
+                key,
+                ValidationErrorKind::Requires { key: other },
+            );
+        }
+    }
+}
+
+/// An Intel HD Audio controller on conventional PCI. Codecs such as
+/// hda-duplex plug into its bus `id.0`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "device")]
+pub struct IntelHdaConfig {
+    // id=id,bus=bus,addr=addr
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+}
+
+/// The Intel HD Audio controller of the ICH9 chipset, built into the root
+/// complex of q35.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "device")]
+pub struct Ich9IntelHdaConfig {
+    // id=id,bus=bus,addr=addr
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+}
+
+/// An HDA codec with line-out and line-in, on the bus of an HDA controller.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "device")]
+pub struct HdaDuplexConfig {
+    // id=id,bus=bus,addr=addr
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+
+    // audiodev=id
+    // The -audiodev to play and record through.
+    #[serde(rename = "audiodev", skip_serializing_if = "Option::is_none")]
+    pub audiodev: Option<String>,
+}
+
+/// An Intel 82801AA AC97 sound card.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "device")]
+pub struct Ac97Config {
+    // id=id,bus=bus,addr=addr
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+
+    // audiodev=id
+    // The -audiodev to play and record through.
+    #[serde(rename = "audiodev", skip_serializing_if = "Option::is_none")]
+    pub audiodev: Option<String>,
+}
+
+/// A PCIe root port, giving one hotpluggable slot on a q35 machine.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "device")]
+pub struct PcieRootPortConfig {
+    // id=id,bus=bus,addr=addr
+    // The id names the bus of the device plugged into the port.
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+
+    // chassis=n,slot=n,port=n
+    // The chassis and slot number must be unique among root ports, port
+    // is the PCIe port number.
+    #[serde(rename = "chassis", skip_serializing_if = "Option::is_none")]
+    pub chassis: Option<u8>,
+
+    #[serde(rename = "slot", skip_serializing_if = "Option::is_none")]
+    pub slot: Option<u16>,
+
+    #[serde(rename = "port", skip_serializing_if = "Option::is_none")]
+    pub port: Option<u8>,
+
+    // multifunction=on|off
+    // Let further root ports share the slot as other functions.
+    #[serde(rename = "multifunction", skip_serializing_if = "Option::is_none")]
+    pub multifunction: Option<OnOff>,
+}
+
+/// A host PCI device passed through with VFIO.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "device")]
+pub struct VfioPciConfig {
+    // id=id,bus=bus,addr=addr
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(rename = "bus", skip_serializing_if = "Option::is_none")]
+    pub bus: Option<String>,
+
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+
+    // host=[domain:]bus:slot.function
+    // The host address of the device, e.g. 0000:01:00.0.
+    #[serde(rename = "host")]
+    pub host: String,
+
+    // rombar=0|1,romfile=path
+    // Whether to expose the option ROM, or the file to expose instead.
+    #[serde(rename = "rombar", skip_serializing_if = "Option::is_none")]
+    pub rombar: Option<u32>,
+
+    #[serde(rename = "romfile", skip_serializing_if = "Option::is_none")]
+    pub romfile: Option<String>,
+
+    // x-vga=on|off
+    // Expose the legacy VGA ranges of a GPU.
+    #[serde(rename = "x-vga", skip_serializing_if = "Option::is_none")]
+    pub x_vga: Option<OnOff>,
+
+    // display=on|off|auto
+    #[serde(rename = "display", skip_serializing_if = "Option::is_none")]
+    pub display: Option<OnOffAuto>,
+
+    // multifunction=on|off
+    #[serde(rename = "multifunction", skip_serializing_if = "Option::is_none")]
+    pub multifunction: Option<OnOff>,
+}
+
+impl Validate for VfioPciConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        // [domain:]bus:slot.function, all hexadecimal.
+        let hex = |s: &str, len: usize| s.len() == len && s.chars().all(|c| c.is_ascii_hexdigit());
+        let address = match self.host.split(':').collect::<Vec<_>>()[..] {
+            [domain, bus, device] if hex(domain, 4) => Some((bus, device)),
+            [bus, device] => Some((bus, device)),
+            _ => None,
+        };
+        let valid = address.is_some_and(|(bus, device)| {
+            hex(bus, 2)
+                && device
+                    .split_once('.')
+                    .is_some_and(|(slot, function)| hex(slot, 2) && hex(function, 1))
+        });
+        if !valid {
+            invalid(
+                errors,
+                path,
+                "host",
+                ValidationErrorKind::Invalid {
+                    expected: "a PCI address such as 0000:01:00.0",
+                },
+            );
+        }
+    }
+}
+
+/// Builder for `VirtioBlkPciConfig`, e.g.
+///     VirtioBlkPciConfig::builder("disk0").iothread("iothread0").build()
+#[derive(Debug, Clone)]
+pub struct VirtioBlkPciConfigBuilder {
+    config: VirtioBlkPciConfig,
+}
+
+impl VirtioBlkPciConfig {
+    pub fn builder(drive: impl Into<String>) -> VirtioBlkPciConfigBuilder {
+        VirtioBlkPciConfigBuilder {
+            config: VirtioBlkPciConfig {
+                id: None,
+                bus: None,
+                addr: None,
+                drive: drive.into(),
+                iothread: None,
+                num_queues: None,
+                serial: None,
+                bootindex: None,
+            },
+        }
+    }
+}
+
+impl VirtioBlkPciConfigBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn iothread(mut self, iothread: impl Into<String>) -> Self {
+        self.config.iothread = Some(iothread.into());
+        self
+    }
+
+    pub fn num_queues(mut self, num_queues: usize) -> Self {
+        self.config.num_queues = Some(num_queues);
+        self
+    }
+
+    pub fn serial(mut self, serial: impl Into<String>) -> Self {
+        self.config.serial = Some(serial.into());
+        self
+    }
+
+    pub fn bootindex(mut self, bootindex: i32) -> Self {
+        self.config.bootindex = Some(bootindex);
+        self
+    }
+
+    pub fn build(self) -> VirtioBlkPciConfig {
+        self.config
+    }
+}
+
+impl From<VirtioBlkPciConfigBuilder> for VirtioBlkPciConfig {
+    fn from(builder: VirtioBlkPciConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<VirtioBlkPciConfig> for DeviceConfig {
+    fn from(device: VirtioBlkPciConfig) -> Self {
+        DeviceConfig::VirtioBlkPci(device)
+    }
+}
+
+impl From<VirtioBlkPciConfigBuilder> for DeviceConfig {
+    fn from(builder: VirtioBlkPciConfigBuilder) -> Self {
+        DeviceConfig::VirtioBlkPci(builder.build())
+    }
+}
+
+/// Builder for `VirtioNetPciConfig`, e.g.
+///     VirtioNetPciConfig::builder("net0").mac("52:54:00:12:34:56").build()
+#[derive(Debug, Clone)]
+pub struct VirtioNetPciConfigBuilder {
+    config: VirtioNetPciConfig,
+}
+
+impl VirtioNetPciConfig {
+    pub fn builder(netdev: impl Into<String>) -> VirtioNetPciConfigBuilder {
+        VirtioNetPciConfigBuilder {
+            config: VirtioNetPciConfig {
+                id: None,
+                bus: None,
+                addr: None,
+                netdev: Some(netdev.into()),
+                mac: None,
+                mq: None,
+                vectors: None,
+                bootindex: None,
+            },
+        }
+    }
+}
+
+impl VirtioNetPciConfigBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn mac(mut self, mac: impl Into<String>) -> Self {
+        self.config.mac = Some(mac.into());
+        self
+    }
+
+    pub fn mq(mut self, mq: impl Into<OnOff>) -> Self {
+        self.config.mq = Some(mq.into());
+        self
+    }
+
+    pub fn vectors(mut self, vectors: usize) -> Self {
+        self.config.vectors = Some(vectors);
+        self
+    }
+
+    pub fn bootindex(mut self, bootindex: i32) -> Self {
+        self.config.bootindex = Some(bootindex);
+        self
+    }
+
+    pub fn build(self) -> VirtioNetPciConfig {
+        self.config
+    }
+}
+
+impl From<VirtioNetPciConfigBuilder> for VirtioNetPciConfig {
+    fn from(builder: VirtioNetPciConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<VirtioNetPciConfig> for DeviceConfig {
+    fn from(device: VirtioNetPciConfig) -> Self {
+        DeviceConfig::VirtioNetPci(device)
+    }
+}
+
+impl From<VirtioNetPciConfigBuilder> for DeviceConfig {
+    fn from(builder: VirtioNetPciConfigBuilder) -> Self {
+        DeviceConfig::VirtioNetPci(builder.build())
+    }
+}
+
+/// Builder for `E1000Config`, e.g.
+///     E1000Config::builder("net0").mac("52:54:00:12:34:56").build()
+#[derive(Debug, Clone)]
+pub struct E1000ConfigBuilder {
+    config: E1000Config,
+}
+
+impl E1000Config {
+    pub fn builder(netdev: impl Into<String>) -> E1000ConfigBuilder {
+        E1000ConfigBuilder {
+            config: E1000Config {
+                id: None,
+                bus: None,
+                addr: None,
+                netdev: Some(netdev.into()),
+                mac: None,
+                bootindex: None,
+            },
+        }
+    }
+}
+
+impl E1000ConfigBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn mac(mut self, mac: impl Into<String>) -> Self {
+        self.config.mac = Some(mac.into());
+        self
+    }
+
+    pub fn bootindex(mut self, bootindex: i32) -> Self {
+        self.config.bootindex = Some(bootindex);
+        self
+    }
+
+    pub fn build(self) -> E1000Config {
+        self.config
+    }
+}
+
+impl From<E1000ConfigBuilder> for E1000Config {
+    fn from(builder: E1000ConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<E1000Config> for DeviceConfig {
+    fn from(device: E1000Config) -> Self {
+        DeviceConfig::E1000(device)
+    }
+}
+
+impl From<E1000ConfigBuilder> for DeviceConfig {
+    fn from(builder: E1000ConfigBuilder) -> Self {
+        DeviceConfig::E1000(builder.build())
+    }
+}
+
+/// Builder for `E1000eConfig`, e.g.
+///     E1000eConfig::builder("net0").mac("52:54:00:12:34:56").build()
+#[derive(Debug, Clone)]
+pub struct E1000eConfigBuilder {
+    config: E1000eConfig,
+}
+
+impl E1000eConfig {
+    pub fn builder(netdev: impl Into<String>) -> E1000eConfigBuilder {
+        E1000eConfigBuilder {
+            config: E1000eConfig {
+                id: None,
+                bus: None,
+                addr: None,
+                netdev: Some(netdev.into()),
+                mac: None,
+                bootindex: None,
+            },
+        }
+    }
+}
+
+impl E1000eConfigBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn mac(mut self, mac: impl Into<String>) -> Self {
+        self.config.mac = Some(mac.into());
+        self
+    }
+
+    pub fn bootindex(mut self, bootindex: i32) -> Self {
+        self.config.bootindex = Some(bootindex);
+        self
+    }
+
+    pub fn build(self) -> E1000eConfig {
+        self.config
+    }
+}
+
+impl From<E1000eConfigBuilder> for E1000eConfig {
+    fn from(builder: E1000eConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<E1000eConfig> for DeviceConfig {
+    fn from(device: E1000eConfig) -> Self {
+        DeviceConfig::E1000e(device)
+    }
+}
+
+impl From<E1000eConfigBuilder> for DeviceConfig {
+    fn from(builder: E1000eConfigBuilder) -> Self {
+        DeviceConfig::E1000e(builder.build())
+    }
+}
+
+/// Builder for `VirtioScsiPciConfig`, e.g.
+///     VirtioScsiPciConfig::builder().id("scsi0").build()
+#[derive(Debug, Clone)]
+pub struct VirtioScsiPciConfigBuilder {
+    config: VirtioScsiPciConfig,
+}
+
+impl VirtioScsiPciConfig {
+    pub fn builder() -> VirtioScsiPciConfigBuilder {
+        VirtioScsiPciConfigBuilder {
+            config: VirtioScsiPciConfig {
+                id: None,
+                bus: None,
+                addr: None,
+                iothread: None,
+                num_queues: None,
+            },
+        }
+    }
+}
+
+impl VirtioScsiPciConfigBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn iothread(mut self, iothread: impl Into<String>) -> Self {
+        self.config.iothread = Some(iothread.into());
+        self
+    }
+
+    pub fn num_queues(mut self, num_queues: usize) -> Self {
+        self.config.num_queues = Some(num_queues);
+        self
+    }
+
+    pub fn build(self) -> VirtioScsiPciConfig {
+        self.config
+    }
+}
+
+impl From<VirtioScsiPciConfigBuilder> for VirtioScsiPciConfig {
+    fn from(builder: VirtioScsiPciConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<VirtioScsiPciConfig> for DeviceConfig {
+    fn from(device: VirtioScsiPciConfig) -> Self {
+        DeviceConfig::VirtioScsiPci(device)
+    }
+}
+
+impl From<VirtioScsiPciConfigBuilder> for DeviceConfig {
+    fn from(builder: VirtioScsiPciConfigBuilder) -> Self {
+        DeviceConfig::VirtioScsiPci(builder.build())
+    }
+}
+
+/// Builder for `ScsiHdConfig`, e.g.
+///     ScsiHdConfig::builder("disk1").bus("scsi0.0").lun(1).build()
+#[derive(Debug, Clone)]
+pub struct ScsiHdConfigBuilder {
+    config: ScsiHdConfig,
+}
+
+impl ScsiHdConfig {
+    pub fn builder(drive: impl Into<String>) -> ScsiHdConfigBuilder {
+        ScsiHdConfigBuilder {
+            config: ScsiHdConfig {
+                id: None,
+                bus: None,
+                addr: None,
+                drive: drive.into(),
+                channel: None,
+                scsi_id: None,
+                lun: None,
+                serial: None,
+                bootindex: None,
+            },
+        }
+    }
+}
+
+impl ScsiHdConfigBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn channel(mut self, channel: u32) -> Self {
+        self.config.channel = Some(channel);
+        self
+    }
+
+    pub fn scsi_id(mut self, scsi_id: u32) -> Self {
+        self.config.scsi_id = Some(scsi_id);
+        self
+    }
+
+    pub fn lun(mut self, lun: u32) -> Self {
+        self.config.lun = Some(lun);
+        self
+    }
+
+    pub fn serial(mut self, serial: impl Into<String>) -> Self {
+        self.config.serial = Some(serial.into());
+        self
+    }
+
+    pub fn bootindex(mut self, bootindex: i32) -> Self {
+        self.config.bootindex = Some(bootindex);
+        self
+    }
+
+    pub fn build(self) -> ScsiHdConfig {
+        self.config
+    }
+}
+
+impl From<ScsiHdConfigBuilder> for ScsiHdConfig {
+    fn from(builder: ScsiHdConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<ScsiHdConfig> for DeviceConfig {
+    fn from(device: ScsiHdConfig) -> Self {
+        DeviceConfig::ScsiHd(device)
+    }
+}
+
+impl From<ScsiHdConfigBuilder> for DeviceConfig {
+    fn from(builder: ScsiHdConfigBuilder) -> Self {
+        DeviceConfig::ScsiHd(builder.build())
+    }
+}
+
+/// Builder for `VirtioRngPciConfig`, e.g.
+///     VirtioRngPciConfig::builder().rng("rng0").build()
+#[derive(Debug, Clone)]
+pub struct VirtioRngPciConfigBuilder {
+    config: VirtioRngPciConfig,
+}
+
+impl VirtioRngPciConfig {
+    pub fn builder() -> VirtioRngPciConfigBuilder {
+        VirtioRngPciConfigBuilder {
+            config: VirtioRngPciConfig {
+                id: None,
+                bus: None,
+                addr: None,
+                rng: None,
+                max_bytes: None,
+                period: None,
+            },
+        }
+    }
 }
 
-impl<'a> OptionFormatting<'a> for DeviceConfig<'a> {
-    fn formatting(&self) -> OptionQ<'a> {
-        todo!()
+impl VirtioRngPciConfigBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
     }
-}
\ No newline at end of file
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn rng(mut self, rng: impl Into<String>) -> Self {
+        self.config.rng = Some(rng.into());
+        self
+    }
+
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.config.max_bytes = Some(max_bytes);
+        self
+    }
+
+    pub fn period(mut self, period: u64) -> Self {
+        self.config.period = Some(period);
+        self
+    }
+
+    pub fn build(self) -> VirtioRngPciConfig {
+        self.config
+    }
+}
+
+impl From<VirtioRngPciConfigBuilder> for VirtioRngPciConfig {
+    fn from(builder: VirtioRngPciConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<VirtioRngPciConfig> for DeviceConfig {
+    fn from(device: VirtioRngPciConfig) -> Self {
+        DeviceConfig::VirtioRngPci(device)
+    }
+}
+
+impl From<VirtioRngPciConfigBuilder> for DeviceConfig {
+    fn from(builder: VirtioRngPciConfigBuilder) -> Self {
+        DeviceConfig::VirtioRngPci(builder.build())
+    }
+}
+
+/// Builder for `VirtioBalloonPciConfig`, e.g.
+///     VirtioBalloonPciConfig::builder().free_page_reporting(true).build()
+#[derive(Debug, Clone)]
+pub struct VirtioBalloonPciConfigBuilder {
+    config: VirtioBalloonPciConfig,
+}
+
+impl VirtioBalloonPciConfig {
+    pub fn builder() -> VirtioBalloonPciConfigBuilder {
+        VirtioBalloonPciConfigBuilder {
+            config: VirtioBalloonPciConfig {
+                id: None,
+                bus: None,
+                addr: None,
+                deflate_on_oom: None,
+                free_page_reporting: None,
+            },
+        }
+    }
+}
+
+impl VirtioBalloonPciConfigBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn deflate_on_oom(mut self, deflate_on_oom: impl Into<OnOff>) -> Self {
+        self.config.deflate_on_oom = Some(deflate_on_oom.into());
+        self
+    }
+
+    pub fn free_page_reporting(mut self, free_page_reporting: impl Into<OnOff>) -> Self {
+        self.config.free_page_reporting = Some(free_page_reporting.into());
+        self
+    }
+
+    pub fn build(self) -> VirtioBalloonPciConfig {
+        self.config
+    }
+}
+
+impl From<VirtioBalloonPciConfigBuilder> for VirtioBalloonPciConfig {
+    fn from(builder: VirtioBalloonPciConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<VirtioBalloonPciConfig> for DeviceConfig {
+    fn from(device: VirtioBalloonPciConfig) -> Self {
+        DeviceConfig::VirtioBalloonPci(device)
+    }
+}
+
+impl From<VirtioBalloonPciConfigBuilder> for DeviceConfig {
+    fn from(builder: VirtioBalloonPciConfigBuilder) -> Self {
+        DeviceConfig::VirtioBalloonPci(builder.build())
+    }
+}
+
+/// Builder for `VirtioGpuPciConfig`, e.g.
+///     VirtioGpuPciConfig::builder().xres(1920).yres(1080).build()
+#[derive(Debug, Clone)]
+pub struct VirtioGpuPciConfigBuilder {
+    config: VirtioGpuPciConfig,
+}
+
+impl VirtioGpuPciConfig {
+    pub fn builder() -> VirtioGpuPciConfigBuilder {
+        VirtioGpuPciConfigBuilder {
+            config: VirtioGpuPciConfig {
+                id: None,
+                bus: None,
+                addr: None,
+                max_outputs: None,
+                xres: None,
+                yres: None,
+                edid: None,
+            },
+        }
+    }
+}
+
+impl VirtioGpuPciConfigBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn max_outputs(mut self, max_outputs: u32) -> Self {
+        self.config.max_outputs = Some(max_outputs);
+        self
+    }
+
+    pub fn xres(mut self, xres: u32) -> Self {
+        self.config.xres = Some(xres);
+        self
+    }
+
+    pub fn yres(mut self, yres: u32) -> Self {
+        self.config.yres = Some(yres);
+        self
+    }
+
+    pub fn edid(mut self, edid: impl Into<OnOff>) -> Self {
+        self.config.edid = Some(edid.into());
+        self
+    }
+
+    pub fn build(self) -> VirtioGpuPciConfig {
+        self.config
+    }
+}
+
+impl From<VirtioGpuPciConfigBuilder> for VirtioGpuPciConfig {
+    fn from(builder: VirtioGpuPciConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<VirtioGpuPciConfig> for DeviceConfig {
+    fn from(device: VirtioGpuPciConfig) -> Self {
+        DeviceConfig::VirtioGpuPci(device)
+    }
+}
+
+impl From<VirtioGpuPciConfigBuilder> for DeviceConfig {
+    fn from(builder: VirtioGpuPciConfigBuilder) -> Self {
+        DeviceConfig::VirtioGpuPci(builder.build())
+    }
+}
+
+/// Builder for `IntelHdaConfig`, e.g.
+///     IntelHdaConfig::builder().id("hda0").build()
+#[derive(Debug, Clone)]
+pub struct IntelHdaConfigBuilder {
+    config: IntelHdaConfig,
+}
+
+impl IntelHdaConfig {
+    pub fn builder() -> IntelHdaConfigBuilder {
+        IntelHdaConfigBuilder {
+            config: IntelHdaConfig {
+                id: None,
+                bus: None,
+                addr: None,
+            },
+        }
+    }
+}
+
+impl IntelHdaConfigBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn build(self) -> IntelHdaConfig {
+        self.config
+    }
+}
+
+impl From<IntelHdaConfigBuilder> for IntelHdaConfig {
+    fn from(builder: IntelHdaConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<IntelHdaConfig> for DeviceConfig {
+    fn from(device: IntelHdaConfig) -> Self {
+        DeviceConfig::IntelHda(device)
+    }
+}
+
+impl From<IntelHdaConfigBuilder> for DeviceConfig {
+    fn from(builder: IntelHdaConfigBuilder) -> Self {
+        DeviceConfig::IntelHda(builder.build())
+    }
+}
+
+/// Builder for `Ich9IntelHdaConfig`, e.g.
+///     Ich9IntelHdaConfig::builder().id("hda0").build()
+#[derive(Debug, Clone)]
+pub struct Ich9IntelHdaConfigBuilder {
+    config: Ich9IntelHdaConfig,
+}
+
+impl Ich9IntelHdaConfig {
+    pub fn builder() -> Ich9IntelHdaConfigBuilder {
+        Ich9IntelHdaConfigBuilder {
+            config: Ich9IntelHdaConfig {
+                id: None,
+                bus: None,
+                addr: None,
+            },
+        }
+    }
+}
+
+impl Ich9IntelHdaConfigBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn build(self) -> Ich9IntelHdaConfig {
+        self.config
+    }
+}
+
+impl From<Ich9IntelHdaConfigBuilder> for Ich9IntelHdaConfig {
+    fn from(builder: Ich9IntelHdaConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<Ich9IntelHdaConfig> for DeviceConfig {
+    fn from(device: Ich9IntelHdaConfig) -> Self {
+        DeviceConfig::Ich9IntelHda(device)
+    }
+}
+
+impl From<Ich9IntelHdaConfigBuilder> for DeviceConfig {
+    fn from(builder: Ich9IntelHdaConfigBuilder) -> Self {
+        DeviceConfig::Ich9IntelHda(builder.build())
+    }
+}
+
+/// Builder for `HdaDuplexConfig`, e.g.
+///     HdaDuplexConfig::builder().bus("hda0.0").audiodev("snd0").build()
+#[derive(Debug, Clone)]
+pub struct HdaDuplexConfigBuilder {
+    config: HdaDuplexConfig,
+}
+
+impl HdaDuplexConfig {
+    pub fn builder() -> HdaDuplexConfigBuilder {
+        HdaDuplexConfigBuilder {
+            config: HdaDuplexConfig {
+                id: None,
+                bus: None,
+                addr: None,
+                audiodev: None,
+            },
+        }
+    }
+}
+
+impl HdaDuplexConfigBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn audiodev(mut self, audiodev: impl Into<String>) -> Self {
+        self.config.audiodev = Some(audiodev.into());
+        self
+    }
+
+    pub fn build(self) -> HdaDuplexConfig {
+        self.config
+    }
+}
+
+impl From<HdaDuplexConfigBuilder> for HdaDuplexConfig {
+    fn from(builder: HdaDuplexConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<HdaDuplexConfig> for DeviceConfig {
+    fn from(device: HdaDuplexConfig) -> Self {
+        DeviceConfig::HdaDuplex(device)
+    }
+}
+
+impl From<HdaDuplexConfigBuilder> for DeviceConfig {
+    fn from(builder: HdaDuplexConfigBuilder) -> Self {
+        DeviceConfig::HdaDuplex(builder.build())
+    }
+}
+
+/// Builder for `Ac97Config`, e.g.
+///     Ac97Config::builder().audiodev("snd0").build()
+#[derive(Debug, Clone)]
+pub struct Ac97ConfigBuilder {
+    config: Ac97Config,
+}
+
+impl Ac97Config {
+    pub fn builder() -> Ac97ConfigBuilder {
+        Ac97ConfigBuilder {
+            config: Ac97Config {
+                id: None,
+                bus: None,
+                addr: None,
+                audiodev: None,
+            },
+        }
+    }
+}
+
+impl Ac97ConfigBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn audiodev(mut self, audiodev: impl Into<String>) -> Self {
+        self.config.audiodev = Some(audiodev.into());
+        self
+    }
+
+    pub fn build(self) -> Ac97Config {
+        self.config
+    }
+}
+
+impl From<Ac97ConfigBuilder> for Ac97Config {
+    fn from(builder: Ac97ConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<Ac97Config> for DeviceConfig {
+    fn from(device: Ac97Config) -> Self {
+        DeviceConfig::Ac97(device)
+    }
+}
+
+impl From<Ac97ConfigBuilder> for DeviceConfig {
+    fn from(builder: Ac97ConfigBuilder) -> Self {
+        DeviceConfig::Ac97(builder.build())
+    }
+}
+
+/// Builder for `PcieRootPortConfig`, e.g.
+///     PcieRootPortConfig::builder().id("rp0").chassis(1).slot(1).build()
+#[derive(Debug, Clone)]
+pub struct PcieRootPortConfigBuilder {
+    config: PcieRootPortConfig,
+}
+
+impl PcieRootPortConfig {
+    pub fn builder() -> PcieRootPortConfigBuilder {
+        PcieRootPortConfigBuilder {
+            config: PcieRootPortConfig {
+                id: None,
+                bus: None,
+                addr: None,
+                chassis: None,
+                slot: None,
+                port: None,
+                multifunction: None,
+            },
+        }
+    }
+}
+
+impl PcieRootPortConfigBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn chassis(mut self, chassis: u8) -> Self {
+        self.config.chassis = Some(chassis);
+        self
+    }
+
+    pub fn slot(mut self, slot: u16) -> Self {
+        self.config.slot = Some(slot);
+        self
+    }
+
+    pub fn port(mut self, port: u8) -> Self {
+        self.config.port = Some(port);
+        self
+    }
+
+    pub fn multifunction(mut self, multifunction: impl Into<OnOff>) -> Self {
+        self.config.multifunction = Some(multifunction.into());
+        self
+    }
+
+    pub fn build(self) -> PcieRootPortConfig {
+        self.config
+    }
+}
+
+impl From<PcieRootPortConfigBuilder> for PcieRootPortConfig {
+    fn from(builder: PcieRootPortConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<PcieRootPortConfig> for DeviceConfig {
+    fn from(device: PcieRootPortConfig) -> Self {
+        DeviceConfig::PcieRootPort(device)
+    }
+}
+
+impl From<PcieRootPortConfigBuilder> for DeviceConfig {
+    fn from(builder: PcieRootPortConfigBuilder) -> Self {
+        DeviceConfig::PcieRootPort(builder.build())
+    }
+}
+
+/// Builder for `VfioPciConfig`, e.g.
+///     VfioPciConfig::builder("0000:01:00.0").rombar(0).build()
+#[derive(Debug, Clone)]
+pub struct VfioPciConfigBuilder {
+    config: VfioPciConfig,
+}
+
+impl VfioPciConfig {
+    pub fn builder(host: impl Into<String>) -> VfioPciConfigBuilder {
+        VfioPciConfigBuilder {
+            config: VfioPciConfig {
+                id: None,
+                bus: None,
+                addr: None,
+                host: host.into(),
+                rombar: None,
+                romfile: None,
+                x_vga: None,
+                display: None,
+                multifunction: None,
+            },
+        }
+    }
+}
+
+impl VfioPciConfigBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.config.id = Some(id.into());
+        self
+    }
+
+    pub fn bus(mut self, bus: impl Into<String>) -> Self {
+        self.config.bus = Some(bus.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn rombar(mut self, rombar: u32) -> Self {
+        self.config.rombar = Some(rombar);
+        self
+    }
+
+    pub fn romfile(mut self, romfile: impl Into<String>) -> Self {
+        self.config.romfile = Some(romfile.into());
+        self
+    }
+
+    pub fn x_vga(mut self, x_vga: impl Into<OnOff>) -> Self {
+        self.config.x_vga = Some(x_vga.into());
+        self
+    }
+
+    pub fn display(mut self, display: impl Into<OnOffAuto>) -> Self {
+        self.config.display = Some(display.into());
+        self
+    }
+
+    pub fn multifunction(mut self, multifunction: impl Into<OnOff>) -> Self {
+        self.config.multifunction = Some(multifunction.into());
+        self
+    }
+
+    pub fn build(self) -> VfioPciConfig {
+        self.config
+    }
+}
+
+impl From<VfioPciConfigBuilder> for VfioPciConfig {
+    fn from(builder: VfioPciConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<VfioPciConfig> for DeviceConfig {
+    fn from(device: VfioPciConfig) -> Self {
+        DeviceConfig::VfioPci(device)
+    }
+}
+
+impl From<VfioPciConfigBuilder> for DeviceConfig {
+    fn from(builder: VfioPciConfigBuilder) -> Self {
+        DeviceConfig::VfioPci(builder.build())
+    }
+}
+
+impl From<GenericDeviceConfig> for DeviceConfig {
+    fn from(device: GenericDeviceConfig) -> Self {
+        DeviceConfig::Generic(device)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &str) -> DeviceConfig {
+        let argv = ["qemu-system-x86_64", "-device", args];
+        let command = parse_args(&argv).unwrap();
+        DeviceConfig::parsing(&command.options[0]).unwrap()
+    }
+
+    #[test]
+    fn test_device() {
+        let disk = DeviceConfig::from(
+            VirtioBlkPciConfig::builder("disk0")
+                .id("vda")
+                .bus("pcie.0")
+                .addr("0x4")
+                .iothread("iothread0")
+                .bootindex(1),
+        );
+        let formatted =
+            "-device virtio-blk-pci,id=vda,bus=pcie.0,addr=0x4,drive=disk0,iothread=iothread0,bootindex=1";
+        assert_eq!(disk.formatting().to_string(), formatted);
+        assert_eq!(parse(&formatted[8..]), disk);
+        assert_eq!(
+            serde_json::to_string(&disk).unwrap(),
+            r#"{"driver":"virtio-blk-pci","id":"vda","bus":"pcie.0","addr":"0x4","drive":"disk0","iothread":"iothread0","bootindex":1}"#
+        );
+
+        let tablet = DeviceConfig::from(
+            GenericDeviceConfig::new("usb-tablet")
+                .bus("usb.0")
+                .set("port", 1),
+        );
+        assert_eq!(
+            tablet.formatting().to_string(),
+            "-device usb-tablet,bus=usb.0,port=1"
+        );
+        assert_eq!(parse("usb-tablet,bus=usb.0,port=1"), tablet);
+        let json = r#"{"driver":"usb-tablet","bus":"usb.0","port":"1"}"#;
+        assert_eq!(serde_json::to_string(&tablet).unwrap(), json);
+        assert_eq!(serde_json::from_str::<DeviceConfig>(json).unwrap(), tablet);
+
+        // Properties without a typed field keep the device in generic form.
+        assert_eq!(
+            parse("virtio-net-pci,netdev=net0,rx_queue_size=1024"),
+            DeviceConfig::Generic(
+                GenericDeviceConfig::new("virtio-net-pci")
+                    .set("netdev", "net0")
+                    .set("rx_queue_size", 1024)
+            )
+        );
+        let json = r#"{"driver":"virtio-net-pci","netdev":"net0","rx_queue_size":1024}"#;
+        assert_eq!(
+            serde_json::from_str::<DeviceConfig>(json).unwrap(),
+            parse("virtio-net-pci,netdev=net0,rx_queue_size=1024")
+        );
+        let json = r#"{"driver":"usb-tablet","port":1,"usb_version":2.0,"msos-desc":false}"#;
+        assert_eq!(
+            serde_json::from_str::<DeviceConfig>(json).unwrap(),
+            parse("usb-tablet,msos-desc=false,port=1,usb_version=2.0")
+        );
+        let json = r#"{"driver":"virtio-blk-pci","drive":"disk0","num-queues":4}"#;
+        assert_eq!(
+            serde_json::from_str::<DeviceConfig>(json).unwrap(),
+            VirtioBlkPciConfig::builder("disk0").num_queues(4).into()
+        );
+
+        let nic = DeviceConfig::from(
+            E1000eConfig::builder("net0")
+                .id("nic0")
+                .mac("52:54:00:12:34:56"),
+        );
+        let formatted = "-device e1000e,id=nic0,netdev=net0,mac=52:54:00:12:34:56";
+        assert_eq!(nic.formatting().to_string(), formatted);
+        assert_eq!(parse(&formatted[8..]), nic);
+        let json = r#"{"driver":"e1000e","id":"nic0","netdev":"net0","mac":"52:54:00:12:34:56"}"#;
+        assert_eq!(serde_json::to_string(&nic).unwrap(), json);
+        assert_eq!(serde_json::from_str::<DeviceConfig>(json).unwrap(), nic);
+
+        // A NIC without a link and a disk left out of the boot order.
+        assert_eq!(
+            parse("virtio-net-pci"),
+            DeviceConfig::VirtioNetPci(VirtioNetPciConfig {
+                netdev: None,
+                ..VirtioNetPciConfig::builder("").build()
+            })
+        );
+        assert_eq!(parse("e1000,mac=52:54:00:12:34:56").netdev(), None);
+        assert_eq!(
+            parse("virtio-blk-pci,drive=d0,bootindex=-1"),
+            VirtioBlkPciConfig::builder("d0").bootindex(-1).into()
+        );
+
+        // A value outside the typed range leaves the device generic.
+        assert_eq!(
+            parse("virtio-blk-pci,drive=disk0,num-queues=abc"),
+            DeviceConfig::Generic(
+                GenericDeviceConfig::new("virtio-blk-pci")
+                    .set("drive", "disk0")
+                    .set("num-queues", "abc")
+            )
+        );
+        let json = r#"{"driver":"virtio-blk-pci","drive":"disk0","num-queues":"abc"}"#;
+        assert_eq!(
+            serde_json::from_str::<DeviceConfig>(json).unwrap(),
+            parse("virtio-blk-pci,drive=disk0,num-queues=abc")
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let devices: Vec<DeviceConfig> = vec![
+            VirtioNetPciConfig::builder("net0")
+                .id("dev0")
+                .mac("52:54:00:12:34")
+                .into(),
+            VfioPciConfig::builder("01:00.0").into(),
+            VfioPciConfig::builder("0000:01:0.0").id("dev0").into(),
+            ScsiHdConfig::builder("disk0").into(),
+            VirtioBlkPciConfig::builder("disk0").into(),
+            VirtioBlkPciConfig::builder("disk9").into(),
+            VirtioGpuPciConfig::builder()
+                .max_outputs(17)
+                .xres(1920)
+                .into(),
+            E1000Config::builder("net1").mac("52-54-00-12-34-56").into(),
+        ];
+        let mut errors = Vec::new();
+        devices.validate("device", &mut errors);
+        validate_references(&devices, &["disk0"], &mut errors);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "device[0].mac: expected a MAC address such as 52:54:00:12:34:56",
+                "device[2].host: expected a PCI address such as 0000:01:00.0",
+                "device[6].max_outputs: 17 exceeds the maximum of 16",
+                "device[6].xres: requires 'yres'",
+                "device[7].mac: expected a MAC address such as 52:54:00:12:34:56",
+                "device[2].id: 'dev0' is used more than once",
+                "device[4].drive: 'disk0' is used more than once",
+                "device[5].drive: 'disk9' is not defined",
+            ]
+        );
+    }
+}
@@ -0,0 +1,643 @@
+//! Graphics output: the local display, a VNC server and the emulated video
+//! card, e.g. a headless VM reachable over VNC only:
+//!     -display none
+//!     -vnc unix:/run/vm/vnc.sock,password-secret=vnc-pw
+//!     -vga none -device virtio-gpu-pci
+use std::fmt;
+use std::str::FromStr;
+
+use crate::command::builder::*;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{
+    types::{qemu_enum, InvalidToken, OnOff},
+    validate::*,
+};
+
+qemu_enum! {
+    /// Front-ends of -display.
+    pub enum DisplayType {
+        None = "none",
+        Gtk = "gtk",
+        Sdl = "sdl",
+        EglHeadless = "egl-headless",
+        Dbus = "dbus",
+    }
+}
+
+qemu_enum! {
+    /// gl=on|off|core|es
+    pub enum GlMode {
+        On = "on" | "yes" | "true" | "y",
+        Off = "off" | "no" | "false" | "n",
+        Core = "core",
+        Es = "es",
+    }
+}
+
+impl From<bool> for GlMode {
+    fn from(b: bool) -> Self {
+        if b {
+            GlMode::On
+        } else {
+            GlMode::Off
+        }
+    }
+}
+
+/// Select the local display of the guest.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "display")]
+pub struct DisplayConfig {
+    // none|gtk|sdl|egl-headless|dbus
+    // none still lets -vnc show the guest.
+    #[serde(rename = "type")]
+    #[qemu(positional, rename = "type")]
+    pub display_type: DisplayType,
+
+    // gl=on|off|core|es (gtk, sdl, egl-headless, dbus)
+    // Render with OpenGL, needed by virtio-gpu-gl-pci.
+    #[serde(rename = "gl", skip_serializing_if = "Option::is_none")]
+    pub gl: Option<GlMode>,
+
+    // full-screen=on|off (gtk, sdl)
+    #[serde(rename = "full-screen", skip_serializing_if = "Option::is_none")]
+    pub full_screen: Option<OnOff>,
+
+    // zoom-to-fit=on|off (gtk)
+    // Scale the guest output to the size of the window.
+    #[serde(rename = "zoom-to-fit", skip_serializing_if = "Option::is_none")]
+    pub zoom_to_fit: Option<OnOff>,
+
+    // grab-on-hover=on|off (gtk)
+    // Grab the keyboard while the pointer is over the window.
+    #[serde(rename = "grab-on-hover", skip_serializing_if = "Option::is_none")]
+    pub grab_on_hover: Option<OnOff>,
+
+    // show-tabs=on|off (gtk)
+    // Show a tab for every console, e.g. the monitor and serial ports.
+    #[serde(rename = "show-tabs", skip_serializing_if = "Option::is_none")]
+    pub show_tabs: Option<OnOff>,
+
+    // show-cursor=on|off (gtk, sdl)
+    #[serde(rename = "show-cursor", skip_serializing_if = "Option::is_none")]
+    pub show_cursor: Option<OnOff>,
+
+    // window-close=on|off (gtk, sdl)
+    // Whether closing the window quits QEMU. (default: on)
+    #[serde(rename = "window-close", skip_serializing_if = "Option::is_none")]
+    pub window_close: Option<OnOff>,
+
+    // rendernode=path (egl-headless, dbus)
+    // The DRM render node to render with, e.g. /dev/dri/renderD128.
+    #[serde(rename = "rendernode", skip_serializing_if = "Option::is_none")]
+    pub rendernode: Option<String>,
+
+    // addr=address (dbus)
+    // The D-Bus bus to export the display on. (default: the session bus)
+    #[serde(rename = "addr", skip_serializing_if = "Option::is_none")]
+    pub addr: Option<String>,
+
+    // p2p=on|off (dbus)
+    // Accept peer-to-peer connections handed over QMP instead of a bus.
+    #[serde(rename = "p2p", skip_serializing_if = "Option::is_none")]
+    pub p2p: Option<OnOff>,
+}
+
+impl DisplayConfig {
+    /// Front-end specific keys which are given, with the front-ends
+    /// accepting them.
+    fn type_keys(&self) -> [(&'static str, bool, &'static [DisplayType]); 10] {
+        use DisplayType::*;
+        [
+            ("gl", self.gl.is_some(), &[Gtk, Sdl, EglHeadless, Dbus]),
+            ("full-screen", self.full_screen.is_some(), &[Gtk, Sdl]),
+            ("zoom-to-fit", self.zoom_to_fit.is_some(), &[Gtk]),
+            ("grab-on-hover", self.grab_on_hover.is_some(), &[Gtk]),
+            ("show-tabs", self.show_tabs.is_some(), &[Gtk]),
+            ("show-cursor", self.show_cursor.is_some(), &[Gtk, Sdl]),
+            ("window-close", self.window_close.is_some(), &[Gtk, Sdl]),
+            (
+                "rendernode",
+                self.rendernode.is_some(),
+                &[EglHeadless, Dbus],
+            ),
+            ("addr", self.addr.is_some(), &[Dbus]),
+            ("p2p", self.p2p.is_some(), &[Dbus]),
+        ]
+    }
+}
+
+impl Validate for DisplayConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        for (key, given, types) in self.type_keys() {
+            if given && !types.contains(&self.display_type) {
+                invalid(
+                    errors,
+                    path,
+                    key,
+                    ValidationErrorKind::NotSupported {
+                        by: format!("type={}", self.display_type),
+                    },
+                );
+            }
+        }
+        if self.addr.is_some() && self.p2p == Some(OnOff::On) {
+            invalid(
+                errors,
+                path,
+                "addr",
+                ValidationErrorKind::Conflicts { key: "p2p=on" },
+            );
+        }
+    }
+}
+
+/// Where a VNC server listens: `host:display` for TCP port 5900 + display,
+/// `unix:path`, or `none` to start without listening until a client is
+/// added over QMP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VncListen {
+    Tcp { host: String, display: u16 },
+    Unix(String),
+    None,
+}
+
+impl VncListen {
+    pub const FORMATS: &'static [&'static str] = &["host:display", "unix:path", "none"];
+
+    /// The TCP port of a TCP listener.
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            VncListen::Tcp { display, .. } => 5900u16.checked_add(*display),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for VncListen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VncListen::Tcp { host, display } => write!(f, "{}:{}", host, display),
+            VncListen::Unix(path) => write!(f, "unix:{}", path),
+            VncListen::None => f.write_str("none"),
+        }
+    }
+}
+
+impl FromStr for VncListen {
+    type Err = InvalidToken;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "none" {
+            return Ok(VncListen::None);
+        }
+        if let Some(path) = s.strip_prefix("unix:") {
+            return Ok(VncListen::Unix(path.to_string()));
+        }
+        // The host may be an IPv6 address in brackets, so split at the
+        // last colon.
+        s.rsplit_once(':')
+            .and_then(|(host, display)| {
+                Some(VncListen::Tcp {
+                    host: host.to_string(),
+                    display: display.parse().ok()?,
+                })
+            })
+            .ok_or_else(|| InvalidToken {
+                value: s.to_string(),
+                expected: Self::FORMATS,
+            })
+    }
+}
+
+impl Serialize for VncListen {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for VncListen {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+qemu_enum! {
+    /// How a VNC server handles several clients.
+    pub enum VncShare {
+        /// A client asking for exclusive access disconnects the others.
+        AllowExclusive = "allow-exclusive",
+        /// Exclusive access is never granted.
+        ForceShared = "force-shared",
+        /// The shared flag of clients is ignored.
+        Ignore = "ignore",
+    }
+}
+
+/// Export the guest display over VNC.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "vnc")]
+pub struct VncConfig {
+    // host:display|unix:path|none
+    #[serde(rename = "vnc")]
+    #[qemu(positional, rename = "vnc")]
+    pub listen: VncListen,
+
+    // to=display
+    // Try the following displays up to this one if the port is taken.
+    #[serde(rename = "to", skip_serializing_if = "Option::is_none")]
+    pub to: Option<u16>,
+
+    // password=on|off
+    // Require a password, set later with the set_password monitor command.
+    // Clients are refused until then.
+    #[serde(rename = "password", skip_serializing_if = "Option::is_none")]
+    pub password: Option<OnOff>,
+
+    // password-secret=id
+    // The secret object holding the password.
+    #[serde(rename = "password-secret", skip_serializing_if = "Option::is_none")]
+    pub password_secret: Option<String>,
+
+    // tls-creds=id
+    // The tls-creds object to encrypt connections with.
+    #[serde(rename = "tls-creds", skip_serializing_if = "Option::is_none")]
+    pub tls_creds: Option<String>,
+
+    // websocket=on|port|host:port
+    // Also accept websocket clients such as noVNC, on port 5700 + display
+    // for on.
+    #[serde(rename = "websocket", skip_serializing_if = "Option::is_none")]
+    pub websocket: Option<String>,
+
+    // share=allow-exclusive|force-shared|ignore
+    #[serde(rename = "share", skip_serializing_if = "Option::is_none")]
+    pub share: Option<VncShare>,
+
+    // lossy=on|off
+    // Allow lossy compression methods such as JPEG.
+    #[serde(rename = "lossy", skip_serializing_if = "Option::is_none")]
+    pub lossy: Option<OnOff>,
+}
+
+impl Validate for VncConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        match (&self.listen, self.to) {
+            (VncListen::Tcp { display, .. }, Some(to)) if to < *display => invalid(
+                errors,
+                path,
+                "to",
+                ValidationErrorKind::Invalid {
+                    expected: "a display no lower than the one listened on",
+                },
+            ),
+            (VncListen::Tcp { .. }, _) | (_, None) => {}
+            (listen, Some(_)) => invalid(
+                errors,
+                path,
+                "to",
+                ValidationErrorKind::NotSupported {
+                    by: listen.to_string(),
+                },
+            ),
+        }
+        if let VncListen::Tcp { display, .. } = self.listen {
+            let last = self.to.map_or(display, |to| to.max(display));
+            if 5900u16.checked_add(last).is_none() {
+                invalid(
+                    errors,
+                    path,
+                    "vnc",
+                    ValidationErrorKind::Invalid {
+                        expected: "a display giving a port below 65536",
+                    },
+                );
+            }
+        }
+        if self.password_secret.is_some() && self.password.is_some() {
+            invalid(
+                errors,
+                path,
+                "password",
+                ValidationErrorKind::Conflicts {
+                    key: "password-secret",
+                },
+            );
+        }
+    }
+}
+
+qemu_enum! {
+    /// Video cards of -vga.
+    pub enum VgaType {
+        Std = "std",
+        Cirrus = "cirrus",
+        Vmware = "vmware",
+        Qxl = "qxl",
+        Virtio = "virtio",
+        None = "none",
+    }
+}
+
+/// Select the built-in video card. `none` leaves the guest without one, e.g.
+/// to add a virtio-gpu-pci device instead.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "vga", separator = "")]
+pub struct VgaConfig(pub VgaType);
+
+impl VgaConfig {
+    pub fn new(vga: VgaType) -> Self {
+        VgaConfig(vga)
+    }
+}
+
+/// Builder for `DisplayConfig`, e.g.
+///     DisplayConfig::gtk().zoom_to_fit(true).build()
+#[derive(Debug, Clone)]
+pub struct DisplayConfigBuilder {
+    config: DisplayConfig,
+}
+
+impl DisplayConfig {
+    pub fn builder(display_type: DisplayType) -> DisplayConfigBuilder {
+        DisplayConfigBuilder {
+            config: DisplayConfig {
+                display_type,
+                gl: None,
+                full_screen: None,
+                zoom_to_fit: None,
+                grab_on_hover: None,
+                show_tabs: None,
+                show_cursor: None,
+                window_close: None,
+                rendernode: None,
+                addr: None,
+                p2p: None,
+            },
+        }
+    }
+
+    /// No local display, for headless hosts.
+    pub fn none() -> DisplayConfigBuilder {
+        Self::builder(DisplayType::None)
+    }
+
+    pub fn gtk() -> DisplayConfigBuilder {
+        Self::builder(DisplayType::Gtk)
+    }
+
+    pub fn sdl() -> DisplayConfigBuilder {
+        Self::builder(DisplayType::Sdl)
+    }
+
+    /// OpenGL rendering without a window, e.g. for VNC of a virgl guest.
+    pub fn egl_headless() -> DisplayConfigBuilder {
+        Self::builder(DisplayType::EglHeadless)
+    }
+
+    /// The display exported on D-Bus.
+    pub fn dbus() -> DisplayConfigBuilder {
+        Self::builder(DisplayType::Dbus)
+    }
+}
+
+impl DisplayConfigBuilder {
+    pub fn gl(mut self, gl: impl Into<GlMode>) -> Self {
+        self.config.gl = Some(gl.into());
+        self
+    }
+
+    pub fn full_screen(mut self, full_screen: impl Into<OnOff>) -> Self {
+        self.config.full_screen = Some(full_screen.into());
+        self
+    }
+
+    pub fn zoom_to_fit(mut self, zoom_to_fit: impl Into<OnOff>) -> Self {
+        self.config.zoom_to_fit = Some(zoom_to_fit.into());
+        self
+    }
+
+    pub fn grab_on_hover(mut self, grab_on_hover: impl Into<OnOff>) -> Self {
+        self.config.grab_on_hover = Some(grab_on_hover.into());
+        self
+    }
+
+    pub fn show_tabs(mut self, show_tabs: impl Into<OnOff>) -> Self {
+        self.config.show_tabs = Some(show_tabs.into());
+        self
+    }
+
+    pub fn show_cursor(mut self, show_cursor: impl Into<OnOff>) -> Self {
+        self.config.show_cursor = Some(show_cursor.into());
+        self
+    }
+
+    pub fn window_close(mut self, window_close: impl Into<OnOff>) -> Self {
+        self.config.window_close = Some(window_close.into());
+        self
+    }
+
+    pub fn rendernode(mut self, rendernode: impl Into<String>) -> Self {
+        self.config.rendernode = Some(rendernode.into());
+        self
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.config.addr = Some(addr.into());
+        self
+    }
+
+    pub fn p2p(mut self, p2p: impl Into<OnOff>) -> Self {
+        self.config.p2p = Some(p2p.into());
+        self
+    }
+
+    pub fn build(self) -> DisplayConfig {
+        self.config
+    }
+}
+
+impl From<DisplayConfigBuilder> for DisplayConfig {
+    fn from(builder: DisplayConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+/// Builder for `VncConfig`, e.g.
+///     VncConfig::unix("/run/vm/vnc.sock").password_secret("vnc-pw").build()
+#[derive(Debug, Clone)]
+pub struct VncConfigBuilder {
+    config: VncConfig,
+}
+
+impl VncConfig {
+    pub fn builder(listen: VncListen) -> VncConfigBuilder {
+        VncConfigBuilder {
+            config: VncConfig {
+                listen,
+                to: None,
+                password: None,
+                password_secret: None,
+                tls_creds: None,
+                websocket: None,
+                share: None,
+                lossy: None,
+            },
+        }
+    }
+
+    /// Listen on TCP port 5900 + `display` of `host`.
+    pub fn tcp(host: impl Into<String>, display: u16) -> VncConfigBuilder {
+        Self::builder(VncListen::Tcp {
+            host: host.into(),
+            display,
+        })
+    }
+
+    /// Listen on the unix socket `path`.
+    pub fn unix(path: impl Into<String>) -> VncConfigBuilder {
+        Self::builder(VncListen::Unix(path.into()))
+    }
+}
+
+impl VncConfigBuilder {
+    pub fn to(mut self, to: u16) -> Self {
+        self.config.to = Some(to);
+        self
+    }
+
+    pub fn password(mut self, password: impl Into<OnOff>) -> Self {
+        self.config.password = Some(password.into());
+        self
+    }
+
+    pub fn password_secret(mut self, password_secret: impl Into<String>) -> Self {
+        self.config.password_secret = Some(password_secret.into());
+        self
+    }
+
+    pub fn tls_creds(mut self, tls_creds: impl Into<String>) -> Self {
+        self.config.tls_creds = Some(tls_creds.into());
+        self
+    }
+
+    pub fn websocket(mut self, websocket: impl Into<String>) -> Self {
+        self.config.websocket = Some(websocket.into());
+        self
+    }
+
+    pub fn share(mut self, share: VncShare) -> Self {
+        self.config.share = Some(share);
+        self
+    }
+
+    pub fn lossy(mut self, lossy: impl Into<OnOff>) -> Self {
+        self.config.lossy = Some(lossy.into());
+        self
+    }
+
+    pub fn build(self) -> VncConfig {
+        self.config
+    }
+}
+
+impl From<VncConfigBuilder> for VncConfig {
+    fn from(builder: VncConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::parser::{parse_args, OptionParsing};
+
+    #[test]
+    fn test_display() {
+        let gtk = DisplayConfig::gtk().gl(true).zoom_to_fit(true).build();
+        assert_eq!(
+            gtk.formatting().to_string(),
+            "-display gtk,gl=on,zoom-to-fit=on"
+        );
+        let vnc = VncConfig::tcp("[::1]", 1)
+            .to(10)
+            .tls_creds("tls0")
+            .websocket("5701")
+            .share(VncShare::ForceShared)
+            .build();
+        let formatted = "-vnc [::1]:1,to=10,tls-creds=tls0,websocket=5701,share=force-shared";
+        assert_eq!(vnc.formatting().to_string(), formatted);
+        assert_eq!(vnc.listen.port(), Some(5901));
+        assert_eq!(
+            VgaConfig::new(VgaType::None).formatting().to_string(),
+            "-vga none"
+        );
+
+        let argv = [
+            "qemu-system-x86_64",
+            "-display",
+            "gtk,gl=on,zoom-to-fit=on",
+            "-vnc",
+            &formatted[5..],
+            "-vnc",
+            "unix:/run/vm/vnc.sock,password=on",
+            "-vga",
+            "none",
+        ];
+        let command = parse_args(&argv).unwrap();
+        assert_eq!(DisplayConfig::parsing(&command.options[0]), Ok(gtk));
+        assert_eq!(VncConfig::parsing(&command.options[1]), Ok(vnc));
+        assert_eq!(
+            VncConfig::parsing(&command.options[2]),
+            Ok(VncConfig::unix("/run/vm/vnc.sock").password(true).build())
+        );
+        assert_eq!(
+            VgaConfig::parsing(&command.options[3]),
+            Ok(VgaConfig::new(VgaType::None))
+        );
+        assert_eq!(
+            "localhost".parse::<VncListen>().unwrap_err().to_string(),
+            "'localhost' is not one of host:display|unix:path|none"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut errors = Vec::new();
+        DisplayConfig::none()
+            .zoom_to_fit(true)
+            .build()
+            .validate("display", &mut errors);
+        DisplayConfig::dbus()
+            .addr("unix:path=/run/vm/dbus")
+            .p2p(true)
+            .build()
+            .validate("display", &mut errors);
+        VncConfig::unix("/run/vm/vnc.sock")
+            .to(3)
+            .password(true)
+            .password_secret("vnc-pw")
+            .build()
+            .validate("vnc", &mut errors);
+        VncConfig::tcp("", 2)
+            .to(1)
+            .build()
+            .validate("vnc", &mut errors);
+        VncConfig::tcp("", 60000)
+            .build()
+            .validate("vnc", &mut errors);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "display.zoom-to-fit: not supported by type=none",
+                "display.addr: conflicts with 'p2p=on'",
+                "vnc.to: not supported by unix:/run/vm/vnc.sock",
+                "vnc.password: conflicts with 'password-secret'",
+                "vnc.to: expected a display no lower than the one listened on",
+                "vnc.vnc: expected a display giving a port below 65536",
+            ]
+        );
+    }
+}
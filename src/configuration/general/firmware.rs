@@ -0,0 +1,452 @@
+//! Firmware of the guest: a -bios image, or UEFI in two pflash devices, the
+//! read-only code and a variable store of the VM's own. Firmware packages
+//! describe their images in JSON descriptors, which pick the image for an
+//! architecture and machine type, e.g.
+//!     /usr/share/qemu/firmware/40-edk2-x86_64-secure-enrolled.json
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::command::builder::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    block::BlockdevConfig,
+    machine::MachineConfig,
+    machine_list::MachineCatalog,
+    types::{qemu_enum, OnOff},
+    validate::*,
+};
+
+/// Where distributions install the descriptors of their firmware.
+pub const FIRMWARE_DIR: &str = "/usr/share/qemu/firmware";
+
+/// Firmware image loaded into guest memory, e.g. SeaBIOS.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "bios", separator = "")]
+pub struct BiosConfig(pub String);
+
+impl BiosConfig {
+    pub fn new(path: impl Into<String>) -> Self {
+        BiosConfig(path.into())
+    }
+}
+
+qemu_enum! {
+    /// interface-types of a descriptor.
+    pub enum FirmwareInterface {
+        Bios = "bios",
+        Openfirmware = "openfirmware",
+        Uboot = "uboot",
+        Uefi = "uefi",
+    }
+}
+
+qemu_enum! {
+    /// mode of flash firmware.
+    pub enum FlashMode {
+        /// Code and variable store in separate images.
+        Split = "split",
+        /// One writable image holding both.
+        Combined = "combined",
+        /// Code only, variables are not kept across boots.
+        Stateless = "stateless",
+    }
+}
+
+qemu_enum! {
+    /// format of a firmware image.
+    pub enum FirmwareFormat {
+        Raw = "raw",
+        Qcow2 = "qcow2",
+    }
+}
+
+/// A firmware descriptor, as in docs/interop/firmware.json of QEMU.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FirmwareDescriptor {
+    #[serde(rename = "description")]
+    pub description: String,
+
+    #[serde(rename = "interface-types")]
+    pub interface_types: Vec<FirmwareInterface>,
+
+    #[serde(rename = "mapping")]
+    pub mapping: FirmwareMapping,
+
+    #[serde(rename = "targets")]
+    pub targets: Vec<FirmwareTarget>,
+
+    // e.g. secure-boot, enrolled-keys, requires-smm, acpi-s3
+    #[serde(rename = "features", default)]
+    pub features: Vec<String>,
+
+    #[serde(rename = "tags", default)]
+    pub tags: Vec<String>,
+}
+
+/// How the firmware is handed to the guest.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "device")]
+pub enum FirmwareMapping {
+    #[serde(rename = "flash")]
+    Flash(FlashMapping),
+
+    /// Loaded with -bios.
+    #[serde(rename = "memory")]
+    Memory {
+        #[serde(rename = "filename")]
+        filename: String,
+    },
+
+    /// Loaded with -kernel.
+    #[serde(rename = "kernel")]
+    Kernel {
+        #[serde(rename = "filename")]
+        filename: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlashMapping {
+    #[serde(rename = "mode", default = "FlashMapping::default_mode")]
+    pub mode: FlashMode,
+
+    #[serde(rename = "executable")]
+    pub executable: FlashFile,
+
+    // split mode only
+    #[serde(rename = "nvram-template", skip_serializing_if = "Option::is_none")]
+    pub nvram_template: Option<FlashFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlashFile {
+    #[serde(rename = "filename")]
+    pub filename: String,
+
+    #[serde(rename = "format")]
+    pub format: FirmwareFormat,
+}
+
+/// Machine types of an architecture the firmware runs on. Machine types
+/// are globs, e.g. `pc-q35-*`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FirmwareTarget {
+    #[serde(rename = "architecture")]
+    pub architecture: String,
+
+    #[serde(rename = "machines")]
+    pub machines: Vec<String>,
+}
+
+/// A descriptor that cannot be read, or flash firmware given no place for
+/// the variable store of the VM.
+#[derive(Debug)]
+pub enum FirmwareError {
+    Descriptor { path: PathBuf, message: String },
+    MissingVars,
+    MissingMachine,
+    UnsupportedMachine(String),
+}
+
+impl fmt::Display for FirmwareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FirmwareError::Descriptor { path, message } => {
+                write!(f, "firmware descriptor {}: {}", path.display(), message)
+            }
+            FirmwareError::MissingVars => {
+                write!(f, "the firmware needs a variable store of the VM")
+            }
+            FirmwareError::MissingMachine => {
+                write!(f, "the firmware needs a -machine to be set")
+            }
+            FirmwareError::UnsupportedMachine(machine) => {
+                write!(f, "the firmware does not support machine {}", machine)
+            }
+        }
+    }
+}
+
+impl Error for FirmwareError {}
+
+impl FirmwareDescriptor {
+    /// Read every `*.json` descriptor in `dir`, in file name order, which
+    /// is the order of preference.
+    pub fn load_dir(dir: impl AsRef<Path>) -> Result<Vec<Self>, FirmwareError> {
+        let error = |path: &Path, message: String| FirmwareError::Descriptor {
+            path: path.to_path_buf(),
+            message,
+        };
+        let dir = dir.as_ref();
+        let mut paths = fs::read_dir(dir)
+            .map_err(|e| error(dir, e.to_string()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()
+            .map_err(|e| error(dir, e.to_string()))?;
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+        paths.sort();
+        paths
+            .iter()
+            .map(|path| {
+                let json = fs::read_to_string(path).map_err(|e| error(path, e.to_string()))?;
+                serde_json::from_str(&json).map_err(|e| error(path, e.to_string()))
+            })
+            .collect()
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    pub fn secure_boot(&self) -> bool {
+        self.has_feature("secure-boot")
+    }
+
+    /// Whether the machine must run with `smm=on`, as secure boot firmware
+    /// keeps its variable store out of reach of the guest with it.
+    pub fn requires_smm(&self) -> bool {
+        self.has_feature("requires-smm")
+    }
+
+    /// Whether the firmware runs on the machine type of the catalog's
+    /// architecture. Aliases such as `q35` are resolved through the
+    /// catalog, as descriptors match the full name, e.g. `pc-q35-*`.
+    pub fn supports(&self, catalog: &MachineCatalog, machine: &str) -> bool {
+        let machine = catalog.resolve(machine).unwrap_or(machine);
+        self.targets.iter().any(|target| {
+            target.architecture == catalog.arch
+                && target
+                    .machines
+                    .iter()
+                    .any(|pattern| matches_glob(pattern, machine))
+        })
+    }
+
+    /// The first UEFI flash firmware for the machine, with or without
+    /// secure boot.
+    pub fn select_uefi<'a>(
+        descriptors: &'a [Self],
+        catalog: &MachineCatalog,
+        machine: &str,
+        secure_boot: bool,
+    ) -> Option<&'a Self> {
+        descriptors.iter().find(|descriptor| {
+            descriptor
+                .interface_types
+                .contains(&FirmwareInterface::Uefi)
+                && matches!(descriptor.mapping, FirmwareMapping::Flash(_))
+                && descriptor.secure_boot() == secure_boot
+                && descriptor.supports(catalog, machine)
+        })
+    }
+}
+
+impl FlashMapping {
+    fn default_mode() -> FlashMode {
+        FlashMode::Split
+    }
+
+    /// The image every VM gets a writable copy of: the variable store
+    /// template, or in combined mode the whole firmware.
+    pub fn template(&self) -> Option<&FlashFile> {
+        match self.mode {
+            FlashMode::Split => self.nvram_template.as_ref(),
+            FlashMode::Combined => Some(&self.executable),
+            FlashMode::Stateless => None,
+        }
+    }
+
+    /// Copy the template to `vars`, the writable image of the VM. An image
+    /// already there is kept, as it holds the boot entries and keys of the
+    /// guest. Returns whether the template was copied.
+    pub fn install_vars(&self, vars: impl AsRef<Path>) -> io::Result<bool> {
+        let vars = vars.as_ref();
+        let Some(template) = self.template() else {
+            return Ok(false);
+        };
+        if vars.exists() {
+            return Ok(false);
+        }
+        if let Some(dir) = vars.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::copy(&template.filename, vars)?;
+        Ok(true)
+    }
+
+    /// The blockdevs of the firmware, as the block nodes of pflash0 and
+    /// pflash1. `vars` is the writable image of the VM, if the mode has
+    /// one.
+    pub(crate) fn blockdevs(&self, vars: Option<&str>) -> Vec<(&'static str, BlockdevConfig)> {
+        let code = match (self.mode, vars) {
+            (FlashMode::Combined, Some(vars)) => {
+                flash_nodes("pflash0", vars, self.executable.format, false)
+            }
+            _ => flash_nodes(
+                "pflash0",
+                &self.executable.filename,
+                self.executable.format,
+                true,
+            ),
+        };
+        let vars = match (self.mode, &self.nvram_template, vars) {
+            (FlashMode::Split, Some(template), Some(vars)) => {
+                flash_nodes("pflash1", vars, template.format, false)
+            }
+            _ => Vec::new(),
+        };
+        code.into_iter()
+            .map(|node| ("pflash0", node))
+            .chain(vars.into_iter().map(|node| ("pflash1", node)))
+            .collect()
+    }
+}
+
+/// A file node named `name` for a raw image, or a qcow2 node named `name`
+/// on top of a file node.
+fn flash_nodes(
+    name: &str,
+    filename: &str,
+    format: FirmwareFormat,
+    read_only: bool,
+) -> Vec<BlockdevConfig> {
+    let read_only = OnOff::from(read_only);
+    match format {
+        FirmwareFormat::Raw => vec![BlockdevConfig::file(name, filename)
+            .read_only(read_only)
+            .build()],
+        FirmwareFormat::Qcow2 => {
+            let file = format!("{}-file", name);
+            vec![
+                BlockdevConfig::file(&file, filename)
+                    .read_only(read_only)
+                    .build(),
+                BlockdevConfig::qcow2(name, file)
+                    .read_only(read_only)
+                    .build(),
+            ]
+        }
+    }
+}
+
+/// Match `name` against a glob where `*` stands for any text.
+fn matches_glob(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => name.strip_prefix(prefix).is_some_and(|name| {
+            name.char_indices()
+                .map(|(i, _)| i)
+                .chain([name.len()])
+                .any(|i| matches_glob(rest, &name[i..]))
+        }),
+    }
+}
+
+/// Check that the pflash devices of the machine are backed by block nodes
+/// and that -bios does not load a second firmware.
+pub(crate) fn validate_pflash(
+    machine: Option<&MachineConfig>,
+    bios: Option<&BiosConfig>,
+    block_ids: &[&str],
+    errors: &mut Vec<ValidationError>,
+) {
+    let Some(machine) = machine else {
+        return;
+    };
+    for (key, node) in [("pflash0", &machine.pflash0), ("pflash1", &machine.pflash1)] {
+        if let Some(node) = node.as_deref().filter(|node| !block_ids.contains(node)) {
+            let id = node.to_string();
+            invalid(
+                errors,
+                "machine",
+                key,
+                ValidationErrorKind::UnknownReference { id },
+            );
+        }
+    }
+    if machine.pflash1.is_some() && machine.pflash0.is_none() {
+        invalid(
+            errors,
+            "machine",
+            "pflash1",
+            ValidationErrorKind::Requires { key: "pflash0" },
+        );
+    }
+    if bios.is_some() && machine.pflash0.is_some() {
+        errors.push(ValidationError {
+            path: "bios".to_string(),
+            kind: ValidationErrorKind::Conflicts {
+                key: "machine.pflash0",
+            },
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::configuration::general::machine_list;
+
+    #[test]
+    fn test_select() {
+        let descriptors = FirmwareDescriptor::load_dir("./test_json/firmware").unwrap();
+        let descriptions = descriptors
+            .iter()
+            .map(|d| d.description.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            descriptions,
+            [
+                "UEFI firmware for x86_64, with Secure Boot and SMM",
+                "UEFI firmware for x86_64",
+                "SeaBIOS",
+            ]
+        );
+
+        let catalog = machine_list::snapshot("x86_64").unwrap();
+        let select = |machine, secure_boot| {
+            FirmwareDescriptor::select_uefi(&descriptors, &catalog, machine, secure_boot)
+                .map(|d| d.description.as_str())
+        };
+        assert_eq!(select("pc-q35-8.2", true), Some(descriptions[0]));
+        assert_eq!(select("q35", true), Some(descriptions[0]));
+        assert_eq!(select("pc", false), Some(descriptions[1]));
+        assert_eq!(select("pc-q35-8.2", false), Some(descriptions[1]));
+        assert_eq!(select("pc-i440fx-8.2", false), Some(descriptions[1]));
+        assert_eq!(select("pc-i440fx-8.2", true), None);
+        assert!(descriptors[0].requires_smm());
+
+        assert!(matches!(
+            FirmwareDescriptor::load_dir("./test_json/nonexistent"),
+            Err(FirmwareError::Descriptor { .. })
+        ));
+        assert!(matches_glob("pc-q35-*", "pc-q35-8.2"));
+        assert!(matches_glob("*-q35-*", "pc-q35-8.2"));
+        assert!(!matches_glob("pc-q35-*", "pc-i440fx-8.2"));
+    }
+
+    #[test]
+    fn test_install_vars() {
+        let descriptors = FirmwareDescriptor::load_dir("./test_json/firmware").unwrap();
+        let FirmwareMapping::Flash(flash) = &descriptors[1].mapping else {
+            panic!("not flash firmware");
+        };
+        let dir = std::env::temp_dir().join(format!("qemu_rs-firmware-{}", std::process::id()));
+        let vars = dir.join("vm-1").join("VARS.fd");
+        assert!(flash.install_vars(&vars).unwrap());
+        assert_eq!(
+            fs::read(&vars).unwrap(),
+            fs::read("./test_json/firmware/OVMF_VARS.fd").unwrap()
+        );
+
+        // The variables of the guest survive a second install.
+        fs::write(&vars, "enrolled").unwrap();
+        assert!(!flash.install_vars(&vars).unwrap());
+        assert_eq!(fs::read_to_string(&vars).unwrap(), "enrolled");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,5 +1,5 @@
 //! Set default value of driver's property prop to value
-use crate::command::builder::*;
+use crate::command::{builder::*, parser::*};
 use serde::{Deserialize, Serialize};
 
 /// Set default value of driver's property prop to value, e.g.:
@@ -7,20 +7,90 @@ use serde::{Deserialize, Serialize};
 /// In particular, you can use this to set driver properties for devices which are  created
 /// automatically  by  the machine model. To create a device which is not created automati‐
 /// cally and set properties on it, use -device.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GlobalConfig<'a> {
-    #[serde(rename="driver")]
-    driver: &'a str,
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GlobalConfig {
+    #[serde(rename = "driver")]
+    pub driver: String,
 
-    #[serde(rename="property")]
-    property: &'a str,
+    #[serde(rename = "property")]
+    pub property: String,
 
-    #[serde(rename="value")]
-    value: &'a str,
+    #[serde(rename = "value")]
+    pub value: String,
 }
 
-impl<'a> OptionFormatting<'a> for GlobalConfig<'a> {
-    fn formatting(&self) -> OptionQ<'a> {
-        todo!()
+impl GlobalConfig {
+    pub fn new(
+        driver: impl Into<String>,
+        property: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        GlobalConfig {
+            driver: driver.into(),
+            property: property.into(),
+            value: value.into(),
+        }
     }
-}
\ No newline at end of file
+}
+
+impl OptionFormatting for GlobalConfig {
+    fn formatting(&self) -> OptionQ<'_> {
+        OptionQ {
+            prefix: "-",
+            raw: "global",
+            option_args_split_with: " ",
+            args_split_with: ",",
+            args: vec![
+                KVArgQ {
+                    key: "driver",
+                    kv_split_with: Some("="),
+                    value: Some(self.driver.to_string()),
+                },
+                KVArgQ {
+                    key: "property",
+                    kv_split_with: Some("="),
+                    value: Some(self.property.to_string()),
+                },
+                KVArgQ {
+                    key: "value",
+                    kv_split_with: Some("="),
+                    value: Some(self.value.to_string()),
+                },
+            ],
+        }
+    }
+}
+
+impl OptionParsing for GlobalConfig {
+    fn parsing(option: &OptionQ<'_>) -> Result<Self, ParseError> {
+        // Either driver=,property=,value= or the short driver.prop=value.
+        if let [KVArgQ {
+            key,
+            value: Some(value),
+            ..
+        }] = option.args.as_slice()
+        {
+            if let Some((driver, property)) = key.split_once('.') {
+                return Ok(GlobalConfig {
+                    driver: driver.to_string(),
+                    property: property.to_string(),
+                    value: value.clone(),
+                });
+            }
+        }
+        let mut args = OptionArgs::new(option);
+        let config = GlobalConfig {
+            driver: args
+                .parsed("driver", false)?
+                .ok_or_else(|| args.missing("driver"))?,
+            property: args
+                .parsed("property", false)?
+                .ok_or_else(|| args.missing("property"))?,
+            value: args
+                .parsed("value", false)?
+                .ok_or_else(|| args.missing("value"))?,
+        };
+        args.finish()?;
+        Ok(config)
+    }
+}
@@ -1,21 +1,12 @@
 use crate::command::builder::*;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LanguageConfig<'a>(&'a str);
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "k", separator = "")]
+pub struct LanguageConfig(pub String);
 
-impl<'a> OptionFormatting<'a> for LanguageConfig<'a> {
-    fn formatting(&self) -> OptionQ<'a> {
-        OptionQ {
-            prefix: "-",
-            raw: "k",
-            option_args_split_with: " ",
-            args_split_with: "",
-            args: vec![KVArgQ {
-                key: self.0,
-                kv_split_with: None,
-                value: None,
-            }],
-        }
+impl LanguageConfig {
+    pub fn new(language: impl Into<String>) -> Self {
+        LanguageConfig(language.into())
     }
 }
@@ -2,38 +2,66 @@
 use crate::command::builder::*;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MachineConfig<'a> {
+use super::{
+    types::{qemu_enum, Accelerator, Arch, OnOff, OnOffAuto},
+    validate::*,
+};
+
+qemu_enum! {
+    /// gic-version=2|3|4|host|max
+    pub enum GicVersion {
+        V2 = "2",
+        V3 = "3",
+        V4 = "4",
+        Host = "host",
+        Max = "max",
+    }
+}
+
+qemu_enum! {
+    /// aia=none|aplic|aplic-imsic
+    pub enum RiscvAia {
+        None = "none",
+        Aplic = "aplic",
+        AplicImsic = "aplic-imsic",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "machine")]
+pub struct MachineConfig {
     // Select the emulated machine by name.
     #[serde(rename = "type")]
-    pub m_type: &'a str,
+    #[qemu(implied, rename = "type")]
+    pub m_type: String,
 
     // accel=accels1[:accels2[:...]]
     // (default: tcg)
-    // 
+    //
     // This is used to enable an accelerator. Depending  on  the
     // target  architecture,  kvm,  xen, hax, hvf, nvmm, whpx or
     // tcg can be available.  By default, tcg is used. If  there
     // is  more  than one accelerator specified, the next one is
     // used if the previous one fails to initialize.
     #[serde(rename = "accel", skip_serializing_if = "Option::is_none")]
-    pub accel: Option<Vec<&'a str>>,
+    #[qemu(separator = ":")]
+    pub accel: Option<Vec<Accelerator>>,
 
     // vmport=on|off|auto
     // (default: auto)
-    // 
+    //
     // Enables emulation of VMWare IO  port,  for  vmmouse  etc.
     // auto  says  to  select  the value based on accel. For ac‐
     // cel=xen the default is off otherwise the default is on.
     #[serde(rename = "vmport", skip_serializing_if = "Option::is_none")]
-    pub vmport: Option<&'a str>,
+    pub vmport: Option<OnOffAuto>,
 
     // dump-guest-core=on|off
     // (default=on)
-    // 
+    //
     // Include guest memory in a core dump. The default is on.
     #[serde(rename = "dump-guest-core", skip_serializing_if = "Option::is_none")]
-    pub dump_guest_core: Option<&'a str>,
+    pub dump_guest_core: Option<OnOff>,
 
     // mem-merge=on|off
     // (default: on)
@@ -42,21 +70,21 @@ pub struct MachineConfig<'a> {
     // when  supported by the host, de-duplicates identical mem‐
     // ory pages among VMs instances (enabled by default).
     #[serde(rename = "mem-merge", skip_serializing_if = "Option::is_none")]
-    pub mem_merge: Option<&'a str>,
+    pub mem_merge: Option<OnOff>,
 
     // aes-key-wrap=on|off
     // (default=on)
-    // 
+    //
     // Enables or disables AES key wrapping support on  s390-ccw
     // hosts.   This  feature controls whether AES wrapping keys
     // will be created to allow execution of  AES  cryptographic
     // functions. The default is on.
     #[serde(rename = "aes-key-wrap", skip_serializing_if = "Option::is_none")]
-    pub aes_key_wrap: Option<&'a str>,
+    pub aes_key_wrap: Option<OnOff>,
 
     // dea-key-wrap=on|off
     // (default=on)
-    // 
+    //
     // Enables  or disables DEA key wrapping support on s390-ccw
     // hosts.  This feature controls whether DEA  wrapping  keys
     // will  be  created to allow execution of DEA cryptographic
@@ -64,21 +92,21 @@ pub struct MachineConfig<'a> {
     //
     // ory pages among VMs instances (enabled by default).
     #[serde(rename = "dea-key-wrap", skip_serializing_if = "Option::is_none")]
-    pub dea_key_wrap: Option<&'a str>,
+    pub dea_key_wrap: Option<OnOff>,
 
     // nvdimm=on|off
     // (default=off)
     //
     // Enables or disables NVDIMM support. The default is off.
     #[serde(rename = "nvdimm", skip_serializing_if = "Option::is_none")]
-    pub nvdimm: Option<&'a str>,
+    pub nvdimm: Option<OnOff>,
 
     // memory-encryption=
     // (default=none)
     //
     // Memory encryption object to use. The default is none.
     #[serde(rename = "memory-encryption", skip_serializing_if = "Option::is_none")]
-    pub memory_encryption: Option<&'a str>,
+    pub memory_encryption: Option<String>,
 
     // hmat=on|off
     // (default=off)
@@ -86,209 +114,252 @@ pub struct MachineConfig<'a> {
     // Enables or disables ACPI Heterogeneous  Memory  Attribute
     // Table (HMAT) support. The default is off.
     #[serde(rename = "hmat", skip_serializing_if = "Option::is_none")]
-    pub hmat: Option<&'a str>,
+    pub hmat: Option<OnOff>,
 
     // memory-backend='id'
     // (default=none)
-    // 
+    //
     // Allows to use a memory backend as main RAM.
     // For example:
     //     -object memory-backend-file,id=pc.ram,size=512M,mem-path=/hugetlbfs,prealloc=on,share=on
     //     -machine memory-backend=pc.ram
     //     -m 512M
     #[serde(rename = "memory-backend", skip_serializing_if = "Option::is_none")]
-    pub memory_backend: Option<&'a str>,
+    pub memory_backend: Option<String>,
+
+    // smm=on|off|auto
+    // (default=auto)
+    //
+    // Enables or disables System Management Mode, which UEFI
+    // firmware with secure boot needs to protect its variable
+    // store from the guest.
+    #[serde(rename = "smm", skip_serializing_if = "Option::is_none")]
+    pub smm: Option<OnOffAuto>,
+
+    // pflash0='id',pflash1='id'
+    // (default=none)
+    //
+    // Block nodes backing the two flash devices of the firmware,
+    // the read-only code and the guest's variable store.
+    // For example:
+    //     -blockdev file,node-name=pflash0,filename=OVMF_CODE.fd,read-only=on
+    //     -blockdev file,node-name=pflash1,filename=vm-1_VARS.fd
+    //     -machine q35,pflash0=pflash0,pflash1=pflash1
+    #[serde(rename = "pflash0", skip_serializing_if = "Option::is_none")]
+    pub pflash0: Option<String>,
+
+    #[serde(rename = "pflash1", skip_serializing_if = "Option::is_none")]
+    pub pflash1: Option<String>,
 
     // memid
-    #[serde(rename = "sgx-epc.0.memdev", skip_serializing_if="Option::is_none")]
+    #[serde(rename = "sgx-epc.0.memdev", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "sgx-epc.0.memdev")]
     pub sgx_epc_0_memdev: Option<usize>,
 
     // numaid
-    #[serde(rename = "sgx-epc.0.node", skip_serializing_if="Option::is_none")]
-    pub sgx_epc_0_node: Option<usize>
+    #[serde(rename = "sgx-epc.0.node", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "sgx-epc.0.node")]
+    pub sgx_epc_0_node: Option<usize>,
+
+    // gic-version=2|3|4|host|max
+    // (default=2, Arm virt only)
+    //
+    // Version of the Generic Interrupt Controller. host uses the GIC of
+    // the host with KVM, max the newest one the accelerator supports.
+    #[serde(rename = "gic-version", skip_serializing_if = "Option::is_none")]
+    pub gic_version: Option<GicVersion>,
+
+    // highmem=on|off
+    // (default=on, Arm virt only)
+    //
+    // Place devices and RAM above 4G in the physical address space, which
+    // a guest with more than 3G of RAM needs.
+    #[serde(rename = "highmem", skip_serializing_if = "Option::is_none")]
+    pub highmem: Option<OnOff>,
+
+    // aia=none|aplic|aplic-imsic
+    // (default=none, RISC-V virt only)
+    //
+    // Interrupt controller of the Advanced Interrupt Architecture. none
+    // keeps the SiFive PLIC, aplic-imsic adds message signaled interrupts.
+    #[serde(rename = "aia", skip_serializing_if = "Option::is_none")]
+    pub aia: Option<RiscvAia>,
 }
 
-impl<'a> MachineConfig<'a> {
-    #[inline]
-    fn m_type(&self) -> Option<KVArgQ<'a>> {
-        Some(KVArgQ {
-            key: "type",
-            kv_split_with: Some("="),
-            value: Some(self.m_type.to_string()),
-        })
+impl MachineConfig {
+    /// The properties that only some architectures have, with those
+    /// architectures.
+    fn arch_props(&self) -> [(&'static str, bool, &'static [Arch]); 9] {
+        [
+            ("vmport", self.vmport.is_some(), &[Arch::X86_64]),
+            ("aes-key-wrap", self.aes_key_wrap.is_some(), &[Arch::S390x]),
+            ("dea-key-wrap", self.dea_key_wrap.is_some(), &[Arch::S390x]),
+            ("smm", self.smm.is_some(), &[Arch::X86_64]),
+            (
+                "sgx-epc.0.memdev",
+                self.sgx_epc_0_memdev.is_some(),
+                &[Arch::X86_64],
+            ),
+            (
+                "sgx-epc.0.node",
+                self.sgx_epc_0_node.is_some(),
+                &[Arch::X86_64],
+            ),
+            ("gic-version", self.gic_version.is_some(), &[Arch::Aarch64]),
+            ("highmem", self.highmem.is_some(), &[Arch::Aarch64]),
+            ("aia", self.aia.is_some(), &[Arch::Riscv64]),
+        ]
     }
 
-    #[inline]
-    fn accel(&self) -> Option<KVArgQ<'a>> {
-        match &self.accel {
-            None => None,
-            Some(accel) => Some(KVArgQ {
-                key: "accel",
-                kv_split_with: Some("="),
-                value: Some(accel.join(":")),
-            }),
+    /// Check that the QEMU binary has every property that is set.
+    pub(crate) fn validate_arch(&self, arch: Arch, errors: &mut Vec<ValidationError>) {
+        for (key, set, archs) in self.arch_props() {
+            if set && !archs.contains(&arch) {
+                let by = arch.binary();
+                invalid(
+                    errors,
+                    "machine",
+                    key,
+                    ValidationErrorKind::NotSupported { by },
+                );
+            }
         }
     }
+}
 
-    #[inline]
-    fn vmport(&self) -> Option<KVArgQ<'a>> {
-        match self.vmport {
-            None => None,
-            Some(vmport) => Some(KVArgQ {
-                key: "vmport",
-                kv_split_with: Some("="),
-                value: Some(vmport.to_string()),
-            }),
+/// Builder for `MachineConfig`, e.g.
+///     MachineConfig::builder("q35").accel([Accelerator::Kvm]).mem_merge(false).build()
+#[derive(Debug, Clone)]
+pub struct MachineConfigBuilder {
+    config: MachineConfig,
+}
+
+impl MachineConfig {
+    pub fn builder(m_type: impl Into<String>) -> MachineConfigBuilder {
+        MachineConfigBuilder {
+            config: MachineConfig {
+                m_type: m_type.into(),
+                accel: None,
+                vmport: None,
+                dump_guest_core: None,
+                mem_merge: None,
+                aes_key_wrap: None,
+                dea_key_wrap: None,
+                nvdimm: None,
+                memory_encryption: None,
+                hmat: None,
+                memory_backend: None,
+                smm: None,
+                pflash0: None,
+                pflash1: None,
+                sgx_epc_0_memdev: None,
+                sgx_epc_0_node: None,
+                gic_version: None,
+                highmem: None,
+                aia: None,
+            },
         }
     }
+}
 
-    #[inline]
-    fn dump_guest_core(&self) -> Option<KVArgQ<'a>> {
-        match self.dump_guest_core {
-            None => None,
-            Some(dump_guest_core) => Some(KVArgQ {
-                key: "dump-guest-core",
-                kv_split_with: Some("="),
-                value: Some(dump_guest_core.to_string()),
-            }),
-        }
+impl MachineConfigBuilder {
+    pub fn accel(mut self, accel: impl IntoIterator<Item = Accelerator>) -> Self {
+        self.config.accel = Some(accel.into_iter().collect());
+        self
     }
 
-    #[inline]
-    fn mem_merge(&self) -> Option<KVArgQ<'a>> {
-        match self.mem_merge {
-            None => None,
-            Some(mem_merge) => Some(KVArgQ {
-                key: "mem-merge",
-                kv_split_with: Some("="),
-                value: Some(mem_merge.to_string()),
-            }),
-        }
+    pub fn vmport(mut self, vmport: impl Into<OnOffAuto>) -> Self {
+        self.config.vmport = Some(vmport.into());
+        self
     }
 
-    #[inline]
-    fn aes_key_wrap(&self) -> Option<KVArgQ<'a>> {
-        match self.aes_key_wrap {
-            None => None,
-            Some(aes_key_wrap) => Some(KVArgQ {
-                key: "aes-key-wrap",
-                kv_split_with: Some("="),
-                value: Some(aes_key_wrap.to_string()),
-            }),
-        }
+    pub fn dump_guest_core(mut self, dump_guest_core: impl Into<OnOff>) -> Self {
+        self.config.dump_guest_core = Some(dump_guest_core.into());
+        self
     }
 
-    #[inline]
-    fn dea_key_wrap(&self) -> Option<KVArgQ<'a>> {
-        match self.dea_key_wrap {
-            None => None,
-            Some(dea_key_wrap) => Some(KVArgQ {
-                key: "dea-key-wrap",
-                kv_split_with: Some("="),
-                value: Some(dea_key_wrap.to_string()),
-            }),
-        }
+    pub fn mem_merge(mut self, mem_merge: impl Into<OnOff>) -> Self {
+        self.config.mem_merge = Some(mem_merge.into());
+        self
     }
 
-    #[inline]
-    fn nvdimm(&self) -> Option<KVArgQ<'a>> {
-        match self.nvdimm {
-            None => None,
-            Some(nvdimm) => Some(KVArgQ {
-                key: "nvdimm",
-                kv_split_with: Some("="),
-                value: Some(nvdimm.to_string()),
-            }),
-        }
+    pub fn aes_key_wrap(mut self, aes_key_wrap: impl Into<OnOff>) -> Self {
+        self.config.aes_key_wrap = Some(aes_key_wrap.into());
+        self
     }
 
-    #[inline]
-    fn memory_encryption(&self) -> Option<KVArgQ<'a>> {
-        match self.memory_encryption {
-            None => None,
-            Some(memory_encryption) => Some(KVArgQ {
-                key: "memory-encryption",
-                kv_split_with: Some("="),
-                value: Some(memory_encryption.to_string()),
-            }),
-        }
+    pub fn dea_key_wrap(mut self, dea_key_wrap: impl Into<OnOff>) -> Self {
+        self.config.dea_key_wrap = Some(dea_key_wrap.into());
+        self
     }
 
-    #[inline]
-    fn hmat(&self) -> Option<KVArgQ<'a>> {
-        match self.hmat {
-            None => None,
-            Some(hmat) => Some(KVArgQ {
-                key: "hmat",
-                kv_split_with: Some("="),
-                value: Some(hmat.to_string()),
-            }),
-        }
+    pub fn nvdimm(mut self, nvdimm: impl Into<OnOff>) -> Self {
+        self.config.nvdimm = Some(nvdimm.into());
+        self
     }
 
-    #[inline]
-    fn memory_backend(&self) -> Option<KVArgQ<'a>> {
-        match self.memory_backend {
-            None => None,
-            Some(memory_backend) => Some(KVArgQ {
-                key: "memory-backend",
-                kv_split_with: Some("="),
-                value: Some(memory_backend.to_string()),
-            }),
-        }
+    pub fn memory_encryption(mut self, memory_encryption: impl Into<String>) -> Self {
+        self.config.memory_encryption = Some(memory_encryption.into());
+        self
     }
 
-    #[inline]
-    fn sgx_epc_0_memdev(&self) -> Option<KVArgQ<'a>> {
-        match self.sgx_epc_0_memdev {
-            None => None,
-            Some(sgx_epc_0_memdev) => Some(KVArgQ {
-                key: "sgx-epc.0.memdev",
-                kv_split_with: Some("="),
-                value: Some(sgx_epc_0_memdev.to_string()),
-            }),
-        }
+    pub fn hmat(mut self, hmat: impl Into<OnOff>) -> Self {
+        self.config.hmat = Some(hmat.into());
+        self
     }
 
-    #[inline]
-    fn sgx_epc_0_node(&self) -> Option<KVArgQ<'a>> {
-        match self.sgx_epc_0_node {
-            None => None,
-            Some(sgx_epc_0_node) => Some(KVArgQ {
-                key: "sgx-epc.0.node",
-                kv_split_with: Some("="),
-                value: Some(sgx_epc_0_node.to_string()),
-            }),
-        }
+    pub fn memory_backend(mut self, memory_backend: impl Into<String>) -> Self {
+        self.config.memory_backend = Some(memory_backend.into());
+        self
+    }
+
+    pub fn smm(mut self, smm: impl Into<OnOffAuto>) -> Self {
+        self.config.smm = Some(smm.into());
+        self
+    }
+
+    pub fn pflash0(mut self, pflash0: impl Into<String>) -> Self {
+        self.config.pflash0 = Some(pflash0.into());
+        self
+    }
+
+    pub fn pflash1(mut self, pflash1: impl Into<String>) -> Self {
+        self.config.pflash1 = Some(pflash1.into());
+        self
+    }
+
+    pub fn sgx_epc_0_memdev(mut self, sgx_epc_0_memdev: usize) -> Self {
+        self.config.sgx_epc_0_memdev = Some(sgx_epc_0_memdev);
+        self
+    }
+
+    pub fn sgx_epc_0_node(mut self, sgx_epc_0_node: usize) -> Self {
+        self.config.sgx_epc_0_node = Some(sgx_epc_0_node);
+        self
+    }
+
+    pub fn gic_version(mut self, gic_version: GicVersion) -> Self {
+        self.config.gic_version = Some(gic_version);
+        self
+    }
+
+    pub fn highmem(mut self, highmem: impl Into<OnOff>) -> Self {
+        self.config.highmem = Some(highmem.into());
+        self
+    }
+
+    pub fn aia(mut self, aia: RiscvAia) -> Self {
+        self.config.aia = Some(aia);
+        self
+    }
+
+    pub fn build(self) -> MachineConfig {
+        self.config
     }
 }
 
-impl<'a> OptionFormatting<'a> for MachineConfig<'a> {
-    fn formatting(&self) -> OptionQ<'a> {
-        OptionQ {
-            prefix: "-",
-            raw: "machine",
-            option_args_split_with: " ",
-            args_split_with: ",",
-            args: vec![
-                self.m_type(),
-                self.accel(),
-                self.vmport(),
-                self.dump_guest_core(),
-                self.mem_merge(),
-                self.aes_key_wrap(),
-                self.dea_key_wrap(),
-                self.nvdimm(),
-                self.memory_encryption(),
-                self.hmat(),
-                self.memory_backend(),
-                self.sgx_epc_0_memdev(),
-                self.sgx_epc_0_node(),
-            ]
-            .into_iter()
-            .filter(|x| x.is_some())
-            .map(|x| x.unwrap())
-            .collect(),
-        }
+impl From<MachineConfigBuilder> for MachineConfig {
+    fn from(builder: MachineConfigBuilder) -> Self {
+        builder.build()
     }
 }
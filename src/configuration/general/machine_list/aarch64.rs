@@ -0,0 +1,58 @@
+//! Machine types of qemu-system-aarch64.
+use super::MachineCatalog;
+
+/// `qemu-system-aarch64 -machine help` of QEMU 8.2, without the boards of
+/// 32-bit Arm cores. There is no default machine, -machine is required.
+pub const MACHINE_HELP: &str = "\
+Supported machines are:
+none                 empty machine
+raspi3ap             Raspberry Pi 3A+ (revision 1.0)
+raspi3b              Raspberry Pi 3B (revision 1.2)
+sbsa-ref             QEMU 'SBSA Reference' ARM Virtual Machine
+virt                 QEMU 8.2 ARM Virtual Machine (alias of virt-8.2)
+virt-8.2             QEMU 8.2 ARM Virtual Machine
+virt-8.1             QEMU 8.1 ARM Virtual Machine
+virt-8.0             QEMU 8.0 ARM Virtual Machine
+virt-7.2             QEMU 7.2 ARM Virtual Machine
+virt-7.1             QEMU 7.1 ARM Virtual Machine
+virt-7.0             QEMU 7.0 ARM Virtual Machine
+virt-6.2             QEMU 6.2 ARM Virtual Machine
+virt-6.1             QEMU 6.1 ARM Virtual Machine
+virt-6.0             QEMU 6.0 ARM Virtual Machine
+virt-5.2             QEMU 5.2 ARM Virtual Machine
+virt-5.1             QEMU 5.1 ARM Virtual Machine
+virt-5.0             QEMU 5.0 ARM Virtual Machine
+virt-4.2             QEMU 4.2 ARM Virtual Machine
+virt-4.1             QEMU 4.1 ARM Virtual Machine
+virt-4.0             QEMU 4.0 ARM Virtual Machine
+virt-3.1             QEMU 3.1 ARM Virtual Machine
+virt-3.0             QEMU 3.0 ARM Virtual Machine
+virt-2.9             QEMU 2.9 ARM Virtual Machine
+virt-2.8             QEMU 2.8 ARM Virtual Machine
+virt-2.7             QEMU 2.7 ARM Virtual Machine
+virt-2.6             QEMU 2.6 ARM Virtual Machine
+virt-2.12            QEMU 2.12 ARM Virtual Machine
+virt-2.11            QEMU 2.11 ARM Virtual Machine
+virt-2.10            QEMU 2.10 ARM Virtual Machine
+xlnx-versal-virt     Xilinx Versal Virtual development board
+xlnx-zcu102          Xilinx ZynqMP ZCU102 board with 4xA53s and 2xR5Fs based on the value of smp
+";
+
+/// The machines of the snapshot.
+pub fn catalog() -> MachineCatalog {
+    MachineCatalog::parse("aarch64", MACHINE_HELP).expect("the aarch64 snapshot parses")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let catalog = catalog();
+        assert_eq!(catalog.machines.len(), 30);
+        assert_eq!(catalog.resolve("virt"), Some("virt-8.2"));
+        assert_eq!(catalog.default_machine(), None);
+        assert!(catalog.find("sbsa-ref").is_some());
+    }
+}
@@ -1 +1,281 @@
-pub mod x86_64;
\ No newline at end of file
+//! Machine types a QEMU binary supports, as listed by
+//! `qemu-system-<arch> -machine help`:
+//!     Supported machines are:
+//!     pc                   Standard PC (i440FX + PIIX, 1996) (alias of pc-i440fx-8.2)
+//!     pc-i440fx-8.2        Standard PC (i440FX + PIIX, 1996) (default)
+//!
+//! Each architecture module holds a snapshot of that list for use without a
+//! QEMU binary at hand.
+pub mod aarch64;
+pub mod ppc64;
+pub mod riscv64;
+pub mod s390x;
+pub mod x86_64;
+
+use std::{error::Error, fmt, io, path::Path, process::Command};
+
+use serde::{Deserialize, Serialize};
+
+use super::{machine::MachineConfig, validate::*};
+
+/// One machine type.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MachineInfo {
+    #[serde(rename = "name")]
+    pub name: String,
+
+    /// A shorter name pointing at the newest version of the machine, e.g.
+    /// `q35` for `pc-q35-8.2`.
+    #[serde(rename = "alias", skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+
+    #[serde(rename = "description")]
+    pub description: String,
+
+    /// The machine QEMU runs without -machine.
+    #[serde(rename = "default", default)]
+    pub default: bool,
+
+    /// The machine is going to be removed from QEMU.
+    #[serde(rename = "deprecated", default)]
+    pub deprecated: bool,
+}
+
+impl MachineInfo {
+    fn matches(&self, name: &str) -> bool {
+        self.name == name || self.alias.as_deref() == Some(name)
+    }
+}
+
+/// The machine types of the QEMU binary for one architecture.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MachineCatalog {
+    /// The target architecture, e.g. `x86_64`.
+    #[serde(rename = "arch")]
+    pub arch: String,
+
+    #[serde(rename = "machines")]
+    pub machines: Vec<MachineInfo>,
+}
+
+/// A machine list that cannot be obtained or read.
+#[derive(Debug)]
+pub enum MachineCatalogError {
+    /// The QEMU binary could not be run.
+    Io(io::Error),
+    /// The QEMU binary exited with an error.
+    Failed { status: Option<i32>, stderr: String },
+    /// A line of the list that is not understood, counted from 1.
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for MachineCatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MachineCatalogError::Io(e) => write!(f, "cannot run QEMU: {}", e),
+            MachineCatalogError::Failed {
+                status: Some(code),
+                stderr,
+            } => write!(f, "QEMU exited with status {}: {}", code, stderr.trim()),
+            MachineCatalogError::Failed {
+                status: None,
+                stderr,
+            } => write!(f, "QEMU was killed: {}", stderr.trim()),
+            MachineCatalogError::Parse { line, message } => {
+                write!(f, "machine list line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl Error for MachineCatalogError {}
+
+impl From<io::Error> for MachineCatalogError {
+    fn from(e: io::Error) -> Self {
+        MachineCatalogError::Io(e)
+    }
+}
+
+impl MachineCatalog {
+    /// Read the output of `-machine help`. Aliases are printed on a line of
+    /// their own before the machine they point at.
+    pub fn parse(arch: impl Into<String>, help: &str) -> Result<Self, MachineCatalogError> {
+        let mut machines: Vec<MachineInfo> = Vec::new();
+        let mut aliases: Vec<(usize, String, String)> = Vec::new();
+        for (i, line) in help.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() || line.ends_with(':') {
+                continue;
+            }
+            let (name, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let mut description = rest.trim_start();
+
+            if let Some(target) = description
+                .strip_suffix(')')
+                .and_then(|d| d.rsplit_once(" (alias of "))
+                .map(|(_, target)| target)
+            {
+                aliases.push((i + 1, name.to_string(), target.to_string()));
+                continue;
+            }
+
+            let mut strip = |suffix: &str| match description.strip_suffix(suffix) {
+                Some(rest) => {
+                    description = rest;
+                    true
+                }
+                None => false,
+            };
+            let deprecated = strip(" (deprecated)");
+            let default = strip(" (default)");
+            if machines.iter().any(|machine| machine.matches(name)) {
+                return Err(MachineCatalogError::Parse {
+                    line: i + 1,
+                    message: format!("machine '{}' is listed twice", name),
+                });
+            }
+            machines.push(MachineInfo {
+                name: name.to_string(),
+                alias: None,
+                description: description.to_string(),
+                default,
+                deprecated,
+            });
+        }
+
+        for (line, alias, target) in aliases {
+            match machines.iter_mut().find(|machine| machine.name == target) {
+                Some(machine) => machine.alias = Some(alias),
+                None => {
+                    return Err(MachineCatalogError::Parse {
+                        line,
+                        message: format!("'{}' is an alias of unknown machine '{}'", alias, target),
+                    })
+                }
+            }
+        }
+        Ok(MachineCatalog {
+            arch: arch.into(),
+            machines,
+        })
+    }
+
+    /// Ask a QEMU binary for its machines. The architecture is taken from
+    /// the binary name, e.g. `qemu-system-aarch64`.
+    pub fn from_binary(qemu: impl AsRef<Path>) -> Result<Self, MachineCatalogError> {
+        let qemu = qemu.as_ref();
+        let output = Command::new(qemu).args(["-machine", "help"]).output()?;
+        if !output.status.success() {
+            return Err(MachineCatalogError::Failed {
+                status: output.status.code(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
+        let arch = qemu
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.strip_prefix("qemu-system-").unwrap_or(name))
+            .unwrap_or_default();
+        Self::parse(arch, &String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// The machine with the given name or alias.
+    pub fn find(&self, name: &str) -> Option<&MachineInfo> {
+        self.machines.iter().find(|machine| machine.matches(name))
+    }
+
+    /// The versioned name of a machine given by name or alias, e.g.
+    /// `pc-q35-8.2` for `q35`.
+    pub fn resolve(&self, name: &str) -> Option<&str> {
+        self.find(name).map(|machine| machine.name.as_str())
+    }
+
+    /// The machine QEMU runs without -machine.
+    pub fn default_machine(&self) -> Option<&MachineInfo> {
+        self.machines.iter().find(|machine| machine.default)
+    }
+
+    /// Every name a machine can be selected by, aliases included.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.machines.iter().flat_map(|machine| {
+            machine
+                .alias
+                .as_deref()
+                .into_iter()
+                .chain([machine.name.as_str()])
+        })
+    }
+}
+
+/// The checked-in catalog of an architecture.
+pub fn snapshot(arch: &str) -> Option<MachineCatalog> {
+    match arch {
+        "x86_64" => Some(x86_64::catalog()),
+        "aarch64" => Some(aarch64::catalog()),
+        "riscv64" => Some(riscv64::catalog()),
+        "s390x" => Some(s390x::catalog()),
+        "ppc64" => Some(ppc64::catalog()),
+        _ => None,
+    }
+}
+
+/// Check that the machine type is one the catalog knows.
+pub(crate) fn validate_machine(
+    catalog: &MachineCatalog,
+    machine: &MachineConfig,
+    errors: &mut Vec<ValidationError>,
+) {
+    if catalog.find(&machine.m_type).is_none() {
+        invalid(
+            errors,
+            "machine",
+            "type",
+            ValidationErrorKind::NotSupported {
+                by: format!("qemu-system-{}", catalog.arch),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let help = "\
+Supported machines are:
+microvm              microvm (i386)
+pc                   Standard PC (i440FX + PIIX, 1996) (alias of pc-i440fx-8.2)
+pc-i440fx-8.2        Standard PC (i440FX + PIIX, 1996) (default)
+pc-i440fx-2.3        Standard PC (i440FX + PIIX, 1996) (deprecated)
+none                 empty machine
+";
+        let catalog = MachineCatalog::parse("x86_64", help).unwrap();
+        assert_eq!(
+            catalog.machines[1],
+            MachineInfo {
+                name: "pc-i440fx-8.2".to_string(),
+                alias: Some("pc".to_string()),
+                description: "Standard PC (i440FX + PIIX, 1996)".to_string(),
+                default: true,
+                deprecated: false,
+            }
+        );
+        assert!(catalog.find("pc-i440fx-2.3").unwrap().deprecated);
+        assert_eq!(catalog.find("none").unwrap().description, "empty machine");
+        assert_eq!(catalog.resolve("pc"), Some("pc-i440fx-8.2"));
+        assert_eq!(catalog.resolve("q35"), None);
+        assert_eq!(catalog.default_machine().unwrap().name, "pc-i440fx-8.2");
+        assert_eq!(
+            catalog.names().collect::<Vec<_>>(),
+            ["microvm", "pc", "pc-i440fx-8.2", "pc-i440fx-2.3", "none"]
+        );
+
+        let error = MachineCatalog::parse("x86_64", "q35   Q35 (alias of pc-q35-8.2)\n");
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "machine list line 1: 'q35' is an alias of unknown machine 'pc-q35-8.2'"
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! Machine types of qemu-system-ppc64.
+use super::MachineCatalog;
+
+/// `qemu-system-ppc64 -machine help` of QEMU 8.2.
+pub const MACHINE_HELP: &str = "\
+Supported machines are:
+40p                  IBM RS/6000 7020 (40p)
+bamboo               bamboo
+g3beige              Heathrow based PowerMAC
+mac99                Mac99 based PowerMAC
+mpc8544ds            mpc8544ds
+none                 empty machine
+pegasos2             Genesi/bPlan Pegasos II
+powernv10            IBM PowerNV (Non-Virtualized) POWER10
+powernv8             IBM PowerNV (Non-Virtualized) POWER8
+powernv              IBM PowerNV (Non-Virtualized) POWER9 (alias of powernv9)
+powernv9             IBM PowerNV (Non-Virtualized) POWER9
+ppce500              generic paravirt e500 platform
+pseries              pSeries Logical Partition (PAPR compliant) (alias of pseries-8.2)
+pseries-8.2          pSeries Logical Partition (PAPR compliant) (default)
+pseries-8.1          pSeries Logical Partition (PAPR compliant)
+pseries-8.0          pSeries Logical Partition (PAPR compliant)
+pseries-7.2          pSeries Logical Partition (PAPR compliant)
+pseries-7.1          pSeries Logical Partition (PAPR compliant)
+pseries-7.0          pSeries Logical Partition (PAPR compliant)
+pseries-6.2          pSeries Logical Partition (PAPR compliant)
+pseries-6.1          pSeries Logical Partition (PAPR compliant)
+pseries-6.0          pSeries Logical Partition (PAPR compliant)
+pseries-5.2          pSeries Logical Partition (PAPR compliant)
+pseries-5.1          pSeries Logical Partition (PAPR compliant)
+pseries-5.0          pSeries Logical Partition (PAPR compliant)
+pseries-4.2          pSeries Logical Partition (PAPR compliant)
+pseries-4.1          pSeries Logical Partition (PAPR compliant)
+pseries-4.0          pSeries Logical Partition (PAPR compliant)
+pseries-3.1          pSeries Logical Partition (PAPR compliant)
+pseries-3.0          pSeries Logical Partition (PAPR compliant)
+pseries-2.9          pSeries Logical Partition (PAPR compliant)
+pseries-2.8          pSeries Logical Partition (PAPR compliant)
+pseries-2.7          pSeries Logical Partition (PAPR compliant)
+pseries-2.6          pSeries Logical Partition (PAPR compliant)
+pseries-2.5          pSeries Logical Partition (PAPR compliant)
+pseries-2.4          pSeries Logical Partition (PAPR compliant)
+pseries-2.3          pSeries Logical Partition (PAPR compliant)
+pseries-2.2          pSeries Logical Partition (PAPR compliant)
+pseries-2.12-sxxm    pSeries Logical Partition (PAPR compliant)
+pseries-2.12         pSeries Logical Partition (PAPR compliant)
+pseries-2.11         pSeries Logical Partition (PAPR compliant)
+pseries-2.10         pSeries Logical Partition (PAPR compliant)
+pseries-2.1          pSeries Logical Partition (PAPR compliant)
+ref405ep             ref405ep (deprecated)
+sam460ex             aCube Sam460ex
+virtex-ml507         Xilinx Virtex ML507 reference design
+";
+
+/// The machines of the snapshot.
+pub fn catalog() -> MachineCatalog {
+    MachineCatalog::parse("ppc64", MACHINE_HELP).expect("the ppc64 snapshot parses")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let catalog = catalog();
+        assert_eq!(catalog.resolve("pseries"), Some("pseries-8.2"));
+        assert_eq!(catalog.resolve("powernv"), Some("powernv9"));
+        assert_eq!(catalog.default_machine().unwrap().name, "pseries-8.2");
+        assert!(catalog.find("ref405ep").unwrap().deprecated);
+    }
+}
@@ -0,0 +1,44 @@
+//! Machine types of qemu-system-riscv64.
+use super::MachineCatalog;
+
+/// `qemu-system-riscv64 -machine help` of QEMU 8.2. The machines are not
+/// versioned, so a guest may see different hardware after an upgrade.
+pub const MACHINE_HELP: &str = "\
+Supported machines are:
+microchip-icicle-kit Microchip PolarFire SoC Icicle Kit
+none                 empty machine
+shakti_c             RISC-V Board compatible with Shakti SDK
+sifive_e             RISC-V Board compatible with SiFive E SDK
+sifive_u             RISC-V Board compatible with SiFive U SDK
+spike                RISC-V Spike board (default)
+virt                 RISC-V VirtIO board
+";
+
+/// The machines of the snapshot.
+pub fn catalog() -> MachineCatalog {
+    MachineCatalog::parse("riscv64", MACHINE_HELP).expect("the riscv64 snapshot parses")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let catalog = catalog();
+        assert_eq!(
+            catalog.names().collect::<Vec<_>>(),
+            [
+                "microchip-icicle-kit",
+                "none",
+                "shakti_c",
+                "sifive_e",
+                "sifive_u",
+                "spike",
+                "virt",
+            ]
+        );
+        assert_eq!(catalog.default_machine().unwrap().name, "spike");
+        assert_eq!(catalog.resolve("virt"), Some("virt"));
+    }
+}
@@ -0,0 +1,59 @@
+//! Machine types of qemu-system-s390x.
+use super::MachineCatalog;
+
+/// `qemu-system-s390x -machine help` of QEMU 8.2.
+pub const MACHINE_HELP: &str = "\
+Supported machines are:
+s390-ccw-virtio      VirtIO-ccw based S390 machine v8.2 (alias of s390-ccw-virtio-8.2)
+s390-ccw-virtio-8.2  VirtIO-ccw based S390 machine v8.2 (default)
+s390-ccw-virtio-8.1  VirtIO-ccw based S390 machine v8.1
+s390-ccw-virtio-8.0  VirtIO-ccw based S390 machine v8.0
+s390-ccw-virtio-7.2  VirtIO-ccw based S390 machine v7.2
+s390-ccw-virtio-7.1  VirtIO-ccw based S390 machine v7.1
+s390-ccw-virtio-7.0  VirtIO-ccw based S390 machine v7.0
+s390-ccw-virtio-6.2  VirtIO-ccw based S390 machine v6.2
+s390-ccw-virtio-6.1  VirtIO-ccw based S390 machine v6.1
+s390-ccw-virtio-6.0  VirtIO-ccw based S390 machine v6.0
+s390-ccw-virtio-5.2  VirtIO-ccw based S390 machine v5.2
+s390-ccw-virtio-5.1  VirtIO-ccw based S390 machine v5.1
+s390-ccw-virtio-5.0  VirtIO-ccw based S390 machine v5.0
+s390-ccw-virtio-4.2  VirtIO-ccw based S390 machine v4.2
+s390-ccw-virtio-4.1  VirtIO-ccw based S390 machine v4.1
+s390-ccw-virtio-4.0  VirtIO-ccw based S390 machine v4.0
+s390-ccw-virtio-3.1  VirtIO-ccw based S390 machine v3.1
+s390-ccw-virtio-3.0  VirtIO-ccw based S390 machine v3.0
+s390-ccw-virtio-2.9  VirtIO-ccw based S390 machine v2.9
+s390-ccw-virtio-2.8  VirtIO-ccw based S390 machine v2.8
+s390-ccw-virtio-2.7  VirtIO-ccw based S390 machine v2.7
+s390-ccw-virtio-2.6  VirtIO-ccw based S390 machine v2.6
+s390-ccw-virtio-2.5  VirtIO-ccw based S390 machine v2.5
+s390-ccw-virtio-2.4  VirtIO-ccw based S390 machine v2.4
+s390-ccw-virtio-2.12 VirtIO-ccw based S390 machine v2.12
+s390-ccw-virtio-2.11 VirtIO-ccw based S390 machine v2.11
+s390-ccw-virtio-2.10 VirtIO-ccw based S390 machine v2.10
+none                 empty machine
+";
+
+/// The machines of the snapshot.
+pub fn catalog() -> MachineCatalog {
+    MachineCatalog::parse("s390x", MACHINE_HELP).expect("the s390x snapshot parses")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let catalog = catalog();
+        assert_eq!(catalog.machines.len(), 27);
+        assert_eq!(
+            catalog.resolve("s390-ccw-virtio"),
+            Some("s390-ccw-virtio-8.2")
+        );
+        assert_eq!(
+            catalog.default_machine().unwrap().name,
+            "s390-ccw-virtio-8.2"
+        );
+    }
+}
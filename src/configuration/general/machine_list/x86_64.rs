@@ -1,9 +1,90 @@
-#[allow(non_upper_case_globals)]
-pub const AvailableMachines_X86_64_Names: [&'static str;0] = [
-    
-];
-
-#[allow(non_camel_case_types)]
-pub enum AvailableMachines_X86_64 {
-    
+//! Machine types of qemu-system-x86_64.
+use super::MachineCatalog;
+
+/// `qemu-system-x86_64 -machine help` of QEMU 8.2.
+pub const MACHINE_HELP: &str = "\
+Supported machines are:
+microvm              microvm (i386)
+pc                   Standard PC (i440FX + PIIX, 1996) (alias of pc-i440fx-8.2)
+pc-i440fx-8.2        Standard PC (i440FX + PIIX, 1996) (default)
+pc-i440fx-8.1        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-8.0        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-7.2        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-7.1        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-7.0        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-6.2        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-6.1        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-6.0        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-5.2        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-5.1        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-5.0        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-4.2        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-4.1        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-4.0        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-3.1        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-3.0        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-2.9        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-2.8        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-2.7        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-2.6        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-2.5        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-2.4        Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-2.3        Standard PC (i440FX + PIIX, 1996) (deprecated)
+pc-i440fx-2.2        Standard PC (i440FX + PIIX, 1996) (deprecated)
+pc-i440fx-2.12       Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-2.11       Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-2.10       Standard PC (i440FX + PIIX, 1996)
+pc-i440fx-2.1        Standard PC (i440FX + PIIX, 1996) (deprecated)
+pc-i440fx-2.0        Standard PC (i440FX + PIIX, 1996) (deprecated)
+q35                  Standard PC (Q35 + ICH9, 2009) (alias of pc-q35-8.2)
+pc-q35-8.2           Standard PC (Q35 + ICH9, 2009)
+pc-q35-8.1           Standard PC (Q35 + ICH9, 2009)
+pc-q35-8.0           Standard PC (Q35 + ICH9, 2009)
+pc-q35-7.2           Standard PC (Q35 + ICH9, 2009)
+pc-q35-7.1           Standard PC (Q35 + ICH9, 2009)
+pc-q35-7.0           Standard PC (Q35 + ICH9, 2009)
+pc-q35-6.2           Standard PC (Q35 + ICH9, 2009)
+pc-q35-6.1           Standard PC (Q35 + ICH9, 2009)
+pc-q35-6.0           Standard PC (Q35 + ICH9, 2009)
+pc-q35-5.2           Standard PC (Q35 + ICH9, 2009)
+pc-q35-5.1           Standard PC (Q35 + ICH9, 2009)
+pc-q35-5.0           Standard PC (Q35 + ICH9, 2009)
+pc-q35-4.2           Standard PC (Q35 + ICH9, 2009)
+pc-q35-4.1           Standard PC (Q35 + ICH9, 2009)
+pc-q35-4.0.1         Standard PC (Q35 + ICH9, 2009)
+pc-q35-4.0           Standard PC (Q35 + ICH9, 2009)
+pc-q35-3.1           Standard PC (Q35 + ICH9, 2009)
+pc-q35-3.0           Standard PC (Q35 + ICH9, 2009)
+pc-q35-2.9           Standard PC (Q35 + ICH9, 2009)
+pc-q35-2.8           Standard PC (Q35 + ICH9, 2009)
+pc-q35-2.7           Standard PC (Q35 + ICH9, 2009)
+pc-q35-2.6           Standard PC (Q35 + ICH9, 2009)
+pc-q35-2.5           Standard PC (Q35 + ICH9, 2009)
+pc-q35-2.4           Standard PC (Q35 + ICH9, 2009)
+pc-q35-2.12          Standard PC (Q35 + ICH9, 2009)
+pc-q35-2.11          Standard PC (Q35 + ICH9, 2009)
+pc-q35-2.10          Standard PC (Q35 + ICH9, 2009)
+isapc                ISA-only PC
+none                 empty machine
+x-remote             Experimental remote machine
+";
+
+/// The machines of the snapshot.
+pub fn catalog() -> MachineCatalog {
+    MachineCatalog::parse("x86_64", MACHINE_HELP).expect("the x86_64 snapshot parses")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let catalog = catalog();
+        assert_eq!(catalog.machines.len(), 61);
+        assert_eq!(catalog.names().count(), 63);
+        assert_eq!(catalog.resolve("q35"), Some("pc-q35-8.2"));
+        assert_eq!(catalog.default_machine().unwrap().name, "pc-i440fx-8.2");
+        assert!(catalog.find("pc-i440fx-2.0").unwrap().deprecated);
+    }
 }
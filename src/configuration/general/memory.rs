@@ -1,135 +1,157 @@
 use crate::command::builder::*;
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use super::{types::*, validate::*};
 
 /// Overall memory configuration.
 /// Note: Some architectures might enforce a specific granularity.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "m")]
 pub struct MConfig {
-    // size: initial amount of guest memory (in MiB).
-    #[serde(rename = "size")]
-    pub(crate) size: usize,
+    // size: initial amount of guest memory (a number without suffix is in MiB).
+    #[serde(rename = "size", deserialize_with = "mib::deserialize")]
+    #[qemu(implied, parse_with = "ByteSize::parse_mib")]
+    pub size: ByteSize,
 
     // slots: number of hotplug slots.
     // (default: none)
     #[serde(rename = "slots", skip_serializing_if = "Option::is_none")]
-    pub(crate) slots: Option<usize>,
+    pub slots: Option<usize>,
 
     // maxmem: maximum amount of guest memory.
     // (default: none)
-    #[serde(rename = "maxmem", skip_serializing_if = "Option::is_none")]
-    pub(crate) maxmem: Option<usize>,
+    #[serde(
+        rename = "maxmem",
+        default,
+        deserialize_with = "mib::option::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[qemu(parse_with = "ByteSize::parse_mib")]
+    pub maxmem: Option<ByteSize>,
 }
 
-impl<'a> MConfig {
-    #[inline]
-    fn size(&self) -> Option<KVArgQ<'a>> {
-        Some(KVArgQ {
-            key: "size",
-            kv_split_with: Some("="),
-            value: Some(self.size.to_string()),
-        })
-    }
-
-    #[inline]
-    fn slots(&self) -> Option<KVArgQ<'a>> {
-        match self.slots {
-            None => None,
-            Some(slots) => Some(KVArgQ {
-                key: "slots",
-                kv_split_with: Some("="),
-                value: Some(slots.to_string()),
-            }),
+impl Validate for MConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        match self.maxmem {
+            Some(maxmem) if maxmem < self.size => invalid(
+                errors,
+                path,
+                "maxmem",
+                ValidationErrorKind::MaxmemBelowSize {
+                    size: self.size,
+                    maxmem,
+                },
+            ),
+            None if self.slots.is_some() => invalid(
+                errors,
+                path,
+                "slots",
+                ValidationErrorKind::Requires { key: "maxmem" },
+            ),
+            _ => {}
         }
     }
+}
 
-    #[inline]
-    fn maxmem(&self) -> Option<KVArgQ<'a>> {
-        match self.maxmem {
-            None => None,
-            Some(maxmem) => Some(KVArgQ {
-                key: "maxmem",
-                kv_split_with: Some("="),
-                value: Some(maxmem.to_string()),
-            }),
-        }
+/// Provide backing storage for guest RAM
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "mem-path", separator = "")]
+pub struct MemPathConfig(pub String);
+
+impl MemPathConfig {
+    pub fn new(path: impl Into<String>) -> Self {
+        MemPathConfig(path.into())
     }
 }
 
-impl<'a> OptionFormatting<'a> for MConfig {
-    fn formatting(&self) -> OptionQ<'a> {
-        OptionQ {
-            prefix: "-",
-            raw: "m",
-            option_args_split_with: " ",
-            args_split_with: ",",
-            args: vec![self.size(), self.slots(), self.maxmem()]
-                .into_iter()
-                .filter(|x| x.is_some())
-                .map(|x| x.unwrap())
-                .collect(),
-        }
+/// Preallocate guest memory (use with -mem-path). The option takes no
+/// argument.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, QemuOption)]
+#[qemu(option = "mem-prealloc")]
+pub struct MemPreallocConfig {}
+
+impl MemPreallocConfig {
+    pub fn new() -> Self {
+        MemPreallocConfig {}
     }
 }
 
-/// Provide backing storage for guest RAM
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MemPathConfig<'a>(&'a str);
-
-impl<'a> OptionFormatting<'a> for MemPathConfig<'a> {
-    fn formatting(&self) -> OptionQ<'a> {
-        OptionQ {
-            prefix: "-",
-            raw: "mem-path",
-            option_args_split_with: " ",
-            args_split_with: "",
-            args: vec![KVArgQ {
-                key: self.0,
-                kv_split_with: None,
-                value: None,
-            }],
+/// JSON may give the option as `{}` or `true`, or as the number older
+/// configs carried, which QEMU never took.
+impl<'de> Deserialize<'de> for MemPreallocConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::Bool(true) | serde_json::Value::Number(_) => {
+                Ok(MemPreallocConfig::new())
+            }
+            serde_json::Value::Object(map) if map.is_empty() => Ok(MemPreallocConfig::new()),
+            _ => Err(de::Error::custom(
+                "expected {}, true or a number for mem-prealloc; leave it out to disable it",
+            )),
         }
     }
 }
 
-/// Preallocate guest memory (use with -mem-path)
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MemPreallocConfig(usize);
-
-impl<'a> OptionFormatting<'a> for MemPreallocConfig {
-    fn formatting(&self) -> OptionQ<'a> {
-        OptionQ {
-            prefix: "-",
-            raw: "mem-prealloc",
-            option_args_split_with: " ",
-            args_split_with: "",
-            args: vec![KVArgQ {
-                key: "",
-                kv_split_with: None,
-                value: Some(self.0.to_string()),
-            }],
+/// Builder for `MConfig`, e.g.
+///     MConfig::builder(ByteSize::gib(2)).maxmem(ByteSize::gib(8)).slots(2).build()
+#[derive(Debug, Clone)]
+pub struct MConfigBuilder {
+    config: MConfig,
+}
+
+impl MConfig {
+    pub fn builder(size: ByteSize) -> MConfigBuilder {
+        MConfigBuilder {
+            config: MConfig {
+                size,
+                slots: None,
+                maxmem: None,
+            },
         }
     }
 }
 
+impl MConfigBuilder {
+    pub fn slots(mut self, slots: usize) -> Self {
+        self.config.slots = Some(slots);
+        self
+    }
+
+    pub fn maxmem(mut self, maxmem: ByteSize) -> Self {
+        self.config.maxmem = Some(maxmem);
+        self
+    }
+
+    pub fn build(self) -> MConfig {
+        self.config
+    }
+}
+
+impl From<MConfigBuilder> for MConfig {
+    fn from(builder: MConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use serde::{Deserialize, Serialize};
-    use std::{error::Error, fs::File, io::Read, path::Path};
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    struct Temp<'a> {
+    use std::{error::Error, fs::File, path::Path};
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Temp {
         #[serde(rename = "m")]
         m_config: MConfig,
 
-        #[serde(rename = "mem-path", borrow)]
-        mem_path_config: MemPathConfig<'a>,
+        #[serde(rename = "mem-path")]
+        mem_path_config: MemPathConfig,
 
         #[serde(rename = "mem-prealloc")]
         mem_prealloc_config: MemPreallocConfig,
     }
 
-    impl<'a> CommandFormatting<'a> for Temp<'a> {
-        fn formatting(&self) -> CommandQ<'a> {
+    impl CommandFormatting for Temp {
+        fn formatting(&self) -> CommandQ<'_> {
             CommandQ {
                 raw: "",
                 option_split_with: " ",
@@ -142,32 +164,28 @@ mod test {
         }
     }
 
-    fn read_from_file<'a, P: AsRef<Path>>(
-        path: P,
-        string: &'a mut String,
-    ) -> Result<Temp<'a>, Box<dyn Error>> {
-        let mut file = File::open(path)?;
-        file.read_to_string(string)?;
-        let c: Temp<'a> = serde_json::from_str(string)?;
+    fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Temp, Box<dyn Error>> {
+        let file = File::open(path)?;
+        let c: Temp = serde_json::from_reader(file)?;
         Ok(c)
     }
 
     #[test]
     fn test_m() {
         let m_config = MConfig {
-            size: 512,
+            size: ByteSize::mib(512),
             slots: Some(2),
-            maxmem: Some(1024),
+            maxmem: Some(ByteSize::mib(1024)),
         };
         assert_eq!(
             m_config.formatting().to_string(),
-            "-m size=512,slots=2,maxmem=1024"
+            "-m size=512M,slots=2,maxmem=1G"
         )
     }
 
     #[test]
     fn test_mem_path() {
-        let mem_path_config = MemPathConfig("/mem_backend_storage/mem.1");
+        let mem_path_config = MemPathConfig("/mem_backend_storage/mem.1".to_string());
         assert_eq!(
             mem_path_config.formatting().to_string(),
             "-mem-path /mem_backend_storage/mem.1"
@@ -176,27 +194,36 @@ mod test {
 
     #[test]
     fn test_mem_prealloc() {
-        let mem_prealloc_config = MemPreallocConfig(128);
+        let mem_prealloc_config = MemPreallocConfig::new();
         assert_eq!(
             mem_prealloc_config.formatting().to_string(),
-            "-mem-prealloc 128"
-        )
+            "-mem-prealloc"
+        );
+        for json in ["{}", "true", "128"] {
+            assert_eq!(
+                serde_json::from_str::<MemPreallocConfig>(json).unwrap(),
+                mem_prealloc_config
+            );
+        }
+        assert!(serde_json::from_str::<MemPreallocConfig>("false").is_err());
     }
 
     #[test]
     fn test_read_json() {
-        let mut aux = String::new();
-        let memory_1 = read_from_file("./test_json/memory.json", &mut aux).unwrap();
+        let memory_1 = read_from_file("./test_json/memory.json").unwrap();
         let memory_2 = Temp {
             m_config: MConfig {
-                size: 512,
+                size: ByteSize::mib(512),
                 slots: Some(2),
-                maxmem: Some(1024),
+                maxmem: Some(ByteSize::mib(1024)),
             },
-            mem_path_config: MemPathConfig("/mem_backend_storage/mem.1"),
-            mem_prealloc_config: MemPreallocConfig(128),
+            mem_path_config: MemPathConfig("/mem_backend_storage/mem.1".to_string()),
+            mem_prealloc_config: MemPreallocConfig::new(),
         };
 
-        assert_eq!(memory_1.formatting().to_string(), memory_2.formatting().to_string())
+        assert_eq!(
+            memory_1.formatting().to_string(),
+            memory_2.formatting().to_string()
+        )
     }
 }
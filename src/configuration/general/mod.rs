@@ -9,8 +9,18 @@ pub mod add_fd;
 pub mod set;
 pub mod global;
 pub mod boot;
+pub mod firmware;
 pub mod memory;
 pub mod language;
 pub mod audio;
 pub mod device;
-pub mod name;
\ No newline at end of file
+pub mod display;
+pub mod pci;
+pub mod name;
+pub mod process;
+pub mod block;
+pub mod network;
+pub mod chardev;
+pub mod object;
+pub mod types;
+pub mod validate;
\ No newline at end of file
@@ -1,91 +1,76 @@
 use crate::command::builder::*;
 use serde::{Deserialize, Serialize};
 
+use super::types::OnOff;
+
 /// Name of the vm.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct NameConfig<'a> {
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "name")]
+pub struct NameConfig {
     // string1 sets the window title.
     #[serde(rename = "window-title")]
-    window_title: &'a str,
+    #[qemu(positional, rename = "guest")]
+    pub window_title: String,
 
     // string2 the process name.
     #[serde(rename = "process", skip_serializing_if = "Option::is_none")]
-    process: Option<&'a str>,
+    pub process: Option<String>,
 
     // debug-threads=on|off
     // When debug-threads is enabled, individual threads are given a separate name
     // NOTE: The thread names are for debugging and not a stable API.
     #[serde(rename = "debug-threads", skip_serializing_if = "Option::is_none")]
-    debug_threads: Option<&'a str>,
+    pub debug_threads: Option<OnOff>,
 }
 
-impl<'a> NameConfig<'a> {
-    #[inline]
-    fn window_title(&self) -> Option<KVArgQ<'a>> {
-        Some(KVArgQ {
-            key: &self.window_title,
-            kv_split_with: None,
-            value: None,
-        })
-    }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "uuid", separator = "")]
+pub struct UuidConfig(pub String);
 
-    #[inline]
-    fn process(&self) -> Option<KVArgQ<'a>> {
-        match self.process {
-            None => None,
-            Some(process) => Some(KVArgQ {
-                key: "process",
-                kv_split_with: Some("="),
-                value: Some(process.to_string()),
-            }),
-        }
+impl UuidConfig {
+    pub fn new(uuid: impl Into<String>) -> Self {
+        UuidConfig(uuid.into())
     }
+}
+
+/// Builder for `NameConfig`, e.g.
+///     NameConfig::builder("vm-1").process("qemu-vm-1").build()
+#[derive(Debug, Clone)]
+pub struct NameConfigBuilder {
+    config: NameConfig,
+}
 
-    #[inline]
-    fn debug_threads(&self) -> Option<KVArgQ<'a>> {
-        match self.debug_threads {
-            None => None,
-            Some(debug_threads) => Some(KVArgQ {
-                key: "debug-threads",
-                kv_split_with: Some("="),
-                value: Some(debug_threads.to_string()),
-            }),
+impl NameConfig {
+    pub fn builder(window_title: impl Into<String>) -> NameConfigBuilder {
+        NameConfigBuilder {
+            config: NameConfig {
+                window_title: window_title.into(),
+                process: None,
+                debug_threads: None,
+            },
         }
     }
 }
 
-impl<'a> OptionFormatting<'a> for NameConfig<'a> {
-    fn formatting(&self) -> OptionQ<'a> {
-        OptionQ {
-            prefix: "-",
-            raw: "name",
-            option_args_split_with: " ",
-            args_split_with: ",",
-            args: vec![self.window_title(), self.process(), self.debug_threads()]
-                .into_iter()
-                .filter(|x| x.is_some())
-                .map(|x| x.unwrap())
-                .collect(),
-        }
+impl NameConfigBuilder {
+    pub fn process(mut self, process: impl Into<String>) -> Self {
+        self.config.process = Some(process.into());
+        self
+    }
+
+    pub fn debug_threads(mut self, debug_threads: impl Into<OnOff>) -> Self {
+        self.config.debug_threads = Some(debug_threads.into());
+        self
+    }
+
+    pub fn build(self) -> NameConfig {
+        self.config
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UuidConfig<'a>(&'a str);
-
-impl<'a> OptionFormatting<'a> for UuidConfig<'a> {
-    fn formatting(&self) -> OptionQ<'a> {
-        OptionQ {
-            prefix: "-",
-            raw: "uuid",
-            option_args_split_with: " ",
-            args_split_with: "",
-            args: vec![KVArgQ {
-                key: self.0,
-                kv_split_with: None,
-                value: None,
-            }],
-        }
+impl From<NameConfigBuilder> for NameConfig {
+    fn from(builder: NameConfigBuilder) -> Self {
+        builder.build()
     }
 }
 
@@ -96,9 +81,9 @@ mod test {
     #[test]
     fn test_name() {
         let name_config = NameConfig {
-            window_title: "qemu-testmachine",
-            process: Some("qemu-simulation-process1"),
-            debug_threads: Some("off"),
+            window_title: "qemu-testmachine".to_string(),
+            process: Some("qemu-simulation-process1".to_string()),
+            debug_threads: Some(OnOff::Off),
         };
 
         assert_eq!(
@@ -107,9 +92,23 @@ mod test {
         )
     }
 
+    #[test]
+    fn test_name_with_comma() {
+        let name_config = NameConfig {
+            window_title: "guest,process=evil".to_string(),
+            process: None,
+            debug_threads: None,
+        };
+
+        assert_eq!(
+            name_config.formatting().to_string(),
+            "-name guest,,process=evil"
+        )
+    }
+
     #[test]
     fn test_uuid() {
-        let uuid_config = UuidConfig("12345678-1234-1234-1234-123456789abc");
+        let uuid_config = UuidConfig("12345678-1234-1234-1234-123456789abc".to_string());
 
         assert_eq!(
             uuid_config.formatting().to_string(),
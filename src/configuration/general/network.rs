@@ -0,0 +1,945 @@
+//! Networking: -netdev host backends, paired with the NIC devices the guest
+//! sees by the id of the netdev, e.g.
+//!     -netdev user,id=net0,hostfwd=tcp::2222-:22
+//!     -device virtio-net-pci,netdev=net0,mac=52:54:00:12:34:56
+use std::{error::Error, fmt, str::FromStr};
+
+use crate::command::builder::*;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{
+    device::DeviceConfig,
+    types::{qemu_enum, OnOff},
+    validate::*,
+};
+
+qemu_enum! {
+    /// Network backends of -netdev.
+    pub enum NetdevBackend {
+        User = "user",
+        Tap = "tap",
+        Bridge = "bridge",
+        Socket = "socket",
+        Stream = "stream",
+        Dgram = "dgram",
+        VhostUser = "vhost-user",
+    }
+}
+
+qemu_enum! {
+    /// Protocol of a forwarded port.
+    pub enum FwdProtocol {
+        Tcp = "tcp",
+        Udp = "udp",
+    }
+}
+
+qemu_enum! {
+    /// addr.type=inet|unix|fd of the stream and dgram backends.
+    pub enum AddrType {
+        Inet = "inet",
+        Unix = "unix",
+        Fd = "fd",
+    }
+}
+
+/// A hostfwd rule redirecting a host port to the guest, written as
+/// `tcp:hostaddr:hostport-guestaddr:guestport`. Empty addresses mean any
+/// host address and the default guest address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostFwd {
+    pub protocol: FwdProtocol,
+    pub host_addr: Option<String>,
+    pub host_port: u16,
+    pub guest_addr: Option<String>,
+    pub guest_port: u16,
+}
+
+impl HostFwd {
+    pub fn tcp(host_port: u16, guest_port: u16) -> Self {
+        HostFwd {
+            protocol: FwdProtocol::Tcp,
+            host_addr: None,
+            host_port,
+            guest_addr: None,
+            guest_port,
+        }
+    }
+
+    pub fn udp(host_port: u16, guest_port: u16) -> Self {
+        HostFwd {
+            protocol: FwdProtocol::Udp,
+            ..Self::tcp(host_port, guest_port)
+        }
+    }
+}
+
+/// A hostfwd rule that cannot be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidHostFwd(pub String);
+
+impl fmt::Display for InvalidHostFwd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid hostfwd rule '{}'", self.0)
+    }
+}
+
+impl Error for InvalidHostFwd {}
+
+impl fmt::Display for HostFwd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}-{}:{}",
+            self.protocol,
+            self.host_addr.as_deref().unwrap_or_default(),
+            self.host_port,
+            self.guest_addr.as_deref().unwrap_or_default(),
+            self.guest_port
+        )
+    }
+}
+
+impl FromStr for HostFwd {
+    type Err = InvalidHostFwd;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidHostFwd(s.to_string());
+        // Host names may contain '-', so the rule splits at the first '-'
+        // after the ':' that ends the host address.
+        fn split(rule: &str) -> Option<(&str, &str)> {
+            let port = rule.find(':')?;
+            let end = port + rule[port..].find('-')?;
+            Some((&rule[..end], &rule[end + 1..]))
+        }
+        // The protocol may be left out or empty, defaulting to tcp.
+        let with_protocol = s.split_once(':').and_then(|(protocol, rest)| {
+            let protocol = match protocol {
+                "" => FwdProtocol::Tcp,
+                protocol => protocol.parse().ok()?,
+            };
+            Some((protocol, split(rest)?))
+        });
+        let (protocol, (host, guest)) = match with_protocol {
+            Some(rule) => rule,
+            None => (FwdProtocol::Tcp, split(s).ok_or_else(invalid)?),
+        };
+        // Addresses are IPv4, so the first ':' ends them.
+        let address = |part: &str| -> Result<(Option<String>, u16), InvalidHostFwd> {
+            let (addr, port) = part.split_once(':').ok_or_else(invalid)?;
+            let addr = (!addr.is_empty()).then(|| addr.to_string());
+            Ok((addr, port.parse().map_err(|_| invalid())?))
+        };
+        let (host_addr, host_port) = address(host)?;
+        let (guest_addr, guest_port) = address(guest)?;
+        Ok(HostFwd {
+            protocol,
+            host_addr,
+            host_port,
+            guest_addr,
+            guest_port,
+        })
+    }
+}
+
+impl Serialize for HostFwd {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for HostFwd {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Configure a host network backend with ID 'id'.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "netdev")]
+pub struct NetdevConfig {
+    // user|tap|bridge|socket|stream|dgram|vhost-user
+    #[serde(rename = "type")]
+    #[qemu(positional, rename = "type")]
+    pub backend: NetdevBackend,
+
+    // id=id
+    // The id NIC front-ends refer to by netdev=id.
+    #[serde(rename = "id")]
+    pub id: String,
+
+    // net=addr[/mask] (user)
+    // Set IP network address the guest will see. (default: 10.0.2.0/24)
+    #[serde(rename = "net", skip_serializing_if = "Option::is_none")]
+    pub net: Option<String>,
+
+    // host=addr (user)
+    // Specify the guest-visible address of the host. (default: x.x.x.2)
+    #[serde(rename = "host", skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+
+    // restrict=on|off (user)
+    // If this option is enabled, the guest will be isolated, i.e. it will
+    // not be able to contact the host and no guest IP packets will be
+    // routed over the host to the outside.
+    #[serde(rename = "restrict", skip_serializing_if = "Option::is_none")]
+    pub restrict: Option<OnOff>,
+
+    // hostname=name (user)
+    // Specifies the client hostname reported by the built-in DHCP server.
+    #[serde(rename = "hostname", skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+
+    // dhcpstart=addr (user)
+    // Specify the first of the 16 IPs the built-in DHCP server can assign.
+    #[serde(rename = "dhcpstart", skip_serializing_if = "Option::is_none")]
+    pub dhcpstart: Option<String>,
+
+    // dns=addr (user)
+    // Specify the guest-visible address of the virtual nameserver.
+    // (default: x.x.x.3)
+    #[serde(rename = "dns", skip_serializing_if = "Option::is_none")]
+    pub dns: Option<String>,
+
+    // dnssearch=domain (user)
+    // Provides an entry for the domain-search list sent by the built-in
+    // DHCP server. More than one domain suffix can be transmitted by
+    // specifying this option multiple times.
+    #[serde(rename = "dnssearch", default, skip_serializing_if = "Vec::is_empty")]
+    pub dnssearch: Vec<String>,
+
+    // domainname=domain (user)
+    // Specifies the client domain name reported by the built-in DHCP server.
+    #[serde(rename = "domainname", skip_serializing_if = "Option::is_none")]
+    pub domainname: Option<String>,
+
+    // ipv4=on|off, ipv6=on|off (user)
+    // Whether the IPv4 and IPv6 stacks are enabled. (default: both on)
+    #[serde(rename = "ipv4", skip_serializing_if = "Option::is_none")]
+    pub ipv4: Option<OnOff>,
+
+    #[serde(rename = "ipv6", skip_serializing_if = "Option::is_none")]
+    pub ipv6: Option<OnOff>,
+
+    // ipv6-net=addr[/int] (user)
+    // Set IPv6 network address the guest will see. (default: fec0::/64)
+    #[serde(rename = "ipv6-net", skip_serializing_if = "Option::is_none")]
+    pub ipv6_net: Option<String>,
+
+    // ipv6-host=addr (user)
+    // Specify the guest-visible IPv6 address of the host. (default: x::2)
+    #[serde(rename = "ipv6-host", skip_serializing_if = "Option::is_none")]
+    pub ipv6_host: Option<String>,
+
+    // ipv6-dns=addr (user)
+    // Specify the guest-visible IPv6 address of the virtual nameserver.
+    // (default: x::3)
+    #[serde(rename = "ipv6-dns", skip_serializing_if = "Option::is_none")]
+    pub ipv6_dns: Option<String>,
+
+    // hostfwd=[tcp|udp]:[hostaddr]:hostport-[guestaddr]:guestport (user)
+    // Redirect incoming TCP or UDP connections to the host port hostport to
+    // the guest IP address guestaddr on guest port guestport. This option
+    // can be given multiple times.
+    #[serde(rename = "hostfwd", default, skip_serializing_if = "Vec::is_empty")]
+    pub hostfwd: Vec<HostFwd>,
+
+    // guestfwd=[tcp]:server:port-dev, guestfwd=[tcp]:server:port-cmd:command (user)
+    // Forward guest TCP connections to the IP address server on port port
+    // to the character device dev or to a program executed by cmd:command.
+    // This option can be given multiple times.
+    #[serde(rename = "guestfwd", default, skip_serializing_if = "Vec::is_empty")]
+    pub guestfwd: Vec<String>,
+
+    // tftp=dir, bootfile=file (user)
+    // Activate the built-in TFTP server serving dir, and broadcast file as
+    // BOOTP filename.
+    #[serde(rename = "tftp", skip_serializing_if = "Option::is_none")]
+    pub tftp: Option<String>,
+
+    #[serde(rename = "bootfile", skip_serializing_if = "Option::is_none")]
+    pub bootfile: Option<String>,
+
+    // ifname=name (tap)
+    // Name of the host TAP interface.
+    #[serde(rename = "ifname", skip_serializing_if = "Option::is_none")]
+    pub ifname: Option<String>,
+
+    // script=file, downscript=dfile (tap)
+    // Scripts configuring and deconfiguring the host TAP interface, "no" to
+    // disable them. (default: /etc/qemu-ifup and /etc/qemu-ifdown)
+    #[serde(rename = "script", skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+
+    #[serde(rename = "downscript", skip_serializing_if = "Option::is_none")]
+    pub downscript: Option<String>,
+
+    // br=bridge (tap, bridge)
+    // The host bridge to attach to through the bridge helper.
+    // (default: br0)
+    #[serde(rename = "br", skip_serializing_if = "Option::is_none")]
+    pub br: Option<String>,
+
+    // helper=helper (tap, bridge)
+    // Executable used to configure the TAP interface and attach it to the
+    // bridge. (default: /usr/libexec/qemu-bridge-helper)
+    #[serde(rename = "helper", skip_serializing_if = "Option::is_none")]
+    pub helper: Option<String>,
+
+    // vhost=on|off (tap)
+    // Enable the experimental in kernel accelerator for virtio-net.
+    #[serde(rename = "vhost", skip_serializing_if = "Option::is_none")]
+    pub vhost: Option<OnOff>,
+
+    // vhostforce=on|off (tap, vhost-user)
+    // Use vhost even for guests without MSI-X.
+    #[serde(rename = "vhostforce", skip_serializing_if = "Option::is_none")]
+    pub vhostforce: Option<OnOff>,
+
+    // queues=n (tap, vhost-user)
+    // Number of queue pairs for a multiqueue virtio-net device.
+    #[serde(rename = "queues", skip_serializing_if = "Option::is_none")]
+    pub queues: Option<usize>,
+
+    // listen=[host]:port, connect=host:port, mcast=maddr:port (socket)
+    // Connect the VLAN to a remote VLAN in another QEMU virtual machine
+    // using a TCP socket or a UDP multicast socket.
+    #[serde(rename = "listen", skip_serializing_if = "Option::is_none")]
+    pub listen: Option<String>,
+
+    #[serde(rename = "connect", skip_serializing_if = "Option::is_none")]
+    pub connect: Option<String>,
+
+    #[serde(rename = "mcast", skip_serializing_if = "Option::is_none")]
+    pub mcast: Option<String>,
+
+    // localaddr=addr (socket)
+    // Local address for mcast or udp sockets.
+    #[serde(rename = "localaddr", skip_serializing_if = "Option::is_none")]
+    pub localaddr: Option<String>,
+
+    // server=on|off (stream)
+    // Whether to listen on addr rather than connect to it.
+    #[serde(rename = "server", skip_serializing_if = "Option::is_none")]
+    pub server: Option<OnOff>,
+
+    // addr.type=inet|unix|fd, addr.host=host, addr.port=port,
+    // addr.path=path (stream)
+    // The address to listen on or connect to.
+    #[serde(rename = "addr.type", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "addr.type")]
+    pub addr_type: Option<AddrType>,
+
+    #[serde(rename = "addr.host", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "addr.host")]
+    pub addr_host: Option<String>,
+
+    #[serde(rename = "addr.port", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "addr.port")]
+    pub addr_port: Option<u16>,
+
+    #[serde(rename = "addr.path", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "addr.path")]
+    pub addr_path: Option<String>,
+
+    // local.type=inet|unix|fd, local.host, local.port, local.path (dgram)
+    // The local address of the datagram socket.
+    #[serde(rename = "local.type", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "local.type")]
+    pub local_type: Option<AddrType>,
+
+    #[serde(rename = "local.host", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "local.host")]
+    pub local_host: Option<String>,
+
+    #[serde(rename = "local.port", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "local.port")]
+    pub local_port: Option<u16>,
+
+    #[serde(rename = "local.path", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "local.path")]
+    pub local_path: Option<String>,
+
+    // remote.type=inet|unix, remote.host, remote.port, remote.path (dgram)
+    // The address datagrams are sent to.
+    #[serde(rename = "remote.type", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "remote.type")]
+    pub remote_type: Option<AddrType>,
+
+    #[serde(rename = "remote.host", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "remote.host")]
+    pub remote_host: Option<String>,
+
+    #[serde(rename = "remote.port", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "remote.port")]
+    pub remote_port: Option<u16>,
+
+    #[serde(rename = "remote.path", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "remote.path")]
+    pub remote_path: Option<String>,
+
+    // chardev=id (vhost-user)
+    // The socket chardev connecting to the vhost-user backend.
+    #[serde(rename = "chardev", skip_serializing_if = "Option::is_none")]
+    pub chardev: Option<String>,
+}
+
+impl NetdevConfig {
+    /// Whether `option` is a -netdev of a backend this crate models, given
+    /// in the key=value form rather than as JSON.
+    pub(crate) fn matches(option: &OptionQ<'_>) -> bool {
+        option
+            .args
+            .first()
+            .filter(|arg| arg.key.is_empty() || arg.key == "type")
+            .and_then(|arg| arg.value.as_deref())
+            .is_some_and(|backend| backend.parse::<NetdevBackend>().is_ok())
+    }
+
+    /// Backend specific keys which are given, with the backends accepting
+    /// them.
+    fn backend_keys(&self) -> Vec<(&'static str, bool, &'static [NetdevBackend])> {
+        use NetdevBackend::*;
+        const ADDR: &[NetdevBackend] = &[Stream];
+        vec![
+            ("net", self.net.is_some(), &[User]),
+            ("host", self.host.is_some(), &[User]),
+            ("restrict", self.restrict.is_some(), &[User]),
+            ("hostname", self.hostname.is_some(), &[User]),
+            ("dhcpstart", self.dhcpstart.is_some(), &[User]),
+            ("dns", self.dns.is_some(), &[User]),
+            ("dnssearch", !self.dnssearch.is_empty(), &[User]),
+            ("domainname", self.domainname.is_some(), &[User]),
+            ("ipv4", self.ipv4.is_some(), &[User]),
+            ("ipv6", self.ipv6.is_some(), &[User]),
+            ("ipv6-net", self.ipv6_net.is_some(), &[User]),
+            ("ipv6-host", self.ipv6_host.is_some(), &[User]),
+            ("ipv6-dns", self.ipv6_dns.is_some(), &[User]),
+            ("hostfwd", !self.hostfwd.is_empty(), &[User]),
+            ("guestfwd", !self.guestfwd.is_empty(), &[User]),
+            ("tftp", self.tftp.is_some(), &[User]),
+            ("bootfile", self.bootfile.is_some(), &[User]),
+            ("ifname", self.ifname.is_some(), &[Tap]),
+            ("script", self.script.is_some(), &[Tap]),
+            ("downscript", self.downscript.is_some(), &[Tap]),
+            ("br", self.br.is_some(), &[Tap, Bridge]),
+            ("helper", self.helper.is_some(), &[Tap, Bridge]),
+            ("vhost", self.vhost.is_some(), &[Tap]),
+            ("vhostforce", self.vhostforce.is_some(), &[Tap, VhostUser]),
+            ("queues", self.queues.is_some(), &[Tap, VhostUser]),
+            ("listen", self.listen.is_some(), &[Socket]),
+            ("connect", self.connect.is_some(), &[Socket]),
+            ("mcast", self.mcast.is_some(), &[Socket]),
+            ("localaddr", self.localaddr.is_some(), &[Socket]),
+            ("server", self.server.is_some(), ADDR),
+            ("addr.type", self.addr_type.is_some(), ADDR),
+            ("addr.host", self.addr_host.is_some(), ADDR),
+            ("addr.port", self.addr_port.is_some(), ADDR),
+            ("addr.path", self.addr_path.is_some(), ADDR),
+            ("local.type", self.local_type.is_some(), &[Dgram]),
+            ("local.host", self.local_host.is_some(), &[Dgram]),
+            ("local.port", self.local_port.is_some(), &[Dgram]),
+            ("local.path", self.local_path.is_some(), &[Dgram]),
+            ("remote.type", self.remote_type.is_some(), &[Dgram]),
+            ("remote.host", self.remote_host.is_some(), &[Dgram]),
+            ("remote.port", self.remote_port.is_some(), &[Dgram]),
+            ("remote.path", self.remote_path.is_some(), &[Dgram]),
+            ("chardev", self.chardev.is_some(), &[VhostUser]),
+        ]
+    }
+}
+
+impl Validate for NetdevConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        for (key, given, backends) in self.backend_keys() {
+            if given && !backends.contains(&self.backend) {
+                invalid(
+                    errors,
+                    path,
+                    key,
+                    ValidationErrorKind::NotSupported {
+                        by: format!("type={}", self.backend),
+                    },
+                );
+            }
+        }
+
+        let required = match self.backend {
+            NetdevBackend::Stream if self.addr_type.is_none() => Some("addr.type"),
+            NetdevBackend::Dgram if self.local_type.is_none() && self.remote_type.is_none() => {
+                Some("local.type")
+            }
+            NetdevBackend::Socket
+                if [&self.listen, &self.connect, &self.mcast]
+                    .iter()
+                    .all(|addr| addr.is_none()) =>
+            {
+                Some("listen")
+            }
+            NetdevBackend::VhostUser if self.chardev.is_none() => Some("chardev"),
+            _ => None,
+        };
+        if let Some(key) = required {
+            invalid(errors, path, "type", ValidationErrorKind::Requires { key });
+        }
+
+        if self.ipv4 == Some(OnOff::Off) && self.ipv6 == Some(OnOff::Off) {
+            invalid(
+                errors,
+                path,
+                "ipv6",
+                ValidationErrorKind::Conflicts { key: "ipv4=off" },
+            );
+        }
+    }
+}
+
+/// Check that netdev ids are unique and every NIC uses its own netdev.
+pub(crate) fn validate_references(
+    netdevs: &[NetdevConfig],
+    devices: &[DeviceConfig],
+    errors: &mut Vec<ValidationError>,
+) {
+    for (i, netdev) in netdevs.iter().enumerate() {
+        if netdevs[..i].iter().any(|other| other.id == netdev.id) {
+            invalid(
+                errors,
+                &format!("netdev[{}]", i),
+                "id",
+                ValidationErrorKind::Duplicate {
+                    id: netdev.id.clone(),
+                },
+            );
+        }
+    }
+    for (i, device) in devices.iter().enumerate() {
+        let Some(netdev) = device.netdev() else {
+            continue;
+        };
+        let kind = if !netdevs.iter().any(|other| other.id == netdev) {
+            ValidationErrorKind::UnknownReference {
+                id: netdev.to_string(),
+            }
+        } else if devices[..i]
+            .iter()
+            .any(|other| other.netdev() == Some(netdev))
+        {
+            ValidationErrorKind::Duplicate {
+                id: netdev.to_string(),
+            }
+        } else {
+            continue;
+        };
+        invalid(errors, &format!("device[{}]", i), "netdev", kind);
+    }
+}
+
+/// Builder for `NetdevConfig`, e.g.
+///     NetdevConfig::user("net0").hostfwd(HostFwd::tcp(2222, 22)).build()
+#[derive(Debug, Clone)]
+pub struct NetdevConfigBuilder {
+    config: NetdevConfig,
+}
+
+impl NetdevConfig {
+    pub fn builder(backend: NetdevBackend, id: impl Into<String>) -> NetdevConfigBuilder {
+        NetdevConfigBuilder {
+            config: NetdevConfig {
+                backend,
+                id: id.into(),
+                net: None,
+                host: None,
+                restrict: None,
+                hostname: None,
+                dhcpstart: None,
+                dns: None,
+                dnssearch: Vec::new(),
+                domainname: None,
+                ipv4: None,
+                ipv6: None,
+                ipv6_net: None,
+                ipv6_host: None,
+                ipv6_dns: None,
+                hostfwd: Vec::new(),
+                guestfwd: Vec::new(),
+                tftp: None,
+                bootfile: None,
+                ifname: None,
+                script: None,
+                downscript: None,
+                br: None,
+                helper: None,
+                vhost: None,
+                vhostforce: None,
+                queues: None,
+                listen: None,
+                connect: None,
+                mcast: None,
+                localaddr: None,
+                server: None,
+                addr_type: None,
+                addr_host: None,
+                addr_port: None,
+                addr_path: None,
+                local_type: None,
+                local_host: None,
+                local_port: None,
+                local_path: None,
+                remote_type: None,
+                remote_host: None,
+                remote_port: None,
+                remote_path: None,
+                chardev: None,
+            },
+        }
+    }
+
+    /// User mode network stack, needing no host privileges.
+    pub fn user(id: impl Into<String>) -> NetdevConfigBuilder {
+        Self::builder(NetdevBackend::User, id)
+    }
+
+    /// A host TAP network interface.
+    pub fn tap(id: impl Into<String>) -> NetdevConfigBuilder {
+        Self::builder(NetdevBackend::Tap, id)
+    }
+
+    /// A TAP interface attached to the host bridge `br`.
+    pub fn bridge(id: impl Into<String>, br: impl Into<String>) -> NetdevConfigBuilder {
+        Self::builder(NetdevBackend::Bridge, id).br(br)
+    }
+
+    /// A vhost-user backend reached through the socket chardev `chardev`.
+    pub fn vhost_user(id: impl Into<String>, chardev: impl Into<String>) -> NetdevConfigBuilder {
+        Self::builder(NetdevBackend::VhostUser, id).chardev(chardev)
+    }
+}
+
+impl NetdevConfigBuilder {
+    pub fn net(mut self, net: impl Into<String>) -> Self {
+        self.config.net = Some(net.into());
+        self
+    }
+
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.config.host = Some(host.into());
+        self
+    }
+
+    pub fn restrict(mut self, restrict: impl Into<OnOff>) -> Self {
+        self.config.restrict = Some(restrict.into());
+        self
+    }
+
+    pub fn hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.config.hostname = Some(hostname.into());
+        self
+    }
+
+    pub fn dhcpstart(mut self, dhcpstart: impl Into<String>) -> Self {
+        self.config.dhcpstart = Some(dhcpstart.into());
+        self
+    }
+
+    pub fn dns(mut self, dns: impl Into<String>) -> Self {
+        self.config.dns = Some(dns.into());
+        self
+    }
+
+    /// Add a domain to the DHCP domain-search list.
+    pub fn dnssearch(mut self, dnssearch: impl Into<String>) -> Self {
+        self.config.dnssearch.push(dnssearch.into());
+        self
+    }
+
+    pub fn domainname(mut self, domainname: impl Into<String>) -> Self {
+        self.config.domainname = Some(domainname.into());
+        self
+    }
+
+    pub fn ipv4(mut self, ipv4: impl Into<OnOff>) -> Self {
+        self.config.ipv4 = Some(ipv4.into());
+        self
+    }
+
+    pub fn ipv6(mut self, ipv6: impl Into<OnOff>) -> Self {
+        self.config.ipv6 = Some(ipv6.into());
+        self
+    }
+
+    pub fn ipv6_net(mut self, ipv6_net: impl Into<String>) -> Self {
+        self.config.ipv6_net = Some(ipv6_net.into());
+        self
+    }
+
+    pub fn ipv6_host(mut self, ipv6_host: impl Into<String>) -> Self {
+        self.config.ipv6_host = Some(ipv6_host.into());
+        self
+    }
+
+    pub fn ipv6_dns(mut self, ipv6_dns: impl Into<String>) -> Self {
+        self.config.ipv6_dns = Some(ipv6_dns.into());
+        self
+    }
+
+    /// Add a hostfwd rule.
+    pub fn hostfwd(mut self, hostfwd: HostFwd) -> Self {
+        self.config.hostfwd.push(hostfwd);
+        self
+    }
+
+    /// Add a guestfwd rule.
+    pub fn guestfwd(mut self, guestfwd: impl Into<String>) -> Self {
+        self.config.guestfwd.push(guestfwd.into());
+        self
+    }
+
+    pub fn tftp(mut self, tftp: impl Into<String>) -> Self {
+        self.config.tftp = Some(tftp.into());
+        self
+    }
+
+    pub fn bootfile(mut self, bootfile: impl Into<String>) -> Self {
+        self.config.bootfile = Some(bootfile.into());
+        self
+    }
+
+    pub fn ifname(mut self, ifname: impl Into<String>) -> Self {
+        self.config.ifname = Some(ifname.into());
+        self
+    }
+
+    pub fn script(mut self, script: impl Into<String>) -> Self {
+        self.config.script = Some(script.into());
+        self
+    }
+
+    pub fn downscript(mut self, downscript: impl Into<String>) -> Self {
+        self.config.downscript = Some(downscript.into());
+        self
+    }
+
+    pub fn br(mut self, br: impl Into<String>) -> Self {
+        self.config.br = Some(br.into());
+        self
+    }
+
+    pub fn helper(mut self, helper: impl Into<String>) -> Self {
+        self.config.helper = Some(helper.into());
+        self
+    }
+
+    pub fn vhost(mut self, vhost: impl Into<OnOff>) -> Self {
+        self.config.vhost = Some(vhost.into());
+        self
+    }
+
+    pub fn vhostforce(mut self, vhostforce: impl Into<OnOff>) -> Self {
+        self.config.vhostforce = Some(vhostforce.into());
+        self
+    }
+
+    pub fn queues(mut self, queues: usize) -> Self {
+        self.config.queues = Some(queues);
+        self
+    }
+
+    pub fn listen(mut self, listen: impl Into<String>) -> Self {
+        self.config.listen = Some(listen.into());
+        self
+    }
+
+    pub fn connect(mut self, connect: impl Into<String>) -> Self {
+        self.config.connect = Some(connect.into());
+        self
+    }
+
+    pub fn mcast(mut self, mcast: impl Into<String>) -> Self {
+        self.config.mcast = Some(mcast.into());
+        self
+    }
+
+    pub fn localaddr(mut self, localaddr: impl Into<String>) -> Self {
+        self.config.localaddr = Some(localaddr.into());
+        self
+    }
+
+    pub fn server(mut self, server: impl Into<OnOff>) -> Self {
+        self.config.server = Some(server.into());
+        self
+    }
+
+    /// Listen on or connect to `host:port` (stream).
+    pub fn addr_inet(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.config.addr_type = Some(AddrType::Inet);
+        self.config.addr_host = Some(host.into());
+        self.config.addr_port = Some(port);
+        self
+    }
+
+    /// Listen on or connect to the unix socket `path` (stream).
+    pub fn addr_unix(mut self, path: impl Into<String>) -> Self {
+        self.config.addr_type = Some(AddrType::Unix);
+        self.config.addr_path = Some(path.into());
+        self
+    }
+
+    /// Receive datagrams on `host:port` (dgram).
+    pub fn local_inet(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.config.local_type = Some(AddrType::Inet);
+        self.config.local_host = Some(host.into());
+        self.config.local_port = Some(port);
+        self
+    }
+
+    /// Receive datagrams on the unix socket `path` (dgram).
+    pub fn local_unix(mut self, path: impl Into<String>) -> Self {
+        self.config.local_type = Some(AddrType::Unix);
+        self.config.local_path = Some(path.into());
+        self
+    }
+
+    /// Send datagrams to `host:port` (dgram).
+    pub fn remote_inet(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.config.remote_type = Some(AddrType::Inet);
+        self.config.remote_host = Some(host.into());
+        self.config.remote_port = Some(port);
+        self
+    }
+
+    /// Send datagrams to the unix socket `path` (dgram).
+    pub fn remote_unix(mut self, path: impl Into<String>) -> Self {
+        self.config.remote_type = Some(AddrType::Unix);
+        self.config.remote_path = Some(path.into());
+        self
+    }
+
+    pub fn chardev(mut self, chardev: impl Into<String>) -> Self {
+        self.config.chardev = Some(chardev.into());
+        self
+    }
+
+    pub fn build(self) -> NetdevConfig {
+        self.config
+    }
+}
+
+impl From<NetdevConfigBuilder> for NetdevConfig {
+    fn from(builder: NetdevConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::parser::{parse_args, OptionParsing};
+    use crate::configuration::general::device::{
+        DeviceConfig, GenericDeviceConfig, VirtioNetPciConfig,
+    };
+
+    #[test]
+    fn test_hostfwd() {
+        let rule: HostFwd = "tcp:127.0.0.1:2222-:22".parse().unwrap();
+        assert_eq!(rule.host_addr.as_deref(), Some("127.0.0.1"));
+        assert_eq!((rule.host_port, rule.guest_port), (2222, 22));
+        assert_eq!(rule.to_string(), "tcp:127.0.0.1:2222-:22");
+
+        assert_eq!("::5555-:5555".parse(), Ok(HostFwd::tcp(5555, 5555)));
+        assert_eq!(
+            "udp::53-10.0.2.15:53"
+                .parse::<HostFwd>()
+                .unwrap()
+                .to_string(),
+            "udp::53-10.0.2.15:53"
+        );
+        let rule: HostFwd = "tcp:my-host:2222-:22".parse().unwrap();
+        assert_eq!(rule.host_addr.as_deref(), Some("my-host"));
+        assert_eq!((rule.host_port, rule.guest_port), (2222, 22));
+        assert_eq!("my-host:2222-:22".parse(), Ok(rule));
+        assert!("tcp::22".parse::<HostFwd>().is_err());
+        assert!("sctp::1-:1".parse::<HostFwd>().is_err());
+    }
+
+    #[test]
+    fn test_netdev() {
+        let user = NetdevConfig::user("net0")
+            .net("10.0.2.0/24")
+            .ipv6_net("fd00::/64")
+            .dnssearch("example.com")
+            .dnssearch("example.org")
+            .hostfwd(HostFwd::tcp(2222, 22))
+            .hostfwd(HostFwd::udp(5353, 53))
+            .build();
+        assert_eq!(
+            user.formatting().to_string(),
+            "-netdev user,id=net0,net=10.0.2.0/24,dnssearch=example.com,dnssearch=example.org,ipv6-net=fd00::/64,hostfwd=tcp::2222-:22,hostfwd=udp::5353-:53"
+        );
+        let argv: Vec<String> = std::iter::once("qemu-system-x86_64".to_string())
+            .chain(
+                user.formatting()
+                    .to_args()
+                    .into_iter()
+                    .map(|arg| arg.into_string().unwrap()),
+            )
+            .collect();
+        let command = parse_args(&argv).unwrap();
+        assert_eq!(NetdevConfig::parsing(&command.options[0]), Ok(user));
+
+        let tap = NetdevConfig::tap("net1")
+            .ifname("tap0")
+            .script("no")
+            .downscript("no")
+            .vhost(true)
+            .queues(4)
+            .build();
+        assert_eq!(
+            tap.formatting().to_string(),
+            "-netdev tap,id=net1,ifname=tap0,script=no,downscript=no,vhost=on,queues=4"
+        );
+
+        let stream = NetdevConfig::builder(NetdevBackend::Stream, "net2")
+            .server(false)
+            .addr_inet("192.168.1.1", 1234)
+            .build();
+        assert_eq!(
+            stream.formatting().to_string(),
+            "-netdev stream,id=net2,server=off,addr.type=inet,addr.host=192.168.1.1,addr.port=1234"
+        );
+
+        let json =
+            serde_json::to_string(&NetdevConfig::user("n").hostfwd(HostFwd::tcp(1, 2)).build())
+                .unwrap();
+        assert_eq!(json, r#"{"type":"user","id":"n","hostfwd":["tcp::1-:2"]}"#);
+    }
+
+    #[test]
+    fn test_validate() {
+        let netdevs = [
+            NetdevConfig::tap("net0")
+                .hostfwd(HostFwd::tcp(1, 2))
+                .build(),
+            NetdevConfig::builder(NetdevBackend::VhostUser, "net0").build(),
+        ];
+        let devices = [
+            DeviceConfig::from(GenericDeviceConfig::new("e1000").set("netdev", "net0")),
+            VirtioNetPciConfig::builder("net0").into(),
+            VirtioNetPciConfig::builder("net9").into(),
+        ];
+        let mut errors = Vec::new();
+        netdevs.to_vec().validate("netdev", &mut errors);
+        validate_references(&netdevs, &devices, &mut errors);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "netdev[0].hostfwd: not supported by type=tap",
+                "netdev[1].type: requires 'chardev'",
+                "netdev[1].id: 'net0' is used more than once",
+                "device[1].netdev: 'net0' is used more than once",
+                "device[2].netdev: 'net9' is not defined",
+            ]
+        );
+    }
+}
@@ -1,13 +1,1052 @@
-use crate::command::builder::*;
-use serde::{Deserialize, Serialize};
+//! NUMA topology of -numa: nodes with their memory and CPUs, the distances
+//! between them and, with `hmat=on` of -machine, the latencies, bandwidths
+//! and caches of the ACPI HMAT, e.g.
+//!     -numa node,nodeid=0,cpus=0-3,memdev=ram0
+//!     -numa dist,src=0,dst=1,val=20
+use std::{collections::BTreeSet, error::Error, fmt, ops::RangeInclusive, str::FromStr};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct NumaConfig<'a> {
-    test: &'a str,
+use crate::command::{builder::*, parser::*};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{
+    memory::MConfig,
+    object::ObjectConfig,
+    smp::SmpConfig,
+    types::{qemu_enum, ByteSize},
+    validate::*,
+};
+
+/// The most NUMA nodes QEMU supports.
+pub const MAX_NODES: u32 = 128;
+
+/// One -numa option. JSON gives the kind as `type` next to the
+/// properties, as in the JSON form of -numa.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum NumaConfig {
+    #[serde(rename = "node")]
+    Node(NumaNodeConfig),
+
+    #[serde(rename = "dist")]
+    Dist(NumaDistConfig),
+
+    #[serde(rename = "cpu")]
+    Cpu(NumaCpuConfig),
+
+    #[serde(rename = "hmat-lb")]
+    HmatLb(NumaHmatLbConfig),
+
+    #[serde(rename = "hmat-cache")]
+    HmatCache(NumaHmatCacheConfig),
+}
+
+impl NumaConfig {
+    pub fn numa_type(&self) -> &'static str {
+        match self {
+            NumaConfig::Node(_) => "node",
+            NumaConfig::Dist(_) => "dist",
+            NumaConfig::Cpu(_) => "cpu",
+            NumaConfig::HmatLb(_) => "hmat-lb",
+            NumaConfig::HmatCache(_) => "hmat-cache",
+        }
+    }
+}
+
+impl OptionFormatting for NumaConfig {
+    fn formatting(&self) -> OptionQ<'_> {
+        let mut option = match self {
+            NumaConfig::Node(n) => n.formatting(),
+            NumaConfig::Dist(n) => n.formatting(),
+            NumaConfig::Cpu(n) => n.formatting(),
+            NumaConfig::HmatLb(n) => n.formatting(),
+            NumaConfig::HmatCache(n) => n.formatting(),
+        };
+        option.args.insert(
+            0,
+            KVArgQ {
+                key: "",
+                kv_split_with: None,
+                value: Some(self.numa_type().to_string()),
+            },
+        );
+        option
+    }
+}
+
+impl OptionParsing for NumaConfig {
+    fn parsing(option: &OptionQ<'_>) -> Result<Self, ParseError> {
+        fn typed<T: FromOptionArgs>(
+            option: &OptionQ<'_>,
+            variant: fn(T) -> NumaConfig,
+        ) -> Result<NumaConfig, ParseError> {
+            let mut args = OptionArgs::new(option);
+            args.value("type", true);
+            let numa = T::from_args(&mut args)?;
+            args.finish()?;
+            Ok(variant(numa))
+        }
+
+        let mut args = OptionArgs::new(option);
+        match args.value("type", true) {
+            Some("node") => typed(option, NumaConfig::Node),
+            Some("dist") => typed(option, NumaConfig::Dist),
+            Some("cpu") => typed(option, NumaConfig::Cpu),
+            Some("hmat-lb") => typed(option, NumaConfig::HmatLb),
+            Some("hmat-cache") => typed(option, NumaConfig::HmatCache),
+            Some(numa_type) => Err(args.invalid("type", numa_type)),
+            None => Err(args.missing("type")),
+        }
+    }
+}
+
+impl Validate for NumaConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        match self {
+            NumaConfig::Node(n) => n.validate(path, errors),
+            NumaConfig::Dist(n) => n.validate(path, errors),
+            NumaConfig::HmatLb(n) => n.validate(path, errors),
+            NumaConfig::HmatCache(n) => n.validate(path, errors),
+            NumaConfig::Cpu(_) => {}
+        }
+    }
+}
+
+/// Check the NUMA options against each other and against the rest of the
+/// machine: node ids are unique and known where referred to, every CPU
+/// index and topology id exists in the -smp topology, HMAT entries have
+/// `hmat=on` of -machine and the memory backends of the nodes add up to
+/// the RAM size of -m.
+pub(crate) fn validate_topology(
+    numa: &[NumaConfig],
+    smp: Option<&SmpConfig>,
+    m: Option<&MConfig>,
+    objects: &[ObjectConfig],
+    hmat: bool,
+    errors: &mut Vec<ValidationError>,
+) {
+    // A node without nodeid takes the number of nodes before it.
+    let nodes = numa
+        .iter()
+        .enumerate()
+        .filter_map(|(i, numa)| match numa {
+            NumaConfig::Node(node) => Some((i, node)),
+            _ => None,
+        })
+        .enumerate()
+        .map(|(n, (i, node))| (i, node.nodeid.unwrap_or(n as u32), node))
+        .collect::<Vec<_>>();
+    let is_node = |id: u32| nodes.iter().any(|(_, nodeid, _)| *nodeid == id);
+    let has_cpus = |id: u32| {
+        nodes
+            .iter()
+            .any(|(_, nodeid, node)| *nodeid == id && !node.cpus.is_empty())
+            || numa
+                .iter()
+                .any(|numa| matches!(numa, NumaConfig::Cpu(cpu) if cpu.node_id == id))
+    };
+    let node_ref = |errors: &mut Vec<ValidationError>, path: &str, key: &str, id: u32| {
+        if !is_node(id) {
+            let id = id.to_string();
+            invalid(
+                errors,
+                path,
+                key,
+                ValidationErrorKind::UnknownReference { id },
+            );
+        }
+    };
+
+    let max_cpus = max_cpus(smp);
+    let mut cpus = BTreeSet::new();
+    let mut memdevs = BTreeSet::new();
+    for (n, &(i, id, node)) in nodes.iter().enumerate() {
+        let path = format!("numa[{}]", i);
+        if nodes[..n].iter().any(|(_, other, _)| *other == id) {
+            let id = id.to_string();
+            invalid(
+                errors,
+                &path,
+                "nodeid",
+                ValidationErrorKind::Duplicate { id },
+            );
+        }
+        for range in &node.cpus {
+            // -smp reports a count of 0 itself.
+            if max_cpus > 0 && range.last >= max_cpus {
+                let kind = ValidationErrorKind::AboveMaximum {
+                    value: range.last,
+                    max: max_cpus - 1,
+                };
+                invalid(errors, &path, "cpus", kind);
+            } else if let Some(cpu) = range.iter().find(|cpu| !cpus.insert(*cpu)) {
+                let id = format!("cpu {}", cpu);
+                invalid(errors, &path, "cpus", ValidationErrorKind::Duplicate { id });
+            }
+        }
+        if let Some(memdev) = &node.memdev {
+            if !memdevs.insert(memdev.as_str()) {
+                let id = memdev.to_string();
+                invalid(
+                    errors,
+                    &path,
+                    "memdev",
+                    ValidationErrorKind::Duplicate { id },
+                );
+            }
+        }
+        if let Some(initiator) = node.initiator {
+            if !is_node(initiator) {
+                node_ref(errors, &path, "initiator", initiator);
+            } else if !has_cpus(initiator) {
+                let kind = ValidationErrorKind::Invalid {
+                    expected: "a node with CPUs",
+                };
+                invalid(errors, &path, "initiator", kind);
+            }
+        }
+    }
+
+    for (i, numa) in numa.iter().enumerate() {
+        let path = format!("numa[{}]", i);
+        match numa {
+            NumaConfig::Node(_) => {}
+            NumaConfig::Dist(dist) => {
+                node_ref(errors, &path, "src", dist.src);
+                node_ref(errors, &path, "dst", dist.dst);
+            }
+            NumaConfig::Cpu(cpu) => {
+                node_ref(errors, &path, "node-id", cpu.node_id);
+                cpu.validate_ids(&path, smp, errors);
+            }
+            NumaConfig::HmatLb(lb) => {
+                node_ref(errors, &path, "initiator", lb.initiator);
+                node_ref(errors, &path, "target", lb.target);
+            }
+            NumaConfig::HmatCache(cache) => node_ref(errors, &path, "node-id", cache.node_id),
+        }
+        if matches!(numa, NumaConfig::HmatLb(_) | NumaConfig::HmatCache(_)) && !hmat {
+            let kind = ValidationErrorKind::Requires {
+                key: "machine.hmat",
+            };
+            invalid(errors, &path, "type", kind);
+        }
+    }
+
+    // Undefined backends are reported with the other -object references.
+    let node_memory = nodes
+        .iter()
+        .filter_map(|(_, _, node)| node.memdev.as_deref())
+        .map(|memdev| {
+            objects
+                .iter()
+                .find(|object| object.id == memdev && object.qom_type.is_memory_backend())
+                .and_then(|object| object.size)
+                .map(|size| size.bytes())
+        })
+        .sum::<Option<u64>>();
+    if let (Some(m), Some(bytes), false) = (m, node_memory, nodes.is_empty()) {
+        if bytes != m.size.bytes() {
+            let kind = ValidationErrorKind::NumaMemoryMismatch {
+                nodes: ByteSize::b(bytes),
+                size: m.size,
+            };
+            invalid(errors, "m", "size", kind);
+        }
+    }
+}
+
+/// The number of CPUs that may ever be plugged, which bounds the CPU
+/// indexes of NUMA nodes. Without -smp QEMU starts a single CPU.
+fn max_cpus(smp: Option<&SmpConfig>) -> usize {
+    let Some(smp) = smp else {
+        return 1;
+    };
+    smp.maxcpus.or(smp.cpus).unwrap_or_else(|| {
+        [
+            smp.drawers,
+            smp.books,
+            smp.sockets,
+            smp.dies,
+            smp.clusters,
+            smp.cores,
+            smp.threads,
+        ]
+        .iter()
+        .map(|n| n.unwrap_or(1))
+        .product()
+    })
+}
+
+/// A CPU index or an inclusive range of them, written as `3` or `0-3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CpuRange {
+    pub first: usize,
+    pub last: usize,
+}
+
+impl CpuRange {
+    pub fn new(first: usize, last: usize) -> Self {
+        CpuRange { first, last }
+    }
+
+    pub fn iter(&self) -> RangeInclusive<usize> {
+        self.first..=self.last
+    }
+}
+
+impl From<usize> for CpuRange {
+    fn from(cpu: usize) -> Self {
+        CpuRange::new(cpu, cpu)
+    }
+}
+
+impl From<RangeInclusive<usize>> for CpuRange {
+    fn from(range: RangeInclusive<usize>) -> Self {
+        CpuRange::new(*range.start(), *range.end())
+    }
+}
+
+/// A CPU range that cannot be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidCpuRange(pub String);
+
+impl fmt::Display for InvalidCpuRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid CPU range '{}'", self.0)
+    }
+}
+
+impl Error for InvalidCpuRange {}
+
+impl fmt::Display for CpuRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.first == self.last {
+            write!(f, "{}", self.first)
+        } else {
+            write!(f, "{}-{}", self.first, self.last)
+        }
+    }
+}
+
+impl FromStr for CpuRange {
+    type Err = InvalidCpuRange;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidCpuRange(s.to_string());
+        let (first, last) = s.split_once('-').unwrap_or((s, s));
+        let first = first.parse().map_err(|_| invalid())?;
+        let last = last.parse().map_err(|_| invalid())?;
+        if first > last {
+            return Err(invalid());
+        }
+        Ok(CpuRange { first, last })
+    }
+}
+
+impl Serialize for CpuRange {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
 }
 
-impl<'a> OptionFormatting<'a> for NumaConfig<'a> {
-    fn formatting(&self) -> OptionQ<'a> {
-        todo!()
+struct CpuRangeVisitor;
+
+impl de::Visitor<'_> for CpuRangeVisitor {
+    type Value = CpuRange;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a CPU index or a range such as 0-3")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<CpuRange, E> {
+        Ok(CpuRange::from(v as usize))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<CpuRange, E> {
+        v.parse()
+            .map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+}
+
+impl<'de> Deserialize<'de> for CpuRange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(CpuRangeVisitor)
+    }
+}
+
+/// A NUMA node, e.g.
+///     -numa node,nodeid=0,cpus=0-1,cpus=4,memdev=ram0
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "numa")]
+pub struct NumaNodeConfig {
+    // nodeid=n
+    // (default: the number of nodes given before)
+    #[serde(rename = "nodeid", skip_serializing_if = "Option::is_none")]
+    pub nodeid: Option<u32>,
+
+    // cpus=first[-last]
+    // The CPU indexes of the node, one range per key.
+    #[serde(rename = "cpus", default, skip_serializing_if = "Vec::is_empty")]
+    pub cpus: Vec<CpuRange>,
+
+    // memdev=id
+    // The memory backend object holding the memory of the node. A node
+    // without one has no memory of its own.
+    #[serde(rename = "memdev", skip_serializing_if = "Option::is_none")]
+    pub memdev: Option<String>,
+
+    // initiator=n
+    // The node whose CPUs access the memory of this one best, for the HMAT.
+    #[serde(rename = "initiator", skip_serializing_if = "Option::is_none")]
+    pub initiator: Option<u32>,
+}
+
+impl Validate for NumaNodeConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        if let Some(nodeid) = self.nodeid.filter(|nodeid| *nodeid >= MAX_NODES) {
+            let kind = ValidationErrorKind::AboveMaximum {
+                value: nodeid as usize,
+                max: MAX_NODES as usize - 1,
+            };
+            invalid(errors, path, "nodeid", kind);
+        }
+    }
+}
+
+/// The distance between two nodes, 10 being the distance of a node to
+/// itself, e.g.
+///     -numa dist,src=0,dst=1,val=20
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "numa")]
+pub struct NumaDistConfig {
+    // src=n,dst=n
+    #[serde(rename = "src")]
+    pub src: u32,
+
+    #[serde(rename = "dst")]
+    pub dst: u32,
+
+    // val=distance
+    #[serde(rename = "val")]
+    pub val: u8,
+}
+
+impl NumaDistConfig {
+    pub fn new(src: u32, dst: u32, val: u8) -> Self {
+        NumaDistConfig { src, dst, val }
+    }
+}
+
+impl Validate for NumaDistConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        let expected = match (self.src == self.dst, self.val) {
+            (true, 10) | (false, 11..) => return,
+            (true, _) => "10 for the distance of a node to itself",
+            (false, _) => "a distance above 10",
+        };
+        invalid(
+            errors,
+            path,
+            "val",
+            ValidationErrorKind::Invalid { expected },
+        );
+    }
+}
+
+/// Put the CPUs with the given topology ids into a node. Ids left out
+/// match every CPU, e.g.
+///     -numa cpu,node-id=1,socket-id=1
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "numa")]
+pub struct NumaCpuConfig {
+    // node-id=n
+    #[serde(rename = "node-id")]
+    pub node_id: u32,
+
+    // drawer-id=n,book-id=n,socket-id=n,die-id=n,cluster-id=n,core-id=n,thread-id=n
+    #[serde(rename = "drawer-id", skip_serializing_if = "Option::is_none")]
+    pub drawer_id: Option<usize>,
+
+    #[serde(rename = "book-id", skip_serializing_if = "Option::is_none")]
+    pub book_id: Option<usize>,
+
+    #[serde(rename = "socket-id", skip_serializing_if = "Option::is_none")]
+    pub socket_id: Option<usize>,
+
+    #[serde(rename = "die-id", skip_serializing_if = "Option::is_none")]
+    pub die_id: Option<usize>,
+
+    #[serde(rename = "cluster-id", skip_serializing_if = "Option::is_none")]
+    pub cluster_id: Option<usize>,
+
+    #[serde(rename = "core-id", skip_serializing_if = "Option::is_none")]
+    pub core_id: Option<usize>,
+
+    #[serde(rename = "thread-id", skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<usize>,
+}
+
+impl NumaCpuConfig {
+    /// Check each id against the count of its level in -smp. Drawers,
+    /// books, dies and clusters default to one; omitted sockets, cores and
+    /// threads are computed by QEMU and cannot be checked.
+    fn validate_ids(&self, path: &str, smp: Option<&SmpConfig>, errors: &mut Vec<ValidationError>) {
+        let count = |n: Option<usize>, computed: bool| match smp {
+            None => Some(1),
+            Some(_) if computed => n,
+            Some(_) => Some(n.unwrap_or(1)),
+        };
+        let smp = smp.cloned().unwrap_or_else(|| SmpConfig::builder().build());
+        let levels = [
+            ("drawer-id", self.drawer_id, count(smp.drawers, false)),
+            ("book-id", self.book_id, count(smp.books, false)),
+            ("socket-id", self.socket_id, count(smp.sockets, true)),
+            ("die-id", self.die_id, count(smp.dies, false)),
+            ("cluster-id", self.cluster_id, count(smp.clusters, false)),
+            ("core-id", self.core_id, count(smp.cores, true)),
+            ("thread-id", self.thread_id, count(smp.threads, true)),
+        ];
+        for (key, id, count) in levels {
+            if let (Some(id), Some(count)) = (id, count) {
+                if count > 0 && id >= count {
+                    let kind = ValidationErrorKind::AboveMaximum {
+                        value: id,
+                        max: count - 1,
+                    };
+                    invalid(errors, path, key, kind);
+                }
+            }
+        }
     }
-}
\ No newline at end of file
+}
+
+qemu_enum! {
+    /// hierarchy=memory|first-level|second-level|third-level
+    pub enum HmatHierarchy {
+        Memory = "memory",
+        FirstLevel = "first-level",
+        SecondLevel = "second-level",
+        ThirdLevel = "third-level",
+    }
+}
+
+qemu_enum! {
+    /// data-type of hmat-lb
+    pub enum HmatDataType {
+        AccessLatency = "access-latency",
+        ReadLatency = "read-latency",
+        WriteLatency = "write-latency",
+        AccessBandwidth = "access-bandwidth",
+        ReadBandwidth = "read-bandwidth",
+        WriteBandwidth = "write-bandwidth",
+    }
+}
+
+impl HmatDataType {
+    pub fn is_latency(&self) -> bool {
+        matches!(
+            self,
+            HmatDataType::AccessLatency | HmatDataType::ReadLatency | HmatDataType::WriteLatency
+        )
+    }
+}
+
+qemu_enum! {
+    /// associativity=none|direct|complex
+    pub enum CacheAssociativity {
+        None = "none",
+        Direct = "direct",
+        Complex = "complex",
+    }
+}
+
+qemu_enum! {
+    /// policy=none|write-back|write-through
+    pub enum CachePolicy {
+        None = "none",
+        WriteBack = "write-back",
+        WriteThrough = "write-through",
+    }
+}
+
+/// The latency or bandwidth from the CPUs of one node to the memory of
+/// another, in the HMAT, e.g.
+///     -numa hmat-lb,initiator=0,target=1,hierarchy=memory,data-type=access-latency,latency=20
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "numa")]
+pub struct NumaHmatLbConfig {
+    // initiator=n,target=n
+    #[serde(rename = "initiator")]
+    pub initiator: u32,
+
+    #[serde(rename = "target")]
+    pub target: u32,
+
+    // hierarchy=memory|first-level|second-level|third-level
+    #[serde(rename = "hierarchy")]
+    pub hierarchy: HmatHierarchy,
+
+    // data-type=access-latency|read-latency|write-latency|access-bandwidth|...
+    #[serde(rename = "data-type")]
+    pub data_type: HmatDataType,
+
+    // latency=ns (latency data types)
+    #[serde(rename = "latency", skip_serializing_if = "Option::is_none")]
+    pub latency: Option<u64>,
+
+    // bandwidth=size (bandwidth data types)
+    // Bytes per second, e.g. 200M.
+    #[serde(rename = "bandwidth", skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<ByteSize>,
+}
+
+impl Validate for NumaHmatLbConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        let (wanted, given, unwanted) = if self.data_type.is_latency() {
+            ("latency", self.latency.is_some(), self.bandwidth.is_some())
+        } else {
+            (
+                "bandwidth",
+                self.bandwidth.is_some(),
+                self.latency.is_some(),
+            )
+        };
+        if !given {
+            invalid(
+                errors,
+                path,
+                "data-type",
+                ValidationErrorKind::Requires { key: wanted },
+            );
+        }
+        if unwanted {
+            let key = if wanted == "latency" {
+                "bandwidth"
+            } else {
+                "latency"
+            };
+            let by = format!("data-type={}", self.data_type);
+            invalid(errors, path, key, ValidationErrorKind::NotSupported { by });
+        }
+    }
+}
+
+/// A memory side cache of a node, in the HMAT, e.g.
+///     -numa hmat-cache,node-id=1,size=10K,level=1,associativity=direct,policy=write-back,line=8
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "numa")]
+pub struct NumaHmatCacheConfig {
+    // node-id=n
+    #[serde(rename = "node-id")]
+    pub node_id: u32,
+
+    // size=size
+    #[serde(rename = "size")]
+    pub size: ByteSize,
+
+    // level=1|2|3
+    #[serde(rename = "level")]
+    pub level: u8,
+
+    // associativity=none|direct|complex
+    #[serde(rename = "associativity")]
+    pub associativity: CacheAssociativity,
+
+    // policy=none|write-back|write-through
+    #[serde(rename = "policy")]
+    pub policy: CachePolicy,
+
+    // line=bytes
+    #[serde(rename = "line")]
+    pub line: u16,
+}
+
+impl NumaHmatCacheConfig {
+    pub fn new(
+        node_id: u32,
+        size: ByteSize,
+        level: u8,
+        associativity: CacheAssociativity,
+        policy: CachePolicy,
+        line: u16,
+    ) -> Self {
+        NumaHmatCacheConfig {
+            node_id,
+            size,
+            level,
+            associativity,
+            policy,
+            line,
+        }
+    }
+}
+
+impl Validate for NumaHmatCacheConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        if !(1..=3).contains(&self.level) {
+            let kind = ValidationErrorKind::Invalid {
+                expected: "a cache level from 1 to 3",
+            };
+            invalid(errors, path, "level", kind);
+        }
+    }
+}
+
+/// Builder for `NumaNodeConfig`, e.g.
+///     NumaNodeConfig::builder().nodeid(0).cpus(0..=3).memdev("ram0").build()
+#[derive(Debug, Clone)]
+pub struct NumaNodeConfigBuilder {
+    config: NumaNodeConfig,
+}
+
+impl NumaNodeConfig {
+    pub fn builder() -> NumaNodeConfigBuilder {
+        NumaNodeConfigBuilder {
+            config: NumaNodeConfig {
+                nodeid: None,
+                cpus: Vec::new(),
+                memdev: None,
+                initiator: None,
+            },
+        }
+    }
+}
+
+impl NumaNodeConfigBuilder {
+    pub fn nodeid(mut self, nodeid: u32) -> Self {
+        self.config.nodeid = Some(nodeid);
+        self
+    }
+
+    /// Add a CPU index or range, e.g. `4` or `0..=3`.
+    pub fn cpus(mut self, cpus: impl Into<CpuRange>) -> Self {
+        self.config.cpus.push(cpus.into());
+        self
+    }
+
+    pub fn memdev(mut self, memdev: impl Into<String>) -> Self {
+        self.config.memdev = Some(memdev.into());
+        self
+    }
+
+    pub fn initiator(mut self, initiator: u32) -> Self {
+        self.config.initiator = Some(initiator);
+        self
+    }
+
+    pub fn build(self) -> NumaNodeConfig {
+        self.config
+    }
+}
+
+impl From<NumaNodeConfigBuilder> for NumaNodeConfig {
+    fn from(builder: NumaNodeConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<NumaNodeConfig> for NumaConfig {
+    fn from(config: NumaNodeConfig) -> Self {
+        NumaConfig::Node(config)
+    }
+}
+
+impl From<NumaNodeConfigBuilder> for NumaConfig {
+    fn from(builder: NumaNodeConfigBuilder) -> Self {
+        NumaConfig::Node(builder.build())
+    }
+}
+
+impl From<NumaDistConfig> for NumaConfig {
+    fn from(config: NumaDistConfig) -> Self {
+        NumaConfig::Dist(config)
+    }
+}
+
+/// Builder for `NumaCpuConfig`, e.g.
+///     NumaCpuConfig::builder(1).socket_id(1).build()
+#[derive(Debug, Clone)]
+pub struct NumaCpuConfigBuilder {
+    config: NumaCpuConfig,
+}
+
+impl NumaCpuConfig {
+    pub fn builder(node_id: u32) -> NumaCpuConfigBuilder {
+        NumaCpuConfigBuilder {
+            config: NumaCpuConfig {
+                node_id,
+                drawer_id: None,
+                book_id: None,
+                socket_id: None,
+                die_id: None,
+                cluster_id: None,
+                core_id: None,
+                thread_id: None,
+            },
+        }
+    }
+}
+
+impl NumaCpuConfigBuilder {
+    pub fn drawer_id(mut self, drawer_id: usize) -> Self {
+        self.config.drawer_id = Some(drawer_id);
+        self
+    }
+
+    pub fn book_id(mut self, book_id: usize) -> Self {
+        self.config.book_id = Some(book_id);
+        self
+    }
+
+    pub fn socket_id(mut self, socket_id: usize) -> Self {
+        self.config.socket_id = Some(socket_id);
+        self
+    }
+
+    pub fn die_id(mut self, die_id: usize) -> Self {
+        self.config.die_id = Some(die_id);
+        self
+    }
+
+    pub fn cluster_id(mut self, cluster_id: usize) -> Self {
+        self.config.cluster_id = Some(cluster_id);
+        self
+    }
+
+    pub fn core_id(mut self, core_id: usize) -> Self {
+        self.config.core_id = Some(core_id);
+        self
+    }
+
+    pub fn thread_id(mut self, thread_id: usize) -> Self {
+        self.config.thread_id = Some(thread_id);
+        self
+    }
+
+    pub fn build(self) -> NumaCpuConfig {
+        self.config
+    }
+}
+
+impl From<NumaCpuConfigBuilder> for NumaCpuConfig {
+    fn from(builder: NumaCpuConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<NumaCpuConfig> for NumaConfig {
+    fn from(config: NumaCpuConfig) -> Self {
+        NumaConfig::Cpu(config)
+    }
+}
+
+impl From<NumaCpuConfigBuilder> for NumaConfig {
+    fn from(builder: NumaCpuConfigBuilder) -> Self {
+        NumaConfig::Cpu(builder.build())
+    }
+}
+
+/// Builder for `NumaHmatLbConfig`, e.g.
+///     NumaHmatLbConfig::builder(0, 1, HmatHierarchy::Memory, HmatDataType::AccessLatency)
+///         .latency(20)
+///         .build()
+#[derive(Debug, Clone)]
+pub struct NumaHmatLbConfigBuilder {
+    config: NumaHmatLbConfig,
+}
+
+impl NumaHmatLbConfig {
+    pub fn builder(
+        initiator: u32,
+        target: u32,
+        hierarchy: HmatHierarchy,
+        data_type: HmatDataType,
+    ) -> NumaHmatLbConfigBuilder {
+        NumaHmatLbConfigBuilder {
+            config: NumaHmatLbConfig {
+                initiator,
+                target,
+                hierarchy,
+                data_type,
+                latency: None,
+                bandwidth: None,
+            },
+        }
+    }
+}
+
+impl NumaHmatLbConfigBuilder {
+    pub fn latency(mut self, latency: u64) -> Self {
+        self.config.latency = Some(latency);
+        self
+    }
+
+    pub fn bandwidth(mut self, bandwidth: ByteSize) -> Self {
+        self.config.bandwidth = Some(bandwidth);
+        self
+    }
+
+    pub fn build(self) -> NumaHmatLbConfig {
+        self.config
+    }
+}
+
+impl From<NumaHmatLbConfigBuilder> for NumaHmatLbConfig {
+    fn from(builder: NumaHmatLbConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl From<NumaHmatLbConfig> for NumaConfig {
+    fn from(config: NumaHmatLbConfig) -> Self {
+        NumaConfig::HmatLb(config)
+    }
+}
+
+impl From<NumaHmatLbConfigBuilder> for NumaConfig {
+    fn from(builder: NumaHmatLbConfigBuilder) -> Self {
+        NumaConfig::HmatLb(builder.build())
+    }
+}
+
+impl From<NumaHmatCacheConfig> for NumaConfig {
+    fn from(config: NumaHmatCacheConfig) -> Self {
+        NumaConfig::HmatCache(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &str) -> NumaConfig {
+        let argv = ["qemu-system-x86_64", "-numa", args];
+        let command = parse_args(&argv).unwrap();
+        NumaConfig::parsing(&command.options[0]).unwrap()
+    }
+
+    #[test]
+    fn test_numa() {
+        let node = NumaConfig::from(
+            NumaNodeConfig::builder()
+                .nodeid(0)
+                .cpus(0..=1)
+                .cpus(4)
+                .memdev("ram0"),
+        );
+        let formatted = "-numa node,nodeid=0,cpus=0-1,cpus=4,memdev=ram0";
+        assert_eq!(node.formatting().to_string(), formatted);
+        assert_eq!(parse(&formatted[6..]), node);
+        let json = r#"{"type":"node","nodeid":0,"cpus":["0-1","4"],"memdev":"ram0"}"#;
+        assert_eq!(serde_json::to_string(&node).unwrap(), json);
+        assert_eq!(serde_json::from_str::<NumaConfig>(json).unwrap(), node);
+
+        let lb = NumaConfig::from(
+            NumaHmatLbConfig::builder(0, 1, HmatHierarchy::Memory, HmatDataType::AccessBandwidth)
+                .bandwidth(ByteSize::mib(200)),
+        );
+        let formatted =
+            "-numa hmat-lb,initiator=0,target=1,hierarchy=memory,data-type=access-bandwidth,bandwidth=200M";
+        assert_eq!(lb.formatting().to_string(), formatted);
+        assert_eq!(parse(&formatted[6..]), lb);
+
+        assert_eq!(
+            parse("cpu,node-id=1,socket-id=1"),
+            NumaCpuConfig::builder(1).socket_id(1).into()
+        );
+        assert_eq!(
+            parse("dist,src=0,dst=1,val=20"),
+            NumaDistConfig::new(0, 1, 20).into()
+        );
+        assert_eq!(
+            "3-1".parse::<CpuRange>(),
+            Err(InvalidCpuRange("3-1".into()))
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let smp = SmpConfig::builder()
+            .cpus(4)
+            .sockets(2)
+            .cores(2)
+            .threads(1)
+            .build();
+        let m = MConfig::builder(ByteSize::gib(2)).build();
+        let objects = [
+            ObjectConfig::memory_backend_ram("ram0", ByteSize::gib(1)).build(),
+            ObjectConfig::memory_backend_ram("ram1", ByteSize::mib(512)).build(),
+        ];
+        let numa: Vec<NumaConfig> = vec![
+            NumaNodeConfig::builder().cpus(0..=1).memdev("ram0").into(),
+            NumaNodeConfig::builder()
+                .cpus(1..=4)
+                .memdev("ram1")
+                .initiator(2)
+                .into(),
+            NumaNodeConfig::builder().nodeid(1).initiator(1).into(),
+            NumaDistConfig::new(0, 0, 20).into(),
+            NumaDistConfig::new(0, 3, 20).into(),
+            NumaCpuConfig::builder(0).socket_id(2).die_id(1).into(),
+            NumaHmatCacheConfig::new(
+                0,
+                ByteSize::kib(10),
+                4,
+                CacheAssociativity::Direct,
+                CachePolicy::WriteBack,
+                8,
+            )
+            .into(),
+            NumaHmatLbConfig::builder(0, 1, HmatHierarchy::Memory, HmatDataType::ReadLatency)
+                .bandwidth(ByteSize::mib(200))
+                .into(),
+        ];
+        let mut errors = Vec::new();
+        numa.validate("numa", &mut errors);
+        validate_topology(&numa, Some(&smp), Some(&m), &objects, false, &mut errors);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "numa[3].val: expected 10 for the distance of a node to itself",
+                "numa[6].level: expected a cache level from 1 to 3",
+                "numa[7].data-type: requires 'latency'",
+                "numa[7].bandwidth: not supported by data-type=read-latency",
+                "numa[1].cpus: 4 exceeds the maximum of 3",
+                "numa[1].initiator: '2' is not defined",
+                "numa[2].nodeid: '1' is used more than once",
+                "numa[4].dst: '3' is not defined",
+                "numa[5].socket-id: 2 exceeds the maximum of 1",
+                "numa[5].die-id: 1 exceeds the maximum of 0",
+                "numa[6].type: requires 'machine.hmat'",
+                "numa[7].type: requires 'machine.hmat'",
+                "m.size: NUMA nodes have 1536M of memory but size=2G",
+            ]
+        );
+
+        // A CPU may only be in one node, and an initiator needs CPUs.
+        let numa: Vec<NumaConfig> = vec![
+            NumaNodeConfig::builder().cpus(0..=2).into(),
+            NumaNodeConfig::builder().cpus(2..=3).initiator(2).into(),
+            NumaNodeConfig::builder().into(),
+        ];
+        let mut errors = Vec::new();
+        validate_topology(&numa, Some(&smp), None, &objects, true, &mut errors);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "numa[1].cpus: 'cpu 2' is used more than once",
+                "numa[1].initiator: expected a node with CPUs",
+            ]
+        );
+
+        // A count of 0 is left to -smp.
+        let smp = SmpConfig::builder().cpus(0).sockets(0).build();
+        let numa: Vec<NumaConfig> = vec![
+            NumaNodeConfig::builder().cpus(0..=0).into(),
+            NumaCpuConfig::builder(0).socket_id(0).into(),
+        ];
+        let mut errors = Vec::new();
+        validate_topology(&numa, Some(&smp), None, &objects, false, &mut errors);
+        assert!(errors.is_empty());
+    }
+}
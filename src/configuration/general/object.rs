@@ -0,0 +1,1022 @@
+//! User creatable objects of -object, referred to by id from other options,
+//! e.g. guest RAM backed by hugepages:
+//!     -object memory-backend-file,id=ram0,size=4G,mem-path=/dev/hugepages,share=on
+//!     -machine q35,memory-backend=ram0
+use std::{collections::BTreeSet, ops::RangeInclusive};
+
+use crate::command::{
+    builder::*,
+    parser::{FromOptionArgs, OptionArgs, ParseError},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    types::{qemu_enum, ByteSize, OnOff},
+    validate::*,
+};
+
+qemu_enum! {
+    /// Object types of -object.
+    pub enum ObjectType {
+        MemoryBackendRam = "memory-backend-ram",
+        MemoryBackendFile = "memory-backend-file",
+        MemoryBackendMemfd = "memory-backend-memfd",
+        Iothread = "iothread",
+        RngRandom = "rng-random",
+        RngBuiltin = "rng-builtin",
+        Secret = "secret",
+        TlsCredsX509 = "tls-creds-x509",
+        TlsCredsPsk = "tls-creds-psk",
+        ThrottleGroup = "throttle-group",
+    }
+}
+
+impl ObjectType {
+    pub fn is_memory_backend(&self) -> bool {
+        matches!(
+            self,
+            ObjectType::MemoryBackendRam
+                | ObjectType::MemoryBackendFile
+                | ObjectType::MemoryBackendMemfd
+        )
+    }
+
+    pub fn is_tls_creds(&self) -> bool {
+        matches!(self, ObjectType::TlsCredsX509 | ObjectType::TlsCredsPsk)
+    }
+}
+
+qemu_enum! {
+    /// NUMA policy of a memory backend.
+    pub enum HostMemPolicy {
+        Default = "default",
+        Preferred = "preferred",
+        Bind = "bind",
+        Interleave = "interleave",
+    }
+}
+
+/// Host NUMA nodes a memory backend is bound to. The command line gives
+/// them as nodes or ranges such as `0-3`, repeating `host-nodes` for each,
+/// and contiguous nodes are written back as one range.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct HostNodes(pub BTreeSet<u32>);
+
+impl HostNodes {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Runs of contiguous nodes, e.g. `0..=2` and `5..=5` for 0, 1, 2, 5.
+    pub fn runs(&self) -> Vec<RangeInclusive<u32>> {
+        let mut runs: Vec<RangeInclusive<u32>> = Vec::new();
+        for &node in &self.0 {
+            match runs.last_mut() {
+                Some(run) if run.end().checked_add(1) == Some(node) => {
+                    *run = *run.start()..=node;
+                }
+                _ => runs.push(node..=node),
+            }
+        }
+        runs
+    }
+}
+
+impl FromIterator<u32> for HostNodes {
+    fn from_iter<I: IntoIterator<Item = u32>>(nodes: I) -> Self {
+        HostNodes(nodes.into_iter().collect())
+    }
+}
+
+impl OptionFormatting for HostNodes {
+    fn formatting(&self) -> OptionQ<'_> {
+        let arg = |run: RangeInclusive<u32>| KVArgQ {
+            key: "host-nodes",
+            kv_split_with: Some("="),
+            value: Some(if run.start() == run.end() {
+                run.start().to_string()
+            } else {
+                format!("{}-{}", run.start(), run.end())
+            }),
+        };
+        OptionQ {
+            prefix: "-",
+            raw: "object",
+            option_args_split_with: " ",
+            args_split_with: ",",
+            args: self.runs().into_iter().map(arg).collect(),
+        }
+    }
+}
+
+impl FromOptionArgs for HostNodes {
+    fn from_args(args: &mut OptionArgs<'_>) -> Result<Self, ParseError> {
+        let runs = args.parsed_all_with("host-nodes", |value| {
+            let (first, last) = value.split_once('-').unwrap_or((value, value));
+            match (first.parse::<u32>(), last.parse::<u32>()) {
+                (Ok(first), Ok(last)) if first <= last => Ok(first..=last),
+                _ => Err(()),
+            }
+        })?;
+        Ok(runs.into_iter().flatten().collect())
+    }
+}
+
+qemu_enum! {
+    /// Encoding of the data of a secret.
+    pub enum SecretFormat {
+        Raw = "raw",
+        Base64 = "base64",
+    }
+}
+
+qemu_enum! {
+    /// Side of the connection TLS credentials are used on.
+    pub enum TlsEndpoint {
+        Client = "client",
+        Server = "server",
+    }
+}
+
+/// Limits shared by the members of a throttle-group, in bytes or operations
+/// per second.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "object")]
+pub struct ThrottleLimits {
+    // limits.bps-total=b,limits.bps-read=r,limits.bps-write=w
+    #[serde(rename = "bps-total", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "limits.bps-total")]
+    pub bps_total: Option<u64>,
+
+    #[serde(rename = "bps-read", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "limits.bps-read")]
+    pub bps_read: Option<u64>,
+
+    #[serde(rename = "bps-write", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "limits.bps-write")]
+    pub bps_write: Option<u64>,
+
+    // limits.bps-total-max=bm
+    // Burst above the total bandwidth limit.
+    #[serde(rename = "bps-total-max", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "limits.bps-total-max")]
+    pub bps_total_max: Option<u64>,
+
+    // limits.iops-total=i,limits.iops-read=r,limits.iops-write=w
+    #[serde(rename = "iops-total", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "limits.iops-total")]
+    pub iops_total: Option<u64>,
+
+    #[serde(rename = "iops-read", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "limits.iops-read")]
+    pub iops_read: Option<u64>,
+
+    #[serde(rename = "iops-write", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "limits.iops-write")]
+    pub iops_write: Option<u64>,
+
+    // limits.iops-total-max=im
+    // Burst above the total request rate limit.
+    #[serde(rename = "iops-total-max", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "limits.iops-total-max")]
+    pub iops_total_max: Option<u64>,
+
+    // limits.iops-size=is
+    // Let every is bytes of a request count as a new request.
+    #[serde(rename = "iops-size", skip_serializing_if = "Option::is_none")]
+    #[qemu(rename = "limits.iops-size")]
+    pub iops_size: Option<u64>,
+}
+
+impl ThrottleLimits {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Validate for ThrottleLimits {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        for (total_key, total, limits) in [
+            (
+                "bps-total",
+                self.bps_total,
+                [("bps-read", self.bps_read), ("bps-write", self.bps_write)],
+            ),
+            (
+                "iops-total",
+                self.iops_total,
+                [
+                    ("iops-read", self.iops_read),
+                    ("iops-write", self.iops_write),
+                ],
+            ),
+        ] {
+            for (key, value) in limits {
+                if total.is_some() && value.is_some() {
+                    invalid(
+                        errors,
+                        path,
+                        key,
+                        ValidationErrorKind::Conflicts { key: total_key },
+                    );
+                }
+            }
+        }
+        for (key, max, limit, value) in [
+            (
+                "bps-total-max",
+                self.bps_total_max,
+                "bps-total",
+                self.bps_total,
+            ),
+            (
+                "iops-total-max",
+                self.iops_total_max,
+                "iops-total",
+                self.iops_total,
+            ),
+        ] {
+            if max.is_some() && value.is_none() {
+                invalid(
+                    errors,
+                    path,
+                    key,
+                    ValidationErrorKind::Requires { key: limit },
+                );
+            }
+        }
+    }
+}
+
+/// Create a new object of type `qom_type`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "object")]
+pub struct ObjectConfig {
+    // memory-backend-ram|memory-backend-file|memory-backend-memfd|iothread|
+    // rng-random|rng-builtin|secret|tls-creds-x509|tls-creds-psk|throttle-group
+    #[serde(rename = "qom-type")]
+    #[qemu(positional, rename = "qom-type")]
+    pub qom_type: ObjectType,
+
+    // id=id
+    #[serde(rename = "id")]
+    pub id: String,
+
+    // size=size (memory backends)
+    // The size of the memory region.
+    #[serde(rename = "size", skip_serializing_if = "Option::is_none")]
+    pub size: Option<ByteSize>,
+
+    // share=on|off (memory backends)
+    // Whether the memory region is shared with other processes, e.g. a
+    // vhost-user backend. (default: off)
+    #[serde(rename = "share", skip_serializing_if = "Option::is_none")]
+    pub share: Option<OnOff>,
+
+    // prealloc=on|off,prealloc-threads=n (memory backends)
+    // Preallocate the memory region, using n threads.
+    #[serde(rename = "prealloc", skip_serializing_if = "Option::is_none")]
+    pub prealloc: Option<OnOff>,
+
+    #[serde(rename = "prealloc-threads", skip_serializing_if = "Option::is_none")]
+    pub prealloc_threads: Option<usize>,
+
+    // merge=on|off,dump=on|off (memory backends)
+    // Whether KSM may merge the pages and whether they appear in core
+    // dumps.
+    #[serde(rename = "merge", skip_serializing_if = "Option::is_none")]
+    pub merge: Option<OnOff>,
+
+    #[serde(rename = "dump", skip_serializing_if = "Option::is_none")]
+    pub dump: Option<OnOff>,
+
+    // host-nodes=n,policy=default|preferred|bind|interleave (memory backends)
+    // Bind the memory region to the given host NUMA nodes.
+    #[serde(
+        rename = "host-nodes",
+        default,
+        skip_serializing_if = "HostNodes::is_empty"
+    )]
+    #[qemu(flatten)]
+    pub host_nodes: HostNodes,
+
+    #[serde(rename = "policy", skip_serializing_if = "Option::is_none")]
+    pub policy: Option<HostMemPolicy>,
+
+    // mem-path=path,align=align,discard-data=on|off (memory-backend-file)
+    // The file or hugetlbfs directory backing the memory region.
+    #[serde(rename = "mem-path", skip_serializing_if = "Option::is_none")]
+    pub mem_path: Option<String>,
+
+    #[serde(rename = "align", skip_serializing_if = "Option::is_none")]
+    pub align: Option<ByteSize>,
+
+    #[serde(rename = "discard-data", skip_serializing_if = "Option::is_none")]
+    pub discard_data: Option<OnOff>,
+
+    // hugetlb=on|off,hugetlbsize=size,seal=on|off (memory-backend-memfd)
+    // Back the memfd with hugepages of the given size, and whether to seal
+    // it. (default: seal=on)
+    #[serde(rename = "hugetlb", skip_serializing_if = "Option::is_none")]
+    pub hugetlb: Option<OnOff>,
+
+    #[serde(rename = "hugetlbsize", skip_serializing_if = "Option::is_none")]
+    pub hugetlbsize: Option<ByteSize>,
+
+    #[serde(rename = "seal", skip_serializing_if = "Option::is_none")]
+    pub seal: Option<OnOff>,
+
+    // poll-max-ns=ns,poll-grow=n,poll-shrink=n (iothread)
+    // Maximum time to busy wait for events, and the factors to grow or
+    // shrink the polling time by.
+    #[serde(rename = "poll-max-ns", skip_serializing_if = "Option::is_none")]
+    pub poll_max_ns: Option<u64>,
+
+    #[serde(rename = "poll-grow", skip_serializing_if = "Option::is_none")]
+    pub poll_grow: Option<u64>,
+
+    #[serde(rename = "poll-shrink", skip_serializing_if = "Option::is_none")]
+    pub poll_shrink: Option<u64>,
+
+    // filename=path (rng-random)
+    // The entropy source. (default: /dev/urandom)
+    #[serde(rename = "filename", skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+
+    // data=string|file=path,format=raw|base64,keyid=id,iv=string (secret)
+    // The secret, given inline or read from a file, optionally encrypted
+    // with the secret keyid and initialization vector iv.
+    #[serde(rename = "data", skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+
+    #[serde(rename = "file", skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+
+    #[serde(rename = "format", skip_serializing_if = "Option::is_none")]
+    pub format: Option<SecretFormat>,
+
+    #[serde(rename = "keyid", skip_serializing_if = "Option::is_none")]
+    pub keyid: Option<String>,
+
+    #[serde(rename = "iv", skip_serializing_if = "Option::is_none")]
+    pub iv: Option<String>,
+
+    // endpoint=server|client,dir=path,priority=priority (TLS credentials)
+    // The directory holding the credential files.
+    #[serde(rename = "endpoint", skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<TlsEndpoint>,
+
+    #[serde(rename = "dir", skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+
+    #[serde(rename = "priority", skip_serializing_if = "Option::is_none")]
+    pub priority: Option<String>,
+
+    // verify-peer=on|off,passwordid=id (tls-creds-x509)
+    // Whether to check the certificate of the peer, and the secret holding
+    // the password of the private key.
+    #[serde(rename = "verify-peer", skip_serializing_if = "Option::is_none")]
+    pub verify_peer: Option<OnOff>,
+
+    #[serde(rename = "passwordid", skip_serializing_if = "Option::is_none")]
+    pub passwordid: Option<String>,
+
+    // username=name (tls-creds-psk)
+    #[serde(rename = "username", skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    // limits.*=n (throttle-group)
+    #[serde(
+        rename = "limits",
+        default,
+        skip_serializing_if = "ThrottleLimits::is_empty"
+    )]
+    #[qemu(flatten)]
+    pub limits: ThrottleLimits,
+}
+
+impl ObjectConfig {
+    /// Whether `option` is an -object of a type this crate models, given in
+    /// the key=value form rather than as JSON.
+    pub(crate) fn matches(option: &OptionQ<'_>) -> bool {
+        option
+            .args
+            .first()
+            .filter(|arg| arg.key.is_empty() || arg.key == "qom-type")
+            .and_then(|arg| arg.value.as_deref())
+            .is_some_and(|qom_type| qom_type.parse::<ObjectType>().is_ok())
+    }
+
+    /// Type specific keys which are given, with the types accepting them.
+    fn type_keys(&self) -> Vec<(&'static str, bool, &'static [ObjectType])> {
+        use ObjectType::*;
+        const MEMORY: &[ObjectType] = &[MemoryBackendRam, MemoryBackendFile, MemoryBackendMemfd];
+        const TLS: &[ObjectType] = &[TlsCredsX509, TlsCredsPsk];
+        vec![
+            ("size", self.size.is_some(), MEMORY),
+            ("share", self.share.is_some(), MEMORY),
+            ("prealloc", self.prealloc.is_some(), MEMORY),
+            ("prealloc-threads", self.prealloc_threads.is_some(), MEMORY),
+            ("merge", self.merge.is_some(), MEMORY),
+            ("dump", self.dump.is_some(), MEMORY),
+            ("host-nodes", !self.host_nodes.is_empty(), MEMORY),
+            ("policy", self.policy.is_some(), MEMORY),
+            ("mem-path", self.mem_path.is_some(), &[MemoryBackendFile]),
+            ("align", self.align.is_some(), &[MemoryBackendFile]),
+            (
+                "discard-data",
+                self.discard_data.is_some(),
+                &[MemoryBackendFile],
+            ),
+            ("hugetlb", self.hugetlb.is_some(), &[MemoryBackendMemfd]),
+            (
+                "hugetlbsize",
+                self.hugetlbsize.is_some(),
+                &[MemoryBackendMemfd],
+            ),
+            ("seal", self.seal.is_some(), &[MemoryBackendMemfd]),
+            ("poll-max-ns", self.poll_max_ns.is_some(), &[Iothread]),
+            ("poll-grow", self.poll_grow.is_some(), &[Iothread]),
+            ("poll-shrink", self.poll_shrink.is_some(), &[Iothread]),
+            ("filename", self.filename.is_some(), &[RngRandom]),
+            ("data", self.data.is_some(), &[Secret]),
+            ("file", self.file.is_some(), &[Secret]),
+            ("format", self.format.is_some(), &[Secret]),
+            ("keyid", self.keyid.is_some(), &[Secret]),
+            ("iv", self.iv.is_some(), &[Secret]),
+            ("endpoint", self.endpoint.is_some(), TLS),
+            ("dir", self.dir.is_some(), TLS),
+            ("priority", self.priority.is_some(), TLS),
+            ("verify-peer", self.verify_peer.is_some(), &[TlsCredsX509]),
+            ("passwordid", self.passwordid.is_some(), &[TlsCredsX509]),
+            ("username", self.username.is_some(), &[TlsCredsPsk]),
+            ("limits", !self.limits.is_empty(), &[ThrottleGroup]),
+        ]
+    }
+}
+
+impl Validate for ObjectConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        for (key, given, types) in self.type_keys() {
+            if given && !types.contains(&self.qom_type) {
+                invalid(
+                    errors,
+                    path,
+                    key,
+                    ValidationErrorKind::NotSupported {
+                        by: format!("qom-type={}", self.qom_type),
+                    },
+                );
+            }
+        }
+
+        let required = match self.qom_type {
+            qom_type if qom_type.is_memory_backend() && self.size.is_none() => Some("size"),
+            ObjectType::MemoryBackendFile if self.mem_path.is_none() => Some("mem-path"),
+            ObjectType::Secret if self.data.is_none() && self.file.is_none() => Some("data"),
+            qom_type if qom_type.is_tls_creds() && self.dir.is_none() => Some("dir"),
+            _ => None,
+        };
+        if let Some(key) = required {
+            invalid(
+                errors,
+                path,
+                "qom-type",
+                ValidationErrorKind::Requires { key },
+            );
+        }
+
+        if self.data.is_some() && self.file.is_some() {
+            invalid(
+                errors,
+                path,
+                "file",
+                ValidationErrorKind::Conflicts { key: "data" },
+            );
+        }
+        if self.iv.is_some() && self.keyid.is_none() {
+            invalid(
+                errors,
+                path,
+                "iv",
+                ValidationErrorKind::Requires { key: "keyid" },
+            );
+        }
+        if self.hugetlbsize.is_some() && self.hugetlb != Some(OnOff::On) {
+            invalid(
+                errors,
+                path,
+                "hugetlbsize",
+                ValidationErrorKind::Requires { key: "hugetlb=on" },
+            );
+        }
+        // QEMU binds to host nodes only with an explicit policy, and such a
+        // policy needs nodes to bind to.
+        match self.policy {
+            None | Some(HostMemPolicy::Default) if !self.host_nodes.is_empty() => invalid(
+                errors,
+                path,
+                "host-nodes",
+                ValidationErrorKind::Requires {
+                    key: "policy=preferred|bind|interleave",
+                },
+            ),
+            Some(HostMemPolicy::Preferred | HostMemPolicy::Bind | HostMemPolicy::Interleave)
+                if self.host_nodes.is_empty() =>
+            {
+                invalid(
+                    errors,
+                    path,
+                    "policy",
+                    ValidationErrorKind::Requires { key: "host-nodes" },
+                )
+            }
+            _ => {}
+        }
+        if let (Some(size), Some(align)) = (self.size, self.align) {
+            if !size.bytes().is_multiple_of(align.bytes().max(1)) {
+                invalid(
+                    errors,
+                    path,
+                    "size",
+                    ValidationErrorKind::Unaligned { value: size, align },
+                );
+            }
+        }
+        self.limits.validate(&format!("{}.limits", path), errors);
+    }
+}
+
+/// A reference to an object from another option.
+pub(crate) struct ObjectRef<'a> {
+    /// The JSON path of the referring key, e.g. `machine.memory-backend`.
+    pub path: String,
+    pub id: &'a str,
+    /// Whether the object is of a type the key may refer to.
+    pub accepts: fn(&ObjectType) -> bool,
+    pub expected: &'static str,
+}
+
+/// Check that object ids are unique and every reference, from other options
+/// or between objects, names an object of a fitting type.
+pub(crate) fn validate_references<'a>(
+    objects: &'a [ObjectConfig],
+    refs: impl IntoIterator<Item = ObjectRef<'a>>,
+    errors: &mut Vec<ValidationError>,
+) {
+    for (i, object) in objects.iter().enumerate() {
+        if objects[..i].iter().any(|other| other.id == object.id) {
+            invalid(
+                errors,
+                &format!("object[{}]", i),
+                "id",
+                ValidationErrorKind::Duplicate {
+                    id: object.id.clone(),
+                },
+            );
+        }
+    }
+
+    let between = objects.iter().enumerate().flat_map(|(i, object)| {
+        [
+            (object.keyid.as_deref(), "keyid"),
+            (object.passwordid.as_deref(), "passwordid"),
+        ]
+        .into_iter()
+        .filter_map(move |(id, key)| {
+            Some(ObjectRef {
+                path: format!("object[{}].{}", i, key),
+                id: id?,
+                accepts: |qom_type| *qom_type == ObjectType::Secret,
+                expected: "a secret",
+            })
+        })
+    });
+    for reference in between.chain(refs) {
+        match objects.iter().find(|object| object.id == reference.id) {
+            None => errors.push(ValidationError {
+                path: reference.path,
+                kind: ValidationErrorKind::UnknownReference {
+                    id: reference.id.to_string(),
+                },
+            }),
+            Some(object) if !(reference.accepts)(&object.qom_type) => {
+                errors.push(ValidationError {
+                    path: reference.path,
+                    kind: ValidationErrorKind::Invalid {
+                        expected: reference.expected,
+                    },
+                })
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// Builder for `ObjectConfig`, e.g.
+///     ObjectConfig::memory_backend_file("ram0", ByteSize::gib(4), "/dev/hugepages").share(true).build()
+#[derive(Debug, Clone)]
+pub struct ObjectConfigBuilder {
+    config: ObjectConfig,
+}
+
+impl ObjectConfig {
+    pub fn builder(qom_type: ObjectType, id: impl Into<String>) -> ObjectConfigBuilder {
+        ObjectConfigBuilder {
+            config: ObjectConfig {
+                qom_type,
+                id: id.into(),
+                size: None,
+                share: None,
+                prealloc: None,
+                prealloc_threads: None,
+                merge: None,
+                dump: None,
+                host_nodes: HostNodes::default(),
+                policy: None,
+                mem_path: None,
+                align: None,
+                discard_data: None,
+                hugetlb: None,
+                hugetlbsize: None,
+                seal: None,
+                poll_max_ns: None,
+                poll_grow: None,
+                poll_shrink: None,
+                filename: None,
+                data: None,
+                file: None,
+                format: None,
+                keyid: None,
+                iv: None,
+                endpoint: None,
+                dir: None,
+                priority: None,
+                verify_peer: None,
+                passwordid: None,
+                username: None,
+                limits: ThrottleLimits::default(),
+            },
+        }
+    }
+
+    /// Anonymous RAM of the given size.
+    pub fn memory_backend_ram(id: impl Into<String>, size: ByteSize) -> ObjectConfigBuilder {
+        Self::builder(ObjectType::MemoryBackendRam, id).size(size)
+    }
+
+    /// Memory backed by a file, or by a hugetlbfs mount when `mem_path` is
+    /// a directory.
+    pub fn memory_backend_file(
+        id: impl Into<String>,
+        size: ByteSize,
+        mem_path: impl Into<String>,
+    ) -> ObjectConfigBuilder {
+        Self::builder(ObjectType::MemoryBackendFile, id)
+            .size(size)
+            .mem_path(mem_path)
+    }
+
+    /// Memory backed by an anonymous memfd, shareable with other processes.
+    pub fn memory_backend_memfd(id: impl Into<String>, size: ByteSize) -> ObjectConfigBuilder {
+        Self::builder(ObjectType::MemoryBackendMemfd, id).size(size)
+    }
+
+    /// A thread running the event loop of the devices assigned to it.
+    pub fn iothread(id: impl Into<String>) -> ObjectConfigBuilder {
+        Self::builder(ObjectType::Iothread, id)
+    }
+
+    /// An entropy source reading `filename`.
+    pub fn rng_random(id: impl Into<String>, filename: impl Into<String>) -> ObjectConfigBuilder {
+        Self::builder(ObjectType::RngRandom, id).filename(filename)
+    }
+
+    /// An entropy source using the random number generator of QEMU.
+    pub fn rng_builtin(id: impl Into<String>) -> ObjectConfigBuilder {
+        Self::builder(ObjectType::RngBuiltin, id)
+    }
+
+    /// A secret given inline. Prefer `secret_file` for anything sensitive,
+    /// since the command line is visible to other users.
+    pub fn secret(id: impl Into<String>, data: impl Into<String>) -> ObjectConfigBuilder {
+        Self::builder(ObjectType::Secret, id).data(data)
+    }
+
+    /// A secret read from `file`.
+    pub fn secret_file(id: impl Into<String>, file: impl Into<String>) -> ObjectConfigBuilder {
+        Self::builder(ObjectType::Secret, id).file(file)
+    }
+
+    /// x509 certificates read from `dir`.
+    pub fn tls_creds_x509(
+        id: impl Into<String>,
+        endpoint: TlsEndpoint,
+        dir: impl Into<String>,
+    ) -> ObjectConfigBuilder {
+        Self::builder(ObjectType::TlsCredsX509, id)
+            .endpoint(endpoint)
+            .dir(dir)
+    }
+
+    /// Pre-shared keys read from `dir`/keys.psk.
+    pub fn tls_creds_psk(
+        id: impl Into<String>,
+        endpoint: TlsEndpoint,
+        dir: impl Into<String>,
+    ) -> ObjectConfigBuilder {
+        Self::builder(ObjectType::TlsCredsPsk, id)
+            .endpoint(endpoint)
+            .dir(dir)
+    }
+
+    /// I/O limits shared by the throttle nodes naming this group.
+    pub fn throttle_group(id: impl Into<String>, limits: ThrottleLimits) -> ObjectConfigBuilder {
+        Self::builder(ObjectType::ThrottleGroup, id).limits(limits)
+    }
+}
+
+impl ObjectConfigBuilder {
+    pub fn size(mut self, size: ByteSize) -> Self {
+        self.config.size = Some(size);
+        self
+    }
+
+    pub fn share(mut self, share: impl Into<OnOff>) -> Self {
+        self.config.share = Some(share.into());
+        self
+    }
+
+    pub fn prealloc(mut self, prealloc: impl Into<OnOff>) -> Self {
+        self.config.prealloc = Some(prealloc.into());
+        self
+    }
+
+    pub fn prealloc_threads(mut self, prealloc_threads: usize) -> Self {
+        self.config.prealloc_threads = Some(prealloc_threads);
+        self
+    }
+
+    pub fn merge(mut self, merge: impl Into<OnOff>) -> Self {
+        self.config.merge = Some(merge.into());
+        self
+    }
+
+    pub fn dump(mut self, dump: impl Into<OnOff>) -> Self {
+        self.config.dump = Some(dump.into());
+        self
+    }
+
+    pub fn host_nodes(mut self, host_nodes: impl IntoIterator<Item = u32>) -> Self {
+        self.config.host_nodes = host_nodes.into_iter().collect();
+        self
+    }
+
+    pub fn policy(mut self, policy: HostMemPolicy) -> Self {
+        self.config.policy = Some(policy);
+        self
+    }
+
+    pub fn mem_path(mut self, mem_path: impl Into<String>) -> Self {
+        self.config.mem_path = Some(mem_path.into());
+        self
+    }
+
+    pub fn align(mut self, align: ByteSize) -> Self {
+        self.config.align = Some(align);
+        self
+    }
+
+    pub fn discard_data(mut self, discard_data: impl Into<OnOff>) -> Self {
+        self.config.discard_data = Some(discard_data.into());
+        self
+    }
+
+    pub fn hugetlb(mut self, hugetlb: impl Into<OnOff>) -> Self {
+        self.config.hugetlb = Some(hugetlb.into());
+        self
+    }
+
+    pub fn hugetlbsize(mut self, hugetlbsize: ByteSize) -> Self {
+        self.config.hugetlbsize = Some(hugetlbsize);
+        self
+    }
+
+    pub fn seal(mut self, seal: impl Into<OnOff>) -> Self {
+        self.config.seal = Some(seal.into());
+        self
+    }
+
+    pub fn poll_max_ns(mut self, poll_max_ns: u64) -> Self {
+        self.config.poll_max_ns = Some(poll_max_ns);
+        self
+    }
+
+    pub fn poll_grow(mut self, poll_grow: u64) -> Self {
+        self.config.poll_grow = Some(poll_grow);
+        self
+    }
+
+    pub fn poll_shrink(mut self, poll_shrink: u64) -> Self {
+        self.config.poll_shrink = Some(poll_shrink);
+        self
+    }
+
+    pub fn filename(mut self, filename: impl Into<String>) -> Self {
+        self.config.filename = Some(filename.into());
+        self
+    }
+
+    pub fn data(mut self, data: impl Into<String>) -> Self {
+        self.config.data = Some(data.into());
+        self
+    }
+
+    pub fn file(mut self, file: impl Into<String>) -> Self {
+        self.config.file = Some(file.into());
+        self
+    }
+
+    pub fn format(mut self, format: SecretFormat) -> Self {
+        self.config.format = Some(format);
+        self
+    }
+
+    pub fn keyid(mut self, keyid: impl Into<String>) -> Self {
+        self.config.keyid = Some(keyid.into());
+        self
+    }
+
+    pub fn iv(mut self, iv: impl Into<String>) -> Self {
+        self.config.iv = Some(iv.into());
+        self
+    }
+
+    pub fn endpoint(mut self, endpoint: TlsEndpoint) -> Self {
+        self.config.endpoint = Some(endpoint);
+        self
+    }
+
+    pub fn dir(mut self, dir: impl Into<String>) -> Self {
+        self.config.dir = Some(dir.into());
+        self
+    }
+
+    pub fn priority(mut self, priority: impl Into<String>) -> Self {
+        self.config.priority = Some(priority.into());
+        self
+    }
+
+    pub fn verify_peer(mut self, verify_peer: impl Into<OnOff>) -> Self {
+        self.config.verify_peer = Some(verify_peer.into());
+        self
+    }
+
+    pub fn passwordid(mut self, passwordid: impl Into<String>) -> Self {
+        self.config.passwordid = Some(passwordid.into());
+        self
+    }
+
+    pub fn username(mut self, username: impl Into<String>) -> Self {
+        self.config.username = Some(username.into());
+        self
+    }
+
+    pub fn limits(mut self, limits: ThrottleLimits) -> Self {
+        self.config.limits = limits;
+        self
+    }
+
+    pub fn build(self) -> ObjectConfig {
+        self.config
+    }
+}
+
+impl From<ObjectConfigBuilder> for ObjectConfig {
+    fn from(builder: ObjectConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::parser::{parse_args, OptionParsing};
+
+    #[test]
+    fn test_object() {
+        let ram = ObjectConfig::memory_backend_file("ram0", ByteSize::gib(4), "/dev/hugepages")
+            .share(true)
+            .host_nodes([0, 1])
+            .policy(HostMemPolicy::Bind)
+            .build();
+        let group = ObjectConfig::throttle_group(
+            "limits0",
+            ThrottleLimits {
+                bps_total: Some(10_000_000),
+                iops_total: Some(500),
+                ..Default::default()
+            },
+        )
+        .build();
+        let tls = ObjectConfig::tls_creds_x509("tls0", TlsEndpoint::Server, "/etc/pki/qemu")
+            .verify_peer(true)
+            .passwordid("sec0")
+            .build();
+        let formatted = [&ram, &group, &tls].map(|object| object.formatting().to_string());
+        assert_eq!(
+            formatted,
+            [
+                "-object memory-backend-file,id=ram0,size=4G,share=on,host-nodes=0-1,policy=bind,mem-path=/dev/hugepages",
+                "-object throttle-group,id=limits0,limits.bps-total=10000000,limits.iops-total=500",
+                "-object tls-creds-x509,id=tls0,endpoint=server,dir=/etc/pki/qemu,verify-peer=on,passwordid=sec0",
+            ]
+        );
+
+        let argv: Vec<&str> = std::iter::once("qemu-system-x86_64")
+            .chain(formatted.iter().flat_map(|option| option.splitn(2, ' ')))
+            .collect();
+        let command = parse_args(&argv).unwrap();
+        for (option, object) in command.options.iter().zip([ram, group, tls]) {
+            assert!(ObjectConfig::matches(option));
+            assert_eq!(ObjectConfig::parsing(option), Ok(object));
+        }
+
+        let argv = [
+            "qemu-system-x86_64",
+            "-object",
+            "memory-backend-ram,id=m0,size=1G,host-nodes=0-1,policy=bind",
+            "-object",
+            "memory-backend-ram,id=m1,size=1G,host-nodes=4,host-nodes=2-3,host-nodes=0,policy=bind",
+        ];
+        let command = parse_args(&argv).unwrap();
+        let ram = ObjectConfig::parsing(&command.options[0]).unwrap();
+        assert_eq!(ram.host_nodes, [0, 1].into_iter().collect());
+        assert_eq!(ram.formatting().to_string(), argv[1..3].join(" "));
+        let ram = ObjectConfig::parsing(&command.options[1]).unwrap();
+        assert_eq!(ram.host_nodes.runs(), [0..=0, 2..=4]);
+        assert_eq!(
+            ram.formatting().to_string(),
+            "-object memory-backend-ram,id=m1,size=1G,host-nodes=0,host-nodes=2-4,policy=bind"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let objects = [
+            ObjectConfig::memory_backend_file("ram0", ByteSize::mib(3), "/dev/hugepages")
+                .align(ByteSize::mib(2))
+                .host_nodes([0])
+                .build(),
+            ObjectConfig::memory_backend_memfd("ram1", ByteSize::gib(1))
+                .hugetlbsize(ByteSize::mib(2))
+                .filename("/dev/random")
+                .build(),
+            ObjectConfig::secret("sec0", "letmein")
+                .file("/run/secret")
+                .build(),
+            ObjectConfig::tls_creds_psk("tls0", TlsEndpoint::Client, "/etc/pki/qemu")
+                .passwordid("ram0")
+                .build(),
+            ObjectConfig::throttle_group(
+                "ram1",
+                ThrottleLimits {
+                    bps_total: Some(1),
+                    bps_read: Some(1),
+                    iops_total_max: Some(1),
+                    ..Default::default()
+                },
+            )
+            .build(),
+        ];
+        let mut errors = Vec::new();
+        objects.to_vec().validate("object", &mut errors);
+        validate_references(
+            &objects,
+            [ObjectRef {
+                path: "machine.memory-backend".to_string(),
+                id: "sec0",
+                accepts: ObjectType::is_memory_backend,
+                expected: "a memory backend",
+            }],
+            &mut errors,
+        );
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "object[0].host-nodes: requires 'policy=preferred|bind|interleave'",
+                "object[0].size: 3M is not a multiple of 2M",
+                "object[1].filename: not supported by qom-type=memory-backend-memfd",
+                "object[1].hugetlbsize: requires 'hugetlb=on'",
+                "object[2].file: conflicts with 'data'",
+                "object[3].passwordid: not supported by qom-type=tls-creds-psk",
+                "object[4].limits.bps-read: conflicts with 'bps-total'",
+                "object[4].limits.iops-total-max: requires 'iops-total'",
+                "object[4].id: 'ram1' is used more than once",
+                "object[3].passwordid: expected a secret",
+                "machine.memory-backend: expected a memory backend",
+            ]
+        );
+    }
+}
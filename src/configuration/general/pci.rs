@@ -0,0 +1,664 @@
+//! PCI topology: which bus a device plugs into and at which address.
+//!
+//! On q35 every PCIe device gets a pcie-root-port of its own, so that it can
+//! be unplugged, and conventional PCI devices sit behind a pcie-pci-bridge.
+//! On i440fx devices fill the root bus and then pci-bridges. Addresses are
+//! written back into the config, so they stay put when devices are added
+//! or removed later, e.g.
+//!     -device pcie-root-port,id=rp1,bus=pcie.0,addr=0x2,chassis=1,port=1,multifunction=on
+//!     -device virtio-blk-pci,bus=rp1,addr=0x0,drive=disk0
+use std::{collections::BTreeSet, error::Error, fmt, str::FromStr};
+
+use super::{
+    device::{DeviceConfig, GenericDeviceConfig, PcieRootPortConfig},
+    types::OnOff,
+    validate::*,
+};
+
+/// The slot and function of a device on its bus, written as `0x3` or
+/// `0x3.1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PciAddress {
+    pub slot: u8,
+    pub function: u8,
+}
+
+impl PciAddress {
+    pub const MAX_SLOT: u8 = 0x1f;
+    pub const MAX_FUNCTION: u8 = 7;
+
+    pub fn new(slot: u8, function: u8) -> Self {
+        PciAddress { slot, function }
+    }
+}
+
+/// A PCI address that cannot be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidPciAddress(pub String);
+
+impl fmt::Display for InvalidPciAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid PCI address '{}'", self.0)
+    }
+}
+
+impl Error for InvalidPciAddress {}
+
+impl fmt::Display for PciAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.function {
+            0 => write!(f, "{:#x}", self.slot),
+            function => write!(f, "{:#x}.{}", self.slot, function),
+        }
+    }
+}
+
+impl FromStr for PciAddress {
+    type Err = InvalidPciAddress;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidPciAddress(s.to_string());
+        let (slot, function) = s.split_once('.').unwrap_or((s, "0"));
+        let slot = slot.strip_prefix("0x").unwrap_or(slot);
+        let slot = u8::from_str_radix(slot, 16).map_err(|_| invalid())?;
+        let function = function.parse::<u8>().map_err(|_| invalid())?;
+        if slot > Self::MAX_SLOT || function > Self::MAX_FUNCTION {
+            return Err(invalid());
+        }
+        Ok(PciAddress { slot, function })
+    }
+}
+
+/// The PCI layout of a machine type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PciLayout {
+    /// pc: a conventional PCI root bus `pci.0`.
+    I440fx,
+    /// q35: a PCIe root complex `pcie.0`.
+    Q35,
+}
+
+impl PciLayout {
+    /// The layout of a machine type such as `q35`, `pc-q35-8.2` or `pc`.
+    pub fn from_machine(m_type: &str) -> Option<Self> {
+        if m_type == "q35" || m_type.starts_with("pc-q35-") {
+            Some(PciLayout::Q35)
+        } else if m_type == "pc" || m_type.starts_with("pc-") {
+            Some(PciLayout::I440fx)
+        } else {
+            None
+        }
+    }
+
+    pub fn root_bus(&self) -> &'static str {
+        match self {
+            PciLayout::I440fx => "pci.0",
+            PciLayout::Q35 => "pcie.0",
+        }
+    }
+
+    /// Root bus slots of the built-in devices: the host bridge and the PIIX3
+    /// or ICH9 functions.
+    fn reserved_slots(&self) -> &'static [u8] {
+        match self {
+            PciLayout::I440fx => &[0x0, 0x1],
+            PciLayout::Q35 => &[0x0, 0x1f],
+        }
+    }
+
+    /// The slot QEMU gives the default display, kept for displays only.
+    fn display_slot(&self) -> u8 {
+        match self {
+            PciLayout::I440fx => 0x2,
+            PciLayout::Q35 => 0x1,
+        }
+    }
+
+    /// The driver of the bridge conventional PCI devices go behind.
+    fn bridge_driver(&self) -> &'static str {
+        match self {
+            PciLayout::I440fx => "pci-bridge",
+            PciLayout::Q35 => "pcie-pci-bridge",
+        }
+    }
+}
+
+/// Why devices could not be given addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PciError {
+    /// The machine type has no known PCI layout.
+    UnsupportedMachine(String),
+    /// The device cannot be plugged into the PCI layout of the machine.
+    UnsupportedDevice { driver: String },
+    /// No free slot is left on the bus.
+    Exhausted { bus: String },
+}
+
+impl fmt::Display for PciError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PciError::UnsupportedMachine(m_type) => {
+                write!(f, "no known PCI layout for machine '{}'", m_type)
+            }
+            PciError::UnsupportedDevice { driver } => {
+                write!(f, "'{}' does not fit the PCI layout of the machine", driver)
+            }
+            PciError::Exhausted { bus } => write!(f, "no free PCI slot left on '{}'", bus),
+        }
+    }
+}
+
+impl Error for PciError {}
+
+/// How a device attaches to PCI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PciKind {
+    /// A PCIe endpoint, hotpluggable behind a root port.
+    Express,
+    /// A conventional PCI device, behind a bridge on q35.
+    Conventional,
+    /// A device built into the root complex.
+    Integrated,
+    /// A display, at the slot QEMU keeps for it.
+    Display,
+    /// A pcie-root-port itself.
+    RootPort,
+}
+
+/// Conventional PCI drivers, as opposed to PCIe ones.
+const CONVENTIONAL: &[&str] = &[
+    "e1000",
+    "rtl8139",
+    "ne2k_pci",
+    "AC97",
+    "ES1370",
+    "intel-hda",
+    "lsi53c895a",
+    "pci-ohci",
+    "piix3-usb-uhci",
+    "pci-bridge",
+];
+
+const PCIE: &[&str] = &["e1000e", "qemu-xhci", "nec-usb-xhci", "pcie-pci-bridge"];
+
+const INTEGRATED: &[&str] = &["ich9-intel-hda", "ich9-usb-ehci1", "ich9-ahci"];
+
+const DISPLAY: &[&str] = &[
+    "VGA",
+    "cirrus-vga",
+    "qxl-vga",
+    "virtio-vga",
+    "bochs-display",
+];
+
+fn pci_kind(device: &DeviceConfig) -> Option<PciKind> {
+    match device {
+        DeviceConfig::ScsiHd(_) | DeviceConfig::HdaDuplex(_) => None,
+        DeviceConfig::E1000(_) | DeviceConfig::IntelHda(_) | DeviceConfig::Ac97(_) => {
+            Some(PciKind::Conventional)
+        }
+        DeviceConfig::Ich9IntelHda(_) => Some(PciKind::Integrated),
+        DeviceConfig::PcieRootPort(_) => Some(PciKind::RootPort),
+        DeviceConfig::Generic(device) => {
+            let driver = device.driver.as_str();
+            if CONVENTIONAL.contains(&driver) {
+                Some(PciKind::Conventional)
+            } else if INTEGRATED.contains(&driver) {
+                Some(PciKind::Integrated)
+            } else if DISPLAY.contains(&driver) {
+                Some(PciKind::Display)
+            } else if PCIE.contains(&driver) || driver.ends_with("-pci") {
+                Some(PciKind::Express)
+            } else {
+                None
+            }
+        }
+        _ => Some(PciKind::Express),
+    }
+}
+
+/// Free and taken addresses while devices are placed.
+struct Allocator {
+    layout: PciLayout,
+    /// Taken (bus, slot, function) triples.
+    used: BTreeSet<(String, u8, u8)>,
+    ids: BTreeSet<String>,
+    /// Buses some device is plugged into.
+    occupied: BTreeSet<String>,
+    /// Root ports nothing is plugged into, in config order.
+    free_ports: Vec<String>,
+    bridges: Vec<String>,
+    chassis: BTreeSet<u8>,
+    /// The root bus address of the last multifunction root port, whose
+    /// slot further ports may share.
+    last_port: Option<PciAddress>,
+    created: Vec<DeviceConfig>,
+}
+
+impl Allocator {
+    fn new(layout: PciLayout, devices: &[DeviceConfig]) -> Self {
+        let root = layout.root_bus();
+        let mut used = BTreeSet::new();
+        for slot in layout.reserved_slots() {
+            used.insert((root.to_string(), *slot, 0));
+        }
+        for device in devices {
+            if let Some(addr) = device
+                .addr()
+                .and_then(|addr| addr.parse::<PciAddress>().ok())
+            {
+                let bus = device.bus().unwrap_or(root).to_string();
+                used.insert((bus, addr.slot, addr.function));
+            }
+        }
+        let occupied: BTreeSet<String> = devices
+            .iter()
+            .filter_map(|device| Some(device.bus()?.to_string()))
+            .collect();
+        let free_ports = devices
+            .iter()
+            .filter(|device| matches!(device, DeviceConfig::PcieRootPort(_)))
+            .filter_map(|device| device.id())
+            .filter(|id| !occupied.contains(*id))
+            .map(str::to_string)
+            .collect();
+        let bridges = devices
+            .iter()
+            .filter(|device| device.driver() == layout.bridge_driver())
+            .filter_map(|device| Some(device.id()?.to_string()))
+            .collect();
+        let chassis = devices
+            .iter()
+            .filter_map(|device| match device {
+                DeviceConfig::PcieRootPort(port) => port.chassis,
+                DeviceConfig::Generic(bridge) => bridge.prop("chassis_nr")?.parse().ok(),
+                _ => None,
+            })
+            .collect();
+        Allocator {
+            layout,
+            used,
+            ids: devices
+                .iter()
+                .filter_map(|device| Some(device.id()?.to_string()))
+                .collect(),
+            occupied,
+            free_ports,
+            bridges,
+            chassis,
+            last_port: devices.iter().rev().find_map(|device| match device {
+                DeviceConfig::PcieRootPort(port) if port.bus.as_deref() == Some(root) => {
+                    let addr = port.addr.as_deref()?.parse::<PciAddress>().ok()?;
+                    (addr.function > 0 || port.multifunction == Some(OnOff::On)).then_some(addr)
+                }
+                _ => None,
+            }),
+            created: Vec::new(),
+        }
+    }
+
+    fn slot_is_free(&self, bus: &str, slot: u8) -> bool {
+        let functions =
+            (bus.to_string(), slot, 0)..=(bus.to_string(), slot, PciAddress::MAX_FUNCTION);
+        self.used.range(functions).next().is_none()
+    }
+
+    /// Free slots on `bus` from `first` on, apart from the display slot.
+    fn free_slots<'a>(&'a self, bus: &'a str, first: u8) -> impl Iterator<Item = u8> + 'a {
+        let display = (bus == self.layout.root_bus()).then(|| self.layout.display_slot());
+        (first..=PciAddress::MAX_SLOT)
+            .filter(move |slot| Some(*slot) != display && self.slot_is_free(bus, *slot))
+    }
+
+    /// Take the first free slot on `bus` from `first` on.
+    fn take_slot(&mut self, bus: &str, first: u8) -> Option<PciAddress> {
+        let slot = self.free_slots(bus, first).next()?;
+        self.used.insert((bus.to_string(), slot, 0));
+        Some(PciAddress::new(slot, 0))
+    }
+
+    fn new_id(&mut self, prefix: &str) -> String {
+        let id = (1..)
+            .map(|n| format!("{}{}", prefix, n))
+            .find(|id| !self.ids.contains(id))
+            .unwrap();
+        self.ids.insert(id.clone());
+        id
+    }
+
+    fn new_chassis(&mut self, bus: &str) -> Result<u8, PciError> {
+        let chassis = (1..=u8::MAX)
+            .find(|chassis| !self.chassis.contains(chassis))
+            .ok_or_else(|| PciError::Exhausted {
+                bus: bus.to_string(),
+            })?;
+        self.chassis.insert(chassis);
+        Ok(chassis)
+    }
+
+    /// A free function on the root bus for a root port. Ports share slots
+    /// as functions of a multifunction device, as real root complexes do.
+    fn root_port_address(&mut self) -> Result<PciAddress, PciError> {
+        let root = self.layout.root_bus();
+        let shared = self
+            .last_port
+            .filter(|addr| addr.function < PciAddress::MAX_FUNCTION)
+            .map(|addr| PciAddress::new(addr.slot, addr.function + 1));
+        match shared {
+            Some(addr)
+                if !self
+                    .used
+                    .contains(&(root.to_string(), addr.slot, addr.function)) =>
+            {
+                self.used
+                    .insert((root.to_string(), addr.slot, addr.function));
+                self.last_port = Some(addr);
+                Ok(addr)
+            }
+            _ => {
+                let addr = self.take_slot(root, 0).ok_or_else(|| PciError::Exhausted {
+                    bus: root.to_string(),
+                })?;
+                self.last_port = Some(addr);
+                Ok(addr)
+            }
+        }
+    }
+
+    /// Add a root port, returning its id.
+    fn new_root_port(&mut self) -> Result<String, PciError> {
+        let root = self.layout.root_bus();
+        let addr = self.root_port_address()?;
+        let chassis = self.new_chassis(root)?;
+        let id = self.new_id("rp");
+        let mut port = PcieRootPortConfig::builder()
+            .id(id.clone())
+            .bus(root)
+            .addr(addr.to_string())
+            .chassis(chassis)
+            .port(chassis);
+        if addr.function == 0 {
+            port = port.multifunction(OnOff::On);
+        }
+        self.created.push(port.into());
+        Ok(id)
+    }
+
+    /// An unused root port, reusing a free one before adding another.
+    fn root_port(&mut self) -> Result<String, PciError> {
+        let id = match self.free_ports.is_empty() {
+            false => self.free_ports.remove(0),
+            true => self.new_root_port()?,
+        };
+        self.occupied.insert(id.clone());
+        Ok(id)
+    }
+
+    /// Add a bridge for conventional PCI devices, returning its id.
+    fn new_bridge(&mut self) -> Result<String, PciError> {
+        let root = self.layout.root_bus();
+        let id = self.new_id("pci.");
+        let bridge = GenericDeviceConfig::new(self.layout.bridge_driver()).id(id.clone());
+        let bridge = match self.layout {
+            PciLayout::Q35 => bridge
+                .bus(self.root_port()?)
+                .addr(PciAddress::new(0, 0).to_string()),
+            PciLayout::I440fx => {
+                let addr = self.take_slot(root, 0).ok_or_else(|| PciError::Exhausted {
+                    bus: root.to_string(),
+                })?;
+                let chassis = self.new_chassis(root)?;
+                bridge
+                    .bus(root)
+                    .addr(addr.to_string())
+                    .set("chassis_nr", chassis)
+            }
+        };
+        self.created.push(bridge.into());
+        self.bridges.push(id.clone());
+        Ok(id)
+    }
+
+    /// A slot behind a bridge, adding a bridge when all are full.
+    fn bridge_slot(&mut self) -> Result<(String, PciAddress), PciError> {
+        for bridge in self.bridges.clone() {
+            if let Some(addr) = self.take_slot(&bridge, 1) {
+                return Ok((bridge, addr));
+            }
+        }
+        let bridge = self.new_bridge()?;
+        let addr = self.take_slot(&bridge, 1).unwrap();
+        Ok((bridge, addr))
+    }
+
+    /// A slot on the root bus. On i440fx the last free one is kept for a
+    /// bridge while `remaining` further devices still need a place, and
+    /// devices go behind bridges once the root bus is full.
+    fn root_slot(&mut self, remaining: usize) -> Result<(String, PciAddress), PciError> {
+        let root = self.layout.root_bus();
+        let free = self.free_slots(root, 0).count();
+        if self.layout == PciLayout::I440fx && (free == 0 || free == 1 && remaining > 0) {
+            return self.bridge_slot();
+        }
+        let addr = self.take_slot(root, 0).ok_or_else(|| PciError::Exhausted {
+            bus: root.to_string(),
+        })?;
+        Ok((root.to_string(), addr))
+    }
+
+    /// Where to plug a device of the given kind.
+    fn place(
+        &mut self,
+        kind: PciKind,
+        driver: &str,
+        remaining: usize,
+    ) -> Result<(String, PciAddress), PciError> {
+        let root = self.layout.root_bus();
+        match (self.layout, kind) {
+            (PciLayout::Q35, PciKind::Express) => Ok((self.root_port()?, PciAddress::new(0, 0))),
+            (PciLayout::Q35, PciKind::Conventional) => self.bridge_slot(),
+            (PciLayout::Q35, PciKind::RootPort) => {
+                Ok((root.to_string(), self.root_port_address()?))
+            }
+            (PciLayout::I440fx, PciKind::RootPort) => Err(PciError::UnsupportedDevice {
+                driver: driver.to_string(),
+            }),
+            (_, PciKind::Display) if self.slot_is_free(root, self.layout.display_slot()) => {
+                let slot = self.layout.display_slot();
+                self.used.insert((root.to_string(), slot, 0));
+                Ok((root.to_string(), PciAddress::new(slot, 0)))
+            }
+            _ => self.root_slot(remaining),
+        }
+    }
+}
+
+/// Give every PCI device that has neither `bus` nor `addr` a place, adding
+/// the root ports and bridges needed in front of the devices. Devices with
+/// a place keep it. On q35, at least `spare_root_ports` empty root ports
+/// are left for hotplug.
+pub fn assign_addresses(
+    layout: PciLayout,
+    devices: &mut Vec<DeviceConfig>,
+    spare_root_ports: usize,
+) -> Result<(), PciError> {
+    let mut allocator = Allocator::new(layout, devices);
+    let pending: Vec<(usize, PciKind)> = devices
+        .iter()
+        .enumerate()
+        .filter(|(_, device)| device.bus().is_none() && device.addr().is_none())
+        .filter_map(|(i, device)| Some((i, pci_kind(device)?)))
+        .collect();
+    for (n, (i, kind)) in pending.iter().enumerate() {
+        let (bus, addr) = allocator.place(*kind, devices[*i].driver(), pending.len() - n - 1)?;
+        devices[*i].set_place(bus, addr.to_string());
+    }
+    if layout == PciLayout::Q35 {
+        while allocator.free_ports.len() < spare_root_ports {
+            let id = allocator.new_root_port()?;
+            allocator.free_ports.push(id);
+        }
+    }
+    devices.splice(0..0, allocator.created);
+    Ok(())
+}
+
+/// Check that PCI addresses can be read and no two devices share one.
+pub(crate) fn validate_addresses(devices: &[DeviceConfig], errors: &mut Vec<ValidationError>) {
+    let mut used = BTreeSet::new();
+    for (i, device) in devices.iter().enumerate() {
+        let Some(addr) = device.addr() else {
+            continue;
+        };
+        let path = format!("device[{}]", i);
+        match addr.parse::<PciAddress>() {
+            Err(_) => invalid(
+                errors,
+                &path,
+                "addr",
+                ValidationErrorKind::Invalid {
+                    expected: "a PCI address such as 0x3 or 0x3.1",
+                },
+            ),
+            Ok(addr) => {
+                if !used.insert((device.bus(), addr)) {
+                    invalid(
+                        errors,
+                        &path,
+                        "addr",
+                        ValidationErrorKind::Duplicate {
+                            id: format!("{}:{}", device.bus().unwrap_or_default(), addr),
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{command::builder::OptionFormatting, configuration::general::device::*};
+
+    fn formatted(devices: &[DeviceConfig]) -> Vec<String> {
+        devices
+            .iter()
+            .map(|device| device.formatting().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_address() {
+        assert_eq!("0x1f.7".parse(), Ok(PciAddress::new(0x1f, 7)));
+        assert_eq!("03".parse(), Ok(PciAddress::new(3, 0)));
+        assert_eq!(PciAddress::new(0xa, 0).to_string(), "0xa");
+        assert_eq!(PciAddress::new(2, 3).to_string(), "0x2.3");
+        assert!("0x20".parse::<PciAddress>().is_err());
+        assert!("0x2.8".parse::<PciAddress>().is_err());
+    }
+
+    #[test]
+    fn test_q35() {
+        let mut devices: Vec<DeviceConfig> = vec![
+            VirtioBlkPciConfig::builder("disk0").into(),
+            VirtioNetPciConfig::builder("net0").into(),
+            GenericDeviceConfig::new("e1000")
+                .set("netdev", "net1")
+                .into(),
+            GenericDeviceConfig::new("VGA").into(),
+            ScsiHdConfig::builder("disk1").bus("scsi0.0").into(),
+        ];
+        assign_addresses(PciLayout::Q35, &mut devices, 2).unwrap();
+        let expected = [
+            "-device pcie-root-port,id=rp1,bus=pcie.0,addr=0x2,chassis=1,port=1,multifunction=on",
+            "-device pcie-root-port,id=rp2,bus=pcie.0,addr=0x2.1,chassis=2,port=2",
+            "-device pcie-root-port,id=rp3,bus=pcie.0,addr=0x2.2,chassis=3,port=3",
+            "-device pcie-pci-bridge,id=pci.1,bus=rp3,addr=0x0",
+            "-device pcie-root-port,id=rp4,bus=pcie.0,addr=0x2.3,chassis=4,port=4",
+            "-device pcie-root-port,id=rp5,bus=pcie.0,addr=0x2.4,chassis=5,port=5",
+            "-device virtio-blk-pci,bus=rp1,addr=0x0,drive=disk0",
+            "-device virtio-net-pci,bus=rp2,addr=0x0,netdev=net0",
+            "-device e1000,bus=pci.1,addr=0x1,netdev=net1",
+            "-device VGA,bus=pcie.0,addr=0x1",
+            "-device scsi-hd,bus=scsi0.0,drive=disk1",
+        ];
+        assert_eq!(formatted(&devices), expected);
+
+        // Placed devices stay where they are, new ones take the spare ports
+        // first and spare ports are topped up.
+        assign_addresses(PciLayout::Q35, &mut devices, 2).unwrap();
+        assert_eq!(formatted(&devices), expected);
+        devices.remove(6);
+        devices.push(VirtioRngPciConfig::builder().into());
+        devices.push(VirtioBalloonPciConfig::builder().into());
+        assign_addresses(PciLayout::Q35, &mut devices, 2).unwrap();
+        let formatted = formatted(&devices);
+        assert_eq!(
+            formatted[..2],
+            [
+                "-device pcie-root-port,id=rp6,bus=pcie.0,addr=0x2.5,chassis=6,port=6",
+                "-device pcie-root-port,id=rp1,bus=pcie.0,addr=0x2,chassis=1,port=1,multifunction=on",
+            ]
+        );
+        assert_eq!(
+            formatted[formatted.len() - 2..],
+            [
+                "-device virtio-rng-pci,bus=rp1,addr=0x0",
+                "-device virtio-balloon-pci,bus=rp4,addr=0x0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_i440fx() {
+        let mut devices: Vec<DeviceConfig> = vec![VirtioNetPciConfig::builder("net0")
+            .bus("pci.0")
+            .addr("0x3")
+            .into()];
+        devices.extend((0..30).map(|_| VirtioRngPciConfig::builder().into()));
+        assign_addresses(PciLayout::I440fx, &mut devices, 2).unwrap();
+        let formatted = formatted(&devices);
+        assert_eq!(
+            formatted[0],
+            "-device pci-bridge,id=pci.1,bus=pci.0,addr=0x1f,chassis_nr=1"
+        );
+        assert_eq!(formatted[2], "-device virtio-rng-pci,bus=pci.0,addr=0x4");
+        assert_eq!(formatted[28], "-device virtio-rng-pci,bus=pci.0,addr=0x1e");
+        assert_eq!(formatted[29], "-device virtio-rng-pci,bus=pci.1,addr=0x1");
+        assert_eq!(formatted[31], "-device virtio-rng-pci,bus=pci.1,addr=0x3");
+
+        let mut devices: Vec<DeviceConfig> = vec![PcieRootPortConfig::builder().into()];
+        assert_eq!(
+            assign_addresses(PciLayout::I440fx, &mut devices, 0),
+            Err(PciError::UnsupportedDevice {
+                driver: "pcie-root-port".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let devices: Vec<DeviceConfig> = vec![
+            VirtioRngPciConfig::builder()
+                .bus("pcie.0")
+                .addr("0x3")
+                .into(),
+            VirtioRngPciConfig::builder()
+                .bus("pcie.0")
+                .addr("3.0")
+                .into(),
+            VirtioRngPciConfig::builder().bus("rp1").addr("0x3").into(),
+            VirtioRngPciConfig::builder().addr("slot3").into(),
+        ];
+        let mut errors = Vec::new();
+        validate_addresses(&devices, &mut errors);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "device[1].addr: 'pcie.0:0x3' is used more than once",
+                "device[3].addr: expected a PCI address such as 0x3 or 0x3.1",
+            ]
+        );
+    }
+}
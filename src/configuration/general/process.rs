@@ -0,0 +1,536 @@
+//! Options of the QEMU process rather than the guest: confining it with
+//! seccomp and dropped privileges, its log messages and memory locking, e.g.
+//!     -sandbox on,obsolete=deny,elevateprivileges=deny,spawn=deny,resourcecontrol=deny
+//!     -run-with chroot=/var/empty,user=qemu
+//!     -msg timestamp=on,guest-name=on
+//!     -overcommit mem-lock=on,cpu-pm=on
+use crate::command::builder::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    name::NameConfig,
+    types::{qemu_enum, Accelerator, OnOff},
+    validate::*,
+};
+
+qemu_enum! {
+    /// allow|deny
+    pub enum SandboxAction {
+        Allow = "allow",
+        Deny = "deny",
+    }
+}
+
+qemu_enum! {
+    /// elevateprivileges=allow|deny|children
+    pub enum ElevatePrivileges {
+        Allow = "allow",
+        Deny = "deny",
+        Children = "children",
+    }
+}
+
+qemu_enum! {
+    /// mem-lock=on|off|on-fault
+    pub enum MemLock {
+        On = "on" | "yes" | "true" | "y",
+        Off = "off" | "no" | "false" | "n",
+        OnFault = "on-fault",
+    }
+}
+
+impl From<bool> for MemLock {
+    fn from(b: bool) -> Self {
+        if b {
+            MemLock::On
+        } else {
+            MemLock::Off
+        }
+    }
+}
+
+/// Filter the system calls of QEMU with seccomp. The other keys only take
+/// effect with the sandbox on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "sandbox")]
+pub struct SandboxConfig {
+    // on|off
+    #[serde(rename = "enable")]
+    #[qemu(positional, rename = "enable")]
+    pub enable: OnOff,
+
+    // obsolete=allow|deny
+    // (default=allow)
+    //
+    // System calls QEMU no longer needs but libraries might.
+    #[serde(rename = "obsolete", skip_serializing_if = "Option::is_none")]
+    pub obsolete: Option<SandboxAction>,
+
+    // elevateprivileges=allow|deny|children
+    // (default=allow)
+    //
+    // set*uid and set*gid calls, children allows them only in spawned
+    // helpers.
+    #[serde(rename = "elevateprivileges", skip_serializing_if = "Option::is_none")]
+    pub elevateprivileges: Option<ElevatePrivileges>,
+
+    // spawn=allow|deny
+    // (default=allow)
+    //
+    // fork and execve, which helper scripts such as a network script need.
+    #[serde(rename = "spawn", skip_serializing_if = "Option::is_none")]
+    pub spawn: Option<SandboxAction>,
+
+    // resourcecontrol=allow|deny
+    // (default=allow)
+    //
+    // Changes of process affinity and scheduler priority.
+    #[serde(rename = "resourcecontrol", skip_serializing_if = "Option::is_none")]
+    pub resourcecontrol: Option<SandboxAction>,
+}
+
+impl Validate for SandboxConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        if self.enable == OnOff::On {
+            return;
+        }
+        let keys = [
+            ("obsolete", self.obsolete.is_some()),
+            ("elevateprivileges", self.elevateprivileges.is_some()),
+            ("spawn", self.spawn.is_some()),
+            ("resourcecontrol", self.resourcecontrol.is_some()),
+        ];
+        for (key, _) in keys.into_iter().filter(|(_, set)| *set) {
+            let by = "enable=off".to_string();
+            invalid(errors, path, key, ValidationErrorKind::NotSupported { by });
+        }
+    }
+}
+
+/// Drop privileges once QEMU has opened its files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "run-with")]
+pub struct RunWithConfig {
+    // chroot=dir
+    // Change the root directory to dir before running the guest.
+    #[serde(rename = "chroot", skip_serializing_if = "Option::is_none")]
+    pub chroot: Option<String>,
+
+    // user=username|uid:gid
+    // Switch to the user, and its groups, before running the guest.
+    #[serde(rename = "user", skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+
+    // async-teardown=on|off
+    // (default=off)
+    //
+    // Free the guest memory in a separate process after QEMU exits.
+    #[serde(rename = "async-teardown", skip_serializing_if = "Option::is_none")]
+    pub async_teardown: Option<OnOff>,
+}
+
+impl Validate for RunWithConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        if let Some(chroot) = &self.chroot {
+            if !chroot.starts_with('/') {
+                let kind = ValidationErrorKind::Invalid {
+                    expected: "an absolute path",
+                };
+                invalid(errors, path, "chroot", kind);
+            }
+        }
+        if let Some(user) = &self.user {
+            let numeric = |id: &str| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit());
+            let valid = match user.split_once(':') {
+                Some((uid, gid)) => numeric(uid) && numeric(gid),
+                None => !user.is_empty(),
+            };
+            if !valid {
+                let kind = ValidationErrorKind::Invalid {
+                    expected: "a user name or uid:gid",
+                };
+                invalid(errors, path, "user", kind);
+            }
+        }
+    }
+}
+
+/// Format of the messages QEMU writes to stderr.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "msg")]
+pub struct MsgConfig {
+    // timestamp=on|off
+    // (default=off)
+    //
+    // Prefix messages with the time.
+    #[serde(rename = "timestamp", skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<OnOff>,
+
+    // guest-name=on|off
+    // (default=off)
+    //
+    // Prefix messages with the guest name of -name, which tells apart the
+    // logs of several VMs.
+    #[serde(rename = "guest-name", skip_serializing_if = "Option::is_none")]
+    pub guest_name: Option<OnOff>,
+}
+
+/// Whether QEMU may swap out guest memory and keep host CPUs busy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "overcommit")]
+pub struct OvercommitConfig {
+    // mem-lock=on|off|on-fault
+    // (default=off)
+    //
+    // Lock the memory of QEMU and the guest in RAM, on-fault only once it
+    // is touched. Needs a high enough RLIMIT_MEMLOCK.
+    #[serde(rename = "mem-lock", skip_serializing_if = "Option::is_none")]
+    pub mem_lock: Option<MemLock>,
+
+    // cpu-pm=on|off
+    // (default=off)
+    //
+    // Let vCPUs idle on the host CPU instead of exiting to QEMU. Only
+    // worth it with one pinned host CPU per vCPU.
+    #[serde(rename = "cpu-pm", skip_serializing_if = "Option::is_none")]
+    pub cpu_pm: Option<OnOff>,
+}
+
+impl OvercommitConfig {
+    /// Check that the accelerators QEMU tries can pass power management
+    /// through, which only KVM does.
+    pub(crate) fn validate_accel(
+        &self,
+        path: &str,
+        accels: &[Accelerator],
+        errors: &mut Vec<ValidationError>,
+    ) {
+        if self.cpu_pm == Some(OnOff::On) && !accels.contains(&Accelerator::Kvm) {
+            let accels = accels.iter().map(Accelerator::as_str).collect::<Vec<_>>();
+            let by = format!("accel={}", accels.join(":"));
+            invalid(
+                errors,
+                path,
+                "cpu-pm",
+                ValidationErrorKind::NotSupported { by },
+            );
+        }
+    }
+}
+
+/// Check that the guest name messages are prefixed with is given.
+pub(crate) fn validate_guest_name(
+    msg: Option<&MsgConfig>,
+    name: Option<&NameConfig>,
+    errors: &mut Vec<ValidationError>,
+) {
+    let guest_name = msg.and_then(|msg| msg.guest_name) == Some(OnOff::On);
+    if guest_name && name.is_none() {
+        invalid(
+            errors,
+            "msg",
+            "guest-name",
+            ValidationErrorKind::Requires { key: "name" },
+        );
+    }
+}
+
+/// Builder for `SandboxConfig`, e.g.
+///     SandboxConfig::strict().build()
+#[derive(Debug, Clone)]
+pub struct SandboxConfigBuilder {
+    config: SandboxConfig,
+}
+
+impl SandboxConfig {
+    pub fn builder(enable: impl Into<OnOff>) -> SandboxConfigBuilder {
+        SandboxConfigBuilder {
+            config: SandboxConfig {
+                enable: enable.into(),
+                obsolete: None,
+                elevateprivileges: None,
+                spawn: None,
+                resourcecontrol: None,
+            },
+        }
+    }
+
+    /// The sandbox with every group of system calls denied, for untrusted
+    /// guests.
+    pub fn strict() -> SandboxConfigBuilder {
+        Self::builder(true)
+            .obsolete(SandboxAction::Deny)
+            .elevateprivileges(ElevatePrivileges::Deny)
+            .spawn(SandboxAction::Deny)
+            .resourcecontrol(SandboxAction::Deny)
+    }
+}
+
+impl SandboxConfigBuilder {
+    pub fn obsolete(mut self, obsolete: SandboxAction) -> Self {
+        self.config.obsolete = Some(obsolete);
+        self
+    }
+
+    pub fn elevateprivileges(mut self, elevateprivileges: ElevatePrivileges) -> Self {
+        self.config.elevateprivileges = Some(elevateprivileges);
+        self
+    }
+
+    pub fn spawn(mut self, spawn: SandboxAction) -> Self {
+        self.config.spawn = Some(spawn);
+        self
+    }
+
+    pub fn resourcecontrol(mut self, resourcecontrol: SandboxAction) -> Self {
+        self.config.resourcecontrol = Some(resourcecontrol);
+        self
+    }
+
+    pub fn build(self) -> SandboxConfig {
+        self.config
+    }
+}
+
+impl From<SandboxConfigBuilder> for SandboxConfig {
+    fn from(builder: SandboxConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+/// Builder for `RunWithConfig`, e.g.
+///     RunWithConfig::builder().chroot("/var/empty").user("qemu").build()
+#[derive(Debug, Clone)]
+pub struct RunWithConfigBuilder {
+    config: RunWithConfig,
+}
+
+impl RunWithConfig {
+    pub fn builder() -> RunWithConfigBuilder {
+        RunWithConfigBuilder {
+            config: RunWithConfig {
+                chroot: None,
+                user: None,
+                async_teardown: None,
+            },
+        }
+    }
+}
+
+impl RunWithConfigBuilder {
+    pub fn chroot(mut self, chroot: impl Into<String>) -> Self {
+        self.config.chroot = Some(chroot.into());
+        self
+    }
+
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.config.user = Some(user.into());
+        self
+    }
+
+    pub fn async_teardown(mut self, async_teardown: impl Into<OnOff>) -> Self {
+        self.config.async_teardown = Some(async_teardown.into());
+        self
+    }
+
+    pub fn build(self) -> RunWithConfig {
+        self.config
+    }
+}
+
+impl From<RunWithConfigBuilder> for RunWithConfig {
+    fn from(builder: RunWithConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+/// Builder for `MsgConfig`, e.g.
+///     MsgConfig::builder().timestamp(true).guest_name(true).build()
+#[derive(Debug, Clone)]
+pub struct MsgConfigBuilder {
+    config: MsgConfig,
+}
+
+impl MsgConfig {
+    pub fn builder() -> MsgConfigBuilder {
+        MsgConfigBuilder {
+            config: MsgConfig {
+                timestamp: None,
+                guest_name: None,
+            },
+        }
+    }
+}
+
+impl MsgConfigBuilder {
+    pub fn timestamp(mut self, timestamp: impl Into<OnOff>) -> Self {
+        self.config.timestamp = Some(timestamp.into());
+        self
+    }
+
+    pub fn guest_name(mut self, guest_name: impl Into<OnOff>) -> Self {
+        self.config.guest_name = Some(guest_name.into());
+        self
+    }
+
+    pub fn build(self) -> MsgConfig {
+        self.config
+    }
+}
+
+impl From<MsgConfigBuilder> for MsgConfig {
+    fn from(builder: MsgConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+/// Builder for `OvercommitConfig`, e.g.
+///     OvercommitConfig::builder().mem_lock(true).cpu_pm(true).build()
+#[derive(Debug, Clone)]
+pub struct OvercommitConfigBuilder {
+    config: OvercommitConfig,
+}
+
+impl OvercommitConfig {
+    pub fn builder() -> OvercommitConfigBuilder {
+        OvercommitConfigBuilder {
+            config: OvercommitConfig {
+                mem_lock: None,
+                cpu_pm: None,
+            },
+        }
+    }
+}
+
+impl OvercommitConfigBuilder {
+    pub fn mem_lock(mut self, mem_lock: impl Into<MemLock>) -> Self {
+        self.config.mem_lock = Some(mem_lock.into());
+        self
+    }
+
+    pub fn cpu_pm(mut self, cpu_pm: impl Into<OnOff>) -> Self {
+        self.config.cpu_pm = Some(cpu_pm.into());
+        self
+    }
+
+    pub fn build(self) -> OvercommitConfig {
+        self.config
+    }
+}
+
+impl From<OvercommitConfigBuilder> for OvercommitConfig {
+    fn from(builder: OvercommitConfigBuilder) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::parser::*;
+
+    fn parse<T: OptionParsing>(argv: &[&str]) -> Result<T, ParseError> {
+        let command = parse_args(argv).unwrap();
+        T::parsing(&command.options[0])
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let sandbox = SandboxConfig::strict().build();
+        let formatted =
+            "-sandbox on,obsolete=deny,elevateprivileges=deny,spawn=deny,resourcecontrol=deny";
+        assert_eq!(sandbox.formatting().to_string(), formatted);
+        assert_eq!(
+            parse(&["qemu-system-x86_64", "-sandbox", &formatted[9..]]),
+            Ok(sandbox)
+        );
+        assert_eq!(
+            parse(&["qemu-system-x86_64", "-sandbox", "enable=on,spawn=allow"]),
+            Ok(SandboxConfig::builder(true)
+                .spawn(SandboxAction::Allow)
+                .build())
+        );
+        assert!(
+            parse::<SandboxConfig>(&["qemu-system-x86_64", "-sandbox", "on,spawn=no"]).is_err()
+        );
+
+        let mut errors = Vec::new();
+        SandboxConfig::builder(false)
+            .spawn(SandboxAction::Deny)
+            .build()
+            .validate("sandbox", &mut errors);
+        assert_eq!(
+            errors[0].to_string(),
+            "sandbox.spawn: not supported by enable=off"
+        );
+    }
+
+    #[test]
+    fn test_run_with() {
+        let run_with = RunWithConfig::builder()
+            .chroot("/var/empty")
+            .user("107:107")
+            .build();
+        assert_eq!(
+            run_with.formatting().to_string(),
+            "-run-with chroot=/var/empty,user=107:107"
+        );
+        let json = r#"{"chroot":"/var/empty","user":"107:107"}"#;
+        assert_eq!(serde_json::to_string(&run_with).unwrap(), json);
+
+        let mut errors = Vec::new();
+        run_with.validate("run-with", &mut errors);
+        RunWithConfig::builder()
+            .chroot("var/empty")
+            .user("107:")
+            .build()
+            .validate("run-with", &mut errors);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "run-with.chroot: expected an absolute path",
+                "run-with.user: expected a user name or uid:gid",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_msg_and_overcommit() {
+        let msg = MsgConfig::builder()
+            .timestamp(true)
+            .guest_name(true)
+            .build();
+        assert_eq!(
+            msg.formatting().to_string(),
+            "-msg timestamp=on,guest-name=on"
+        );
+        let overcommit = OvercommitConfig::builder()
+            .mem_lock(MemLock::OnFault)
+            .cpu_pm(true)
+            .build();
+        assert_eq!(
+            overcommit.formatting().to_string(),
+            "-overcommit mem-lock=on-fault,cpu-pm=on"
+        );
+        assert_eq!(
+            parse(&[
+                "qemu-system-x86_64",
+                "-overcommit",
+                "mem-lock=on-fault,cpu-pm=on"
+            ]),
+            Ok(overcommit.clone())
+        );
+
+        let mut errors = Vec::new();
+        validate_guest_name(Some(&msg), None, &mut errors);
+        overcommit.validate_accel("overcommit", &[Accelerator::Tcg], &mut errors);
+        overcommit.validate_accel("overcommit", &[Accelerator::Kvm], &mut errors);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "msg.guest-name: requires 'name'",
+                "overcommit.cpu-pm: not supported by accel=tcg",
+            ]
+        );
+    }
+}
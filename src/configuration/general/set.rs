@@ -1,26 +1,113 @@
-//! Set parameter arg for item id of type group
-use crate::command::builder::*;
+//! Set property prop of the item id of type group, e.g. a property of an
+//! automatically created device that -device cannot reach:
+//!     -set device.net0.mac=52:54:00:12:34:56
+use crate::command::{builder::*, parser::*};
 use serde::{Deserialize, Serialize};
 
-/// Set parameter arg for item id of type group
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SetConfig<'a> {
-    #[serde(rename = "group.id.arg")]
-    group_id_arg: &'a str,
+/// Set property prop of the item id of type group, written as
+/// `group.id.prop=value`. The value is taken verbatim, commas included.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetConfig {
+    // Option group of the item, e.g. device, drive or netdev.
+    #[serde(rename = "group")]
+    pub group: String,
+
+    #[serde(rename = "id")]
+    pub id: String,
+
+    #[serde(rename = "property")]
+    pub property: String,
+
+    #[serde(rename = "value")]
+    pub value: String,
 }
 
-impl<'a> OptionFormatting<'a> for SetConfig<'a> {
-    fn formatting(&self) -> OptionQ<'a> {
+impl SetConfig {
+    pub fn new(
+        group: impl Into<String>,
+        id: impl Into<String>,
+        property: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        SetConfig {
+            group: group.into(),
+            id: id.into(),
+            property: property.into(),
+            value: value.into(),
+        }
+    }
+}
+
+impl OptionFormatting for SetConfig {
+    fn formatting(&self) -> OptionQ<'_> {
         OptionQ {
             prefix: "-",
             raw: "set",
             option_args_split_with: " ",
             args_split_with: "",
             args: vec![KVArgQ {
-                key: "group.id.arg",
-                kv_split_with: Some("="),
-                value: Some(self.group_id_arg.to_string()),
+                key: "",
+                kv_split_with: None,
+                value: Some(format!(
+                    "{}.{}.{}={}",
+                    self.group, self.id, self.property, self.value
+                )),
             }],
         }
     }
 }
+
+impl OptionParsing for SetConfig {
+    /// The group and id end at the first two dots, the property at the
+    /// first `=`, as in QEMU.
+    fn parsing(option: &OptionQ<'_>) -> Result<Self, ParseError> {
+        let arg = single_value(option)?;
+        let invalid = || ParseError::InvalidValue {
+            option: option.raw.to_string(),
+            key: "group.id.prop".to_string(),
+            value: arg.to_string(),
+        };
+        let (path, value) = arg.split_once('=').ok_or_else(invalid)?;
+        let mut path = path.splitn(3, '.');
+        let (Some(group), Some(id), Some(property)) = (path.next(), path.next(), path.next())
+        else {
+            return Err(invalid());
+        };
+        if [group, id, property].iter().any(|part| part.is_empty()) {
+            return Err(invalid());
+        }
+        Ok(SetConfig::new(group, id, property, value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(arg: &str) -> Result<SetConfig, ParseError> {
+        let argv = ["qemu-system-x86_64", "-set", arg];
+        let command = parse_args(&argv).unwrap();
+        SetConfig::parsing(&command.options[0])
+    }
+
+    #[test]
+    fn test_set() {
+        let set = SetConfig::new("device", "net0", "mac", "52:54:00:12:34:56");
+        let formatted = "-set device.net0.mac=52:54:00:12:34:56";
+        assert_eq!(set.formatting().to_string(), formatted);
+        assert_eq!(parse(&formatted[5..]), Ok(set));
+
+        // Commas and further dots belong to the value and property.
+        assert_eq!(
+            parse("drive.disk0.file.filename=/images/a,b.img"),
+            Ok(SetConfig::new(
+                "drive",
+                "disk0",
+                "file.filename",
+                "/images/a,b.img"
+            ))
+        );
+        assert!(parse("device.net0=1").is_err());
+        assert!(parse("device..mac=1").is_err());
+    }
+}
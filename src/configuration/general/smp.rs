@@ -1,11 +1,15 @@
 use crate::command::builder::*;
 use serde::{Deserialize, Serialize};
 
+use super::validate::*;
+
 /// Configuration for a SMP system.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, QemuOption)]
+#[qemu(option = "smp")]
 pub struct SmpConfig {
     // Simulate a SMP system with 'n' CPUs initially present on the  machine  type  board.
     #[serde(rename = "cpus", skip_serializing_if = "Option::is_none")]
+    #[qemu(implied)]
     pub cpus: Option<usize>,
 
     //  On boards  supporting  CPU  hotplug, the optional 'maxcpus' parameter can be set to
@@ -49,138 +53,192 @@ pub struct SmpConfig {
     pub threads: Option<usize>,
 }
 
-impl<'a> SmpConfig {
-    #[inline]
-    fn cpus(&self) -> Option<KVArgQ<'a>> {
-        match self.cpus {
-            None => None,
-            Some(cpus) => Some(KVArgQ {
-                key: "cpus",
-                kv_split_with: Some("="),
-                value: Some(cpus.to_string()),
-            }),
+impl Validate for SmpConfig {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        for (key, n) in [
+            ("cpus", self.cpus),
+            ("maxcpus", self.maxcpus),
+            ("drawers", self.drawers),
+            ("books", self.books),
+            ("sockets", self.sockets),
+            ("dies", self.dies),
+            ("clusters", self.clusters),
+            ("cores", self.cores),
+            ("threads", self.threads),
+        ] {
+            if n == Some(0) {
+                invalid(
+                    errors,
+                    path,
+                    key,
+                    ValidationErrorKind::Invalid {
+                        expected: "a number greater than 0",
+                    },
+                );
+            }
         }
-    }
 
-    #[inline]
-    fn maxcpus(&self) -> Option<KVArgQ<'a>> {
-        match self.maxcpus {
-            None => None,
-            Some(maxcpus) => Some(KVArgQ {
-                key: "maxcpus",
-                kv_split_with: Some("="),
-                value: Some(maxcpus.to_string()),
-            }),
+        if let (Some(cpus), Some(maxcpus)) = (self.cpus, self.maxcpus) {
+            if cpus > maxcpus {
+                invalid(
+                    errors,
+                    path,
+                    "cpus",
+                    ValidationErrorKind::CpusAboveMaxcpus { cpus, maxcpus },
+                );
+            }
         }
-    }
 
-    #[inline]
-    fn drawers(&self) -> Option<KVArgQ<'a>> {
-        match self.drawers {
-            None => None,
-            Some(drawers) => Some(KVArgQ {
-                key: "drawers",
-                kv_split_with: Some("="),
-                value: Some(drawers.to_string()),
-            }),
+        // QEMU computes omitted sockets, cores or threads itself, so the
+        // product can only be checked once all of them are given.
+        if let (Some(sockets), Some(cores), Some(threads)) =
+            (self.sockets, self.cores, self.threads)
+        {
+            let topology = [self.drawers, self.books, self.dies, self.clusters]
+                .iter()
+                .map(|n| n.unwrap_or(1))
+                .product::<usize>()
+                * sockets
+                * cores
+                * threads;
+            let (key, maxcpus) = match (self.maxcpus, self.cpus) {
+                (Some(maxcpus), _) => ("maxcpus", maxcpus),
+                (None, Some(cpus)) => ("cpus", cpus),
+                (None, None) => return,
+            };
+            if topology != maxcpus {
+                invalid(
+                    errors,
+                    path,
+                    key,
+                    ValidationErrorKind::TopologyMismatch { topology, maxcpus },
+                );
+            }
         }
     }
+}
 
-    #[inline]
-    fn books(&self) -> Option<KVArgQ<'a>> {
-        match self.books {
-            None => None,
-            Some(books) => Some(KVArgQ {
-                key: "books",
-                kv_split_with: Some("="),
-                value: Some(books.to_string()),
-            }),
+/// Builder for `SmpConfig`, e.g.
+///     SmpConfig::builder().cpus(4).sockets(2).cores(2).build()
+#[derive(Debug, Clone)]
+pub struct SmpConfigBuilder {
+    config: SmpConfig,
+}
+
+impl SmpConfig {
+    pub fn builder() -> SmpConfigBuilder {
+        SmpConfigBuilder {
+            config: SmpConfig {
+                cpus: None,
+                maxcpus: None,
+                drawers: None,
+                books: None,
+                sockets: None,
+                dies: None,
+                clusters: None,
+                cores: None,
+                threads: None,
+            },
         }
     }
+}
+
+impl SmpConfigBuilder {
+    pub fn cpus(mut self, cpus: usize) -> Self {
+        self.config.cpus = Some(cpus);
+        self
+    }
 
-    #[inline]
-    fn sockets(&self) -> Option<KVArgQ<'a>> {
-        match self.sockets {
-            None => None,
-            Some(sockets) => Some(KVArgQ {
-                key: "sockets",
-                kv_split_with: Some("="),
-                value: Some(sockets.to_string()),
-            }),
-        }
+    pub fn maxcpus(mut self, maxcpus: usize) -> Self {
+        self.config.maxcpus = Some(maxcpus);
+        self
     }
 
-    #[inline]
-    fn dies(&self) -> Option<KVArgQ<'a>> {
-        match self.sockets {
-            None => None,
-            Some(dies) => Some(KVArgQ {
-                key: "dies",
-                kv_split_with: Some("="),
-                value: Some(dies.to_string()),
-            }),
-        }
+    pub fn drawers(mut self, drawers: usize) -> Self {
+        self.config.drawers = Some(drawers);
+        self
     }
 
-    #[inline]
-    fn clusters(&self) -> Option<KVArgQ<'a>> {
-        match self.clusters {
-            None => None,
-            Some(clusters) => Some(KVArgQ {
-                key: "clusters",
-                kv_split_with: Some("="),
-                value: Some(clusters.to_string()),
-            }),
-        }
+    pub fn books(mut self, books: usize) -> Self {
+        self.config.books = Some(books);
+        self
     }
 
-    #[inline]
-    fn cores(&self) -> Option<KVArgQ<'a>> {
-        match self.cores {
-            None => None,
-            Some(cores) => Some(KVArgQ {
-                key: "cores",
-                kv_split_with: Some("="),
-                value: Some(cores.to_string()),
-            }),
-        }
+    pub fn sockets(mut self, sockets: usize) -> Self {
+        self.config.sockets = Some(sockets);
+        self
     }
 
-    #[inline]
-    fn threads(&self) -> Option<KVArgQ<'a>> {
-        match self.threads {
-            None => None,
-            Some(threads) => Some(KVArgQ {
-                key: "threads",
-                kv_split_with: Some("="),
-                value: Some(threads.to_string()),
-            }),
-        }
+    pub fn dies(mut self, dies: usize) -> Self {
+        self.config.dies = Some(dies);
+        self
+    }
+
+    pub fn clusters(mut self, clusters: usize) -> Self {
+        self.config.clusters = Some(clusters);
+        self
+    }
+
+    pub fn cores(mut self, cores: usize) -> Self {
+        self.config.cores = Some(cores);
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.config.threads = Some(threads);
+        self
+    }
+
+    pub fn build(self) -> SmpConfig {
+        self.config
+    }
+}
+
+impl From<SmpConfigBuilder> for SmpConfig {
+    fn from(builder: SmpConfigBuilder) -> Self {
+        builder.build()
     }
 }
 
-impl<'a> OptionFormatting<'a> for SmpConfig {
-    fn formatting(&self) -> OptionQ<'a> {
-        OptionQ {
-            prefix: "-",
-            raw: "smp",
-            option_args_split_with: " ",
-            args_split_with: ",",
-            args: vec![
-                self.cpus(),
-                self.maxcpus(),
-                self.drawers(),
-                self.books(),
-                self.sockets(),
-                self.dies(),
-                self.clusters(),
-                self.cores(),
-                self.threads(),
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_smp() {
+        let smp = SmpConfig {
+            cpus: Some(8),
+            maxcpus: None,
+            drawers: None,
+            books: None,
+            sockets: Some(2),
+            dies: Some(1),
+            clusters: None,
+            cores: Some(2),
+            threads: Some(2),
+        };
+        assert_eq!(
+            smp.formatting().to_string(),
+            "-smp cpus=8,sockets=2,dies=1,cores=2,threads=2"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let smp = SmpConfig::builder()
+            .cpus(0)
+            .sockets(1)
+            .cores(0)
+            .threads(1)
+            .build();
+        let mut errors = Vec::new();
+        smp.validate("smp", &mut errors);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "smp.cpus: expected a number greater than 0",
+                "smp.cores: expected a number greater than 0",
             ]
-            .into_iter()
-            .filter(|x| x.is_some())
-            .map(|x| x.unwrap())
-            .collect(),
-        }
+        );
     }
 }
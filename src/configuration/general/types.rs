@@ -0,0 +1,500 @@
+//! Typed option values, rendered as the exact tokens QEMU expects.
+use std::{error::Error, fmt, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// A value that is not one of the tokens of an enumerated option.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidToken {
+    pub value: String,
+    pub expected: &'static [&'static str],
+}
+
+impl fmt::Display for InvalidToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}' is not one of {}",
+            self.value,
+            self.expected.join("|")
+        )
+    }
+}
+
+impl Error for InvalidToken {}
+
+/// Declare an enum of QEMU tokens. The first token of a variant is the one
+/// emitted, the others are aliases accepted when parsing. JSON booleans are
+/// read as `on` and `off`.
+macro_rules! qemu_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $(
+                $(#[$variant_meta:meta])*
+                $variant:ident = $token:literal $(| $alias:literal)*
+            ),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $(
+                $(#[$variant_meta])*
+                $variant,
+            )+
+        }
+
+        impl $name {
+            pub const TOKENS: &'static [&'static str] = &[$($token),+];
+
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $token,)+
+                }
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = $crate::configuration::general::types::InvalidToken;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $($token $(| $alias)* => Ok($name::$variant),)+
+                    _ => Err($crate::configuration::general::types::InvalidToken {
+                        value: s.to_string(),
+                        expected: Self::TOKENS,
+                    }),
+                }
+            }
+        }
+
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct TokenVisitor;
+
+                impl<'de> serde::de::Visitor<'de> for TokenVisitor {
+                    type Value = $name;
+
+                    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                        write!(f, "one of {}", $name::TOKENS.join("|"))
+                    }
+
+                    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<$name, E> {
+                        v.parse()
+                            .map_err(|_| E::invalid_value(serde::de::Unexpected::Str(v), &self))
+                    }
+
+                    fn visit_bool<E: serde::de::Error>(self, v: bool) -> Result<$name, E> {
+                        (if v { "on" } else { "off" })
+                            .parse()
+                            .map_err(|_| E::invalid_value(serde::de::Unexpected::Bool(v), &self))
+                    }
+                }
+
+                deserializer.deserialize_any(TokenVisitor)
+            }
+        }
+    };
+}
+
+pub(crate) use qemu_enum;
+
+qemu_enum! {
+    /// on|off
+    pub enum OnOff {
+        On = "on" | "yes" | "true" | "y",
+        Off = "off" | "no" | "false" | "n",
+    }
+}
+
+impl From<bool> for OnOff {
+    fn from(b: bool) -> Self {
+        if b {
+            OnOff::On
+        } else {
+            OnOff::Off
+        }
+    }
+}
+
+qemu_enum! {
+    /// on|off|auto
+    pub enum OnOffAuto {
+        On = "on" | "yes" | "true" | "y",
+        Off = "off" | "no" | "false" | "n",
+        Auto = "auto",
+    }
+}
+
+impl From<bool> for OnOffAuto {
+    fn from(b: bool) -> Self {
+        if b {
+            OnOffAuto::On
+        } else {
+            OnOffAuto::Off
+        }
+    }
+}
+
+impl From<OnOff> for OnOffAuto {
+    fn from(value: OnOff) -> Self {
+        match value {
+            OnOff::On => OnOffAuto::On,
+            OnOff::Off => OnOffAuto::Off,
+        }
+    }
+}
+
+qemu_enum! {
+    /// kernel-irqchip=on|off|split
+    pub enum KernelIrqchip {
+        On = "on" | "yes" | "true" | "y",
+        Off = "off" | "no" | "false" | "n",
+        Split = "split",
+    }
+}
+
+qemu_enum! {
+    /// thread=single|multi
+    pub enum TcgThread {
+        Single = "single",
+        Multi = "multi",
+    }
+}
+
+qemu_enum! {
+    /// Accelerators QEMU can be built with.
+    pub enum Accelerator {
+        Kvm = "kvm",
+        Xen = "xen",
+        Hax = "hax",
+        Hvf = "hvf",
+        Nvmm = "nvmm",
+        Whpx = "whpx",
+        Tcg = "tcg",
+        Qtest = "qtest",
+    }
+}
+
+qemu_enum! {
+    /// Target architectures, as in the name of `qemu-system-<arch>`.
+    pub enum Arch {
+        X86_64 = "x86_64",
+        Aarch64 = "aarch64",
+        Riscv64 = "riscv64",
+        S390x = "s390x",
+        Ppc64 = "ppc64",
+    }
+}
+
+impl Arch {
+    /// The architecture of a QEMU binary given by name or path, e.g.
+    /// `/usr/bin/qemu-system-aarch64`. Other binaries such as `qemu-kvm`
+    /// have none.
+    pub fn from_binary(qemu: &str) -> Option<Self> {
+        let name = qemu.rsplit('/').next().unwrap_or(qemu);
+        name.strip_prefix("qemu-system-")?.parse().ok()
+    }
+
+    /// The name of the QEMU binary, e.g. `qemu-system-s390x`.
+    pub fn binary(&self) -> String {
+        format!("qemu-system-{}", self)
+    }
+}
+
+/// An amount of bytes, written with QEMU's size suffixes, e.g. `512M` or
+/// `8G`. Suffixes are binary multiples, as in QEMU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByteSize(u64);
+
+/// A size that cannot be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidByteSize(pub String);
+
+impl fmt::Display for InvalidByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid size '{}'", self.0)
+    }
+}
+
+impl Error for InvalidByteSize {}
+
+impl ByteSize {
+    pub const B: u64 = 1;
+    pub const K: u64 = 1 << 10;
+    pub const M: u64 = 1 << 20;
+    pub const G: u64 = 1 << 30;
+    pub const T: u64 = 1 << 40;
+
+    const SUFFIXES: [(char, u64); 7] = [
+        ('E', 1 << 60),
+        ('P', 1 << 50),
+        ('T', Self::T),
+        ('G', Self::G),
+        ('M', Self::M),
+        ('K', Self::K),
+        ('B', Self::B),
+    ];
+
+    pub const fn b(n: u64) -> Self {
+        ByteSize(n)
+    }
+
+    // The unit constructors saturate at `u64::MAX` bytes rather than panic
+    // on overflow.
+    pub const fn kib(n: u64) -> Self {
+        ByteSize(n.saturating_mul(Self::K))
+    }
+
+    pub const fn mib(n: u64) -> Self {
+        ByteSize(n.saturating_mul(Self::M))
+    }
+
+    pub const fn gib(n: u64) -> Self {
+        ByteSize(n.saturating_mul(Self::G))
+    }
+
+    pub const fn tib(n: u64) -> Self {
+        ByteSize(n.saturating_mul(Self::T))
+    }
+
+    pub const fn bytes(&self) -> u64 {
+        self.0
+    }
+
+    /// Read a size. A number without suffix counts in `unit`, since QEMU
+    /// reads e.g. `-m 512` as MiB but `kvm-shadow-mem=4096` as bytes.
+    pub fn parse_with_unit(s: &str, unit: u64) -> Result<Self, InvalidByteSize> {
+        let invalid = || InvalidByteSize(s.to_string());
+        let s = s.trim();
+        let (number, unit) = match s.chars().last() {
+            Some(c) if c.is_ascii_alphabetic() => {
+                let (_, unit) = Self::SUFFIXES
+                    .iter()
+                    .find(|(suffix, _)| suffix.eq_ignore_ascii_case(&c))
+                    .ok_or_else(invalid)?;
+                (&s[..s.len() - 1], *unit)
+            }
+            _ => (s, unit),
+        };
+        // Fractions such as 1.5G are accepted as long as they come out as a
+        // whole number of bytes.
+        let bytes = match number.split_once('.') {
+            None => number.parse::<u64>().ok().and_then(|n| n.checked_mul(unit)),
+            Some(_) => {
+                let n = number.parse::<f64>().map_err(|_| invalid())? * unit as f64;
+                (n.fract() == 0.0 && n >= 0.0 && n < u64::MAX as f64).then_some(n as u64)
+            }
+        };
+        bytes.map(ByteSize).ok_or_else(invalid)
+    }
+
+    /// Read a size where a number without suffix counts in MiB.
+    pub fn parse_mib(s: &str) -> Result<Self, InvalidByteSize> {
+        Self::parse_with_unit(s, Self::M)
+    }
+
+    /// The size as a bare count of MiB, for properties that take no suffix.
+    /// Rounds down to whole MiB.
+    pub fn to_mib_string(&self) -> String {
+        (self.0 / Self::M).to_string()
+    }
+
+    /// The size as a bare count of bytes, for properties that take no suffix.
+    pub fn to_bytes_string(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The largest suffix that divides evenly, so the value stays exact.
+        let (suffix, unit) = Self::SUFFIXES
+            .iter()
+            .find(|(_, unit)| self.0.is_multiple_of(*unit) && self.0 != 0)
+            .unwrap_or(&('B', Self::B));
+        write!(f, "{}{}", self.0 / unit, suffix)
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = InvalidByteSize;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_with_unit(s, Self::B)
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+struct ByteSizeVisitor(u64);
+
+impl<'de> de::Visitor<'de> for ByteSizeVisitor {
+    type Value = ByteSize;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a size such as 512M or a number")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<ByteSize, E> {
+        v.checked_mul(self.0)
+            .map(ByteSize)
+            .ok_or_else(|| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<ByteSize, E> {
+        ByteSize::parse_with_unit(v, self.0)
+            .map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ByteSizeVisitor(ByteSize::B))
+    }
+}
+
+/// Deserialize a `ByteSize` whose bare numbers count in MiB, for use with
+/// `#[serde(deserialize_with = "...")]` on options such as `-m`.
+pub mod mib {
+    use super::*;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ByteSize, D::Error> {
+        deserializer.deserialize_any(ByteSizeVisitor(ByteSize::M))
+    }
+
+    pub mod option {
+        use super::*;
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<ByteSize>, D::Error> {
+            super::deserialize(deserializer).map(Some)
+        }
+    }
+}
+
+/// Deserialize a repeatable option from either a list or, as older configs
+/// wrote it, a single object.
+pub mod one_or_many {
+    use super::*;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        Many(Vec<T>),
+        One(T),
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>,
+    {
+        Ok(match OneOrMany::deserialize(deserializer)? {
+            OneOrMany::Many(many) => many,
+            OneOrMany::One(one) => vec![one],
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tokens() {
+        assert_eq!(OnOffAuto::Auto.to_string(), "auto");
+        assert_eq!("yes".parse::<OnOff>(), Ok(OnOff::On));
+        assert_eq!("split".parse::<KernelIrqchip>(), Ok(KernelIrqchip::Split));
+        assert_eq!(
+            "of".parse::<OnOff>(),
+            Err(InvalidToken {
+                value: "of".to_string(),
+                expected: &["on", "off"]
+            })
+        );
+    }
+
+    #[test]
+    fn test_arch() {
+        assert_eq!(
+            Arch::from_binary("/usr/bin/qemu-system-aarch64"),
+            Some(Arch::Aarch64)
+        );
+        assert_eq!(Arch::from_binary("qemu-system-s390x"), Some(Arch::S390x));
+        assert_eq!(Arch::from_binary("qemu-kvm"), None);
+        assert_eq!(Arch::from_binary("qemu-system-sparc"), None);
+        assert_eq!(Arch::Ppc64.binary(), "qemu-system-ppc64");
+    }
+
+    #[test]
+    fn test_serde() {
+        let values: Vec<OnOffAuto> = serde_json::from_str(r#"["on", false, "auto"]"#).unwrap();
+        assert_eq!(values, vec![OnOffAuto::On, OnOffAuto::Off, OnOffAuto::Auto]);
+        assert_eq!(
+            serde_json::to_string(&values).unwrap(),
+            r#"["on","off","auto"]"#
+        );
+
+        assert!(serde_json::from_str::<OnOff>(r#""of""#).is_err());
+        assert!(serde_json::from_str::<TcgThread>("true").is_err());
+    }
+
+    #[test]
+    fn test_byte_size() {
+        assert_eq!("8G".parse::<ByteSize>(), Ok(ByteSize::gib(8)));
+        assert_eq!("512m".parse::<ByteSize>(), Ok(ByteSize::mib(512)));
+        assert_eq!("1.5G".parse::<ByteSize>(), Ok(ByteSize::mib(1536)));
+        assert_eq!("4096".parse::<ByteSize>(), Ok(ByteSize::kib(4)));
+        assert_eq!(
+            ByteSize::parse_with_unit("512", ByteSize::M),
+            Ok(ByteSize::mib(512))
+        );
+        assert!("12X".parse::<ByteSize>().is_err());
+        assert!("0.3B".parse::<ByteSize>().is_err());
+
+        assert_eq!(ByteSize::mib(1024).to_string(), "1G");
+        assert_eq!(ByteSize::mib(1536).to_string(), "1536M");
+        assert_eq!(ByteSize::b(1000).to_string(), "1000B");
+        assert_eq!(ByteSize::b(0).to_string(), "0B");
+
+        assert_eq!(ByteSize::gib(u64::MAX).bytes(), u64::MAX);
+        assert_eq!(ByteSize::kib(1 << 54).bytes(), u64::MAX);
+    }
+
+    #[test]
+    fn test_byte_size_serde() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Sizes {
+            bytes: ByteSize,
+            #[serde(deserialize_with = "mib::deserialize")]
+            mib: ByteSize,
+        }
+
+        let sizes: Sizes = serde_json::from_str(r#"{"bytes": 4096, "mib": 512}"#).unwrap();
+        assert_eq!(sizes.bytes, ByteSize::kib(4));
+        assert_eq!(sizes.mib, ByteSize::mib(512));
+
+        let sizes: Sizes = serde_json::from_str(r#"{"bytes": "1M", "mib": "8G"}"#).unwrap();
+        assert_eq!(sizes.mib, ByteSize::gib(8));
+        assert_eq!(serde_json::to_string(&sizes.bytes).unwrap(), r#""1M""#);
+    }
+}
@@ -0,0 +1,122 @@
+//! Semantic checks of a configuration before it is handed to QEMU.
+use std::{error::Error, fmt};
+
+use super::types::ByteSize;
+
+/// A problem found in a configuration. `path` points at the offending key in
+/// the JSON format, e.g. `smp.maxcpus`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub path: String,
+    pub kind: ValidationErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationErrorKind {
+    /// The CPU topology does not multiply out to the expected CPU count.
+    TopologyMismatch { topology: usize, maxcpus: usize },
+    /// More CPUs present at boot than may ever be plugged.
+    CpusAboveMaxcpus { cpus: usize, maxcpus: usize },
+    /// The maximum amount of memory is below the initial amount.
+    MaxmemBelowSize { size: ByteSize, maxmem: ByteSize },
+    /// Memory given to the NUMA nodes does not add up to the RAM size.
+    NumaMemoryMismatch { nodes: ByteSize, size: ByteSize },
+    /// A number or index above the largest allowed value.
+    AboveMaximum { value: usize, max: usize },
+    /// The size must be a multiple of `align`.
+    Unaligned { value: ByteSize, align: ByteSize },
+    /// A key that is only meaningful together with another one.
+    Requires { key: &'static str },
+    /// The value must be zero or a power of two no less than `min`.
+    NotPowerOfTwo { value: usize, min: usize },
+    /// A key that does not apply to the chosen driver or mode.
+    NotSupported { by: String },
+    /// Two keys that cannot be given together.
+    Conflicts { key: &'static str },
+    /// A reference to an id or node name that is not defined.
+    UnknownReference { id: String },
+    /// An id that must be unique, or an object only one user may take.
+    Duplicate { id: String },
+    /// A value not in the expected format.
+    Invalid { expected: &'static str },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.path)?;
+        match &self.kind {
+            ValidationErrorKind::TopologyMismatch { topology, maxcpus } => write!(
+                f,
+                "topology gives {} CPUs but {} are expected",
+                topology, maxcpus
+            ),
+            ValidationErrorKind::CpusAboveMaxcpus { cpus, maxcpus } => {
+                write!(f, "{} CPUs exceed maxcpus={}", cpus, maxcpus)
+            }
+            ValidationErrorKind::MaxmemBelowSize { size, maxmem } => {
+                write!(f, "maxmem={} is below size={}", maxmem, size)
+            }
+            ValidationErrorKind::NumaMemoryMismatch { nodes, size } => {
+                write!(f, "NUMA nodes have {} of memory but size={}", nodes, size)
+            }
+            ValidationErrorKind::AboveMaximum { value, max } => {
+                write!(f, "{} exceeds the maximum of {}", value, max)
+            }
+            ValidationErrorKind::Unaligned { value, align } => {
+                write!(f, "{} is not a multiple of {}", value, align)
+            }
+            ValidationErrorKind::Requires { key } => write!(f, "requires '{}'", key),
+            ValidationErrorKind::NotPowerOfTwo { value, min } => write!(
+                f,
+                "{} is not 0 or a power of two no less than {}",
+                value, min
+            ),
+            ValidationErrorKind::NotSupported { by } => write!(f, "not supported by {}", by),
+            ValidationErrorKind::Conflicts { key } => write!(f, "conflicts with '{}'", key),
+            ValidationErrorKind::UnknownReference { id } => {
+                write!(f, "'{}' is not defined", id)
+            }
+            ValidationErrorKind::Duplicate { id } => write!(f, "'{}' is used more than once", id),
+            ValidationErrorKind::Invalid { expected } => write!(f, "expected {}", expected),
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+/// Check an option config, appending every problem found to `errors`.
+/// `path` is the JSON path of the option itself.
+pub trait Validate {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>);
+}
+
+impl<T: Validate> Validate for Option<T> {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        if let Some(t) = self {
+            t.validate(path, errors);
+        }
+    }
+}
+
+/// Repeatable options are checked one by one, as `path[index]`.
+impl<T: Validate> Validate for Vec<T> {
+    fn validate(&self, path: &str, errors: &mut Vec<ValidationError>) {
+        for (i, t) in self.iter().enumerate() {
+            t.validate(&format!("{}[{}]", path, i), errors);
+        }
+    }
+}
+
+/// Record a `ValidationError` for `key` under `path`.
+#[inline]
+pub fn invalid(
+    errors: &mut Vec<ValidationError>,
+    path: &str,
+    key: &str,
+    kind: ValidationErrorKind,
+) {
+    errors.push(ValidationError {
+        path: format!("{}.{}", path, key),
+        kind,
+    });
+}
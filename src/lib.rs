@@ -1,5 +1,8 @@
+// Lets `#[derive(QemuOption)]` refer to this crate as `::qemu_rs` inside it.
+extern crate self as qemu_rs;
+
 pub mod command;
 pub mod configuration;
 pub mod qmp;
 
-pub mod utils;
\ No newline at end of file
+pub mod utils;
@@ -1,15 +1,11 @@
-use std::{error::Error, fs::File, io::Read, path::Path};
+use std::{error::Error, fs::File, io::BufReader, path::Path};
 
 use crate::configuration::*;
 
 use self::general::config;
 
-pub fn read_json_from_file<'a, P: AsRef<Path>>(
-    path: P,
-    string: &'a mut String,
-) -> Result<config::Config<'a>, Box<dyn Error>> {
-    let mut file = File::open(path)?;
-    file.read_to_string(string)?;
-    let c: config::Config = serde_json::from_str(string)?;
+pub fn read_json_from_file<P: AsRef<Path>>(path: P) -> Result<config::Config, Box<dyn Error>> {
+    let file = File::open(path)?;
+    let c: config::Config = serde_json::from_reader(BufReader::new(file))?;
     Ok(c)
 }